@@ -1,11 +1,16 @@
 // Copyright 2023-2024 The Milton Hirsch Institute, B.V.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::labels::LabelSelector;
+use crate::selector::SubnetSelector;
+use crate::size::{SizeRequest, SubnetSize};
 use cidr::IpCidr;
-use subnet_garden_core::Bits;
+use std::net::IpAddr;
+use subnet_garden_core::{AllocationStrategy, Bits, RootSelector};
 
 pub(crate) mod init {
     use cidr::IpCidr;
+    use subnet_garden_core::AllocationStrategy;
 
     #[derive(Debug, clap::Args)]
     /// Initialize the subnet garden pool file
@@ -14,9 +19,102 @@ pub(crate) mod init {
         /// Force initialization even if the pool file already exists
         pub(crate) force: bool,
 
+        #[arg(short, long, default_value_t)]
+        /// Default allocation strategy of the pool
+        /// (lowest-first, highest-first, best-fit, worst-fit)
+        pub(crate) strategy: AllocationStrategy,
+
+        #[arg(required = true)]
+        /// Pool root CIDRs, such as an IPv4 and an IPv6 range
+        pub(crate) cidrs: Vec<IpCidr>,
+    }
+}
+
+pub(crate) mod host {
+    use std::net::IpAddr;
+
+    #[derive(Debug, clap::Args)]
+    /// Assign the addresses of hosts inside an allocated subnet
+    pub(crate) struct HostArgs {
+        #[command(subcommand)]
+        pub(crate) command: HostCommands,
+    }
+
+    #[derive(Debug, clap::Args)]
+    /// Allocate the next free host address
+    pub(crate) struct HostAllocateArgs {
+        #[arg()]
+        /// Name or CIDR of the subnet
+        pub(crate) subnet: String,
+
+        #[arg()]
+        /// Name of the host
+        pub(crate) name: Option<String>,
+
+        #[arg(long)]
+        /// Also hand out the network and broadcast addresses of IPv4 subnets
+        pub(crate) all_addresses: bool,
+    }
+
+    #[derive(Debug, clap::Args)]
+    /// Claim a specific host address
+    pub(crate) struct HostClaimArgs {
+        #[arg()]
+        /// Name or CIDR of the subnet
+        pub(crate) subnet: String,
+
+        #[arg()]
+        /// Address of the host
+        pub(crate) address: IpAddr,
+
         #[arg()]
-        /// Pool subnet CIDR
-        pub(crate) cidr: IpCidr,
+        /// Name of the host
+        pub(crate) name: Option<String>,
+    }
+
+    #[derive(Debug, clap::Args)]
+    /// Free a host address
+    pub(crate) struct HostFreeArgs {
+        #[arg()]
+        /// Name or CIDR of the subnet
+        pub(crate) subnet: String,
+
+        #[arg()]
+        /// Name or address of the host
+        pub(crate) host: String,
+    }
+
+    #[derive(Debug, clap::Args)]
+    /// Rename a host
+    pub(crate) struct HostRenameArgs {
+        #[arg()]
+        /// Name or CIDR of the subnet
+        pub(crate) subnet: String,
+
+        #[arg()]
+        /// Name or address of the host
+        pub(crate) host: String,
+
+        #[arg()]
+        /// New name of the host or omit to remove the name
+        pub(crate) name: Option<String>,
+    }
+
+    #[derive(Debug, clap::Args)]
+    /// List the hosts of a subnet
+    pub(crate) struct HostListArgs {
+        #[arg()]
+        /// Name or CIDR of the subnet
+        pub(crate) subnet: String,
+    }
+
+    #[derive(Debug, clap::Subcommand)]
+    pub(crate) enum HostCommands {
+        Allocate(HostAllocateArgs),
+        Claim(HostClaimArgs),
+        Free(HostFreeArgs),
+        List(HostListArgs),
+        Rename(HostRenameArgs),
     }
 }
 
@@ -24,24 +122,46 @@ pub(crate) mod init {
 /// Allocate subnet
 pub(crate) struct AllocateArgs {
     #[arg()]
-    /// Number of subnet bits
-    pub(crate) bits: Bits,
+    /// Size of the subnet as host bits (9), prefix length (/23) or
+    /// number of usable hosts (300h)
+    pub(crate) size: SubnetSize,
 
     #[arg()]
-    /// Name or format of the subnet to allocate
+    /// Name or format of the subnet to allocate, preceded by the size of
+    /// the IPv6 subnet when allocating with --dual
     pub(crate) name_format: Option<String>,
 
     #[arg()]
     /// Parameters for subnet name format
     pub(crate) param: Option<Vec<String>>,
+
+    #[arg(short, long)]
+    /// Allocation strategy, overriding the pool default
+    /// (lowest-first, highest-first, best-fit, worst-fit)
+    pub(crate) strategy: Option<AllocationStrategy>,
+
+    #[arg(short, long, default_value_t = 0)]
+    /// Addresses reserved by the provider in each subnet when sizing by
+    /// number of hosts
+    pub(crate) reserved: u128,
+
+    #[arg(long)]
+    /// Root to allocate from (ipv4, ipv6 or a root CIDR)
+    pub(crate) root: Option<RootSelector>,
+
+    #[arg(long, conflicts_with = "root")]
+    /// Allocate an IPv4 and an IPv6 subnet under one name, sized by the
+    /// first two arguments
+    pub(crate) dual: bool,
 }
 
 #[derive(Debug, clap::Args)]
 /// Free subnet
 pub(crate) struct FreeArgs {
-    #[arg()]
-    /// Name CIDR of a subnet or format for multiple subnets
-    pub(crate) identifier_format: String,
+    #[arg(required_unless_present = "selector")]
+    /// Name or CIDR of a subnet, format for multiple subnets or subnet
+    /// selector (glob:PATTERN, re:PATTERN, within:CIDR)
+    pub(crate) identifier_format: Option<String>,
 
     #[arg()]
     /// Parameters for subnet name format
@@ -50,11 +170,28 @@ pub(crate) struct FreeArgs {
     #[arg(short, long)]
     /// Ignore missing subnets
     pub(crate) ignore_missing: bool,
+
+    #[arg(long)]
+    /// Free subnets even if their child pools have allocations
+    pub(crate) force: bool,
+
+    #[arg(long)]
+    /// Only free subnets with matching labels (env=prod,team=infra)
+    pub(crate) selector: Option<LabelSelector>,
+
+    #[arg(short, long)]
+    /// Free every subnet matched by a selector instead of listing them
+    pub(crate) yes: bool,
 }
 
 #[derive(Debug, clap::Args)]
 /// List allocate CIDRs
 pub(crate) struct CidrsArgs {
+    #[arg()]
+    /// List CIDRs matching a subnet selector (glob:PATTERN, re:PATTERN,
+    /// within:CIDR)
+    pub(crate) subnets: Option<SubnetSelector>,
+
     #[arg(short)]
     /// List CIDRs in long format
     pub(crate) long: bool,
@@ -62,14 +199,103 @@ pub(crate) struct CidrsArgs {
     #[arg(short, long, default_value = None)]
     /// List CIDRs within the given CIDR
     pub(crate) within: Option<IpCidr>,
+
+    #[arg(long)]
+    /// List CIDRs with matching labels (env=prod,team=infra)
+    pub(crate) selector: Option<LabelSelector>,
+}
+
+#[derive(Debug, clap::Args)]
+/// List free space that can be allocated or claimed
+pub(crate) struct AvailableArgs {
+    #[arg(short, long)]
+    /// List every place a subnet with this many host bits could go instead
+    /// of the largest free blocks
+    pub(crate) bits: Option<Bits>,
+
+    #[arg(short, long, default_value = None)]
+    /// List free space within the given CIDR
+    pub(crate) within: Option<IpCidr>,
+}
+
+#[derive(Debug, clap::Args)]
+/// Show how full and how fragmented the pool is
+pub(crate) struct StatsArgs {
+    #[arg(long)]
+    /// Print statistics as JSON
+    pub(crate) json: bool,
+}
+
+#[derive(Debug, clap::Args)]
+/// Find the subnets holding IP addresses
+pub(crate) struct LookupArgs {
+    #[arg()]
+    /// Addresses to look up, read one per line from standard input if none
+    /// are given
+    pub(crate) addresses: Vec<IpAddr>,
 }
 
 #[derive(Debug, clap::Args)]
 /// List named subnets
 pub(crate) struct NamesArgs {
+    #[arg()]
+    /// List names matching a subnet selector (glob:PATTERN, re:PATTERN,
+    /// within:CIDR)
+    pub(crate) subnets: Option<SubnetSelector>,
+
     #[arg(short)]
     /// List named CIDRs in long format
     pub(crate) long: bool,
+
+    #[arg(long)]
+    /// List names with matching labels (env=prod,team=infra)
+    pub(crate) selector: Option<LabelSelector>,
+}
+
+#[derive(Debug, clap::Args)]
+/// Grow or shrink an allocated subnet in place
+pub(crate) struct ResizeArgs {
+    #[arg()]
+    /// Name or CIDR of the subnet
+    pub(crate) identifier: String,
+
+    #[arg()]
+    /// New size of the subnet as host bits (9), prefix length (/23) or
+    /// number of usable hosts (300h)
+    pub(crate) size: SubnetSize,
+}
+
+#[derive(Debug, clap::Args)]
+/// Split an allocated subnet into equal smaller subnets
+pub(crate) struct SplitArgs {
+    #[arg()]
+    /// Name or CIDR of the subnet
+    pub(crate) identifier: String,
+
+    #[arg()]
+    /// Size of each new subnet as host bits (9), prefix length (/23) or
+    /// number of usable hosts (300h)
+    pub(crate) size: SubnetSize,
+
+    #[arg()]
+    /// Name or format of the new subnets
+    pub(crate) name_format: Option<String>,
+
+    #[arg()]
+    /// Parameters for subnet name format
+    pub(crate) param: Option<Vec<String>>,
+}
+
+#[derive(Debug, clap::Args)]
+/// Merge allocated subnets that fill one aligned block
+pub(crate) struct MergeArgs {
+    #[arg(required = true)]
+    /// Names or CIDRs of the subnets
+    pub(crate) identifiers: Vec<String>,
+
+    #[arg(short, long)]
+    /// Name of the merged subnet
+    pub(crate) name: Option<String>,
 }
 
 #[derive(Debug, clap::Args)]
@@ -82,13 +308,37 @@ pub(crate) struct ClaimArgs {
     #[arg()]
     /// Name of the subnet to claim
     pub(crate) name: Option<String>,
+
+    #[arg(long)]
+    /// Move the subnets inside the claimed subnet into its child pool
+    pub(crate) adopt: bool,
+}
+
+#[derive(Debug, clap::Args)]
+/// Reserve subnet so that it is never allocated
+pub(crate) struct ReserveArgs {
+    #[arg()]
+    /// CIDR subnet to reserve
+    pub(crate) cidr: IpCidr,
+
+    #[arg()]
+    /// Description of the reservation
+    pub(crate) description: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+/// Release reserved subnet
+pub(crate) struct UnreserveArgs {
+    #[arg()]
+    /// CIDR of the reserved subnet
+    pub(crate) cidr: IpCidr,
 }
 
 #[derive(Debug, clap::Args)]
 /// Rename subnet
 pub(crate) struct RenameArgs {
     #[arg()]
-    /// Name or CIDR of the subnet to rename
+    /// Name, CIDR or selector of the subnet to rename
     pub(crate) identifier: String,
 
     #[arg()]
@@ -96,20 +346,143 @@ pub(crate) struct RenameArgs {
     pub(crate) name: Option<String>,
 }
 
+#[derive(Debug, clap::Args)]
+/// Add or change subnet labels
+pub(crate) struct LabelArgs {
+    #[arg()]
+    /// Name or CIDR of the subnet to label
+    pub(crate) identifier: String,
+
+    #[arg(value_parser = crate::labels::parse_label)]
+    /// Labels to set as key=value
+    pub(crate) labels: Vec<(String, String)>,
+
+    #[arg(short, long)]
+    /// Description of the subnet
+    pub(crate) description: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+/// Remove subnet labels
+pub(crate) struct UnlabelArgs {
+    #[arg()]
+    /// Name or CIDR of the subnet to unlabel
+    pub(crate) identifier: String,
+
+    #[arg()]
+    /// Keys of the labels to remove
+    pub(crate) keys: Vec<String>,
+
+    #[arg(short, long)]
+    /// Remove the description of the subnet
+    pub(crate) description: bool,
+}
+
+#[derive(Debug, clap::Args)]
+/// Add a root CIDR to the pool
+pub(crate) struct AddRootArgs {
+    #[arg()]
+    /// Root CIDR to add
+    pub(crate) cidr: IpCidr,
+}
+
+#[derive(Debug, clap::Args)]
+/// Remove an empty root CIDR from the pool
+pub(crate) struct RemoveRootArgs {
+    #[arg()]
+    /// Root CIDR to remove
+    pub(crate) cidr: IpCidr,
+}
+
+#[derive(Debug, clap::Args)]
+/// Grow or shrink the root CIDR of the pool
+pub(crate) struct ResizePoolArgs {
+    #[arg()]
+    /// New root CIDR, a supernet or subnet of an existing root
+    pub(crate) cidr: IpCidr,
+}
+
+#[derive(Debug, clap::Args)]
+/// Turn an allocated subnet into a child pool
+pub(crate) struct MakePoolArgs {
+    #[arg()]
+    /// Name or CIDR of the subnet
+    pub(crate) identifier: String,
+}
+
+#[derive(Debug, clap::Args)]
+/// How many more subnets of a size fit, or whether a set of subnets fits
+pub(crate) struct CapacityArgs {
+    #[arg(required_unless_present = "requests", conflicts_with = "requests")]
+    /// Size of the subnets as host bits (9), prefix length (/23) or
+    /// number of usable hosts (300h)
+    pub(crate) size: Option<SubnetSize>,
+
+    #[arg(long = "request")]
+    /// Number and size of subnets that must fit together, such as 10x8
+    /// or 20x/26, repeated for each size
+    pub(crate) requests: Vec<SizeRequest>,
+
+    #[arg(long)]
+    /// Roots to count in (ipv4, ipv6 or a root CIDR)
+    pub(crate) root: Option<RootSelector>,
+}
+
 #[derive(Debug, clap::Args)]
 /// Largest available subnet (by bits)
 pub(crate) struct MaxAvailableArgs {}
 
+#[derive(Debug, clap::Args)]
+/// Upgrade the pool file to the current format version
+pub(crate) struct MigrateArgs {}
+
+#[derive(Debug, clap::Args)]
+/// Check that the pool file loads and its derived state is consistent
+pub(crate) struct CheckArgs {}
+
+#[derive(Debug, clap::Args)]
+/// Rewrite the pool file in canonical form
+pub(crate) struct FmtArgs {
+    #[arg(long)]
+    /// Leave the file as it is and fail with a diff if it is not in
+    /// canonical form
+    pub(crate) check: bool,
+}
+
+#[derive(Debug, clap::Args)]
+/// Rebuild the derived state of the pool and store it again
+pub(crate) struct RepairArgs {}
+
 #[derive(Debug, clap::Subcommand)]
 pub(crate) enum SubgCommands {
+    AddRoot(AddRootArgs),
     Allocate(AllocateArgs),
+    Available(AvailableArgs),
+    Capacity(CapacityArgs),
+    Check(CheckArgs),
     Cidrs(CidrsArgs),
     Claim(ClaimArgs),
+    Fmt(FmtArgs),
     Free(FreeArgs),
+    Host(host::HostArgs),
     Init(init::InitArgs),
+    Label(LabelArgs),
+    Lookup(LookupArgs),
+    MakePool(MakePoolArgs),
     MaxAvailable(MaxAvailableArgs),
+    Merge(MergeArgs),
+    Migrate(MigrateArgs),
     Names(NamesArgs),
+    RemoveRoot(RemoveRootArgs),
     Rename(RenameArgs),
+    Repair(RepairArgs),
+    Reserve(ReserveArgs),
+    Resize(ResizeArgs),
+    ResizePool(ResizePoolArgs),
+    Split(SplitArgs),
+    Stats(StatsArgs),
+    Unlabel(UnlabelArgs),
+    Unreserve(UnreserveArgs),
 }
 
 #[derive(Debug, clap::Args)]
@@ -117,6 +490,10 @@ pub(crate) enum SubgCommands {
 pub(crate) struct SubgArgs {
     #[arg(short = 'p', long, default_value = subg::DEFAULT_STORAGE_PATH, env = "SUBG_POOL_PATH")]
     pub(crate) pool_path: String,
+
+    #[arg(long = "parent", global = true)]
+    /// Name or CIDR of the child pool to act on, repeated for nested pools
+    pub(crate) parents: Vec<String>,
 }
 
 #[derive(Debug, clap::Parser)]
@@ -0,0 +1,150 @@
+// Copyright 2024 The Milton Hirsch Institute, B.V.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::error::Error;
+use std::fmt::Display;
+use std::str::FromStr;
+use subnet_garden_core::CidrRecord;
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum LabelError {
+    InvalidLabel(String),
+    InvalidSelector(String),
+}
+
+impl Display for LabelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LabelError::InvalidLabel(label) => write!(f, "Invalid label: {}", label),
+            LabelError::InvalidSelector(selector) => {
+                write!(f, "Invalid selector: {}", selector)
+            }
+        }
+    }
+}
+
+impl Error for LabelError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+/// Parse a `key=value` label argument.
+pub(crate) fn parse_label(s: &str) -> Result<(String, String), LabelError> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(LabelError::InvalidLabel(s.to_string())),
+    }
+}
+
+/// Comma-separated label requirements, such as `env=prod,team=infra`.
+///
+/// A requirement without a value, such as `env`, only requires that the
+/// label is present.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LabelSelector {
+    requirements: Vec<(String, Option<String>)>,
+}
+
+impl LabelSelector {
+    pub(crate) fn matches(&self, record: &CidrRecord) -> bool {
+        self.requirements
+            .iter()
+            .all(|(key, value)| match (record.labels.get(key), value) {
+                (Some(_), None) => true,
+                (Some(actual), Some(expected)) => actual == expected,
+                (None, _) => false,
+            })
+    }
+}
+
+impl FromStr for LabelSelector {
+    type Err = LabelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut requirements = Vec::new();
+        for requirement in s.split(',') {
+            let (key, value) = match requirement.split_once('=') {
+                Some((key, value)) => (key, Some(value.to_string())),
+                None => (requirement, None),
+            };
+            if key.is_empty() {
+                return Err(LabelError::InvalidSelector(s.to_string()));
+            }
+            requirements.push((key.to_string(), value));
+        }
+        Ok(LabelSelector { requirements })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod parse_label {
+        use super::*;
+
+        #[test]
+        fn success() {
+            assert_eq!(
+                parse_label("env=prod"),
+                Ok(("env".to_string(), "prod".to_string()))
+            );
+            assert_eq!(
+                parse_label("note=a=b"),
+                Ok(("note".to_string(), "a=b".to_string()))
+            );
+            assert_eq!(
+                parse_label("empty="),
+                Ok(("empty".to_string(), "".to_string()))
+            );
+        }
+
+        #[test]
+        fn invalid() {
+            for label in ["env", "=prod", ""] {
+                assert_eq!(
+                    parse_label(label),
+                    Err(LabelError::InvalidLabel(label.to_string()))
+                );
+            }
+        }
+    }
+
+    mod label_selector {
+        use super::*;
+
+        fn record(labels: &[(&str, &str)]) -> CidrRecord {
+            let mut pool =
+                subnet_garden_core::pool::SubnetPool::new("10.10.0.0/16".parse().unwrap());
+            let cidr = pool.allocate(4, None).unwrap();
+            pool.label(&cidr, labels).unwrap();
+            pool.find_record(&cidr).unwrap().clone()
+        }
+
+        #[test]
+        fn invalid() {
+            for selector in ["", "env=prod,", "=prod"] {
+                assert_eq!(
+                    selector.parse::<LabelSelector>(),
+                    Err(LabelError::InvalidSelector(selector.to_string()))
+                );
+            }
+        }
+
+        #[test]
+        fn matches_all() {
+            let selector: LabelSelector = "env=prod,team=infra".parse().unwrap();
+            assert!(selector.matches(&record(&[("env", "prod"), ("team", "infra")])));
+            assert!(!selector.matches(&record(&[("env", "prod"), ("team", "web")])));
+            assert!(!selector.matches(&record(&[("env", "prod")])));
+        }
+
+        #[test]
+        fn matches_present() {
+            let selector: LabelSelector = "env".parse().unwrap();
+            assert!(selector.matches(&record(&[("env", "dev")])));
+            assert!(!selector.matches(&record(&[])));
+        }
+    }
+}
@@ -5,11 +5,18 @@ use crate::args::{Subg, SubgCommands};
 
 use clap::Parser;
 use subcommands::init;
+use subcommands::integrity;
+use subcommands::roots;
 use subcommands::subnet;
+use subcommands::subnet::hosts;
+use subcommands::subnet::labeling;
 use subcommands::subnet::listing;
 
 mod args;
+mod labels;
 mod param_str;
+mod selector;
+mod size;
 mod subcommands;
 mod util;
 
@@ -20,6 +27,15 @@ fn main() {
         SubgCommands::Init(args) => {
             init::init(&subg.args, &args);
         }
+        SubgCommands::AddRoot(args) => {
+            roots::add_root(&subg.args, &args);
+        }
+        SubgCommands::RemoveRoot(args) => {
+            roots::remove_root(&subg.args, &args);
+        }
+        SubgCommands::ResizePool(args) => {
+            roots::resize_pool(&subg.args, &args);
+        }
         SubgCommands::Allocate(args) => {
             subnet::allocate(&subg.args, &args);
         }
@@ -29,6 +45,15 @@ fn main() {
         SubgCommands::Cidrs(args) => {
             listing::cidrs(&subg.args, &args);
         }
+        SubgCommands::Available(args) => {
+            listing::available(&subg.args, &args);
+        }
+        SubgCommands::Stats(args) => {
+            listing::stats(&subg.args, &args);
+        }
+        SubgCommands::Lookup(args) => {
+            listing::lookup(&subg.args, &args);
+        }
         SubgCommands::Names(args) => {
             listing::names(&subg.args, &args);
         }
@@ -38,9 +63,51 @@ fn main() {
         SubgCommands::Rename(args) => {
             subnet::rename(&subg.args, &args);
         }
+        SubgCommands::Resize(args) => {
+            subnet::resize(&subg.args, &args);
+        }
+        SubgCommands::Split(args) => {
+            subnet::split(&subg.args, &args);
+        }
+        SubgCommands::Merge(args) => {
+            subnet::merge(&subg.args, &args);
+        }
+        SubgCommands::Reserve(args) => {
+            subnet::reserve(&subg.args, &args);
+        }
+        SubgCommands::Unreserve(args) => {
+            subnet::unreserve(&subg.args, &args);
+        }
+        SubgCommands::Host(args) => {
+            hosts::host(&subg.args, &args);
+        }
+        SubgCommands::Label(args) => {
+            labeling::label(&subg.args, &args);
+        }
+        SubgCommands::Unlabel(args) => {
+            labeling::unlabel(&subg.args, &args);
+        }
+        SubgCommands::MakePool(args) => {
+            subnet::make_pool(&subg.args, &args);
+        }
+        SubgCommands::Capacity(args) => {
+            subnet::capacity(&subg.args, &args);
+        }
         SubgCommands::MaxAvailable(_) => {
             subnet::max_bits(&subg.args);
         }
+        SubgCommands::Check(_) => {
+            integrity::check(&subg.args);
+        }
+        SubgCommands::Repair(_) => {
+            integrity::repair(&subg.args);
+        }
+        SubgCommands::Fmt(args) => {
+            integrity::fmt(&subg.args, &args);
+        }
+        SubgCommands::Migrate(_) => {
+            integrity::migrate(&subg.args);
+        }
     }
 }
 
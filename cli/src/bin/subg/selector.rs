@@ -0,0 +1,169 @@
+// Copyright 2024 The Milton Hirsch Institute, B.V.
+// SPDX-License-Identifier: Apache-2.0
+
+use cidr::IpCidr;
+use globset::{Glob, GlobMatcher};
+use regex::Regex;
+use std::error::Error;
+use std::fmt::Display;
+use std::str::FromStr;
+use subnet_garden_core::CidrRecord;
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum SelectorError {
+    UnknownSelector(String),
+    InvalidGlob(String),
+    InvalidRegex(String),
+    InvalidCidr(String),
+}
+
+impl Display for SelectorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SelectorError::UnknownSelector(selector) => {
+                write!(f, "Unknown subnet selector: {}", selector)
+            }
+            SelectorError::InvalidGlob(glob) => write!(f, "Invalid glob: {}", glob),
+            SelectorError::InvalidRegex(regex) => write!(f, "Invalid regex: {}", regex),
+            SelectorError::InvalidCidr(cidr) => write!(f, "Invalid CIDR: {}", cidr),
+        }
+    }
+}
+
+impl Error for SelectorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+/// Pattern selecting any number of subnets, such as `glob:lab-*`,
+/// `re:^prod-.*-a$` or `within:10.10.0.0/20`.
+///
+/// Glob and regex patterns match subnet names, so unnamed subnets are only
+/// selected by `within:`.
+#[derive(Debug, Clone)]
+pub(crate) enum SubnetSelector {
+    Glob(GlobMatcher),
+    Regex(Regex),
+    Within(IpCidr),
+}
+
+impl SubnetSelector {
+    /// Parse an identifier argument, or `None` if it does not use the
+    /// selector syntax and names a single subnet instead.
+    pub(crate) fn parse_identifier(identifier: &str) -> Option<Result<Self, SelectorError>> {
+        let (kind, _) = identifier.split_once(':')?;
+        match kind {
+            "glob" | "re" | "within" => Some(identifier.parse()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn matches(&self, record: &CidrRecord) -> bool {
+        match self {
+            SubnetSelector::Glob(glob) => record
+                .name
+                .as_deref()
+                .is_some_and(|name| glob.is_match(name)),
+            SubnetSelector::Regex(regex) => record
+                .name
+                .as_deref()
+                .is_some_and(|name| regex.is_match(name)),
+            SubnetSelector::Within(cidr) => {
+                cidr.contains(&record.cidr.first_address())
+                    && cidr.contains(&record.cidr.last_address())
+            }
+        }
+    }
+}
+
+impl FromStr for SubnetSelector {
+    type Err = SelectorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("glob", glob)) => Glob::new(glob)
+                .map(|glob| SubnetSelector::Glob(glob.compile_matcher()))
+                .map_err(|_| SelectorError::InvalidGlob(glob.to_string())),
+            Some(("re", regex)) => Regex::new(regex)
+                .map(SubnetSelector::Regex)
+                .map_err(|_| SelectorError::InvalidRegex(regex.to_string())),
+            Some(("within", cidr)) => cidr
+                .parse()
+                .map(SubnetSelector::Within)
+                .map_err(|_| SelectorError::InvalidCidr(cidr.to_string())),
+            _ => Err(SelectorError::UnknownSelector(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(cidr: &str, name: Option<&str>) -> CidrRecord {
+        let mut pool = subnet_garden_core::pool::SubnetPool::new("10.10.0.0/16".parse().unwrap());
+        pool.add_root("fc00::/56".parse().unwrap()).unwrap();
+        let cidr = cidr.parse().unwrap();
+        pool.claim(&cidr, name).unwrap();
+        pool.find_record(&cidr).unwrap().clone()
+    }
+
+    #[test]
+    fn parse_identifier() {
+        assert!(SubnetSelector::parse_identifier("lab-1").is_none());
+        assert!(SubnetSelector::parse_identifier("10.10.0.0/24").is_none());
+        assert!(SubnetSelector::parse_identifier("fc00::/64").is_none());
+        assert!(SubnetSelector::parse_identifier("other:lab").is_none());
+        assert!(matches!(
+            SubnetSelector::parse_identifier("glob:lab-*"),
+            Some(Ok(SubnetSelector::Glob(_)))
+        ));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_eq!(
+            "glob:lab-[".parse::<SubnetSelector>().unwrap_err(),
+            SelectorError::InvalidGlob("lab-[".to_string())
+        );
+        assert_eq!(
+            "re:(".parse::<SubnetSelector>().unwrap_err(),
+            SelectorError::InvalidRegex("(".to_string())
+        );
+        assert_eq!(
+            "within:10.10.0.0/33".parse::<SubnetSelector>().unwrap_err(),
+            SelectorError::InvalidCidr("10.10.0.0/33".to_string())
+        );
+        assert_eq!(
+            "lab-*".parse::<SubnetSelector>().unwrap_err(),
+            SelectorError::UnknownSelector("lab-*".to_string())
+        );
+    }
+
+    #[test]
+    fn matches_glob() {
+        let selector: SubnetSelector = "glob:lab-*".parse().unwrap();
+        assert!(selector.matches(&record("10.10.0.0/24", Some("lab-1"))));
+        assert!(!selector.matches(&record("10.10.0.0/24", Some("prod-lab-1"))));
+        assert!(!selector.matches(&record("10.10.0.0/24", None)));
+    }
+
+    #[test]
+    fn matches_regex() {
+        let selector: SubnetSelector = "re:^prod-.*-a$".parse().unwrap();
+        assert!(selector.matches(&record("10.10.0.0/24", Some("prod-web-a"))));
+        assert!(!selector.matches(&record("10.10.0.0/24", Some("prod-web-b"))));
+        assert!(!selector.matches(&record("10.10.0.0/24", None)));
+    }
+
+    #[test]
+    fn matches_within() {
+        let selector: SubnetSelector = "within:10.10.0.0/20".parse().unwrap();
+        assert!(selector.matches(&record("10.10.0.0/20", None)));
+        assert!(selector.matches(&record("10.10.15.0/24", Some("lab-1"))));
+        assert!(!selector.matches(&record("10.10.0.0/19", None)));
+        assert!(!selector.matches(&record("10.10.16.0/24", None)));
+        assert!(!selector.matches(&record("fc00::/64", None)));
+    }
+}
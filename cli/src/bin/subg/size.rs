@@ -0,0 +1,196 @@
+// Copyright 2024 The Milton Hirsch Institute, B.V.
+// SPDX-License-Identifier: Apache-2.0
+
+use cidr::IpCidr;
+use std::error::Error;
+use std::fmt::Display;
+use std::str::FromStr;
+use subnet_garden_core::{Bits, RequestSize};
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum SizeError {
+    InvalidSize(String),
+    InvalidRequest(String),
+}
+
+impl Display for SizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SizeError::InvalidSize(size) => write!(f, "Invalid subnet size: {}", size),
+            SizeError::InvalidRequest(request) => write!(f, "Invalid size request: {}", request),
+        }
+    }
+}
+
+impl Error for SizeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+/// Requested size of a subnet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SubnetSize {
+    /// Number of host bits, such as `9`
+    Bits(Bits),
+    /// Network prefix length, such as `/23`
+    PrefixLength(Bits),
+    /// Number of usable hosts, such as `300h`
+    Hosts(u128),
+}
+
+impl SubnetSize {
+    /// Host bits needed for this size in a pool of the given CIDR.
+    pub(crate) fn bits(&self, pool_cidr: &IpCidr, reserved: u128) -> Option<Bits> {
+        match self {
+            SubnetSize::Bits(bits) => Some(*bits),
+            SubnetSize::PrefixLength(length) => {
+                let max_bits: Bits = match pool_cidr {
+                    IpCidr::V4(_) => 32,
+                    IpCidr::V6(_) => 128,
+                };
+                max_bits.checked_sub(*length)
+            }
+            SubnetSize::Hosts(count) => subnet_garden_core::bits_for_hosts(*count, reserved),
+        }
+    }
+}
+
+impl SubnetSize {
+    /// Size to request from a pool, which resolves a prefix length against
+    /// each root.
+    pub(crate) fn request_size(&self, reserved: u128) -> Option<RequestSize> {
+        match self {
+            SubnetSize::Bits(bits) => Some(RequestSize::HostBits(*bits)),
+            SubnetSize::PrefixLength(length) => Some(RequestSize::PrefixLength(*length)),
+            SubnetSize::Hosts(count) => {
+                subnet_garden_core::bits_for_hosts(*count, reserved).map(RequestSize::HostBits)
+            }
+        }
+    }
+}
+
+impl FromStr for SubnetSize {
+    type Err = SizeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || SizeError::InvalidSize(s.to_string());
+        if let Some(hosts) = s.strip_suffix('h') {
+            return hosts.parse().map(SubnetSize::Hosts).map_err(|_| invalid());
+        }
+        if let Some(length) = s.strip_prefix('/') {
+            return length
+                .parse()
+                .map(SubnetSize::PrefixLength)
+                .map_err(|_| invalid());
+        }
+        s.parse().map(SubnetSize::Bits).map_err(|_| invalid())
+    }
+}
+
+/// Number of subnets of one size, such as `10x8` or `20x/26`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SizeRequest {
+    pub(crate) count: usize,
+    pub(crate) size: SubnetSize,
+}
+
+impl FromStr for SizeRequest {
+    type Err = SizeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || SizeError::InvalidRequest(s.to_string());
+        let (count, size) = s.split_once('x').ok_or_else(invalid)?;
+        Ok(SizeRequest {
+            count: count.parse().map_err(|_| invalid())?,
+            size: size.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bits() {
+        assert_eq!("9".parse(), Ok(SubnetSize::Bits(9)));
+    }
+
+    #[test]
+    fn parse_prefix_length() {
+        assert_eq!("/23".parse(), Ok(SubnetSize::PrefixLength(23)));
+    }
+
+    #[test]
+    fn parse_hosts() {
+        assert_eq!("300h".parse(), Ok(SubnetSize::Hosts(300)));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for size in ["", "h", "/", "x", "-1", "/x", "12x", "300hh"] {
+            assert_eq!(
+                size.parse::<SubnetSize>(),
+                Err(SizeError::InvalidSize(size.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn parse_request() {
+        assert_eq!(
+            "10x8".parse(),
+            Ok(SizeRequest {
+                count: 10,
+                size: SubnetSize::Bits(8)
+            })
+        );
+        assert_eq!(
+            "20x/26".parse(),
+            Ok(SizeRequest {
+                count: 20,
+                size: SubnetSize::PrefixLength(26)
+            })
+        );
+    }
+
+    #[test]
+    fn parse_request_invalid() {
+        for request in ["", "10", "x8", "10x", "-1x8", "10x8x", "10y8"] {
+            assert_eq!(
+                request.parse::<SizeRequest>(),
+                Err(SizeError::InvalidRequest(request.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn bits() {
+        let v4: IpCidr = "10.10.0.0/16".parse().unwrap();
+        let v6: IpCidr = "fc00::/112".parse().unwrap();
+        assert_eq!(SubnetSize::Bits(9).bits(&v4, 0), Some(9));
+        assert_eq!(SubnetSize::PrefixLength(23).bits(&v4, 0), Some(9));
+        assert_eq!(SubnetSize::PrefixLength(120).bits(&v6, 0), Some(8));
+        assert_eq!(SubnetSize::PrefixLength(33).bits(&v4, 0), None);
+        assert_eq!(SubnetSize::Hosts(300).bits(&v4, 0), Some(9));
+        assert_eq!(SubnetSize::Hosts(510).bits(&v4, 5), Some(10));
+    }
+
+    #[test]
+    fn request_size() {
+        assert_eq!(
+            SubnetSize::Bits(9).request_size(0),
+            Some(RequestSize::HostBits(9))
+        );
+        assert_eq!(
+            SubnetSize::PrefixLength(23).request_size(0),
+            Some(RequestSize::PrefixLength(23))
+        );
+        assert_eq!(
+            SubnetSize::Hosts(510).request_size(5),
+            Some(RequestSize::HostBits(10))
+        );
+        assert_eq!(SubnetSize::Hosts(u128::MAX).request_size(1), None);
+    }
+}
@@ -2,4 +2,6 @@
 // SPDX-License-Identifier: Apache-2.0
 
 pub(crate) mod init;
+pub(crate) mod integrity;
+pub(crate) mod roots;
 pub(crate) mod subnet;
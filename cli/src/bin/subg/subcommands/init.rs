@@ -19,5 +19,14 @@ pub(crate) fn init(subg: &SubgArgs, args: &InitArgs) {
             exit(exitcode::CANTCREAT);
         }
     }
-    subg::store_pool(&subg.pool_path, &pool::SubnetPool::new(args.cidr));
+    let mut pool = pool::SubnetPool::new(args.cidrs[0]);
+    for cidr in &args.cidrs[1..] {
+        subg::result(
+            pool.add_root(*cidr),
+            exitcode::USAGE,
+            &format!("Could not add root {}", cidr),
+        );
+    }
+    pool.set_strategy(args.strategy);
+    subg::store_pool(&subg.pool_path, &pool);
 }
@@ -0,0 +1,87 @@
+// Copyright 2024 The Milton Hirsch Institute, B.V.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::args::{FmtArgs, SubgArgs};
+use std::process::exit;
+use subnet_garden_core::errors::InvariantError;
+use subnet_garden_core::schema::SCHEMA_VERSION;
+
+fn reject_parents(subg: &SubgArgs) {
+    if !subg.parents.is_empty() {
+        eprintln!("Command covers the whole pool file, child pools included");
+        exit(exitcode::USAGE);
+    }
+}
+
+fn show_problems(problems: &[InvariantError]) {
+    for problem in problems {
+        eprintln!("{}", problem);
+    }
+}
+
+pub(crate) fn check(subg: &SubgArgs) {
+    reject_parents(subg);
+    // Records that repair can fix are reported rather than refused
+    let (subg::PoolFile { pool, .. }, mut problems) = subg::repair_pool_file(&subg.pool_path);
+    problems.extend(pool.check_invariants());
+    if !problems.is_empty() {
+        eprintln!("Subnet pool is inconsistent");
+        show_problems(&problems);
+        exit(exitcode::DATAERR);
+    }
+}
+
+pub(crate) fn repair(subg: &SubgArgs) {
+    reject_parents(subg);
+    // Records of the file are fixed before they are loaded, derived state
+    // after
+    let (subg::PoolFile { mut pool, .. }, mut problems) = subg::repair_pool_file(&subg.pool_path);
+    problems.extend(pool.repair());
+    let (repaired, remaining): (Vec<_>, Vec<_>) = problems
+        .into_iter()
+        .partition(|problem| problem.is_repairable());
+    for problem in repaired.iter() {
+        println!("Repaired: {}", problem);
+    }
+    subg::store_pool(&subg.pool_path, &pool);
+    if !remaining.is_empty() {
+        eprintln!("Could not repair subnet pool");
+        show_problems(&remaining);
+        exit(exitcode::DATAERR);
+    }
+}
+
+pub(crate) fn fmt(subg: &SubgArgs, args: &FmtArgs) {
+    reject_parents(subg);
+    let subg::PoolFile { source, pool, .. } = subg::load_pool_file(&subg.pool_path);
+    let formatted = subg::reformat_pool(&subg.pool_path, &source, &pool);
+    if formatted == source {
+        return;
+    }
+    if args.check {
+        let diff = similar::TextDiff::from_lines(&source, &formatted);
+        let path = &subg.pool_path;
+        print!("{}", diff.unified_diff().header(path, path));
+        eprintln!("Pool file is not in canonical form");
+        exit(exitcode::DATAERR);
+    }
+    subg::result(
+        std::fs::write(&subg.pool_path, formatted),
+        exitcode::CANTCREAT,
+        "Could not store pool file",
+    );
+}
+
+pub(crate) fn migrate(subg: &SubgArgs) {
+    reject_parents(subg);
+    let pool_file = subg::load_pool_file(&subg.pool_path);
+    if pool_file.version == SCHEMA_VERSION {
+        println!("Pool file is already at version {}", SCHEMA_VERSION);
+        return;
+    }
+    subg::store_pool(&subg.pool_path, &pool_file.pool);
+    println!(
+        "Migrated pool file from version {} to {}",
+        pool_file.version, SCHEMA_VERSION
+    );
+}
@@ -0,0 +1,55 @@
+// Copyright 2024 The Milton Hirsch Institute, B.V.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::args::{AddRootArgs, RemoveRootArgs, ResizePoolArgs, SubgArgs};
+use crate::subcommands::subnet::show_resize_error;
+use std::process::exit;
+use subnet_garden_core::errors::ResizeError;
+
+fn reject_parents(subg: &SubgArgs) {
+    if !subg.parents.is_empty() {
+        eprintln!("Child pools have a single root");
+        exit(exitcode::USAGE);
+    }
+}
+
+pub(crate) fn add_root(subg: &SubgArgs, args: &AddRootArgs) {
+    reject_parents(subg);
+    let mut pool = subg::load_pool(&subg.pool_path);
+    subg::result(
+        pool.add_root(args.cidr),
+        exitcode::SOFTWARE,
+        &format!("Could not add root {}", args.cidr),
+    );
+    subg::store_pool(&subg.pool_path, &pool);
+}
+
+pub(crate) fn remove_root(subg: &SubgArgs, args: &RemoveRootArgs) {
+    reject_parents(subg);
+    let mut pool = subg::load_pool(&subg.pool_path);
+    subg::result(
+        pool.remove_root(&args.cidr),
+        exitcode::SOFTWARE,
+        &format!("Could not remove root {}", args.cidr),
+    );
+    subg::store_pool(&subg.pool_path, &pool);
+}
+
+pub(crate) fn resize_pool(subg: &SubgArgs, args: &ResizePoolArgs) {
+    reject_parents(subg);
+    let mut pool = subg::load_pool(&subg.pool_path);
+    let resized = match pool.expand_to(&args.cidr) {
+        Err(ResizeError::NoSuchRoot) => pool.shrink_to(&args.cidr),
+        resized => resized,
+    };
+    let replaced = match resized {
+        Ok(replaced) => replaced,
+        Err(err) => show_resize_error(
+            &pool,
+            err,
+            &format!("Could not resize pool to {}", args.cidr),
+        ),
+    };
+    println!("Resized root {} to {}", replaced, args.cidr);
+    subg::store_pool(&subg.pool_path, &pool);
+}
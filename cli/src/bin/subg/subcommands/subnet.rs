@@ -1,49 +1,235 @@
 // Copyright 2023-2024 The Milton Hirsch Institute, B.V.
 // SPDX-License-Identifier: Apache-2.0
 
+pub(crate) mod hosts;
+pub(crate) mod labeling;
 pub(crate) mod listing;
 
-use crate::args::{AllocateArgs, ClaimArgs, FreeArgs, RenameArgs, SubgArgs};
+use crate::args::{
+    AllocateArgs, CapacityArgs, ClaimArgs, FreeArgs, MakePoolArgs, MergeArgs, RenameArgs,
+    ReserveArgs, ResizeArgs, SplitArgs, SubgArgs, UnreserveArgs,
+};
 use crate::param_str;
+use crate::selector::SubnetSelector;
+use crate::size::SubnetSize;
 use cidr::IpCidr;
 use std::process::exit;
+use subnet_garden_core::batch::PoolBatch;
+use subnet_garden_core::errors::{AllocateError, BatchError, DeleteError, ResizeError, SplitError};
+use subnet_garden_core::pool::SubnetPool;
+use subnet_garden_core::{Bits, RequestSize, RootSelector};
+
+/// Allocated subnets matched by a selector, in address order.
+pub(crate) fn select(pool: &SubnetPool, selector: &SubnetSelector) -> Vec<IpCidr> {
+    pool.records()
+        .filter(|record| selector.matches(record))
+        .map(|record| record.cidr)
+        .collect()
+}
+
+/// List subnets on standard error, one per line.
+fn show_subnets(pool: &SubnetPool, cidrs: &[IpCidr]) {
+    for cidr in cidrs {
+        let reserved = pool.reserved().any(|record| record.cidr == *cidr);
+        match pool
+            .find_record(cidr)
+            .and_then(|record| record.name.as_deref())
+        {
+            Some(name) => eprintln!("  {}  {}", cidr, name),
+            None if reserved => eprintln!("  {}  [reserved]", cidr),
+            None => eprintln!("  {}", cidr),
+        }
+    }
+}
+
+/// Find a subnet by name, falling back to a selector that matches exactly
+/// one subnet or to parsing the identifier as a CIDR.
+pub(crate) fn resolve_identifier(pool: &SubnetPool, identifier: &str) -> IpCidr {
+    if let Some(cidr) = pool.find_by_name(identifier) {
+        return cidr;
+    }
+    match SubnetSelector::parse_identifier(identifier) {
+        Some(selector) => {
+            let selector =
+                subg::result(selector, exitcode::USAGE, "Could not parse arg IDENTIFIER");
+            let cidrs = select(pool, &selector);
+            match cidrs.as_slice() {
+                [cidr] => *cidr,
+                [] => {
+                    eprintln!("No subnet matches {}", identifier);
+                    exit(exitcode::SOFTWARE);
+                }
+                _ => {
+                    eprintln!("{} matches {} subnets", identifier, cidrs.len());
+                    show_subnets(pool, &cidrs);
+                    exit(exitcode::SOFTWARE);
+                }
+            }
+        }
+        None => subg::result(
+            identifier.parse::<IpCidr>(),
+            exitcode::USAGE,
+            "Could not parse arg IDENTIFIER",
+        ),
+    }
+}
+
+fn not_a_pool(identifier: &str) -> ! {
+    eprintln!("Subnet {} is not a pool", identifier);
+    exit(exitcode::SOFTWARE);
+}
+
+/// Follow the `--parent` arguments down to the child pool they name.
+pub(crate) fn parent_pool<'a>(root: &'a SubnetPool, subg: &SubgArgs) -> &'a SubnetPool {
+    let mut pool = root;
+    for parent in subg.parents.iter() {
+        let cidr = resolve_identifier(pool, parent);
+        pool = match pool.child(&cidr) {
+            Some(child) => child,
+            None => not_a_pool(parent),
+        };
+    }
+    pool
+}
+
+pub(crate) fn parent_pool_mut<'a>(root: &'a mut SubnetPool, subg: &SubgArgs) -> &'a mut SubnetPool {
+    let mut pool = root;
+    for parent in subg.parents.iter() {
+        let cidr = resolve_identifier(pool, parent);
+        pool = match pool.child_mut(&cidr) {
+            Some(child) => child,
+            None => not_a_pool(parent),
+        };
+    }
+    pool
+}
+
+fn size_bits(pool: &SubnetPool, size: &SubnetSize, selector: RootSelector, reserved: u128) -> Bits {
+    let root_cidr = subg::result(
+        pool.roots()
+            .find(|cidr| selector.matches(cidr))
+            .ok_or(AllocateError::NoSuchRoot),
+        exitcode::SOFTWARE,
+        "Could not allocate subnet",
+    );
+    subg::result(
+        size.bits(root_cidr, reserved)
+            .ok_or(AllocateError::NoSpaceAvailable),
+        exitcode::SOFTWARE,
+        "Could not allocate subnet",
+    )
+}
 
 pub(crate) fn allocate(subg: &SubgArgs, args: &AllocateArgs) {
-    let mut pool = subg::load_pool(&subg.pool_path);
-    match &args.param {
-        None => {
-            subg::result(
-                pool.allocate(args.bits, args.name_format.as_deref()),
-                exitcode::SOFTWARE,
-                "Could not allocate subnet",
+    let mut root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool_mut(&mut root, subg);
+    let strategy = args.strategy.unwrap_or(pool.strategy());
+    let selector = args.root.unwrap_or_default();
+    // With --dual, the argument after the IPv4 size is the IPv6 size
+    let (v6_size, name_format, param) = match args.dual {
+        false => (None, args.name_format.clone(), args.param.clone()),
+        true => {
+            let v6_size = subg::result(
+                args.name_format
+                    .as_deref()
+                    .unwrap_or_default()
+                    .parse::<SubnetSize>(),
+                exitcode::USAGE,
+                "Could not parse IPv6 subnet size",
             );
+            let mut rest = args.param.clone().unwrap_or_default();
+            let name_format = (!rest.is_empty()).then(|| rest.remove(0));
+            let param = (!rest.is_empty()).then_some(rest);
+            (Some(v6_size), name_format, param)
         }
+    };
+    let dual_bits = v6_size.map(|v6_size| {
+        (
+            size_bits(pool, &args.size, RootSelector::V4, args.reserved),
+            size_bits(pool, &v6_size, RootSelector::V6, args.reserved),
+        )
+    });
+    let names: Vec<Option<String>> = match &param {
+        None => vec![name_format.clone()],
         Some(params) => {
-            let format = args.name_format.as_deref().unwrap();
+            let format = name_format.as_deref().unwrap();
             let param_strs: param_str::format::Args = params.iter().map(|s| s.as_str()).collect();
-            let names = subg::result(
+            subg::result(
                 param_str::format::format_strings(format, &param_strs),
                 exitcode::SOFTWARE,
                 "Could not format subnet names",
-            );
-            for name in names {
-                subg::result(
-                    pool.allocate(args.bits, Some(name.to_string().as_str())),
-                    exitcode::SOFTWARE,
-                    format!("Could not allocate subnet {}", name).as_str(),
-                );
-            }
+            )
+            .into_iter()
+            .map(Some)
+            .collect()
         }
     };
-    subg::store_pool(&subg.pool_path, &pool);
+    let mut batch = PoolBatch::new();
+    for name in names.iter() {
+        match (args.size, dual_bits) {
+            (SubnetSize::Bits(bits), None) => {
+                batch.allocate_in(bits, name.as_deref(), strategy, selector)
+            }
+            // Resolved against each root, as the host bits of a prefix
+            // length depend on the address family
+            (SubnetSize::PrefixLength(length), None) => {
+                batch.allocate_prefix_in(length, name.as_deref(), Some(strategy), selector)
+            }
+            (SubnetSize::Hosts(count), None) => batch.allocate_for_hosts_in(
+                count,
+                args.reserved,
+                name.as_deref(),
+                Some(strategy),
+                selector,
+            ),
+            (_, Some((v4_bits, v6_bits))) => {
+                batch.allocate_dual(v4_bits, v6_bits, name.as_deref(), Some(strategy))
+            }
+        };
+    }
+    let cidrs = pool.commit(&batch).unwrap_or_else(|err| {
+        let message = match (&param, &names[err.index()]) {
+            (Some(_), Some(name)) => format!("Could not allocate subnet {}", name),
+            _ => "Could not allocate subnet".to_string(),
+        };
+        subg::result(Err(err), exitcode::SOFTWARE, &message)
+    });
+    for cidr in cidrs {
+        match pool.find_record(&cidr).and_then(|record| record.pair) {
+            Some(pair) => println!("{}  {}", cidr, pair),
+            None => println!(
+                "{}  {} usable hosts",
+                cidr,
+                subnet_garden_core::usable_hosts(&cidr, args.reserved)
+            ),
+        }
+    }
+    subg::store_pool(&subg.pool_path, &root);
 }
 
 pub(crate) fn free(subg: &SubgArgs, args: &FreeArgs) {
-    let mut pool = subg::load_pool(&subg.pool_path);
-    let identifier_list = match args.param {
-        None => vec![args.identifier_format.clone()],
-        Some(ref params) => {
-            let format = args.identifier_format.as_str();
+    let mut root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool_mut(&mut root, subg);
+    // Subnets picked out by a selector rather than named one by one
+    let mut bulk = args.identifier_format.is_none();
+    let identifier_list = match (&args.identifier_format, &args.param) {
+        (None, _) => pool.cidrs().map(|cidr| cidr.to_string()).collect(),
+        (Some(identifier), None) => match (
+            pool.find_by_name(identifier),
+            SubnetSelector::parse_identifier(identifier),
+        ) {
+            (None, Some(selector)) => {
+                let selector =
+                    subg::result(selector, exitcode::USAGE, "Could not parse arg IDENTIFIER");
+                bulk = true;
+                select(pool, &selector)
+                    .iter()
+                    .map(|cidr| cidr.to_string())
+                    .collect()
+            }
+            _ => vec![identifier.clone()],
+        },
+        (Some(format), Some(params)) => {
             let param_strs: param_str::format::Args = params.iter().map(|s| s.as_str()).collect();
             subg::result(
                 param_str::format::format_strings(format, &param_strs),
@@ -52,6 +238,8 @@ pub(crate) fn free(subg: &SubgArgs, args: &FreeArgs) {
             )
         }
     };
+    let mut batch = PoolBatch::new();
+    let mut cidrs: Vec<IpCidr> = Vec::new();
     for identifier in identifier_list {
         let cidr = match pool.find_by_name(identifier.as_str()) {
             Some(cidr) => cidr,
@@ -67,44 +255,230 @@ pub(crate) fn free(subg: &SubgArgs, args: &FreeArgs) {
                 )
             }
         };
-        if !pool.free(&cidr) && !args.ignore_missing {
-            eprintln!("Could not free subnet {}", cidr);
-            exit(exitcode::SOFTWARE);
+        if let Some(selector) = &args.selector {
+            match pool.find_record(&cidr) {
+                Some(record) if selector.matches(record) => {}
+                _ => continue,
+            }
+        }
+        if args.ignore_missing && (!pool.contains(&cidr) || cidrs.contains(&cidr)) {
+            continue;
         }
+        match args.force {
+            true => batch.free_forced(&cidr),
+            false => batch.free(&cidr),
+        };
+        cidrs.push(cidr);
     }
-    subg::store_pool(&subg.pool_path, &pool);
+    if bulk && cidrs.len() > 1 && !args.yes {
+        eprintln!(
+            "Would free {} subnets, pass --yes to free them",
+            cidrs.len()
+        );
+        show_subnets(pool, &cidrs);
+        exit(exitcode::USAGE);
+    }
+    if let Err(err) = pool.commit(&batch) {
+        eprintln!("Could not free subnet {}", cidrs[err.index()]);
+        if let BatchError::Free {
+            error: DeleteError::HasChildren,
+            ..
+        } = err
+        {
+            eprintln!("{}", err);
+        }
+        exit(exitcode::SOFTWARE);
+    }
+    subg::store_pool(&subg.pool_path, &root);
 }
 
 pub(crate) fn claim(subg: &SubgArgs, args: &ClaimArgs) {
-    let mut pool = subg::load_pool(&subg.pool_path);
+    let mut root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool_mut(&mut root, subg);
+    let result = match args.adopt {
+        true => pool.adopt(&args.cidr, args.name.as_deref()),
+        false => pool.claim(&args.cidr, args.name.as_deref()),
+    };
+    subg::result(result, exitcode::SOFTWARE, "Could not claim subnet");
+    subg::store_pool(&subg.pool_path, &root);
+}
+
+pub(crate) fn reserve(subg: &SubgArgs, args: &ReserveArgs) {
+    let mut root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool_mut(&mut root, subg);
     subg::result(
-        pool.claim(&args.cidr, args.name.as_deref()),
+        pool.reserve(&args.cidr, args.description.as_deref()),
         exitcode::SOFTWARE,
-        "Could not claim subnet",
+        "Could not reserve subnet",
     );
-    subg::store_pool(&subg.pool_path, &pool);
+    subg::store_pool(&subg.pool_path, &root);
+}
+
+pub(crate) fn unreserve(subg: &SubgArgs, args: &UnreserveArgs) {
+    let mut root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool_mut(&mut root, subg);
+    if !pool.unreserve(&args.cidr) {
+        eprintln!("Could not unreserve subnet {}", args.cidr);
+        exit(exitcode::SOFTWARE);
+    }
+    subg::store_pool(&subg.pool_path, &root);
 }
 
 pub(crate) fn rename(subg: &SubgArgs, args: &RenameArgs) {
-    let mut pool = subg::load_pool(&subg.pool_path);
-    let cidr = match pool.find_by_name(args.identifier.as_str()) {
-        Some(cidr) => cidr,
-        None => subg::result(
-            args.identifier.parse::<IpCidr>(),
-            exitcode::USAGE,
-            "Could not parse arg IDENTIFIER",
-        ),
-    };
+    let mut root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool_mut(&mut root, subg);
+    let cidr = resolve_identifier(pool, &args.identifier);
     subg::result(
         pool.rename(&cidr, args.name.as_deref()),
         exitcode::SOFTWARE,
         "Could not rename subnet",
     );
-    subg::store_pool(&subg.pool_path, &pool);
+    subg::store_pool(&subg.pool_path, &root);
+}
+
+/// Report a resize that failed because of other subnets, one per line.
+pub(crate) fn show_resize_error(pool: &SubnetPool, err: ResizeError, message: &str) -> ! {
+    let cidrs = match &err {
+        ResizeError::Blocked(cidrs) | ResizeError::Occupied(cidrs) => cidrs.clone(),
+        _ => subg::result(Err(err.clone()), exitcode::SOFTWARE, message),
+    };
+    eprintln!("{}", message);
+    eprintln!("{}", err);
+    show_subnets(pool, &cidrs);
+    exit(exitcode::SOFTWARE);
+}
+
+pub(crate) fn resize(subg: &SubgArgs, args: &ResizeArgs) {
+    let mut root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool_mut(&mut root, subg);
+    let cidr = resolve_identifier(pool, &args.identifier);
+    let bits = subg::result(
+        args.size.bits(&cidr, 0).ok_or(ResizeError::InvalidSize),
+        exitcode::USAGE,
+        "Could not parse arg SIZE",
+    );
+    let message = format!("Could not resize subnet {}", args.identifier);
+    let resized = match bits > subnet_garden_core::host_length(&cidr) {
+        true => pool.grow(&cidr, bits),
+        false => pool.shrink(&cidr, bits),
+    };
+    let resized = match resized {
+        Ok(resized) => resized,
+        Err(err) => show_resize_error(pool, err, &message),
+    };
+    println!("Resized subnet {} to {}", cidr, resized);
+    subg::store_pool(&subg.pool_path, &root);
+}
+
+pub(crate) fn split(subg: &SubgArgs, args: &SplitArgs) {
+    let mut root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool_mut(&mut root, subg);
+    let cidr = resolve_identifier(pool, &args.identifier);
+    let bits = subg::result(
+        args.size.bits(&cidr, 0).ok_or(SplitError::InvalidSize),
+        exitcode::USAGE,
+        "Could not parse arg SIZE",
+    );
+    let names: Vec<String> = match (&args.name_format, &args.param) {
+        (None, _) => Vec::new(),
+        (Some(name), None) => vec![name.clone()],
+        (Some(format), Some(params)) => {
+            let param_strs: param_str::format::Args = params.iter().map(|s| s.as_str()).collect();
+            subg::result(
+                param_str::format::format_strings(format, &param_strs),
+                exitcode::SOFTWARE,
+                "Could not format subnet names",
+            )
+        }
+    };
+    let names: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+    let cidrs = subg::result(
+        pool.split(&cidr, bits, &names),
+        exitcode::SOFTWARE,
+        &format!("Could not split subnet {}", args.identifier),
+    );
+    for (index, cidr) in cidrs.iter().enumerate() {
+        match names.get(index) {
+            Some(name) => println!("{}  {}", cidr, name),
+            None => println!("{}", cidr),
+        }
+    }
+    subg::store_pool(&subg.pool_path, &root);
+}
+
+pub(crate) fn merge(subg: &SubgArgs, args: &MergeArgs) {
+    let mut root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool_mut(&mut root, subg);
+    let cidrs: Vec<IpCidr> = args
+        .identifiers
+        .iter()
+        .map(|identifier| resolve_identifier(pool, identifier))
+        .collect();
+    let merged = subg::result(
+        pool.merge(&cidrs, args.name.as_deref()),
+        exitcode::SOFTWARE,
+        "Could not merge subnets",
+    );
+    println!("{}", merged);
+    subg::store_pool(&subg.pool_path, &root);
+}
+
+pub(crate) fn make_pool(subg: &SubgArgs, args: &MakePoolArgs) {
+    let mut root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool_mut(&mut root, subg);
+    let cidr = resolve_identifier(pool, &args.identifier);
+    subg::result(
+        pool.make_child(&cidr).map(|_| ()),
+        exitcode::SOFTWARE,
+        "Could not make pool",
+    );
+    subg::store_pool(&subg.pool_path, &root);
+}
+
+pub(crate) fn capacity(subg: &SubgArgs, args: &CapacityArgs) {
+    let root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool(&root, subg);
+    let selector = args.root.unwrap_or_default();
+    if !pool.roots().any(|cidr| selector.matches(cidr)) {
+        subg::result(
+            Err(AllocateError::NoSuchRoot),
+            exitcode::SOFTWARE,
+            "Could not compute capacity",
+        )
+    }
+    // A size must fit the address family of at least one of the roots
+    let request_size = |size: &SubnetSize| {
+        subg::result(
+            size.request_size(0)
+                .filter(|size| {
+                    pool.roots()
+                        .filter(|cidr| selector.matches(cidr))
+                        .any(|cidr| size.host_bits(cidr).is_some())
+                })
+                .ok_or(AllocateError::NoSpaceAvailable),
+            exitcode::USAGE,
+            "Could not parse subnet size",
+        )
+    };
+    if let Some(size) = &args.size {
+        println!("{}", pool.capacity_in(request_size(size), selector));
+        return;
+    }
+    let requests: Vec<(RequestSize, usize)> = args
+        .requests
+        .iter()
+        .map(|request| (request_size(&request.size), request.count))
+        .collect();
+    if !pool.can_fit_in(&requests, selector) {
+        eprintln!("Request does not fit");
+        exit(exitcode::SOFTWARE);
+    }
+    println!("Request fits");
 }
 
 pub(crate) fn max_bits(subg: &SubgArgs) {
-    let pool = subg::load_pool(&subg.pool_path);
+    let root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool(&root, subg);
     let largest = pool.max_available_bits();
     println!("{}", largest);
 }
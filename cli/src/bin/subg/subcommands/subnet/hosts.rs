@@ -0,0 +1,99 @@
+// Copyright 2024 The Milton Hirsch Institute, B.V.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::args::host::{
+    HostAllocateArgs, HostArgs, HostClaimArgs, HostCommands, HostFreeArgs, HostListArgs,
+    HostRenameArgs,
+};
+use crate::args::SubgArgs;
+use crate::subcommands::subnet::{parent_pool, parent_pool_mut, resolve_identifier};
+use cidr::IpCidr;
+use std::net::IpAddr;
+use subnet_garden_core::errors::HostError;
+use subnet_garden_core::pool::SubnetPool;
+
+pub(crate) fn host(subg: &SubgArgs, args: &HostArgs) {
+    match &args.command {
+        HostCommands::Allocate(args) => allocate(subg, args),
+        HostCommands::Claim(args) => claim(subg, args),
+        HostCommands::Free(args) => free(subg, args),
+        HostCommands::List(args) => list(subg, args),
+        HostCommands::Rename(args) => rename(subg, args),
+    }
+}
+
+/// Find a host by name, falling back to parsing it as an address.
+fn resolve_host(pool: &SubnetPool, cidr: &IpCidr, host: &str, message: &str) -> IpAddr {
+    match pool.find_host(cidr, host) {
+        Some(address) => address,
+        None => match host.parse::<IpAddr>() {
+            Ok(address) => address,
+            Err(_) => subg::result(Err(HostError::NoSuchHost), exitcode::SOFTWARE, message),
+        },
+    }
+}
+
+fn allocate(subg: &SubgArgs, args: &HostAllocateArgs) {
+    let mut root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool_mut(&mut root, subg);
+    let cidr = resolve_identifier(pool, &args.subnet);
+    let address = subg::result(
+        pool.allocate_host_with(&cidr, args.name.as_deref(), args.all_addresses),
+        exitcode::SOFTWARE,
+        "Could not allocate host",
+    );
+    println!("{}", address);
+    subg::store_pool(&subg.pool_path, &root);
+}
+
+fn claim(subg: &SubgArgs, args: &HostClaimArgs) {
+    let mut root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool_mut(&mut root, subg);
+    let cidr = resolve_identifier(pool, &args.subnet);
+    subg::result(
+        pool.claim_host(&cidr, &args.address, args.name.as_deref()),
+        exitcode::SOFTWARE,
+        "Could not claim host",
+    );
+    subg::store_pool(&subg.pool_path, &root);
+}
+
+fn free(subg: &SubgArgs, args: &HostFreeArgs) {
+    let mut root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool_mut(&mut root, subg);
+    let cidr = resolve_identifier(pool, &args.subnet);
+    let address = resolve_host(pool, &cidr, &args.host, "Could not free host");
+    subg::result(
+        pool.free_host(&cidr, &address),
+        exitcode::SOFTWARE,
+        "Could not free host",
+    );
+    subg::store_pool(&subg.pool_path, &root);
+}
+
+fn rename(subg: &SubgArgs, args: &HostRenameArgs) {
+    let mut root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool_mut(&mut root, subg);
+    let cidr = resolve_identifier(pool, &args.subnet);
+    let address = resolve_host(pool, &cidr, &args.host, "Could not rename host");
+    subg::result(
+        pool.rename_host(&cidr, &address, args.name.as_deref()),
+        exitcode::SOFTWARE,
+        "Could not rename host",
+    );
+    subg::store_pool(&subg.pool_path, &root);
+}
+
+fn list(subg: &SubgArgs, args: &HostListArgs) {
+    let root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool(&root, subg);
+    let cidr = resolve_identifier(pool, &args.subnet);
+    let record = subg::result(
+        pool.find_record(&cidr).ok_or(HostError::NoSuchObject),
+        exitcode::SOFTWARE,
+        "Could not list hosts",
+    );
+    for (address, name) in record.hosts.iter() {
+        println!("{}  {}", address, name.as_deref().unwrap_or("-"));
+    }
+}
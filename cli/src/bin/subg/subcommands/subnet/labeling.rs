@@ -0,0 +1,49 @@
+// Copyright 2024 The Milton Hirsch Institute, B.V.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::args::{LabelArgs, SubgArgs, UnlabelArgs};
+use crate::subcommands::subnet::{parent_pool_mut, resolve_identifier};
+
+pub(crate) fn label(subg: &SubgArgs, args: &LabelArgs) {
+    let mut root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool_mut(&mut root, subg);
+    let cidr = resolve_identifier(pool, &args.identifier);
+    let labels: Vec<(&str, &str)> = args
+        .labels
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .collect();
+    subg::result(
+        pool.label(&cidr, &labels),
+        exitcode::SOFTWARE,
+        "Could not label subnet",
+    );
+    if let Some(description) = &args.description {
+        subg::result(
+            pool.describe(&cidr, Some(description)),
+            exitcode::SOFTWARE,
+            "Could not label subnet",
+        );
+    }
+    subg::store_pool(&subg.pool_path, &root);
+}
+
+pub(crate) fn unlabel(subg: &SubgArgs, args: &UnlabelArgs) {
+    let mut root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool_mut(&mut root, subg);
+    let cidr = resolve_identifier(pool, &args.identifier);
+    let keys: Vec<&str> = args.keys.iter().map(|key| key.as_str()).collect();
+    subg::result(
+        pool.unlabel(&cidr, &keys),
+        exitcode::SOFTWARE,
+        "Could not unlabel subnet",
+    );
+    if args.description {
+        subg::result(
+            pool.describe(&cidr, None),
+            exitcode::SOFTWARE,
+            "Could not unlabel subnet",
+        );
+    }
+    subg::store_pool(&subg.pool_path, &root);
+}
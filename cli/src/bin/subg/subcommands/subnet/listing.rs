@@ -1,63 +1,227 @@
 // Copyright 2024 The Milton Hirsch Institute, B.V.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::args::{CidrsArgs, NamesArgs, SubgArgs};
+use crate::args::{AvailableArgs, CidrsArgs, LookupArgs, NamesArgs, StatsArgs, SubgArgs};
+use crate::subcommands::subnet::parent_pool;
 use crate::util;
+use std::io::BufRead;
+use std::net::IpAddr;
+use std::process::exit;
+use subnet_garden_core::{address_value, CidrRecord, Containing};
 
 pub(crate) fn cidrs(subg: &SubgArgs, args: &CidrsArgs) {
-    let pool = subg::load_pool(&subg.pool_path);
+    let root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool(&root, subg);
 
     if args.long {
         println!("total {}", pool.allocated_count());
     }
 
-    let start_cidr = match args.within {
-        Some(within) => within,
-        None => *pool.cidr(),
+    let (allocated, reserved) = match &args.within {
+        Some(within) => (pool.records_within(within), pool.reserved_within(within)),
+        None => (pool.records(), pool.reserved()),
     };
 
-    let max_cidr_width = match args.long {
-        true => pool
-            .records_within(&start_cidr)
-            .map(|r| r.cidr.to_string().len())
-            .max()
-            .unwrap_or(0),
-        false => 0,
+    let selected = |record: &&CidrRecord| {
+        args.selector
+            .as_ref()
+            .is_none_or(|selector| selector.matches(record))
+            && args
+                .subnets
+                .as_ref()
+                .is_none_or(|subnets| subnets.matches(record))
     };
-    for entry in pool.records_within(&start_cidr) {
+    let records: Vec<&CidrRecord> = allocated.filter(selected).collect();
+
+    if !args.long {
+        for entry in records {
+            println!("{}", entry.cidr);
+        }
+        return;
+    }
+
+    let mut entries: Vec<(&CidrRecord, bool)> =
+        records.into_iter().map(|record| (record, false)).collect();
+    entries.extend(reserved.filter(selected).map(|record| (record, true)));
+    entries.sort_by_key(|(record, _)| record.cidr.first_address());
+
+    let max_cidr_width = entries
+        .iter()
+        .map(|(record, _)| record.cidr.to_string().len())
+        .max()
+        .unwrap_or(0);
+    for (entry, reserved) in entries {
         let mut cidr = entry.cidr.to_string();
-        if args.long {
-            util::right_pad(&mut cidr, max_cidr_width);
-            let name = entry.name.clone().unwrap_or("-".to_string());
-            println!("{}  {}", cidr, name);
-        } else {
-            println!("{}", cidr);
+        util::right_pad(&mut cidr, max_cidr_width);
+        let name = match reserved {
+            true => match &entry.description {
+                Some(description) => format!("[reserved] {}", description),
+                None => "[reserved]".to_string(),
+            },
+            false => entry.name.clone().unwrap_or("-".to_string()),
+        };
+        println!("{}  {}", cidr, name);
+    }
+}
+
+pub(crate) fn available(subg: &SubgArgs, args: &AvailableArgs) {
+    let root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool(&root, subg);
+
+    let cidrs = match (args.bits, &args.within) {
+        (None, None) => pool.free_blocks(),
+        (None, Some(within)) => pool.free_blocks_within(within),
+        (Some(bits), None) => pool.candidates(bits),
+        (Some(bits), Some(within)) => pool.candidates_within(bits, within),
+    };
+    for cidr in cidrs {
+        println!("{}", cidr);
+    }
+}
+
+pub(crate) fn stats(subg: &SubgArgs, args: &StatsArgs) {
+    let root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool(&root, subg);
+    let stats = pool.stats();
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&stats).unwrap());
+        return;
+    }
+
+    println!("Addresses:     {}", stats.total);
+    println!(
+        "Allocated:     {} ({:.2}%)",
+        stats.allocated,
+        stats.utilization * 100.0
+    );
+    println!("Reserved:      {}", stats.reserved);
+    println!("Free:          {}", stats.free);
+    println!("Subnets:       {}", stats.subnets);
+    println!("Fragmentation: {:.2}", stats.fragmentation);
+    if !stats.prefixes.is_empty() {
+        println!();
+        println!("Allocated by prefix length:");
+        for (length, prefix) in stats.prefixes.iter() {
+            println!(
+                "  /{}  {} subnets  {} addresses",
+                length, prefix.subnets, prefix.addresses
+            );
+        }
+    }
+    if !stats.free_blocks.is_empty() {
+        println!();
+        println!("Free blocks by prefix length:");
+        for (length, count) in stats.free_blocks.iter() {
+            println!("  /{}  {}", length, count);
         }
     }
 }
 
+pub(crate) fn lookup(subg: &SubgArgs, args: &LookupArgs) {
+    let root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool(&root, subg);
+
+    let addresses: Vec<IpAddr> = match args.addresses.is_empty() {
+        false => args.addresses.clone(),
+        true => std::io::stdin()
+            .lock()
+            .lines()
+            .map(|line| subg::result(line, exitcode::IOERR, "Could not read addresses"))
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                subg::result(
+                    line.trim().parse::<IpAddr>(),
+                    exitcode::USAGE,
+                    &format!("Could not parse address {}", line.trim()),
+                )
+            })
+            .collect(),
+    };
+
+    let mut missing = false;
+    for address in addresses {
+        let containing = match pool.find_containing(&address) {
+            Some(containing) => containing,
+            None => {
+                eprintln!("Address {} is not in the pool", address);
+                missing = true;
+                continue;
+            }
+        };
+        let name = match &containing {
+            Containing::Allocated(record) => record.name.clone().unwrap_or("-".to_string()),
+            Containing::Reserved(_) => "[reserved]".to_string(),
+            Containing::Free(_) => "[free]".to_string(),
+        };
+        let cidr = containing.cidr();
+        let offset = address_value(&address) - address_value(&cidr.first_address());
+        println!("{}  {}  {}  +{}", address, name, cidr, offset);
+    }
+    if missing {
+        exit(exitcode::SOFTWARE);
+    }
+}
+
 pub(crate) fn names(subg: &SubgArgs, args: &NamesArgs) {
-    let pool = subg::load_pool(&subg.pool_path);
+    let root = subg::load_pool(&subg.pool_path);
+    let pool = parent_pool(&root, subg);
 
     if args.long {
         println!("total {} of {}", pool.named_count(), pool.allocated_count());
     }
 
+    let mut names: Vec<String> = pool
+        .names()
+        .filter(|name| {
+            let cidr = pool.find_by_name(name).unwrap();
+            let record = pool.find_record(&cidr).unwrap();
+            args.selector
+                .as_ref()
+                .is_none_or(|selector| selector.matches(record))
+                && args
+                    .subnets
+                    .as_ref()
+                    .is_none_or(|subnets| subnets.matches(record))
+        })
+        .collect();
+    names.sort();
+
     let max_name_width = match args.long {
-        true => pool.names().map(|n| n.len()).max().unwrap_or(0),
+        true => names.iter().map(|n| n.len()).max().unwrap_or(0),
+        false => 0,
+    };
+    // Paired subnets add a column for their IPv6 half
+    let pair = |name: &str| {
+        let cidr = pool.find_by_name(name).unwrap();
+        pool.find_record(&cidr).and_then(|record| record.pair)
+    };
+    let has_pairs = args.long && names.iter().any(|name| pair(name).is_some());
+    let max_cidr_width = match has_pairs {
+        true => names
+            .iter()
+            .map(|name| pool.find_by_name(name).unwrap().to_string().len())
+            .max()
+            .unwrap_or(0),
         false => 0,
     };
-
-    let mut names: Vec<String> = pool.names().collect();
-    names.sort();
     for mut name in names {
-        if args.long {
-            let cidr = pool.find_by_name(&name).unwrap();
-            let cidr_string = cidr.to_string();
-            util::right_pad(&mut name, max_name_width);
-            println!("{}  {}", name, cidr_string);
-        } else {
+        if !args.long {
             println!("{}", name);
+            continue;
+        }
+        let cidr = pool.find_by_name(&name).unwrap();
+        let pair_string = match pair(&name) {
+            Some(pair) => pair.to_string(),
+            None => "-".to_string(),
+        };
+        let mut cidr_string = cidr.to_string();
+        util::right_pad(&mut name, max_name_width);
+        if has_pairs {
+            util::right_pad(&mut cidr_string, max_cidr_width);
+            println!("{}  {}  {}", name, cidr_string, pair_string);
+        } else {
+            println!("{}  {}", name, cidr_string);
         }
     }
 }
@@ -0,0 +1,416 @@
+// Copyright 2024 The Milton Hirsch Institute, B.V.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Pool file problems shown like compiler errors, with the line they point
+//! at.
+
+use crate::layout::UnknownKey;
+use crate::PoolFormat;
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use std::fmt;
+use std::fmt::Write;
+use subnet_garden_core::errors::{InvalidRecord, LoadError, StoredRecord};
+use subnet_garden_core::pool::StoredPool;
+use subnet_garden_core::schema::{StoredVersion, SCHEMA_VERSION};
+
+/// Position in a pool file, counting lines and columns from 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Location {
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Level {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = format!("{:?}", self);
+        write!(f, "{}", s.to_lowercase())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Diagnostic {
+    level: Level,
+    message: String,
+    location: Option<Location>,
+    notes: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Diagnostic for a parse error, without the position the parser
+    /// appends to its message.
+    fn from_error(error: impl fmt::Display, location: Option<Location>) -> Self {
+        let message = error.to_string();
+        let message = match location {
+            Some(Location { line, column }) => message
+                .strip_suffix(&format!(" at line {} column {}", line, column))
+                .unwrap_or(&message)
+                .to_string(),
+            None => message,
+        };
+        Diagnostic {
+            level: Level::Error,
+            message,
+            location,
+            notes: Vec::new(),
+        }
+    }
+
+    pub(crate) fn for_record(source: &str, format: &PoolFormat, invalid: &InvalidRecord) -> Self {
+        let mut diagnostic = Diagnostic {
+            level: Level::Error,
+            message: invalid.to_string(),
+            location: locate_record(source, format, &invalid.record),
+            notes: Vec::new(),
+        };
+        if let Some(conflict) = invalid.error.conflict() {
+            if let Some(location) = locate_record(source, format, conflict) {
+                diagnostic
+                    .notes
+                    .push(format!("{} is defined at line {}", conflict, location.line));
+            }
+        }
+        diagnostic
+    }
+
+    pub(crate) fn message(message: impl fmt::Display) -> Self {
+        Diagnostic::from_error(message, None)
+    }
+
+    /// Warning for an unknown key that looks like a typo of a known one.
+    /// Other unknown keys are kept without a word.
+    pub(crate) fn for_unknown_key(unknown: &UnknownKey) -> Option<Self> {
+        let suggestion = unknown.suggestion?;
+        Some(Diagnostic {
+            level: Level::Warning,
+            message: format!(
+                "Unknown key `{}`, did you mean `{}`?",
+                unknown.key, suggestion
+            ),
+            location: Some(Location {
+                line: unknown.line,
+                column: unknown.column,
+            }),
+            notes: vec!["Unknown keys are kept as they are".to_string()],
+        })
+    }
+
+    pub(crate) fn render(&self, path: &str, source: &str) -> String {
+        let mut rendered = format!("{}: {}\n", self.level, self.message);
+        let snippet = self.location.and_then(|location| {
+            let text = source.lines().nth(location.line.checked_sub(1)?)?;
+            Some((location, text))
+        });
+        let gutter = match snippet {
+            Some((location, _)) => " ".repeat(location.line.to_string().len()),
+            None => String::new(),
+        };
+        if let Some((Location { line, column }, text)) = snippet {
+            let pad = " ".repeat(column.saturating_sub(1));
+            writeln!(rendered, "{}--> {}:{}:{}", gutter, path, line, column).unwrap();
+            writeln!(rendered, "{} |", gutter).unwrap();
+            writeln!(rendered, "{} | {}", line, text).unwrap();
+            writeln!(rendered, "{} | {}^", gutter, pad).unwrap();
+            if !self.notes.is_empty() {
+                writeln!(rendered, "{} |", gutter).unwrap();
+            }
+        }
+        for note in self.notes.iter() {
+            writeln!(rendered, "{} = note: {}", gutter, note).unwrap();
+        }
+        rendered
+    }
+}
+
+fn json_location(error: &serde_json::Error) -> Option<Location> {
+    match error.line() {
+        0 => None,
+        line => Some(Location {
+            line,
+            column: error.column(),
+        }),
+    }
+}
+
+fn yaml_location(error: &serde_yaml::Error) -> Option<Location> {
+    error.location().map(|location| Location {
+        line: location.line(),
+        column: location.column(),
+    })
+}
+
+/// Parse a pool file without checking its records.
+///
+/// A file newer than this binary is refused before the rest of it is
+/// parsed, as its other keys may not mean what they used to.
+pub(crate) fn parse(source: &str, format: &PoolFormat) -> Result<StoredPool, Diagnostic> {
+    let version = match format {
+        PoolFormat::Json => serde_json::from_str::<StoredVersion>(source).ok(),
+        PoolFormat::Yaml => serde_yaml::from_str::<StoredVersion>(source).ok(),
+    };
+    if let Some(StoredVersion { version }) = version {
+        if version > SCHEMA_VERSION {
+            return Err(Diagnostic::message(LoadError::NewerVersion {
+                version,
+                supported: SCHEMA_VERSION,
+            }));
+        }
+    }
+    match format {
+        PoolFormat::Json => serde_json::from_str(source)
+            .map_err(|error| Diagnostic::from_error(&error, json_location(&error))),
+        PoolFormat::Yaml => serde_yaml::from_str(source)
+            .map_err(|error| Diagnostic::from_error(&error, yaml_location(&error))),
+    }
+}
+
+/// Find where a record of a pool file starts.
+///
+/// The file is parsed again up to the record and stopped there, so the
+/// parser reports the position.
+fn locate_record(source: &str, format: &PoolFormat, record: &StoredRecord) -> Option<Location> {
+    let probe = PoolProbe {
+        list: match record.reserved {
+            true => "reserved",
+            false => "subnets",
+        },
+        index: record.index,
+    };
+    let location = match format {
+        PoolFormat::Json => {
+            let mut deserializer = serde_json::Deserializer::from_str(source);
+            probe
+                .deserialize(&mut deserializer)
+                .err()
+                .and_then(|error| json_location(&error))
+        }
+        PoolFormat::Yaml => probe
+            .deserialize(serde_yaml::Deserializer::from_str(source))
+            .err()
+            .and_then(|error| yaml_location(&error)),
+    }?;
+    // Parsers stop either on or just before the first key of the record
+    let text = source.lines().nth(location.line - 1)?;
+    let skipped = text
+        .chars()
+        .skip(location.column.saturating_sub(1))
+        .take_while(|c| c.is_whitespace())
+        .count();
+    Some(Location {
+        column: location.column + skipped,
+        ..location
+    })
+}
+
+const STOP: &str = "stop";
+
+struct PoolProbe {
+    list: &'static str,
+    index: usize,
+}
+
+impl<'de> DeserializeSeed<'de> for PoolProbe {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for PoolProbe {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a subnet pool")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            match key == self.list {
+                true => map.next_value_seed(ListProbe(self.index))?,
+                false => map.next_value::<IgnoredAny>().map(|_| ())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+struct ListProbe(usize);
+
+impl<'de> DeserializeSeed<'de> for ListProbe {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ListProbe {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of subnets")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        for _ in 0..self.0 {
+            if seq.next_element::<IgnoredAny>()?.is_none() {
+                return Ok(());
+            }
+        }
+        seq.next_element_seed(RecordProbe).map(|_| ())
+    }
+}
+
+// Fails as soon as the record is entered.
+struct RecordProbe;
+
+impl<'de> DeserializeSeed<'de> for RecordProbe {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for RecordProbe {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a subnet")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, _map: A) -> Result<(), A::Error> {
+        Err(serde::de::Error::custom(STOP))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YAML: &str = "\
+cidr: 10.10.0.0/16
+subnets:
+- cidr: 10.10.0.0/24
+  name: a
+- cidr: 10.10.0.16/28
+reserved:
+- cidr: 10.10.1.0/24
+";
+
+    const JSON: &str = r#"{
+  "cidr": "10.10.0.0/16",
+  "subnets": [
+    {
+      "cidr": "10.10.0.0/24"
+    },
+    { "cidr": "10.10.0.16/28" }
+  ]
+}"#;
+
+    fn stored(index: usize, reserved: bool) -> StoredRecord {
+        StoredRecord {
+            index,
+            reserved,
+            cidr: "10.10.0.0/24".parse().unwrap(),
+            name: None,
+        }
+    }
+
+    #[test]
+    fn locate_yaml() {
+        let locate =
+            |index, reserved| locate_record(YAML, &PoolFormat::Yaml, &stored(index, reserved));
+        assert_eq!(locate(0, false), Some(Location { line: 3, column: 3 }));
+        assert_eq!(locate(1, false), Some(Location { line: 5, column: 3 }));
+        assert_eq!(locate(0, true), Some(Location { line: 7, column: 3 }));
+        assert_eq!(locate(2, false), None);
+    }
+
+    #[test]
+    fn locate_json() {
+        let locate = |index| locate_record(JSON, &PoolFormat::Json, &stored(index, false));
+        assert_eq!(locate(0), Some(Location { line: 5, column: 7 }));
+        assert_eq!(locate(1), Some(Location { line: 7, column: 7 }));
+        assert_eq!(locate(2), None);
+    }
+
+    #[test]
+    fn render() {
+        let diagnostic = Diagnostic {
+            level: Level::Error,
+            message: "Something is wrong".to_string(),
+            location: Some(Location { line: 5, column: 3 }),
+            notes: vec!["Look elsewhere".to_string()],
+        };
+        assert_eq!(
+            diagnostic.render("pool.yaml", YAML),
+            "error: Something is wrong\n \
+            --> pool.yaml:5:3\n  \
+             |\n\
+            5 | - cidr: 10.10.0.16/28\n  \
+             |   ^\n  \
+             |\n  \
+             = note: Look elsewhere\n"
+        );
+    }
+
+    #[test]
+    fn render_without_location() {
+        assert_eq!(
+            Diagnostic::message("No root CIDR").render("pool.yaml", YAML),
+            "error: No root CIDR\n"
+        );
+    }
+
+    #[test]
+    fn render_unknown_key() {
+        let unknown = |suggestion| UnknownKey {
+            key: "nmae".to_string(),
+            line: 4,
+            column: 3,
+            suggestion,
+        };
+        assert_eq!(Diagnostic::for_unknown_key(&unknown(None)), None);
+        assert_eq!(
+            Diagnostic::for_unknown_key(&unknown(Some("name")))
+                .unwrap()
+                .render("pool.yaml", YAML),
+            "warning: Unknown key `nmae`, did you mean `name`?\n \
+            --> pool.yaml:4:3\n  \
+             |\n\
+            4 |   name: a\n  \
+             |   ^\n  \
+             |\n  \
+             = note: Unknown keys are kept as they are\n"
+        );
+    }
+
+    #[test]
+    fn parse_error() {
+        let error = parse("cidr: [", &PoolFormat::Yaml).unwrap_err();
+        assert_eq!(error.location, Some(Location { line: 1, column: 7 }));
+        assert!(!error.message.contains("at line"));
+    }
+
+    #[test]
+    fn parse_newer_version() {
+        let source = format!(
+            "subnets: {{}}\nversion: {}\ncidr: 10.10.0.0/16\n",
+            SCHEMA_VERSION + 1
+        );
+        assert_eq!(
+            parse(&source, &PoolFormat::Yaml),
+            Err(Diagnostic::message(LoadError::NewerVersion {
+                version: SCHEMA_VERSION + 1,
+                supported: SCHEMA_VERSION,
+            }))
+        );
+    }
+}
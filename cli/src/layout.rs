@@ -0,0 +1,945 @@
+// Copyright 2024 The Milton Hirsch Institute, B.V.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Rewriting YAML pool files around what was written by hand.
+//!
+//! The old file and the freshly serialized pool are both split into
+//! top-level keys, subnet records and their keys, by indentation alone.
+//! Text is taken from the old file wherever the data it holds did not
+//! change, so comments, blank lines and keys `subg` does not know about
+//! stay in place, and only changed records are written anew. Anything the
+//! split does not understand, such as flow style, falls back to the new
+//! text.
+//!
+//! The same split lets a YAML file be loaded with unknown keys: they are
+//! commented out before the file is parsed, which leaves every other line
+//! where it was.
+
+use cidr::IpCidr;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use subnet_garden_core::pool::SubnetPool;
+use subnet_garden_core::CidrRecord;
+
+/// Comments and blank lines above a key or record, and its own lines.
+#[derive(Debug)]
+struct Entry {
+    leading: Vec<String>,
+    key: String,
+    lines: Vec<String>,
+}
+
+/// Keys of a block mapping at one indentation.
+#[derive(Debug)]
+struct Mapping {
+    entries: Vec<Entry>,
+    trailing: Vec<String>,
+}
+
+/// Items of a block sequence at one indentation.
+#[derive(Debug)]
+struct Sequence {
+    indent: usize,
+    items: Vec<Item>,
+    trailing: Vec<String>,
+}
+
+#[derive(Debug)]
+struct Item {
+    leading: Vec<String>,
+    lines: Vec<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Schema {
+    Pool,
+    Record,
+}
+
+/// How the value of a known key is merged
+enum Field {
+    Value,
+    Records,
+    Pool,
+}
+
+impl Schema {
+    fn field(self, key: &str) -> Option<Field> {
+        match (self, key) {
+            (Schema::Pool, "version" | "cidr" | "cidrs" | "strategy") => Some(Field::Value),
+            (Schema::Pool, "subnets" | "reserved") => Some(Field::Records),
+            (Schema::Record, "cidr" | "name" | "pair" | "description" | "labels" | "hosts") => {
+                Some(Field::Value)
+            }
+            (Schema::Record, "pool") => Some(Field::Pool),
+            _ => None,
+        }
+    }
+
+    fn keys(self) -> &'static [&'static str] {
+        match self {
+            Schema::Pool => &[
+                "version", "cidr", "cidrs", "subnets", "reserved", "strategy",
+            ],
+            Schema::Record => &[
+                "cidr",
+                "name",
+                "pair",
+                "description",
+                "labels",
+                "hosts",
+                "pool",
+            ],
+        }
+    }
+
+    /// Known key an unknown one is probably a typo of.
+    fn suggest(self, key: &str) -> Option<&'static str> {
+        let limit = match key.chars().count() {
+            0..=4 => 1,
+            _ => 2,
+        };
+        self.keys()
+            .iter()
+            .map(|known| (distance(key, known), *known))
+            .filter(|(distance, _)| *distance <= limit)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, known)| known)
+    }
+}
+
+/// Edits needed to turn one key into the other, where swapping two
+/// neighbouring characters counts as one edit.
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows: Vec<Vec<usize>> = vec![(0..=b.len()).collect()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let substitution = rows[i - 1][j - 1] + (a[i - 1] != b[j - 1]) as usize;
+            row[j] = cmp::min(cmp::min(rows[i - 1][j], row[j - 1]) + 1, substitution);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = cmp::min(row[j], rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_filler(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+/// Key of a `key: value` line at the given indentation.
+fn key_of(line: &str, indent: usize) -> Option<&str> {
+    if indent_of(line) != indent {
+        return None;
+    }
+    let text = &line[indent..];
+    let end = text.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))?;
+    let rest = &text[end..];
+    let after = rest.strip_prefix(':')?;
+    match end > 0 && (after.is_empty() || after.starts_with(' ')) {
+        true => Some(&text[..end]),
+        false => None,
+    }
+}
+
+/// Value written on the line of a key, without any comment.
+fn inline_value(line: &str) -> &str {
+    let value = line.split_once(':').map_or("", |(_, value)| value.trim());
+    match value.starts_with('#') {
+        true => "",
+        false => value,
+    }
+}
+
+fn is_block_scalar(line: &str) -> bool {
+    let value = inline_value(line);
+    value.starts_with('|') || value.starts_with('>')
+}
+
+fn is_item_start(line: &str, indent: usize) -> bool {
+    indent_of(line) == indent && line[indent..].starts_with("- ")
+}
+
+fn parse_mapping(lines: &[String], indent: usize) -> Option<Mapping> {
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut pending = Vec::new();
+    let mut in_block_scalar = false;
+    for line in lines {
+        // Lines of a literal or folded block are text, even if they look
+        // like comments
+        let in_text = in_block_scalar && (indent_of(line) > indent || line.trim().is_empty());
+        if is_filler(line) && !in_text {
+            pending.push(line.clone());
+            continue;
+        }
+        if let Some(key) = key_of(line, indent) {
+            entries.push(Entry {
+                leading: std::mem::take(&mut pending),
+                key: key.to_string(),
+                lines: vec![line.clone()],
+            });
+            in_block_scalar = is_block_scalar(line);
+            continue;
+        }
+        if !(indent_of(line) > indent || is_item_start(line, indent)) {
+            return None;
+        }
+        let entry = entries.last_mut()?;
+        entry.lines.append(&mut pending);
+        entry.lines.push(line.clone());
+    }
+    Some(Mapping {
+        entries,
+        trailing: pending,
+    })
+}
+
+/// Block sequence under a key that has nothing else on its line.
+fn parse_sequence(entry: &Entry, indent: usize) -> Option<Sequence> {
+    let (key_line, rest) = entry.lines.split_first()?;
+    if !inline_value(key_line).is_empty() {
+        return None;
+    }
+    let indent = rest
+        .iter()
+        .find(|line| !is_filler(line))
+        .map(|line| indent_of(line))
+        .filter(|seq_indent| *seq_indent >= indent)?;
+    let mut items: Vec<Item> = Vec::new();
+    let mut pending = Vec::new();
+    for line in rest {
+        if is_filler(line) {
+            pending.push(line.clone());
+        } else if is_item_start(line, indent) {
+            items.push(Item {
+                leading: std::mem::take(&mut pending),
+                lines: vec![line.clone()],
+            });
+        } else if indent_of(line) > indent {
+            let item = items.last_mut()?;
+            item.lines.append(&mut pending);
+            item.lines.push(line.clone());
+        } else {
+            return None;
+        }
+    }
+    Some(Sequence {
+        indent,
+        items,
+        trailing: pending,
+    })
+}
+
+/// Lines of a sequence item with the dash turned into indentation, and the
+/// indentation of its keys.
+fn undashed(item: &Item, seq_indent: usize) -> (Vec<String>, usize) {
+    let mut lines = item.lines.clone();
+    lines[0].replace_range(seq_indent..seq_indent + 1, " ");
+    let indent = indent_of(&lines[0]);
+    (lines, indent)
+}
+
+fn item_mapping(item: &Item, seq_indent: usize) -> Option<(Mapping, usize)> {
+    let (lines, indent) = undashed(item, seq_indent);
+    Some((parse_mapping(&lines, indent)?, indent))
+}
+
+/// Mapping nested under a key that has nothing else on its line.
+fn nested_mapping(entry: &Entry) -> Option<(Mapping, usize)> {
+    let (key_line, rest) = entry.lines.split_first()?;
+    if !inline_value(key_line).is_empty() {
+        return None;
+    }
+    let indent = rest
+        .iter()
+        .find(|line| !is_filler(line))
+        .map(|line| indent_of(line))?;
+    Some((parse_mapping(rest, indent)?, indent))
+}
+
+fn shift(lines: &[String], from: usize, to: usize) -> Vec<String> {
+    lines
+        .iter()
+        .map(|line| match (line.trim().is_empty(), to >= from) {
+            (true, _) => line.clone(),
+            (false, true) => " ".repeat(to - from) + line,
+            (false, false) => line[cmp::min(from - to, indent_of(line))..].to_string(),
+        })
+        .collect()
+}
+
+/// Key `subg` does not know, counting lines and columns from 1.
+#[derive(Debug, PartialEq)]
+pub(crate) struct UnknownKey {
+    pub(crate) key: String,
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+    /// Known key it is probably a typo of
+    pub(crate) suggestion: Option<&'static str>,
+}
+
+/// YAML text with its unknown keys commented out.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Masked {
+    pub(crate) text: String,
+    pub(crate) unknown: Vec<UnknownKey>,
+}
+
+/// Lines written so far while masking, and the unknown keys among them.
+#[derive(Default)]
+struct Masking {
+    lines: Vec<String>,
+    unknown: Vec<UnknownKey>,
+}
+
+impl Masking {
+    fn mapping(&mut self, mapping: &Mapping, indent: usize, schema: Schema) -> Option<()> {
+        for entry in mapping.entries.iter() {
+            self.lines.extend(entry.leading.iter().cloned());
+            self.entry(entry, indent, schema)?;
+        }
+        self.lines.extend(mapping.trailing.iter().cloned());
+        Some(())
+    }
+
+    fn entry(&mut self, entry: &Entry, indent: usize, schema: Schema) -> Option<()> {
+        // Flow style values are left to the parser
+        let block = inline_value(&entry.lines[0]).is_empty();
+        match schema.field(&entry.key) {
+            None => {
+                self.unknown.push(UnknownKey {
+                    key: entry.key.clone(),
+                    line: self.lines.len() + 1,
+                    column: indent + 1,
+                    suggestion: schema.suggest(&entry.key),
+                });
+                self.lines.extend(entry.lines.iter().map(|line| {
+                    let mut line = line.clone();
+                    if !line.trim().is_empty() {
+                        line.insert(indent, '#');
+                    }
+                    line
+                }));
+            }
+            Some(Field::Records) if block => {
+                let sequence = parse_sequence(entry, indent)?;
+                self.lines.push(entry.lines[0].clone());
+                for item in sequence.items.iter() {
+                    self.lines.extend(item.leading.iter().cloned());
+                    let (mapping, item_indent) = item_mapping(item, sequence.indent)?;
+                    let start = self.lines.len();
+                    self.mapping(&mapping, item_indent, Schema::Record)?;
+                    // Put the dash back on the first line with a key
+                    let first = self.lines[start..]
+                        .iter_mut()
+                        .find(|line| !is_filler(line))?;
+                    if indent_of(first) != item_indent {
+                        return None;
+                    }
+                    first.replace_range(sequence.indent..sequence.indent + 1, "-");
+                }
+                self.lines.extend(sequence.trailing.iter().cloned());
+            }
+            Some(Field::Pool) if block => {
+                let (mapping, pool_indent) = nested_mapping(entry)?;
+                self.lines.push(entry.lines[0].clone());
+                self.mapping(&mapping, pool_indent, Schema::Pool)?;
+            }
+            Some(_) => self.lines.extend(entry.lines.iter().cloned()),
+        }
+        Some(())
+    }
+}
+
+/// Comment out the keys of a YAML pool file that `subg` does not know.
+///
+/// Every line stays where it was, so positions in the masked text are
+/// positions in the source. Text the split does not understand is not
+/// masked.
+pub(crate) fn mask(source: &str) -> Option<Masked> {
+    let lines: Vec<String> = source.lines().map(|line| line.to_string()).collect();
+    let marker = lines.first().is_some_and(|line| line == "---") as usize;
+    let mapping = parse_mapping(&lines[marker..], 0)?;
+    let mut masking = Masking {
+        lines: lines[..marker].to_vec(),
+        unknown: Vec::new(),
+    };
+    masking.mapping(&mapping, 0, Schema::Pool)?;
+    let mut text = masking.lines.join("\n");
+    if source.ends_with('\n') {
+        text.push('\n');
+    }
+    Some(Masked {
+        text,
+        unknown: masking.unknown,
+    })
+}
+
+fn parse_value(lines: &[String], indent: usize) -> Option<serde_yaml::Value> {
+    serde_yaml::from_str(&shift(lines, indent, 0).join("\n")).ok()
+}
+
+fn parse_record(item: &Item, seq_indent: usize) -> Option<CidrRecord> {
+    let (mapping, indent) = item_mapping(item, seq_indent)?;
+    let mut masking = Masking::default();
+    masking.mapping(&mapping, indent, Schema::Record)?;
+    serde_yaml::from_str(&shift(&masking.lines, indent, 0).join("\n")).ok()
+}
+
+fn verbatim(entry: &Entry) -> impl Iterator<Item = String> + '_ {
+    entry.leading.iter().chain(entry.lines.iter()).cloned()
+}
+
+/// Merge the keys of an old and a new mapping.
+///
+/// Known keys keep the order of the old mapping, and each unknown key stays
+/// after the known key that came before it. Keys only the new mapping has
+/// are put where it has them. Canonical text has the known keys in the
+/// order of the new mapping instead, followed by the unknown keys.
+fn merge_mapping(
+    (old, old_indent): (&Mapping, usize),
+    (new, new_indent): (&Mapping, usize),
+    schema: Schema,
+    canonical: bool,
+) -> Option<Vec<String>> {
+    let new_text = |entry: &Entry| shift(&entry.lines, new_indent, old_indent);
+
+    let mut front = Vec::new();
+    let mut known: Vec<(&Entry, Vec<&Entry>)> = Vec::new();
+    for entry in old.entries.iter() {
+        match (schema.field(&entry.key), known.last_mut()) {
+            (Some(_), _) => known.push((entry, Vec::new())),
+            (None, Some((_, unknown))) => unknown.push(entry),
+            (None, None) => front.push(entry),
+        }
+    }
+    let old_entry = |key: &str| known.iter().find(|(entry, _)| entry.key == key);
+    let new_position = |key: &str| new.entries.iter().position(|entry| entry.key == key);
+
+    let mut lines: Vec<String> = Vec::new();
+    let merge = |lines: &mut Vec<String>, old: &Entry, new: &Entry| {
+        lines.extend(old.leading.iter().cloned());
+        lines.extend(merge_entry(
+            (old, old_indent),
+            (new, new_indent),
+            schema,
+            canonical,
+        )?);
+        Some(())
+    };
+    match canonical {
+        true => {
+            for new_entry in new.entries.iter() {
+                match old_entry(&new_entry.key) {
+                    Some((old_entry, _)) => merge(&mut lines, old_entry, new_entry)?,
+                    None => lines.extend(new_text(new_entry)),
+                }
+            }
+            let unknown = old
+                .entries
+                .iter()
+                .filter(|entry| schema.field(&entry.key).is_none());
+            lines.extend(unknown.flat_map(verbatim));
+        }
+        false => {
+            lines.extend(front.into_iter().flat_map(verbatim));
+            let mut missing = new
+                .entries
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| old_entry(&entry.key).is_none())
+                .peekable();
+            for (old_entry, unknown) in known.iter() {
+                if let Some(position) = new_position(&old_entry.key) {
+                    while let Some((_, entry)) = missing.next_if(|(index, _)| *index < position) {
+                        lines.extend(new_text(entry));
+                    }
+                    merge(&mut lines, old_entry, &new.entries[position])?;
+                }
+                lines.extend(unknown.iter().flat_map(|entry| verbatim(entry)));
+            }
+            for (_, entry) in missing {
+                lines.extend(new_text(entry));
+            }
+        }
+    }
+    lines.extend(old.trailing.iter().cloned());
+    Some(lines)
+}
+
+fn merge_entry(
+    (old, old_indent): (&Entry, usize),
+    (new, new_indent): (&Entry, usize),
+    schema: Schema,
+    canonical: bool,
+) -> Option<Vec<String>> {
+    let field = schema.field(&old.key).expect("Known key");
+    let structured = matches!(field, Field::Records | Field::Pool);
+    let merged = match field {
+        Field::Value => None,
+        Field::Records => merge_records((old, old_indent), (new, new_indent), canonical),
+        Field::Pool => merge_child(old, new, canonical),
+    };
+    if let Some(lines) = merged {
+        return Some(lines);
+    }
+    // The new text would lose the unknown keys within
+    let mut masking = Masking::default();
+    if structured && masking.entry(old, old_indent, schema).is_some() && !masking.unknown.is_empty()
+    {
+        return None;
+    }
+    let unchanged = !canonical
+        && parse_value(&old.lines, old_indent)
+            .is_some_and(|value| Some(value) == parse_value(&new.lines, new_indent));
+    match unchanged {
+        true => Some(old.lines.clone()),
+        false => Some(shift(&new.lines, new_indent, old_indent)),
+    }
+}
+
+/// Merge records by CIDR, keeping the text of those that did not change.
+///
+/// Items whose text is the same on both sides are kept without being
+/// parsed, which is most of them when one subnet changed in a large file.
+fn merge_records(
+    (old, old_indent): (&Entry, usize),
+    (new, new_indent): (&Entry, usize),
+    canonical: bool,
+) -> Option<Vec<String>> {
+    let old_seq = parse_sequence(old, old_indent)?;
+    let new_seq = parse_sequence(new, new_indent)?;
+    let text = |item: &Item, indent: usize| shift(&item.lines, indent, 0).join("\n");
+    let new_texts: Vec<String> = new_seq
+        .items
+        .iter()
+        .map(|item| text(item, new_seq.indent))
+        .collect();
+    let new_set: HashSet<&str> = new_texts.iter().map(|text| text.as_str()).collect();
+    let mut same_items: HashMap<String, &Item> = HashMap::new();
+    let mut old_items: HashMap<IpCidr, (CidrRecord, &Item)> = HashMap::new();
+    for item in old_seq.items.iter() {
+        let old_text = text(item, old_seq.indent);
+        match new_set.contains(old_text.as_str()) {
+            true => {
+                same_items.insert(old_text, item);
+            }
+            false => {
+                let record = parse_record(item, old_seq.indent)?;
+                old_items.insert(record.cidr, (record, item));
+            }
+        }
+    }
+
+    let mut lines = vec![old.lines[0].clone()];
+    for (item, new_text) in new_seq.items.iter().zip(new_texts.iter()) {
+        if let Some(old_item) = same_items.get(new_text) {
+            lines.extend(old_item.leading.iter().cloned());
+            lines.extend(old_item.lines.iter().cloned());
+            continue;
+        }
+        let record = parse_record(item, new_seq.indent)?;
+        match old_items.get(&record.cidr) {
+            Some((old_record, old_item)) => {
+                lines.extend(old_item.leading.iter().cloned());
+                match !canonical && *old_record == record {
+                    true => lines.extend(old_item.lines.iter().cloned()),
+                    false => lines.extend(merge_item(
+                        (old_item, old_seq.indent),
+                        (item, new_seq.indent),
+                        canonical,
+                    )?),
+                }
+            }
+            None => lines.extend(shift(&item.lines, new_seq.indent, old_seq.indent)),
+        }
+    }
+    lines.extend(old_seq.trailing);
+    Some(lines)
+}
+
+fn merge_item(
+    (old, old_indent): (&Item, usize),
+    (new, new_indent): (&Item, usize),
+    canonical: bool,
+) -> Option<Vec<String>> {
+    let old_mapping = item_mapping(old, old_indent)?;
+    let new_mapping = item_mapping(new, new_indent)?;
+    let (_, mapping_indent) = old_mapping;
+    let mut lines = merge_mapping(
+        (&old_mapping.0, mapping_indent),
+        (&new_mapping.0, new_mapping.1),
+        Schema::Record,
+        canonical,
+    )?;
+    // Put the dash back on the first line with a key
+    let first = lines.iter_mut().find(|line| !is_filler(line))?;
+    if indent_of(first) != mapping_indent {
+        return None;
+    }
+    first.replace_range(old_indent..old_indent + 1, "-");
+    Some(lines)
+}
+
+fn merge_child(old: &Entry, new: &Entry, canonical: bool) -> Option<Vec<String>> {
+    let (old_mapping, old_indent) = nested_mapping(old)?;
+    let (new_mapping, new_indent) = nested_mapping(new)?;
+    let mut lines = vec![old.lines[0].clone()];
+    lines.extend(merge_mapping(
+        (&old_mapping, old_indent),
+        (&new_mapping, new_indent),
+        Schema::Pool,
+        canonical,
+    )?);
+    Some(lines)
+}
+
+fn merge_document(old: &str, new: &str, pool: &SubnetPool, canonical: bool) -> Option<String> {
+    let old_lines: Vec<String> = old.lines().map(|line| line.to_string()).collect();
+    let new_lines: Vec<String> = new.lines().map(|line| line.to_string()).collect();
+    // A document marker stays at the top
+    let marker = old_lines.first().is_some_and(|line| line == "---") as usize;
+    let mut old_mapping = parse_mapping(&old_lines[marker..], 0)?;
+    let new_mapping = parse_mapping(&new_lines, 0)?;
+    // Comments at the top of the file stay there, too
+    let mut lines = old_lines[..marker].to_vec();
+    if let Some(first) = old_mapping.entries.first_mut() {
+        lines.append(&mut first.leading);
+    }
+    lines.extend(merge_mapping(
+        (&old_mapping, 0),
+        (&new_mapping, 0),
+        Schema::Pool,
+        canonical,
+    )?);
+    let mut text = lines.join("\n");
+    text.push('\n');
+
+    // Whatever the text looks like, it must hold the new pool
+    let merged: SubnetPool = serde_yaml::from_str(&mask(&text)?.text).ok()?;
+    match merged == *pool {
+        true => Some(text),
+        false => None,
+    }
+}
+
+/// Text of a YAML pool file that keeps what was written by hand.
+///
+/// `new` is `pool` as serialized. Records and keys whose data did not
+/// change keep the text of `old`, unless `canonical` is set, in which case
+/// every known key is written as serialized and only comments, blank lines
+/// and unknown keys are kept.
+pub(crate) fn merge(old: &str, new: &str, pool: &SubnetPool, canonical: bool) -> String {
+    merge_document(old, new, pool, canonical).unwrap_or_else(|| {
+        if mask(old).is_some_and(|masked| !masked.unknown.is_empty()) {
+            eprintln!("warning: Unknown keys of the pool file could not be kept");
+        }
+        new.to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool_text(pool: &SubnetPool) -> String {
+        serde_yaml::to_string(pool).unwrap()
+    }
+
+    fn new_pool() -> SubnetPool {
+        let mut pool = SubnetPool::new("10.10.0.0/16".parse().unwrap());
+        pool.claim(&"10.10.0.0/24".parse().unwrap(), Some("web"))
+            .unwrap();
+        pool.claim(&"10.10.1.0/24".parse().unwrap(), Some("db"))
+            .unwrap();
+        pool
+    }
+
+    const OLD: &str = "\
+# Lab pool
+cidr: 10.10.0.0/16
+
+subnets:
+# Do not touch, legacy DC
+- cidr: 10.10.0.0/24   # racks 1-4
+  name: web
+  owner: infra
+
+- cidr: \"10.10.1.0/24\"
+  name: db
+";
+
+    #[test]
+    fn keeps_unchanged_records() {
+        let pool = new_pool();
+        assert_eq!(merge(OLD, &pool_text(&pool), &pool, false), OLD);
+    }
+
+    #[test]
+    fn edits_changed_records() {
+        let mut pool = new_pool();
+        pool.rename(&"10.10.1.0/24".parse().unwrap(), Some("store"))
+            .unwrap();
+        pool.claim(&"10.10.2.0/24".parse().unwrap(), None).unwrap();
+        assert_eq!(
+            merge(OLD, &pool_text(&pool), &pool, false),
+            "\
+# Lab pool
+cidr: 10.10.0.0/16
+
+subnets:
+# Do not touch, legacy DC
+- cidr: 10.10.0.0/24   # racks 1-4
+  name: web
+  owner: infra
+
+- cidr: \"10.10.1.0/24\"
+  name: store
+- cidr: 10.10.2.0/24
+"
+        );
+    }
+
+    #[test]
+    fn drops_freed_records() {
+        let mut pool = new_pool();
+        assert!(pool.free(&"10.10.0.0/24".parse().unwrap()));
+        assert_eq!(
+            merge(OLD, &pool_text(&pool), &pool, false),
+            "\
+# Lab pool
+cidr: 10.10.0.0/16
+
+subnets:
+
+- cidr: \"10.10.1.0/24\"
+  name: db
+"
+        );
+    }
+
+    #[test]
+    fn canonical() {
+        let pool = new_pool();
+        assert_eq!(
+            merge(OLD, &pool_text(&pool), &pool, true),
+            "\
+# Lab pool
+cidr: 10.10.0.0/16
+
+subnets:
+# Do not touch, legacy DC
+- cidr: 10.10.0.0/24
+  name: web
+  owner: infra
+
+- cidr: 10.10.1.0/24
+  name: db
+"
+        );
+    }
+
+    #[test]
+    fn indented_sequence() {
+        let old = "\
+cidr: 10.10.0.0/16
+subnets:
+  # web tier
+  - cidr: 10.10.0.0/24
+    name: web
+";
+        let mut pool = new_pool();
+        assert!(pool.free(&"10.10.1.0/24".parse().unwrap()));
+        pool.describe(&"10.10.0.0/24".parse().unwrap(), Some("Front"))
+            .unwrap();
+        assert_eq!(
+            merge(old, &pool_text(&pool), &pool, false),
+            "\
+cidr: 10.10.0.0/16
+subnets:
+  # web tier
+  - cidr: 10.10.0.0/24
+    name: web
+    description: Front
+"
+        );
+    }
+
+    #[test]
+    fn child_pool() {
+        let old = "\
+cidr: 10.10.0.0/16
+subnets:
+- cidr: 10.10.0.0/24
+  name: web
+  pool:
+    cidr: 10.10.0.0/24
+    # Reserved for the load balancers
+    subnets: []
+";
+        let mut pool = new_pool();
+        assert!(pool.free(&"10.10.1.0/24".parse().unwrap()));
+        pool.make_child(&"10.10.0.0/24".parse().unwrap())
+            .unwrap()
+            .claim(&"10.10.0.0/28".parse().unwrap(), Some("lb"))
+            .unwrap();
+        assert_eq!(
+            merge(old, &pool_text(&pool), &pool, false),
+            "\
+cidr: 10.10.0.0/16
+subnets:
+- cidr: 10.10.0.0/24
+  name: web
+  pool:
+    cidr: 10.10.0.0/24
+    # Reserved for the load balancers
+    subnets:
+    - cidr: 10.10.0.0/28
+      name: lb
+"
+        );
+    }
+
+    #[test]
+    fn block_scalar() {
+        let old = "\
+# Lab pool
+cidr: 10.10.0.0/16
+subnets:
+- cidr: 10.10.0.0/24
+  name: web
+  description: |
+    First line
+    # not a comment
+- cidr: 10.10.1.0/24
+  name: db
+";
+        let mut pool = new_pool();
+        pool.describe(
+            &"10.10.0.0/24".parse().unwrap(),
+            Some("First line\n# not a comment\n"),
+        )
+        .unwrap();
+        assert_eq!(merge(old, &pool_text(&pool), &pool, false), old);
+    }
+
+    #[test]
+    fn flow_style_falls_back() {
+        let old = "{cidr: 10.10.0.0/16, subnets: []}\n";
+        let pool = new_pool();
+        let new = pool_text(&pool);
+        assert_eq!(merge(old, &new, &pool, false), new);
+    }
+
+    #[test]
+    fn first_key_unknown() {
+        let old = "\
+cidr: 10.10.0.0/16
+subnets:
+- owner: infra
+  cidr: 10.10.0.0/24
+  name: web
+- cidr: 10.10.1.0/24
+  name: db
+";
+        let mut pool = new_pool();
+        pool.rename(&"10.10.0.0/24".parse().unwrap(), Some("front"))
+            .unwrap();
+        assert_eq!(
+            merge(old, &pool_text(&pool), &pool, false),
+            old.replace("name: web", "name: front")
+        );
+    }
+
+    #[test]
+    fn mask_unknown_keys() {
+        let source = "\
+---
+cidr: 10.10.0.0/16
+site: lab
+subnets:
+- lables:
+    tier: web
+  cidr: 10.10.0.0/24
+  pool:
+    cidr: 10.10.0.0/24
+    owner: infra
+    subnets: []
+";
+        assert_eq!(
+            mask(source),
+            Some(Masked {
+                text: "\
+---
+cidr: 10.10.0.0/16
+#site: lab
+subnets:
+  #lables:
+  #  tier: web
+- cidr: 10.10.0.0/24
+  pool:
+    cidr: 10.10.0.0/24
+    #owner: infra
+    subnets: []
+"
+                .to_string(),
+                unknown: vec![
+                    UnknownKey {
+                        key: "site".to_string(),
+                        line: 3,
+                        column: 1,
+                        suggestion: None,
+                    },
+                    UnknownKey {
+                        key: "lables".to_string(),
+                        line: 5,
+                        column: 3,
+                        suggestion: Some("labels"),
+                    },
+                    UnknownKey {
+                        key: "owner".to_string(),
+                        line: 10,
+                        column: 5,
+                        suggestion: None,
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn mask_flow_style() {
+        assert_eq!(mask("{cidr: 10.10.0.0/16, site: lab}\n"), None);
+        let source = "cidr: 10.10.0.0/16\nsubnets: [{cidr: 10.10.0.0/24, site: lab}]\n";
+        assert_eq!(mask(source).unwrap().text, source);
+    }
+
+    #[test]
+    fn suggest() {
+        assert_eq!(Schema::Record.suggest("nmae"), Some("name"));
+        assert_eq!(Schema::Record.suggest("descripton"), Some("description"));
+        assert_eq!(Schema::Record.suggest("host"), Some("hosts"));
+        assert_eq!(Schema::Record.suggest("team"), None);
+        assert_eq!(Schema::Pool.suggest("cidrz"), Some("cidr"));
+        assert_eq!(Schema::Pool.suggest("owner"), None);
+    }
+
+    #[test]
+    fn keys_are_known() {
+        for schema in [Schema::Pool, Schema::Record] {
+            for key in schema.keys() {
+                assert!(schema.field(key).is_some(), "{}", key);
+            }
+        }
+    }
+}
@@ -1,15 +1,19 @@
 // Copyright 2024 The Milton Hirsch Institute, B.V.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::diagnostic::Diagnostic;
 use exitcode::ExitCode;
 use std::error::Error;
 use std::fmt;
 use std::fmt::{Display, Formatter};
-use std::fs::File;
 use std::path::Path;
 use std::process::exit;
+use subnet_garden_core::errors::{InvariantError, LoadError};
 use subnet_garden_core::pool;
 
+mod diagnostic;
+mod layout;
+
 pub const DEFAULT_STORAGE_PATH: &str = "subnet-garden-pool.yaml";
 
 pub const SUBG_COMMAND: &str = "subg";
@@ -64,7 +68,30 @@ fn parse_pool_path(pool_path: &str) -> (&Path, crate::PoolFormat) {
     (path, format)
 }
 
+/// Pool loaded from a file, with what the file looked like on disk
+pub struct PoolFile {
+    pub source: String,
+    /// Format version of the file, before the pool was migrated
+    pub version: u32,
+    pub pool: pool::SubnetPool,
+}
+
 pub fn load_pool(pool_path: &str) -> pool::SubnetPool {
+    load_pool_file(pool_path).pool
+}
+
+/// Load a pool along with the text and format version of its file.
+pub fn load_pool_file(pool_path: &str) -> PoolFile {
+    load(pool_path, false).0
+}
+
+/// Load a pool after fixing what can be fixed in the records of its file,
+/// along with the problems that were fixed.
+pub fn repair_pool_file(pool_path: &str) -> (PoolFile, Vec<InvariantError>) {
+    load(pool_path, true)
+}
+
+fn load(pool_path: &str, repair: bool) -> (PoolFile, Vec<InvariantError>) {
     let (path, pool_format) = parse_pool_path(pool_path);
     if !path.exists() {
         eprintln!("Subnet pool file does not exist at {}", path.display());
@@ -74,46 +101,112 @@ pub fn load_pool(pool_path: &str) -> pool::SubnetPool {
         eprintln!("Path is not a file at {}", path.display());
         exit(exitcode::NOINPUT);
     }
-    let pool_file = File::open(path).unwrap();
-    fn from_reader<'a, E: Error>(
-        reader: &'a File,
-        from_reader: fn(&'a File) -> Result<pool::SubnetPool, E>,
-    ) -> pool::SubnetPool {
-        result(
-            from_reader(reader),
-            exitcode::DATAERR,
-            "Unable to load subnet pool file",
-        )
+    let source = result(
+        std::fs::read_to_string(path),
+        exitcode::IOERR,
+        "Unable to read subnet pool file",
+    );
+    // Unknown keys of a YAML file are kept when it is stored, so they are
+    // left out of what is parsed without moving the lines around them
+    let masked = match pool_format {
+        PoolFormat::Json => None,
+        PoolFormat::Yaml => layout::mask(&source),
+    };
+    let (text, warnings) = match &masked {
+        Some(masked) => (
+            masked.text.as_str(),
+            masked
+                .unknown
+                .iter()
+                .filter_map(Diagnostic::for_unknown_key)
+                .collect(),
+        ),
+        None => (source.as_str(), Vec::new()),
+    };
+    let render = |diagnostics: &[Diagnostic]| {
+        let rendered: Vec<String> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.render(pool_path, &source))
+            .collect();
+        eprint!("{}", rendered.join("\n"));
+    };
+    render(&warnings);
+    let diagnostics = match diagnostic::parse(text, &pool_format) {
+        Err(diagnostic) => vec![diagnostic],
+        Ok(mut stored) => {
+            let repaired = match repair {
+                true => stored.repair(),
+                false => Vec::new(),
+            };
+            match (stored.version, stored.into_pool()) {
+                (version, Ok(pool)) => {
+                    let pool_file = PoolFile {
+                        source,
+                        version,
+                        pool,
+                    };
+                    return (pool_file, repaired);
+                }
+                (_, Err(LoadError::InvalidRecords(records))) => records
+                    .iter()
+                    .map(|invalid| Diagnostic::for_record(text, &pool_format, invalid))
+                    .collect(),
+                (_, Err(error)) => vec![Diagnostic::message(error)],
+            }
+        }
+    };
+    eprintln!("Unable to load subnet pool file");
+    render(&diagnostics);
+    exit(exitcode::DATAERR);
+}
+
+/// Pool file contents in canonical form.
+///
+/// The file starts with the current format version, records are in address
+/// order and keys in a fixed order, and the text ends with a single newline,
+/// so storing an unchanged pool gives the same file.
+pub fn format_pool(pool_path: &str, pool: &pool::SubnetPool) -> String {
+    let (_, pool_format) = parse_pool_path(pool_path);
+    let message = "Could not store pool file";
+    let versioned = pool::VersionedPool(pool);
+    match pool_format {
+        PoolFormat::Json => {
+            result(
+                serde_json::to_string_pretty(&versioned),
+                exitcode::CANTCREAT,
+                message,
+            ) + "\n"
+        }
+        PoolFormat::Yaml => result(
+            serde_yaml::to_string(&versioned),
+            exitcode::CANTCREAT,
+            message,
+        ),
     }
+}
 
+/// Pool file contents in canonical form, keeping the comments, blank lines
+/// and unknown keys of the YAML source it was loaded from.
+pub fn reformat_pool(pool_path: &str, source: &str, pool: &pool::SubnetPool) -> String {
+    let (_, pool_format) = parse_pool_path(pool_path);
+    let formatted = format_pool(pool_path, pool);
     match pool_format {
-        PoolFormat::Json => from_reader(&pool_file, serde_json::from_reader),
-        PoolFormat::Yaml => from_reader(&pool_file, serde_yaml::from_reader),
+        PoolFormat::Json => formatted,
+        PoolFormat::Yaml => layout::merge(source, &formatted, pool, true),
     }
 }
 
+/// Store a pool, changing only the parts of an existing YAML file whose
+/// data changed.
 pub fn store_pool(pool_path: &str, pool: &pool::SubnetPool) {
     let (path, pool_format) = parse_pool_path(pool_path);
-
-    let pool_file = result(
-        File::create(path),
+    let mut contents = format_pool(pool_path, pool);
+    if let (PoolFormat::Yaml, Ok(old)) = (&pool_format, std::fs::read_to_string(path)) {
+        contents = layout::merge(&old, &contents, pool, false);
+    }
+    result(
+        std::fs::write(path, contents),
         exitcode::CANTCREAT,
         &format!("Could not create pool file at {}", path.display()),
     );
-    fn to_writer<'a, E: Error>(
-        writer: &'a File,
-        to_writer: fn(&'a File, &pool::SubnetPool) -> Result<(), E>,
-        subnet_pool: &pool::SubnetPool,
-    ) {
-        result(
-            to_writer(writer, subnet_pool),
-            exitcode::CANTCREAT,
-            "Could not store pool file",
-        );
-    }
-
-    match pool_format {
-        PoolFormat::Json => to_writer(&pool_file, serde_json::to_writer_pretty, pool),
-        PoolFormat::Yaml => to_writer(&pool_file, serde_yaml::to_writer, pool),
-    }
 }
@@ -0,0 +1,236 @@
+// Copyright 2024 The Milton Hirsch Institute, B.V.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::fixture;
+use crate::fixture::Test;
+use cidr::IpCidr;
+use std::net::IpAddr;
+
+fn new_host_test(command: &str) -> (Test, IpCidr) {
+    let mut test = fixture::new_test();
+    let cidr = test.pool.allocate(2, Some("lan")).unwrap();
+    test.store();
+    test.subg.arg("host").arg(command);
+    (test, cidr)
+}
+
+fn address(address: &str) -> IpAddr {
+    address.parse().unwrap()
+}
+
+mod allocate {
+    use super::*;
+
+    #[test]
+    fn no_such_object() {
+        let (mut test, _) = new_host_test("allocate");
+        test.subg.arg("10.10.1.0/24");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not allocate host\nNo such object\n");
+    }
+
+    #[test]
+    fn no_space_available() {
+        let (mut test, cidr) = new_host_test("allocate");
+        test.pool.allocate_host(&cidr, None).unwrap();
+        test.pool.allocate_host(&cidr, None).unwrap();
+        test.store();
+        test.subg.arg("lan");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not allocate host\nNo space available\n");
+    }
+
+    #[test]
+    fn success() {
+        let (mut test, cidr) = new_host_test("allocate");
+        test.subg.arg("lan").arg("router");
+        test.subg
+            .assert()
+            .success()
+            .stdout("10.10.0.1\n")
+            .stderr("");
+        test.load();
+        assert_eq!(
+            test.pool.find_host(&cidr, "router"),
+            Some(address("10.10.0.1"))
+        );
+    }
+
+    #[test]
+    fn all_addresses() {
+        let (mut test, _) = new_host_test("allocate");
+        test.subg.arg("lan").arg("--all-addresses");
+        test.subg
+            .assert()
+            .success()
+            .stdout("10.10.0.0\n")
+            .stderr("");
+    }
+}
+
+mod claim {
+    use super::*;
+
+    #[test]
+    fn out_of_range() {
+        let (mut test, _) = new_host_test("claim");
+        test.subg.arg("lan").arg("10.10.0.9");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not claim host\nAddress is outside the subnet\n");
+    }
+
+    #[test]
+    fn duplicate_host() {
+        let (mut test, cidr) = new_host_test("claim");
+        test.pool
+            .claim_host(&cidr, &address("10.10.0.2"), None)
+            .unwrap();
+        test.store();
+        test.subg.arg("lan").arg("10.10.0.2");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not claim host\nDuplicate host\n");
+    }
+
+    #[test]
+    fn success() {
+        let (mut test, cidr) = new_host_test("claim");
+        test.subg.arg("lan").arg("10.10.0.2").arg("dns");
+        test.subg.assert().success().stdout("").stderr("");
+        test.load();
+        assert_eq!(
+            test.pool.find_host(&cidr, "dns"),
+            Some(address("10.10.0.2"))
+        );
+    }
+}
+
+mod free {
+    use super::*;
+
+    #[test]
+    fn no_such_host() {
+        let (mut test, _) = new_host_test("free");
+        test.subg.arg("lan").arg("dns");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not free host\nNo such host\n");
+    }
+
+    #[test]
+    fn by_name() {
+        let (mut test, cidr) = new_host_test("free");
+        test.pool
+            .claim_host(&cidr, &address("10.10.0.2"), Some("dns"))
+            .unwrap();
+        test.store();
+        test.subg.arg("lan").arg("dns");
+        test.subg.assert().success().stdout("").stderr("");
+        test.load();
+        assert!(test.pool.find_record(&cidr).unwrap().hosts.is_empty());
+    }
+
+    #[test]
+    fn by_address() {
+        let (mut test, cidr) = new_host_test("free");
+        test.pool
+            .claim_host(&cidr, &address("10.10.0.2"), None)
+            .unwrap();
+        test.store();
+        test.subg.arg("lan").arg("10.10.0.2");
+        test.subg.assert().success().stdout("").stderr("");
+        test.load();
+        assert!(test.pool.find_record(&cidr).unwrap().hosts.is_empty());
+    }
+}
+
+mod rename {
+    use super::*;
+
+    #[test]
+    fn duplicate_name() {
+        let (mut test, cidr) = new_host_test("rename");
+        test.pool
+            .claim_host(&cidr, &address("10.10.0.1"), Some("router"))
+            .unwrap();
+        test.pool
+            .claim_host(&cidr, &address("10.10.0.2"), Some("dns"))
+            .unwrap();
+        test.store();
+        test.subg.arg("lan").arg("dns").arg("router");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not rename host\nDuplicate name\n");
+    }
+
+    #[test]
+    fn success() {
+        let (mut test, cidr) = new_host_test("rename");
+        test.pool
+            .claim_host(&cidr, &address("10.10.0.2"), Some("dns"))
+            .unwrap();
+        test.store();
+        test.subg.arg("lan").arg("10.10.0.2").arg("resolver");
+        test.subg.assert().success().stdout("").stderr("");
+        test.load();
+        assert_eq!(
+            test.pool.find_host(&cidr, "resolver"),
+            Some(address("10.10.0.2"))
+        );
+    }
+}
+
+mod list {
+    use super::*;
+
+    #[test]
+    fn no_such_object() {
+        let (mut test, _) = new_host_test("list");
+        test.subg.arg("10.10.1.0/24");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not list hosts\nNo such object\n");
+    }
+
+    #[test]
+    fn success() {
+        let (mut test, cidr) = new_host_test("list");
+        test.pool
+            .claim_host(&cidr, &address("10.10.0.2"), None)
+            .unwrap();
+        test.pool
+            .claim_host(&cidr, &address("10.10.0.1"), Some("router"))
+            .unwrap();
+        test.store();
+        test.subg.arg("lan");
+        test.subg
+            .assert()
+            .success()
+            .stdout("10.10.0.1  router\n10.10.0.2  -\n")
+            .stderr("");
+    }
+}
@@ -26,6 +26,29 @@ fn unforced() {
     assert_eq!(test.pool.allocated_count(), 0);
 }
 
+#[test]
+fn multiple_roots() {
+    let mut test = new_init_test(fixture::TEST_CIDR);
+    test.subg.arg("fc00::/56");
+    test.subg.assert().success().stdout("").stderr("");
+
+    test.load();
+    let roots: Vec<String> = test.pool.roots().map(|cidr| cidr.to_string()).collect();
+    assert_eq!(roots, vec![fixture::TEST_CIDR, "fc00::/56"]);
+}
+
+#[test]
+fn overlapping_roots() {
+    let mut test = new_init_test(fixture::TEST_CIDR);
+    test.subg.arg("10.10.1.0/24");
+    test.subg
+        .assert()
+        .failure()
+        .code(exitcode::USAGE)
+        .stdout("")
+        .stderr("Could not add root 10.10.1.0/24\nOverlapping object\n");
+}
+
 #[test]
 fn already_exists() {
     let mut test = new_init_test(fixture::TEST_CIDR);
@@ -50,10 +73,23 @@ fn forced() {
     test.subg.assert().success().stdout("").stderr("");
 
     let pool = pool::SubnetPool::new(fixture::TEST_CIDR.parse().unwrap());
-    let expected_content = serde_yaml::to_string(&pool).unwrap();
+    let expected_content = serde_yaml::to_string(&pool::VersionedPool(&pool)).unwrap();
     test.pool_path.assert(expected_content);
 }
 
+#[test]
+fn with_strategy() {
+    let mut test = new_init_test(fixture::TEST_CIDR);
+    test.subg.arg("--strategy").arg("best-fit");
+    test.subg.assert().success().stdout("").stderr("");
+
+    test.load();
+    assert_eq!(
+        test.pool.strategy(),
+        subnet_garden_core::AllocationStrategy::BestFit
+    );
+}
+
 #[test]
 fn not_a_file() {
     let mut test = new_init_test(fixture::TEST_CIDR);
@@ -0,0 +1,295 @@
+// Copyright 2024 The Milton Hirsch Institute, B.V.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::fixture;
+use assert_fs::assert::PathAssert;
+use assert_fs::fixture::FileWriteStr;
+
+// The IPv6 half does not point back at the IPv4 half
+const ONE_SIDED_PAIR: &str = "\
+# Dual stack
+version: 2
+cidrs:
+- 10.10.0.0/16
+- fc00::/56
+subnets:
+- cidr: 10.10.0.0/24
+  name: web
+  pair: fc00::/64   # v6 half
+- cidr: fc00::/64
+";
+
+mod check {
+    use super::*;
+
+    #[test]
+    fn consistent() {
+        let mut test = fixture::new_test();
+        test.pool.allocate(4, Some("a-name")).unwrap();
+        let parent = test.pool.allocate(8, None).unwrap();
+        test.pool.make_child(&parent).unwrap();
+        test.store();
+        test.subg.arg("check");
+        test.subg.assert().success().stdout("").stderr("");
+    }
+
+    #[test]
+    fn invalid_file() {
+        let mut test = fixture::new_test_with_path("pool.yaml");
+        test.pool_path
+            .write_str(
+                "cidr: 10.10.0.0/16\n\
+                subnets:\n\
+                - cidr: 10.10.0.0/24\n\
+                - cidr: 10.10.0.0/28\n",
+            )
+            .unwrap();
+        test.subg.arg("check");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::DATAERR)
+            .stdout("");
+    }
+
+    #[test]
+    fn one_sided_pair() {
+        let mut test = fixture::new_test_with_path("pool.yaml");
+        test.pool_path.write_str(ONE_SIDED_PAIR).unwrap();
+        test.subg.arg("check");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::DATAERR)
+            .stdout("")
+            .stderr(
+                "Subnet pool is inconsistent\n\
+                Pair fc00::/64 of 10.10.0.0/24 does not match it\n",
+            );
+        test.pool_path.assert(ONE_SIDED_PAIR);
+    }
+
+    #[test]
+    fn with_parent() {
+        let mut test = fixture::new_test();
+        test.store();
+        test.subg.args(["check", "--parent", "region"]);
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::USAGE)
+            .stdout("")
+            .stderr("Command covers the whole pool file, child pools included\n");
+    }
+}
+
+mod repair {
+    use super::*;
+
+    #[test]
+    fn success() {
+        let mut test = fixture::new_test();
+        let allocated = test.pool.allocate(4, Some("a-name")).unwrap();
+        test.store();
+        test.subg.arg("repair");
+        test.subg.assert().success().stdout("").stderr("");
+        test.load();
+        assert_eq!(test.pool.find_by_name("a-name"), Some(allocated));
+        assert_eq!(test.pool.check_invariants(), vec![]);
+    }
+
+    #[test]
+    fn one_sided_pair() {
+        let mut test = fixture::new_test_with_path("pool.yaml");
+        test.pool_path.write_str(ONE_SIDED_PAIR).unwrap();
+        test.subg.arg("repair");
+        test.subg
+            .assert()
+            .success()
+            .stdout("Repaired: Pair fc00::/64 of 10.10.0.0/24 does not match it\n")
+            .stderr("");
+        test.pool_path.assert(format!(
+            "{}  name: web\n  pair: 10.10.0.0/24\n",
+            ONE_SIDED_PAIR
+        ));
+        test.load();
+        assert_eq!(test.pool.check_invariants(), vec![]);
+    }
+}
+
+mod fmt {
+    use super::*;
+
+    const UNFORMATTED: &str = "\
+subnets:
+- name: b
+  cidr: \"10.10.1.0/24\"
+- cidr: 10.10.0.0/24
+  name: a
+cidr: 10.10.0.0/16
+";
+
+    const FORMATTED: &str = "\
+version: 2
+cidr: 10.10.0.0/16
+subnets:
+- cidr: 10.10.0.0/24
+  name: a
+- cidr: 10.10.1.0/24
+  name: b
+";
+
+    fn new_fmt_test(contents: &str) -> fixture::Test {
+        let mut test = fixture::new_test_with_path("pool.yaml");
+        test.pool_path.write_str(contents).unwrap();
+        test.subg.arg("fmt");
+        test
+    }
+
+    #[test]
+    fn rewrite() {
+        let mut test = new_fmt_test(UNFORMATTED);
+        test.subg.assert().success().stdout("").stderr("");
+        test.pool_path.assert(FORMATTED);
+    }
+
+    #[test]
+    fn check_formatted() {
+        let mut test = new_fmt_test(FORMATTED);
+        test.subg.arg("--check");
+        test.subg.assert().success().stdout("").stderr("");
+    }
+
+    #[test]
+    fn check_unformatted() {
+        let mut test = new_fmt_test(UNFORMATTED);
+        let path = test.pool_path.to_str().unwrap().to_string();
+        test.subg.arg("--check");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::DATAERR)
+            .stdout(format!(
+                "--- {path}\n\
+                +++ {path}\n\
+                @@ -1,6 +1,7 @@\n\
+                +version: 2\n\
+                +cidr: 10.10.0.0/16\n \
+                subnets:\n\
+                -- name: b\n\
+                -  cidr: \"10.10.1.0/24\"\n \
+                - cidr: 10.10.0.0/24\n   \
+                name: a\n\
+                -cidr: 10.10.0.0/16\n\
+                +- cidr: 10.10.1.0/24\n\
+                +  name: b\n"
+            ))
+            .stderr("Pool file is not in canonical form\n");
+        test.pool_path.assert(UNFORMATTED);
+    }
+
+    #[test]
+    fn keeps_comments() {
+        let mut test = new_fmt_test(
+            "# Lab pool\n\
+            subnets:\n\
+            # do not touch\n\
+            - name: b\n  \
+              cidr: \"10.10.1.0/24\"\n  \
+              owner: infra\n\
+            cidr: 10.10.0.0/16\n",
+        );
+        test.subg.assert().success().stdout("").stderr("");
+        test.pool_path.assert(
+            "# Lab pool\n\
+            version: 2\n\
+            cidr: 10.10.0.0/16\n\
+            subnets:\n\
+            # do not touch\n\
+            - cidr: 10.10.1.0/24\n  \
+              name: b\n  \
+              owner: infra\n",
+        );
+    }
+
+    #[test]
+    fn stored_pool_is_formatted() {
+        let mut test = fixture::new_test_with_path("pool.json");
+        test.pool.allocate(4, Some("a-name")).unwrap();
+        test.store();
+        test.subg.args(["fmt", "--check"]);
+        test.subg.assert().success().stdout("").stderr("");
+    }
+}
+
+mod migrate {
+    use super::*;
+
+    const UNVERSIONED: &str = "\
+# Lab pool
+cidr: 10.10.0.0/16
+subnets:
+- cidr: 10.10.0.0/24
+  name: a
+";
+
+    fn new_migrate_test(contents: &str) -> fixture::Test {
+        let mut test = fixture::new_test_with_path("pool.yaml");
+        test.pool_path.write_str(contents).unwrap();
+        test.subg.arg("migrate");
+        test
+    }
+
+    #[test]
+    fn unversioned() {
+        let mut test = new_migrate_test(UNVERSIONED);
+        test.subg
+            .assert()
+            .success()
+            .stdout("Migrated pool file from version 1 to 2\n")
+            .stderr("");
+        test.pool_path
+            .assert(UNVERSIONED.replace("# Lab pool\n", "# Lab pool\nversion: 2\n"));
+    }
+
+    #[test]
+    fn current() {
+        let current = UNVERSIONED.replace("# Lab pool\n", "# Lab pool\nversion: 2\n");
+        let mut test = new_migrate_test(&current);
+        test.subg
+            .assert()
+            .success()
+            .stdout("Pool file is already at version 2\n")
+            .stderr("");
+        test.pool_path.assert(current);
+    }
+
+    #[test]
+    fn newer() {
+        let newer = "version: 3\ncidr: 10.10.0.0/16\nsubnets: {}\n";
+        let mut test = new_migrate_test(newer);
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::DATAERR)
+            .stdout("")
+            .stderr(
+                "Unable to load subnet pool file\n\
+                error: Pool file version 3 is newer than the supported version 2\n",
+            );
+        test.pool_path.assert(newer);
+    }
+
+    #[test]
+    fn with_parent() {
+        let mut test = new_migrate_test(UNVERSIONED);
+        test.subg.args(["--parent", "a"]);
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::USAGE)
+            .stdout("")
+            .stderr("Command covers the whole pool file, child pools included\n");
+        test.pool_path.assert(UNVERSIONED);
+    }
+}
@@ -0,0 +1,100 @@
+// Copyright 2024 The Milton Hirsch Institute, B.V.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::fixture;
+use crate::fixture::Test;
+
+mod label {
+    use super::*;
+
+    fn new_label_test(identifier: &str) -> Test {
+        let mut test = fixture::new_test();
+        test.store();
+        test.subg.arg("label").arg(identifier);
+        test
+    }
+
+    #[test]
+    fn no_such_object() {
+        let mut test = new_label_test("10.10.0.0/24");
+        test.subg.arg("env=prod");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not label subnet\nNo such object\n");
+    }
+
+    #[test]
+    fn invalid_label() {
+        let mut test = new_label_test("test");
+        test.subg.arg("env");
+        test.subg
+            .assert()
+            .failure()
+            .code(fixture::HELP_EXIT_CODE)
+            .stdout("")
+            .stderr(predicates::str::contains("Invalid label: env"));
+    }
+
+    #[test]
+    fn success() {
+        let mut test = new_label_test("test");
+        test.subg
+            .arg("env=prod")
+            .arg("team=infra")
+            .arg("--description")
+            .arg("Infrastructure");
+        test.pool.allocate(4, Some("test")).unwrap();
+        test.store();
+        test.subg.assert().success().stdout("").stderr("");
+        test.load();
+        let cidr = test.pool.find_by_name("test").unwrap();
+        let record = test.pool.find_record(&cidr).unwrap();
+        assert_eq!(record.labels.get("env").unwrap(), "prod");
+        assert_eq!(record.labels.get("team").unwrap(), "infra");
+        assert_eq!(record.description.as_deref(), Some("Infrastructure"));
+    }
+}
+
+mod unlabel {
+    use super::*;
+
+    fn new_unlabel_test(identifier: &str) -> Test {
+        let mut test = fixture::new_test();
+        test.store();
+        test.subg.arg("unlabel").arg(identifier);
+        test
+    }
+
+    #[test]
+    fn no_such_object() {
+        let mut test = new_unlabel_test("10.10.0.0/24");
+        test.subg.arg("env");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not unlabel subnet\nNo such object\n");
+    }
+
+    #[test]
+    fn success() {
+        let mut test = new_unlabel_test("10.10.0.0/28");
+        test.subg.arg("env").arg("--description");
+        let cidr = test.pool.allocate(4, Some("test")).unwrap();
+        test.pool
+            .label(&cidr, &[("env", "prod"), ("team", "infra")])
+            .unwrap();
+        test.pool.describe(&cidr, Some("Infrastructure")).unwrap();
+        test.store();
+        test.subg.assert().success().stdout("").stderr("");
+        test.load();
+        let record = test.pool.find_record(&cidr).unwrap();
+        assert_eq!(record.labels.get("env"), None);
+        assert_eq!(record.labels.get("team").unwrap(), "infra");
+        assert_eq!(record.description, None);
+    }
+}
@@ -31,6 +31,48 @@ mod cidrs {
             .stderr("");
     }
 
+    #[test]
+    fn subnet_selector() {
+        let mut test = new_cidrs_test();
+        test.subg.arg("glob:lab-*");
+        test.pool.allocate(4, Some("lab-1")).unwrap();
+        test.pool.allocate(4, Some("prod-1")).unwrap();
+        test.pool.allocate(4, Some("lab-2")).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .success()
+            .stdout("10.10.0.0/28\n10.10.0.32/28\n")
+            .stderr("");
+    }
+
+    #[test]
+    fn subnet_selector_within() {
+        let mut test = new_cidrs_test();
+        test.subg.arg("within:10.10.0.0/27");
+        test.pool.allocate(4, None).unwrap();
+        test.pool.allocate(4, Some("lab-1")).unwrap();
+        test.pool.allocate(4, Some("lab-2")).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .success()
+            .stdout("10.10.0.0/28\n10.10.0.16/28\n")
+            .stderr("");
+    }
+
+    #[test]
+    fn invalid_subnet_selector() {
+        let mut test = new_cidrs_test();
+        test.subg.arg("lab-*");
+        test.subg
+            .assert()
+            .failure()
+            .code(fixture::HELP_EXIT_CODE)
+            .stdout("")
+            .stderr(predicates::str::contains("Unknown subnet selector: lab-*"));
+    }
+
     #[test]
     fn has_cidrs_long() {
         let mut test = new_cidrs_test();
@@ -51,6 +93,64 @@ mod cidrs {
             .stderr("");
     }
 
+    #[test]
+    fn reserved_long() {
+        let mut test = new_cidrs_test();
+        test.subg.arg("-l");
+        test.pool
+            .reserve(&"10.10.0.0/24".parse().unwrap(), Some("management"))
+            .unwrap();
+        test.pool
+            .reserve(&"10.10.2.0/24".parse().unwrap(), None)
+            .unwrap();
+        test.pool.allocate(8, Some("test1")).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .success()
+            .stdout(
+                "total 1\n\
+                 10.10.0.0/24  [reserved] management\n\
+                 10.10.1.0/24  test1\n\
+                 10.10.2.0/24  [reserved]\n",
+            )
+            .stderr("");
+    }
+
+    #[test]
+    fn reserved_short() {
+        let mut test = new_cidrs_test();
+        test.pool
+            .reserve(&"10.10.0.0/24".parse().unwrap(), None)
+            .unwrap();
+        test.pool.allocate(8, Some("test1")).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .success()
+            .stdout("10.10.1.0/24\n")
+            .stderr("");
+    }
+
+    #[test]
+    fn selector() {
+        let mut test = new_cidrs_test();
+        let cidr1 = test.pool.allocate(4, Some("test1")).unwrap();
+        test.pool.allocate(4, Some("test2")).unwrap();
+        let cidr3 = test.pool.allocate(4, None).unwrap();
+        test.pool
+            .label(&cidr1, &[("env", "prod"), ("team", "infra")])
+            .unwrap();
+        test.pool.label(&cidr3, &[("env", "prod")]).unwrap();
+        test.subg.arg("--selector").arg("env=prod");
+        test.store();
+        test.subg
+            .assert()
+            .success()
+            .stdout("10.10.0.0/28\n10.10.0.32/28\n")
+            .stderr("");
+    }
+
     #[test]
     fn subset_cidrs() {
         let mut test = new_cidrs_test();
@@ -65,6 +165,22 @@ mod cidrs {
             .stdout("10.10.0.0/28\n10.10.0.16/28\n")
             .stderr("");
     }
+
+    #[test]
+    fn multiple_roots() {
+        let mut test = new_cidrs_test();
+        test.pool
+            .add_root("172.16.0.0/20".parse().unwrap())
+            .unwrap();
+        test.pool.allocate(16, Some("test1")).unwrap();
+        test.pool.allocate(4, Some("test2")).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .success()
+            .stdout("10.10.0.0/16\n172.16.0.0/28\n")
+            .stderr("");
+    }
 }
 
 mod names {
@@ -97,6 +213,48 @@ mod names {
             .stderr("");
     }
 
+    #[test]
+    fn subnet_selector() {
+        let mut test = new_names_test();
+        test.subg.arg("re:^prod-.*-a$");
+        test.pool.allocate(4, Some("prod-web-a")).unwrap();
+        test.pool.allocate(4, Some("prod-web-b")).unwrap();
+        test.pool.allocate(4, Some("dev-web-a")).unwrap();
+        test.pool.allocate(4, Some("prod-db-a")).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .success()
+            .stdout("prod-db-a\nprod-web-a\n")
+            .stderr("");
+    }
+
+    #[test]
+    fn selector() {
+        let mut test = new_names_test();
+        test.subg.arg("-l").arg("--selector").arg("team=infra,env");
+        let cidr1 = test.pool.allocate(4, Some("test1")).unwrap();
+        let cidr2 = test.pool.allocate(4, Some("test2")).unwrap();
+        let cidr3 = test.pool.allocate(4, Some("test3")).unwrap();
+        test.pool
+            .label(&cidr1, &[("env", "prod"), ("team", "infra")])
+            .unwrap();
+        test.pool.label(&cidr2, &[("team", "infra")]).unwrap();
+        test.pool
+            .label(&cidr3, &[("env", "dev"), ("team", "infra")])
+            .unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .success()
+            .stdout(
+                "total 3 of 3\n\
+                 test1  10.10.0.0/28\n\
+                 test3  10.10.0.32/28\n",
+            )
+            .stderr("");
+    }
+
     #[test]
     fn has_names_long() {
         let mut test = new_names_test();
@@ -117,4 +275,233 @@ mod names {
             )
             .stderr("");
     }
+
+    #[test]
+    fn has_pairs_long() {
+        let mut test = new_names_test();
+        test.subg.arg("-l");
+        test.pool.add_root("fc00::/56".parse().unwrap()).unwrap();
+        test.pool.allocate_dual(8, 64, Some("web-a")).unwrap();
+        test.pool.allocate(4, Some("other")).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .success()
+            .stdout(
+                "total 2 of 3\n\
+                 other  10.10.1.0/28  -\n\
+                 web-a  10.10.0.0/24  fc00::/64\n",
+            )
+            .stderr("");
+    }
+}
+
+mod available {
+    use super::*;
+
+    fn new_available_test() -> Test {
+        let mut test = fixture::new_test();
+        test.pool.allocate(14, None).unwrap();
+        test.pool.allocate(8, Some("web")).unwrap();
+        test.store();
+        test.subg.arg("available");
+        test
+    }
+
+    #[test]
+    fn free_blocks() {
+        let mut test = new_available_test();
+        test.subg
+            .assert()
+            .success()
+            .stdout(
+                "10.10.65.0/24\n\
+                 10.10.66.0/23\n\
+                 10.10.68.0/22\n\
+                 10.10.72.0/21\n\
+                 10.10.80.0/20\n\
+                 10.10.96.0/19\n\
+                 10.10.128.0/17\n",
+            )
+            .stderr("");
+    }
+
+    #[test]
+    fn within() {
+        let mut test = new_available_test();
+        test.subg.args(["--within", "10.10.64.0/21"]);
+        test.subg
+            .assert()
+            .success()
+            .stdout("10.10.65.0/24\n10.10.66.0/23\n10.10.68.0/22\n")
+            .stderr("");
+    }
+
+    #[test]
+    fn bits() {
+        let mut test = new_available_test();
+        test.subg.args(["--bits", "9", "--within", "10.10.64.0/21"]);
+        test.subg
+            .assert()
+            .success()
+            .stdout("10.10.66.0/23\n10.10.68.0/23\n10.10.70.0/23\n")
+            .stderr("");
+    }
+
+    #[test]
+    fn bits_too_large() {
+        let mut test = new_available_test();
+        test.subg.args(["--bits", "16"]);
+        test.subg.assert().success().stdout("").stderr("");
+    }
+}
+
+mod stats {
+    use super::*;
+
+    fn new_stats_test() -> Test {
+        let mut test = fixture::new_test();
+        test.pool.allocate(15, None).unwrap();
+        test.pool.allocate(8, Some("web")).unwrap();
+        test.pool.allocate(8, None).unwrap();
+        test.store();
+        test.subg.arg("stats");
+        test
+    }
+
+    #[test]
+    fn text() {
+        let mut test = new_stats_test();
+        test.subg
+            .assert()
+            .success()
+            .stdout(
+                "Addresses:     65536\n\
+                 Allocated:     33280 (50.78%)\n\
+                 Reserved:      0\n\
+                 Free:          32256\n\
+                 Subnets:       3\n\
+                 Fragmentation: 0.49\n\
+                 \n\
+                 Allocated by prefix length:\n  \
+                 /17  1 subnets  32768 addresses\n  \
+                 /24  2 subnets  512 addresses\n\
+                 \n\
+                 Free blocks by prefix length:\n  \
+                 /18  1\n  \
+                 /19  1\n  \
+                 /20  1\n  \
+                 /21  1\n  \
+                 /22  1\n  \
+                 /23  1\n",
+            )
+            .stderr("");
+    }
+
+    #[test]
+    fn empty() {
+        let mut test = fixture::new_test();
+        test.store();
+        test.subg.arg("stats");
+        test.subg
+            .assert()
+            .success()
+            .stdout(
+                "Addresses:     65536\n\
+                 Allocated:     0 (0.00%)\n\
+                 Reserved:      0\n\
+                 Free:          65536\n\
+                 Subnets:       0\n\
+                 Fragmentation: 0.00\n\
+                 \n\
+                 Free blocks by prefix length:\n  \
+                 /16  1\n",
+            )
+            .stderr("");
+    }
+
+    #[test]
+    fn json() {
+        let mut test = new_stats_test();
+        test.subg.arg("--json");
+        let output = test.subg.output().unwrap();
+        assert!(output.status.success());
+        let stats: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(stats["total"], 65536);
+        assert_eq!(stats["allocated"], 33280);
+        assert_eq!(stats["subnets"], 3);
+        assert_eq!(stats["prefixes"]["24"]["subnets"], 2);
+        assert_eq!(stats["free_blocks"]["18"], 1);
+    }
+}
+
+mod lookup {
+    use super::*;
+
+    fn new_lookup_test() -> Test {
+        let mut test = fixture::new_test();
+        test.pool.allocate(8, None).unwrap();
+        test.pool.allocate(8, Some("web")).unwrap();
+        test.pool
+            .reserve(&"10.10.255.0/24".parse().unwrap(), None)
+            .unwrap();
+        test.store();
+        test.subg.arg("lookup");
+        test
+    }
+
+    #[test]
+    fn arguments() {
+        let mut test = new_lookup_test();
+        test.subg
+            .args(["10.10.1.20", "10.10.0.1", "10.10.255.255", "10.10.2.3"]);
+        test.subg
+            .assert()
+            .success()
+            .stdout(
+                "10.10.1.20  web  10.10.1.0/24  +20\n\
+                 10.10.0.1  -  10.10.0.0/24  +1\n\
+                 10.10.255.255  [reserved]  10.10.255.0/24  +255\n\
+                 10.10.2.3  [free]  10.10.2.0/23  +3\n",
+            )
+            .stderr("");
+    }
+
+    #[test]
+    fn stdin() {
+        let mut test = new_lookup_test();
+        test.subg.write_stdin("10.10.1.1\n\n  10.10.0.9 \n");
+        test.subg
+            .assert()
+            .success()
+            .stdout(
+                "10.10.1.1  web  10.10.1.0/24  +1\n\
+                 10.10.0.9  -  10.10.0.0/24  +9\n",
+            )
+            .stderr("");
+    }
+
+    #[test]
+    fn stdin_invalid() {
+        let mut test = new_lookup_test();
+        test.subg.write_stdin("10.10.1.1\nbad\n");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::USAGE)
+            .stdout("")
+            .stderr("Could not parse address bad\ninvalid IP address syntax\n");
+    }
+
+    #[test]
+    fn outside() {
+        let mut test = new_lookup_test();
+        test.subg.args(["192.168.0.1", "10.10.1.1"]);
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("10.10.1.1  web  10.10.1.0/24  +1\n")
+            .stderr("Address 192.168.0.1 is not in the pool\n");
+    }
 }
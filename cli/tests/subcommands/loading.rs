@@ -0,0 +1,178 @@
+// Copyright 2024 The Milton Hirsch Institute, B.V.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::fixture;
+use assert_fs::fixture::FileWriteStr;
+
+fn new_loading_test(path: &str, contents: &str) -> fixture::Test {
+    let mut test = fixture::new_test_with_path(path);
+    test.pool_path.write_str(contents).unwrap();
+    test.subg.arg("cidrs");
+    test
+}
+
+#[test]
+fn invalid_records_yaml() {
+    let mut test = new_loading_test(
+        "pool.yaml",
+        "cidr: 10.10.0.0/16\n\
+        subnets:\n\
+        - cidr: 10.10.0.0/24\n  \
+          name: a\n\
+        - cidr: 10.10.0.16/28\n  \
+          name: b\n\
+        - cidr: 10.10.1.0/24\n  \
+          name: a\n",
+    );
+    let path = test.pool_path.to_str().unwrap().to_string();
+    test.subg
+        .assert()
+        .failure()
+        .code(exitcode::DATAERR)
+        .stdout("")
+        .stderr(format!(
+            "Unable to load subnet pool file\n\
+            error: 10.10.0.16/28 (b): Overlaps 10.10.0.0/24 (a)\n \
+            --> {path}:5:3\n  \
+             |\n\
+            5 | - cidr: 10.10.0.16/28\n  \
+             |   ^\n  \
+             |\n  \
+             = note: 10.10.0.0/24 (a) is defined at line 3\n\
+            \n\
+            error: 10.10.1.0/24 (a): Name already used by 10.10.0.0/24 (a)\n \
+            --> {path}:7:3\n  \
+             |\n\
+            7 | - cidr: 10.10.1.0/24\n  \
+             |   ^\n  \
+             |\n  \
+             = note: 10.10.0.0/24 (a) is defined at line 3\n"
+        ));
+}
+
+#[test]
+fn invalid_records_json() {
+    let mut test = new_loading_test(
+        "pool.json",
+        "{\n  \
+          \"cidr\": \"10.10.0.0/16\",\n  \
+          \"subnets\": [],\n  \
+          \"reserved\": [\n    \
+            {\n      \
+              \"cidr\": \"10.20.0.0/24\"\n    \
+            }\n  \
+          ]\n\
+        }\n",
+    );
+    let path = test.pool_path.to_str().unwrap().to_string();
+    test.subg
+        .assert()
+        .failure()
+        .code(exitcode::DATAERR)
+        .stdout("")
+        .stderr(format!(
+            "Unable to load subnet pool file\n\
+            error: 10.20.0.0/24: Outside every root\n \
+            --> {path}:6:7\n  \
+             |\n\
+            6 |       \"cidr\": \"10.20.0.0/24\"\n  \
+             |       ^\n"
+        ));
+}
+
+#[test]
+fn syntax_error() {
+    let mut test = new_loading_test("pool.yaml", "cidr: 10.10.0.0/16\nsubnets: 10\n");
+    let path = test.pool_path.to_str().unwrap().to_string();
+    test.subg
+        .assert()
+        .failure()
+        .code(exitcode::DATAERR)
+        .stdout("")
+        .stderr(format!(
+            "Unable to load subnet pool file\n\
+            error: subnets: invalid type: integer `10`, expected a sequence\n \
+            --> {path}:2:10\n  \
+             |\n\
+            2 | subnets: 10\n  \
+             |          ^\n"
+        ));
+}
+
+#[test]
+fn unknown_key_json() {
+    let mut test = new_loading_test(
+        "pool.json",
+        "{\"cidr\": \"10.10.0.0/16\", \"owner\": \"infra\"}\n",
+    );
+    let path = test.pool_path.to_str().unwrap().to_string();
+    test.subg
+        .assert()
+        .failure()
+        .code(exitcode::DATAERR)
+        .stdout("")
+        .stderr(format!(
+            "Unable to load subnet pool file\n\
+            error: unknown field `owner`, expected one of \
+            `version`, `cidr`, `cidrs`, `subnets`, `reserved`, `strategy`\n \
+            --> {path}:1:32\n  \
+             |\n\
+            1 | {{\"cidr\": \"10.10.0.0/16\", \"owner\": \"infra\"}}\n  \
+             |                                ^\n"
+        ));
+}
+
+#[test]
+fn unknown_key_yaml() {
+    let mut test = new_loading_test(
+        "pool.yaml",
+        "cidr: 10.10.0.0/16\n\
+        owner: infra\n\
+        subnets:\n\
+        - cidr: 10.10.0.0/24\n  \
+          lables:\n    \
+            tier: web\n",
+    );
+    let path = test.pool_path.to_str().unwrap().to_string();
+    test.subg
+        .assert()
+        .success()
+        .stdout("10.10.0.0/24\n")
+        .stderr(format!(
+            "warning: Unknown key `lables`, did you mean `labels`?\n \
+            --> {path}:5:3\n  \
+             |\n\
+            5 |   lables:\n  \
+             |   ^\n  \
+             |\n  \
+             = note: Unknown keys are kept as they are\n"
+        ));
+}
+
+#[test]
+fn reserved_with_name() {
+    let mut test = new_loading_test(
+        "pool.yaml",
+        "cidr: 10.10.0.0/16\n\
+        subnets: []\n\
+        reserved:\n\
+        - cidr: 10.10.255.0/24\n  \
+          name: legacy\n  \
+          labels:\n    \
+            dc: old\n",
+    );
+    let path = test.pool_path.to_str().unwrap().to_string();
+    test.subg
+        .assert()
+        .failure()
+        .code(exitcode::DATAERR)
+        .stdout("")
+        .stderr(format!(
+            "Unable to load subnet pool file\n\
+            error: reserved[0]: unknown field `name`, expected `cidr` or `description`\n \
+            --> {path}:5:3\n  \
+             |\n\
+            5 |   name: legacy\n  \
+             |   ^\n"
+        ));
+}
@@ -1,6 +1,12 @@
 // Copyright 2024 The Milton Hirsch Institute, B.V.
 // SPDX-License-Identifier: Apache-2.0
 
+mod hosts;
 mod init;
+mod integrity;
+mod labeling;
 mod listing;
+mod loading;
+mod roots;
+mod storing;
 mod subnet;
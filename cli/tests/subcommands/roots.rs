@@ -0,0 +1,172 @@
+// Copyright 2024 The Milton Hirsch Institute, B.V.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::fixture;
+use crate::fixture::Test;
+
+fn new_roots_test(command: &str, cidr: &str) -> Test {
+    let mut test = fixture::new_test();
+    test.store();
+    test.subg.arg(command).arg(cidr);
+    test
+}
+
+mod add_root {
+    use super::*;
+
+    #[test]
+    fn success() {
+        let mut test = new_roots_test("add-root", "172.16.0.0/20");
+        test.subg.assert().success().stdout("").stderr("");
+        test.load();
+        let roots: Vec<String> = test.pool.roots().map(|cidr| cidr.to_string()).collect();
+        assert_eq!(roots, vec![fixture::TEST_CIDR, "172.16.0.0/20"]);
+    }
+
+    #[test]
+    fn duplicate() {
+        let mut test = new_roots_test("add-root", fixture::TEST_CIDR);
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not add root 10.10.0.0/16\nDuplicate object\n");
+    }
+
+    #[test]
+    fn with_parent() {
+        let mut test = new_roots_test("add-root", "172.16.0.0/20");
+        test.subg.args(["--parent", "region"]);
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::USAGE)
+            .stdout("")
+            .stderr("Child pools have a single root\n");
+    }
+}
+
+mod remove_root {
+    use super::*;
+
+    #[test]
+    fn success() {
+        let mut test = new_roots_test("remove-root", "172.16.0.0/20");
+        test.pool
+            .add_root("172.16.0.0/20".parse().unwrap())
+            .unwrap();
+        test.store();
+        test.subg.assert().success().stdout("").stderr("");
+        test.load();
+        assert_eq!(test.pool.roots().count(), 1);
+    }
+
+    #[test]
+    fn not_empty() {
+        let mut test = new_roots_test("remove-root", fixture::TEST_CIDR);
+        test.pool
+            .add_root("172.16.0.0/20".parse().unwrap())
+            .unwrap();
+        test.pool.allocate(8, None).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not remove root 10.10.0.0/16\nNot empty\n");
+    }
+
+    #[test]
+    fn last_root() {
+        let mut test = new_roots_test("remove-root", fixture::TEST_CIDR);
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not remove root 10.10.0.0/16\nCannot remove the last root\n");
+    }
+}
+
+mod resize_pool {
+    use super::*;
+
+    #[test]
+    fn expand() {
+        let mut test = new_roots_test("resize-pool", "10.8.0.0/13");
+        test.subg
+            .assert()
+            .success()
+            .stdout("Resized root 10.10.0.0/16 to 10.8.0.0/13\n")
+            .stderr("");
+        test.load();
+        assert_eq!(test.pool.cidr().to_string(), "10.8.0.0/13");
+    }
+
+    #[test]
+    fn shrink() {
+        let mut test = new_roots_test("resize-pool", "10.10.0.0/20");
+        test.pool.allocate(8, Some("a")).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .success()
+            .stdout("Resized root 10.10.0.0/16 to 10.10.0.0/20\n")
+            .stderr("");
+        test.load();
+        assert_eq!(test.pool.cidr().to_string(), "10.10.0.0/20");
+        assert_eq!(
+            test.pool.find_by_name("a").map(|cidr| cidr.to_string()),
+            Some("10.10.0.0/24".to_string())
+        );
+    }
+
+    #[test]
+    fn shrink_blocked() {
+        let mut test = new_roots_test("resize-pool", "10.10.0.0/20");
+        test.pool.allocate(8, Some("a")).unwrap();
+        test.pool
+            .claim(&"10.10.128.0/24".parse().unwrap(), Some("b"))
+            .unwrap();
+        test.pool
+            .reserve(&"10.10.64.0/24".parse().unwrap(), None)
+            .unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr(
+                "Could not resize pool to 10.10.0.0/20\n\
+                 2 subnets outside the new root\n  \
+                 10.10.64.0/24  [reserved]\n  \
+                 10.10.128.0/24  b\n",
+            );
+    }
+
+    #[test]
+    fn no_such_root() {
+        let mut test = new_roots_test("resize-pool", "172.16.0.0/20");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not resize pool to 172.16.0.0/20\nNo such root\n");
+    }
+
+    #[test]
+    fn with_parent() {
+        let mut test = new_roots_test("resize-pool", "10.8.0.0/13");
+        test.subg.args(["--parent", "region"]);
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::USAGE)
+            .stdout("")
+            .stderr("Child pools have a single root\n");
+    }
+}
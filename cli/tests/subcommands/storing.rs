@@ -0,0 +1,134 @@
+// Copyright 2024 The Milton Hirsch Institute, B.V.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::fixture;
+use assert_fs::assert::PathAssert;
+use assert_fs::fixture::FileWriteStr;
+
+const HAND_EDITED: &str = "\
+# Lab pool
+version: 2
+cidr: 10.10.0.0/16
+
+subnets:
+# do not touch, legacy DC
+- cidr: 10.10.0.0/24   # racks 1-4
+  name: legacy
+  owner: infra
+
+- cidr: 10.10.1.0/24
+  name: web
+";
+
+fn new_storing_test() -> fixture::Test {
+    let test = fixture::new_test_with_path("pool.yaml");
+    test.pool_path.write_str(HAND_EDITED).unwrap();
+    test
+}
+
+#[test]
+fn keeps_comments_and_unknown_keys() {
+    let mut test = new_storing_test();
+    test.subg.args(["claim", "10.10.2.0/24", "db"]);
+    test.subg.assert().success();
+    test.pool_path
+        .assert(format!("{}- cidr: 10.10.2.0/24\n  name: db\n", HAND_EDITED));
+}
+
+#[test]
+fn edits_changed_record() {
+    let mut test = new_storing_test();
+    test.subg.args(["rename", "web", "front"]);
+    test.subg.assert().success();
+    test.pool_path
+        .assert(HAND_EDITED.replace("name: web", "name: front"));
+}
+
+#[test]
+fn json_is_canonical() {
+    let mut test = fixture::new_test_with_path("pool.json");
+    test.pool_path
+        .write_str("{\"subnets\": [], \"cidr\": \"10.10.0.0/16\"}")
+        .unwrap();
+    test.subg.args(["claim", "10.10.0.0/24"]);
+    test.subg.assert().success();
+    test.pool_path.assert(
+        "{\n  \
+          \"version\": 2,\n  \
+          \"cidr\": \"10.10.0.0/16\",\n  \
+          \"subnets\": [\n    \
+            {\n      \
+              \"cidr\": \"10.10.0.0/24\"\n    \
+            }\n  \
+          ]\n\
+        }\n",
+    );
+}
+
+const ROUND_TRIP: &str = r#"{
+  "version": 2,
+  "cidr": "10.10.0.0/16",
+  "subnets": [
+    {
+      "cidr": "10.10.0.0/24",
+      "name": "web",
+      "description": "Front end",
+      "labels": {
+        "tier": "front"
+      },
+      "hosts": {
+        "10.10.0.5": "lb"
+      }
+    },
+    {
+      "cidr": "10.10.1.0/24",
+      "name": "db",
+      "pool": {
+        "cidr": "10.10.1.0/24",
+        "subnets": [
+          {
+            "cidr": "10.10.1.0/28",
+            "name": "replica"
+          }
+        ]
+      }
+    }
+  ],
+  "reserved": [
+    {
+      "cidr": "10.10.255.0/24",
+      "description": "Infra"
+    }
+  ],
+  "strategy": "highest-first"
+}
+"#;
+
+#[test]
+fn json_round_trip() {
+    let mut test = fixture::new_test_with_path("pool.json");
+    test.pool_path.write_str(ROUND_TRIP).unwrap();
+    test.subg.args(["claim", "10.10.2.0/24"]);
+    test.subg.assert().success();
+    test.load();
+    assert!(test.pool.free(&"10.10.2.0/24".parse().unwrap()));
+    test.store();
+    test.pool_path.assert(ROUND_TRIP);
+}
+
+#[test]
+fn json_refuses_unknown_keys() {
+    let mut test = fixture::new_test_with_path("pool.json");
+    let contents = ROUND_TRIP.replace(
+        "\"name\": \"web\",",
+        "\"name\": \"web\", \"owner\": \"infra\",",
+    );
+    test.pool_path.write_str(&contents).unwrap();
+    test.subg.args(["claim", "10.10.2.0/24"]);
+    test.subg
+        .assert()
+        .failure()
+        .code(exitcode::DATAERR)
+        .stderr(predicates::str::contains("unknown field `owner`"));
+    test.pool_path.assert(contents);
+}
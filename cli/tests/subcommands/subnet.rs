@@ -44,6 +44,23 @@ mod allocate {
             .code(exitcode::SOFTWARE)
             .stdout("")
             .stderr("Could not allocate subnet name-128\nNo space available\n");
+        test.load();
+        assert_eq!(test.pool.allocated_count(), 1);
+        assert_eq!(test.pool.find_by_name("name-0"), None);
+    }
+
+    #[test]
+    fn allocate_multi_duplicate_name() {
+        let mut test = new_allocate_test("8", Some("name-{}"));
+        test.subg.arg("0,1,0");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not allocate subnet name-0\nDuplicate name\n");
+        test.load();
+        assert_eq!(test.pool.allocated_count(), 0);
     }
 
     #[test]
@@ -68,7 +85,11 @@ mod allocate {
     #[test]
     fn allocate_with_name() {
         let mut test = new_allocate_test("8", Some("test"));
-        test.subg.assert().success().stdout("").stderr("");
+        test.subg
+            .assert()
+            .success()
+            .stdout("10.10.0.0/24  256 usable hosts\n")
+            .stderr("");
         test.load();
         let subnets: Vec<&CidrRecord> = test.pool.records().collect();
         assert_eq!(subnets.len(), 1);
@@ -79,7 +100,11 @@ mod allocate {
     #[test]
     fn allocate_without_name() {
         let mut test = new_allocate_test("8", None);
-        test.subg.assert().success().stdout("").stderr("");
+        test.subg
+            .assert()
+            .success()
+            .stdout("10.10.0.0/24  256 usable hosts\n")
+            .stderr("");
         test.load();
         let subnets: Vec<&CidrRecord> = test.pool.records().collect();
         assert_eq!(subnets.len(), 1);
@@ -87,12 +112,258 @@ mod allocate {
         assert_eq!(subnets[0].cidr.to_string(), "10.10.0.0/24");
     }
 
+    #[test]
+    fn allocate_prefix_length() {
+        let mut test = new_allocate_test("/23", Some("test"));
+        test.subg
+            .assert()
+            .success()
+            .stdout("10.10.0.0/23  512 usable hosts\n")
+            .stderr("");
+        test.load();
+        let subnets: Vec<&CidrRecord> = test.pool.records().collect();
+        assert_eq!(subnets.len(), 1);
+        assert_eq!(subnets[0].cidr.to_string(), "10.10.0.0/23");
+    }
+
+    #[test]
+    fn allocate_hosts() {
+        let mut test = new_allocate_test("300h", Some("test"));
+        test.subg
+            .assert()
+            .success()
+            .stdout("10.10.0.0/23  512 usable hosts\n")
+            .stderr("");
+        test.load();
+        assert_eq!(
+            test.pool.find_by_name("test").unwrap().to_string(),
+            "10.10.0.0/23"
+        );
+    }
+
+    #[test]
+    fn allocate_hosts_reserved() {
+        let mut test = new_allocate_test("254h", Some("test-{}"));
+        test.subg.arg("a,b").arg("--reserved").arg("5");
+        test.subg
+            .assert()
+            .success()
+            .stdout(
+                "10.10.0.0/23  507 usable hosts\n\
+                 10.10.2.0/23  507 usable hosts\n",
+            )
+            .stderr("");
+    }
+
+    #[test]
+    fn allocate_hosts_with_strategy() {
+        let mut test = new_allocate_test("300h", Some("test"));
+        test.subg.arg("--strategy").arg("highest-first");
+        test.subg
+            .assert()
+            .success()
+            .stdout("10.10.254.0/23  512 usable hosts\n")
+            .stderr("");
+    }
+
+    #[test]
+    fn allocate_hosts_too_many() {
+        let mut test = new_allocate_test("65537h", None);
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not allocate subnet\nNo space available\n");
+    }
+
+    #[test]
+    fn allocate_invalid_size() {
+        let mut test = new_allocate_test("300x", None);
+        test.subg
+            .assert()
+            .failure()
+            .code(fixture::HELP_EXIT_CODE)
+            .stdout("")
+            .stderr(predicates::str::contains("Invalid subnet size: 300x"));
+    }
+
+    #[test]
+    fn allocate_with_strategy() {
+        let mut test = new_allocate_test("8", Some("test"));
+        test.subg.arg("--strategy").arg("highest-first");
+        test.subg
+            .assert()
+            .success()
+            .stdout("10.10.255.0/24  256 usable hosts\n")
+            .stderr("");
+        test.load();
+        let subnets: Vec<&CidrRecord> = test.pool.records().collect();
+        assert_eq!(subnets.len(), 1);
+        assert_eq!(subnets[0].cidr.to_string(), "10.10.255.0/24");
+    }
+
+    #[test]
+    fn allocate_with_pool_strategy() {
+        let mut test = new_allocate_test("8", Some("test"));
+        test.pool
+            .set_strategy(subnet_garden_core::AllocationStrategy::HighestFirst);
+        test.store();
+        test.subg
+            .assert()
+            .success()
+            .stdout("10.10.255.0/24  256 usable hosts\n")
+            .stderr("");
+        test.load();
+        let subnets: Vec<&CidrRecord> = test.pool.records().collect();
+        assert_eq!(subnets.len(), 1);
+        assert_eq!(subnets[0].cidr.to_string(), "10.10.255.0/24");
+    }
+
+    #[test]
+    fn allocate_unknown_strategy() {
+        let mut test = new_allocate_test("8", Some("test"));
+        test.subg.arg("--strategy").arg("first-fit");
+        test.subg
+            .assert()
+            .failure()
+            .code(fixture::HELP_EXIT_CODE)
+            .stdout("")
+            .stderr(predicates::str::contains(
+                "Unknown allocation strategy: first-fit",
+            ));
+    }
+
+    #[test]
+    fn allocate_with_root() {
+        let mut test = new_allocate_test("/64", Some("v6"));
+        test.subg.args(["--root", "ipv6"]);
+        test.pool.add_root("fc00::/56".parse().unwrap()).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .success()
+            .stdout("fc00::/64  18446744073709551616 usable hosts\n")
+            .stderr("");
+        test.load();
+        assert_eq!(
+            test.pool.find_by_name("v6"),
+            Some("fc00::/64".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn allocate_prefix_length_in_family() {
+        let mut test = new_allocate_test("/64", Some("v6"));
+        test.pool.add_root("fc00::/56".parse().unwrap()).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .success()
+            .stdout("fc00::/64  18446744073709551616 usable hosts\n")
+            .stderr("");
+        test.load();
+        assert_eq!(
+            test.pool.find_by_name("v6"),
+            Some("fc00::/64".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn allocate_missing_root() {
+        let mut test = new_allocate_test("8", None);
+        test.subg.args(["--root", "ipv6"]);
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not allocate subnet\nNo such root\n");
+    }
+
+    #[test]
+    fn allocate_invalid_root() {
+        let mut test = new_allocate_test("8", None);
+        test.subg.args(["--root", "ipv5"]);
+        test.subg
+            .assert()
+            .failure()
+            .code(fixture::HELP_EXIT_CODE)
+            .stdout("")
+            .stderr(predicates::str::contains("Invalid root selector: ipv5"));
+    }
+
+    #[test]
+    fn allocate_dual() {
+        let mut test = fixture::new_test();
+        test.pool.add_root("fc00::/56".parse().unwrap()).unwrap();
+        test.store();
+        test.subg
+            .args(["allocate", "--dual", "8", "64", "web-a"])
+            .assert()
+            .success()
+            .stdout("10.10.0.0/24  fc00::/64\n")
+            .stderr("");
+        test.load();
+        let v4 = test.pool.find_by_name("web-a").unwrap();
+        assert_eq!(
+            test.pool.find_record(&v4).unwrap().pair,
+            Some("fc00::/64".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn allocate_dual_multiple() {
+        let mut test = fixture::new_test();
+        test.pool.add_root("fc00::/56".parse().unwrap()).unwrap();
+        test.store();
+        test.subg
+            .args(["allocate", "--dual", "/24", "/64", "web-{}", "a,b"])
+            .assert()
+            .success()
+            .stdout("10.10.0.0/24  fc00::/64\n10.10.1.0/24  fc00:0:0:1::/64\n")
+            .stderr("");
+    }
+
+    #[test]
+    fn allocate_dual_without_v6_root() {
+        let mut test = new_allocate_test("8", Some("64"));
+        test.subg.args(["--dual", "web-a"]);
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not allocate subnet\nNo such root\n");
+    }
+
+    #[test]
+    fn allocate_dual_without_v6_size() {
+        let mut test = new_allocate_test("8", None);
+        test.subg.arg("--dual");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::USAGE)
+            .stdout("")
+            .stderr("Could not parse IPv6 subnet size\nInvalid subnet size: \n");
+    }
+
     #[test]
     fn allocate_multiple() {
         let mut test = new_allocate_test("8", Some("name-{}-{}"));
         test.subg.arg("%0..2");
         test.subg.arg("a,b");
-        test.subg.assert().success().stdout("").stderr("");
+        test.subg
+            .assert()
+            .success()
+            .stdout(
+                "10.10.0.0/24  256 usable hosts\n\
+                 10.10.1.0/24  256 usable hosts\n\
+                 10.10.2.0/24  256 usable hosts\n\
+                 10.10.3.0/24  256 usable hosts\n",
+            )
+            .stderr("");
         test.load();
         let subnets: Vec<&CidrRecord> = test.pool.records().collect();
         assert_eq!(subnets.len(), 4);
@@ -143,6 +414,40 @@ mod free {
             .stderr("Could not free subnet 20.20.0.0/24\n");
     }
 
+    #[test]
+    fn free_with_children() {
+        let mut test = new_free_test("region");
+        let cidr = test.pool.allocate(8, Some("region")).unwrap();
+        let child = test.pool.make_child(&cidr).unwrap();
+        child.allocate(4, None).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr(
+                "Could not free subnet 10.10.0.0/24
+Subnet has child allocations
+",
+            );
+        test.load();
+        assert!(test.pool.contains(&cidr));
+    }
+
+    #[test]
+    fn free_forced() {
+        let mut test = new_free_test("region");
+        test.subg.arg("--force");
+        let cidr = test.pool.allocate(8, Some("region")).unwrap();
+        let child = test.pool.make_child(&cidr).unwrap();
+        child.allocate(4, None).unwrap();
+        test.store();
+        test.subg.assert().success().stdout("").stderr("");
+        test.load();
+        assert!(!test.pool.contains(&cidr));
+    }
+
     #[test]
     fn free_success_with_name() {
         let mut test = new_free_test("test");
@@ -176,6 +481,59 @@ mod free {
         assert_eq!(test.pool.find_by_name("test2"), None);
     }
 
+    #[test]
+    fn free_multiple_failure() {
+        let mut test = new_free_test("{}");
+        test.subg.arg("test1,10.10.1.0/24");
+        test.pool.allocate(4, Some("test1")).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not free subnet 10.10.1.0/24\n");
+        test.load();
+        assert_eq!(
+            test.pool.find_by_name("test1").unwrap().to_string(),
+            "10.10.0.0/28"
+        );
+    }
+
+    #[test]
+    fn free_selector() {
+        let mut test = fixture::new_test();
+        test.subg.arg("free").arg("--selector").arg("env=dev");
+        let cidr1 = test.pool.allocate(4, Some("test1")).unwrap();
+        let cidr2 = test.pool.allocate(4, Some("test2")).unwrap();
+        test.pool.allocate(4, Some("test3")).unwrap();
+        test.pool.label(&cidr1, &[("env", "dev")]).unwrap();
+        test.pool.label(&cidr2, &[("env", "prod")]).unwrap();
+        test.store();
+        test.subg.assert().success().stdout("").stderr("");
+        test.load();
+        assert_eq!(test.pool.find_by_name("test1"), None);
+        assert_eq!(test.pool.find_by_name("test2"), Some(cidr2));
+        assert!(test.pool.find_by_name("test3").is_some());
+    }
+
+    #[test]
+    fn free_selector_with_format() {
+        let mut test = new_free_test("test{}");
+        test.subg.arg("%1..3").arg("--selector").arg("env=dev");
+        let cidr1 = test.pool.allocate(4, Some("test1")).unwrap();
+        test.pool.allocate(4, Some("test2")).unwrap();
+        let cidr3 = test.pool.allocate(4, Some("test3")).unwrap();
+        test.pool.label(&cidr1, &[("env", "dev")]).unwrap();
+        test.pool.label(&cidr3, &[("env", "dev")]).unwrap();
+        test.store();
+        test.subg.assert().success().stdout("").stderr("");
+        test.load();
+        assert_eq!(test.pool.find_by_name("test1"), None);
+        assert!(test.pool.find_by_name("test2").is_some());
+        assert_eq!(test.pool.find_by_name("test3"), Some(cidr3));
+    }
+
     #[test]
     fn ignore_missing_name() {
         let mut test = new_free_test("test{}");
@@ -189,6 +547,73 @@ mod free {
         assert_eq!(test.pool.find_by_name("test2"), None);
     }
 
+    fn new_bulk_free_test(identifier: &str) -> Test {
+        let mut test = new_free_test(identifier);
+        test.pool.allocate(4, Some("lab-1")).unwrap();
+        test.pool.allocate(4, Some("lab-2")).unwrap();
+        test.pool.allocate(4, Some("prod-1")).unwrap();
+        test.store();
+        test
+    }
+
+    #[test]
+    fn glob_requires_yes() {
+        let mut test = new_bulk_free_test("glob:lab-*");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::USAGE)
+            .stdout("")
+            .stderr(
+                "Would free 2 subnets, pass --yes to free them\n  \
+                 10.10.0.0/28  lab-1\n  \
+                 10.10.0.16/28  lab-2\n",
+            );
+        test.load();
+        assert_eq!(test.pool.allocated_count(), 3);
+    }
+
+    #[test]
+    fn glob_with_yes() {
+        let mut test = new_bulk_free_test("glob:lab-*");
+        test.subg.arg("--yes");
+        test.subg.assert().success().stdout("").stderr("");
+        test.load();
+        assert_eq!(test.pool.find_by_name("lab-1"), None);
+        assert_eq!(test.pool.find_by_name("lab-2"), None);
+        assert!(test.pool.find_by_name("prod-1").is_some());
+    }
+
+    #[test]
+    fn regex_single_match() {
+        let mut test = new_bulk_free_test("re:^prod-");
+        test.subg.assert().success().stdout("").stderr("");
+        test.load();
+        assert_eq!(test.pool.find_by_name("prod-1"), None);
+        assert_eq!(test.pool.allocated_count(), 2);
+    }
+
+    #[test]
+    fn within_with_yes() {
+        let mut test = new_bulk_free_test("within:10.10.0.0/27");
+        test.subg.arg("-y");
+        test.subg.assert().success().stdout("").stderr("");
+        test.load();
+        let names: Vec<String> = test.pool.names().collect();
+        assert_eq!(names, vec!["prod-1".to_string()]);
+    }
+
+    #[test]
+    fn invalid_selector() {
+        let mut test = new_bulk_free_test("re:(");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::USAGE)
+            .stdout("")
+            .stderr("Could not parse arg IDENTIFIER\nInvalid regex: (\n");
+    }
+
     #[test]
     fn ignore_missing_cidr() {
         let mut test = new_free_test("10.10.0.0/28");
@@ -238,6 +663,37 @@ mod claim {
         assert_eq!(subnets[0].cidr.to_string(), "10.10.0.0/24");
     }
 
+    #[test]
+    fn conflict() {
+        let mut test = new_claim_test("10.10.0.0/24", Some("test"));
+        test.pool.allocate(4, Some("a")).unwrap();
+        test.pool.allocate(4, None).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not claim subnet\nOverlaps 10.10.0.0/28, 10.10.0.16/28\n");
+        test.load();
+        assert_eq!(test.pool.find_by_name("test"), None);
+    }
+
+    #[test]
+    fn adopt() {
+        let mut test = new_claim_test("10.10.0.0/24", Some("test"));
+        test.subg.arg("--adopt");
+        let a = test.pool.allocate(4, Some("a")).unwrap();
+        test.store();
+        test.subg.assert().success().stdout("").stderr("");
+        test.load();
+        let cidr = test.pool.find_by_name("test").unwrap();
+        assert_eq!(cidr.to_string(), "10.10.0.0/24");
+        assert_eq!(test.pool.find_by_name("a"), None);
+        let child = test.pool.child(&cidr).unwrap();
+        assert_eq!(child.find_by_name("a"), Some(a));
+    }
+
     #[test]
     fn named() {
         let mut test = new_claim_test("10.10.0.0/24", Some("test"));
@@ -250,10 +706,88 @@ mod claim {
     }
 }
 
-mod rename {
+mod reserve {
     use super::*;
     use subnet_garden_core::CidrRecord;
-    fn new_rename_test(identifier: &str, name: Option<&str>) -> Test {
+    fn new_reserve_test(cidr: &str, description: Option<&str>) -> Test {
+        let mut test = fixture::new_test();
+        test.store();
+        test.subg.arg("reserve").arg(cidr);
+        if let Some(description) = description {
+            test.subg.arg(description);
+        }
+        test
+    }
+
+    #[test]
+    fn reserve_failed() {
+        let mut test = new_reserve_test("10.10.0.0/24", None);
+        test.pool.allocate(4, None).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not reserve subnet\nNo space available\n");
+    }
+
+    #[test]
+    fn success() {
+        let mut test = new_reserve_test("10.10.0.0/24", Some("management"));
+        test.subg.assert().success().stdout("").stderr("");
+        test.load();
+        let reserved: Vec<&CidrRecord> = test.pool.reserved().collect();
+        assert_eq!(reserved.len(), 1);
+        assert_eq!(reserved[0].cidr.to_string(), "10.10.0.0/24");
+        assert_eq!(reserved[0].description.as_deref(), Some("management"));
+        assert_eq!(
+            test.pool.allocate(8, None).unwrap().to_string(),
+            "10.10.1.0/24"
+        );
+    }
+}
+
+mod unreserve {
+    use super::*;
+    fn new_unreserve_test(cidr: &str) -> Test {
+        let mut test = fixture::new_test();
+        test.store();
+        test.subg.arg("unreserve").arg(cidr);
+        test
+    }
+
+    #[test]
+    fn unreserve_failed() {
+        let mut test = new_unreserve_test("10.10.0.0/28");
+        test.pool.allocate(4, None).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not unreserve subnet 10.10.0.0/28\n");
+    }
+
+    #[test]
+    fn success() {
+        let mut test = new_unreserve_test("10.10.0.0/24");
+        test.pool
+            .reserve(&"10.10.0.0/24".parse().unwrap(), None)
+            .unwrap();
+        test.store();
+        test.subg.assert().success().stdout("").stderr("");
+        test.load();
+        assert_eq!(test.pool.reserved().count(), 0);
+        assert_eq!(test.pool.max_available_bits(), 16);
+    }
+}
+
+mod rename {
+    use super::*;
+    use subnet_garden_core::CidrRecord;
+    fn new_rename_test(identifier: &str, name: Option<&str>) -> Test {
         let mut test = fixture::new_test();
         test.store();
         test.subg.arg("rename").arg(identifier);
@@ -279,7 +813,7 @@ mod rename {
 
     #[test]
     fn rename_failure() {
-        let mut test = new_rename_test("10.10.0.0/24", Some("test"));
+        let mut test = new_rename_test("10.10.0.16/28", Some("test"));
         test.pool.allocate(4, Some("test")).unwrap();
         test.pool.allocate(4, None).unwrap();
         test.store();
@@ -291,6 +825,20 @@ mod rename {
             .stderr("Could not rename subnet\nDuplicate name\n");
     }
 
+    #[test]
+    fn not_allocated() {
+        let mut test = new_rename_test("10.10.0.0/24", Some("test"));
+        test.pool.allocate(4, Some("test")).unwrap();
+        test.pool.allocate(4, None).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not rename subnet\nNo such object\n");
+    }
+
     #[test]
     fn success_with_name() {
         let mut test = new_rename_test("test", Some("test2"));
@@ -304,6 +852,51 @@ mod rename {
         assert_eq!(subnets[0].cidr.to_string(), "10.10.0.0/28");
     }
 
+    #[test]
+    fn selector() {
+        let mut test = new_rename_test("re:^te", Some("test2"));
+        test.pool.allocate(4, Some("test")).unwrap();
+        test.pool.allocate(4, Some("other")).unwrap();
+        test.store();
+        test.subg.assert().success().stdout("").stderr("");
+        test.load();
+        assert_eq!(
+            test.pool.find_by_name("test2").unwrap().to_string(),
+            "10.10.0.0/28"
+        );
+    }
+
+    #[test]
+    fn selector_no_match() {
+        let mut test = new_rename_test("glob:lab-*", Some("test2"));
+        test.pool.allocate(4, Some("test")).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("No subnet matches glob:lab-*\n");
+    }
+
+    #[test]
+    fn selector_ambiguous() {
+        let mut test = new_rename_test("within:10.10.0.0/24", Some("test2"));
+        test.pool.allocate(4, Some("test")).unwrap();
+        test.pool.allocate(4, None).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr(
+                "within:10.10.0.0/24 matches 2 subnets\n  \
+                 10.10.0.0/28  test\n  \
+                 10.10.0.16/28\n",
+            );
+    }
+
     #[test]
     fn success_with_cidr() {
         let mut test = new_rename_test("10.10.0.0/28", Some("test2"));
@@ -318,6 +911,448 @@ mod rename {
     }
 }
 
+mod make_pool {
+    use super::*;
+
+    fn new_make_pool_test(identifier: &str) -> Test {
+        let mut test = fixture::new_test();
+        test.store();
+        test.subg.arg("make-pool").arg(identifier);
+        test
+    }
+
+    #[test]
+    fn not_allocated() {
+        let mut test = new_make_pool_test("10.10.0.0/24");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not make pool\nNo such object\n");
+    }
+
+    #[test]
+    fn already_pool() {
+        let mut test = new_make_pool_test("region");
+        let cidr = test.pool.allocate(8, Some("region")).unwrap();
+        test.pool.make_child(&cidr).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not make pool\nDuplicate object\n");
+    }
+
+    #[test]
+    fn success() {
+        let mut test = new_make_pool_test("region");
+        let cidr = test.pool.allocate(8, Some("region")).unwrap();
+        test.store();
+        test.subg.assert().success().stdout("").stderr("");
+        test.load();
+        assert_eq!(test.pool.child(&cidr).unwrap().cidr(), &cidr);
+    }
+}
+
+mod resize {
+    use super::*;
+
+    fn new_resize_test(identifier: &str, size: &str) -> Test {
+        let mut test = fixture::new_test();
+        test.pool.allocate(8, Some("web")).unwrap();
+        test.store();
+        test.subg.arg("resize").arg(identifier).arg(size);
+        test
+    }
+
+    #[test]
+    fn grow() {
+        let mut test = new_resize_test("web", "/22");
+        test.subg
+            .assert()
+            .success()
+            .stdout("Resized subnet 10.10.0.0/24 to 10.10.0.0/22\n")
+            .stderr("");
+        test.load();
+        assert_eq!(
+            test.pool.find_by_name("web").unwrap().to_string(),
+            "10.10.0.0/22"
+        );
+    }
+
+    #[test]
+    fn grow_occupied() {
+        let mut test = new_resize_test("web", "10");
+        test.pool.allocate(8, Some("db")).unwrap();
+        test.pool
+            .reserve(&"10.10.3.0/24".parse().unwrap(), None)
+            .unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr(
+                "Could not resize subnet web\n\
+                 2 subnets in the way\n  \
+                 10.10.1.0/24  db\n  \
+                 10.10.3.0/24  [reserved]\n",
+            );
+    }
+
+    #[test]
+    fn shrink() {
+        let mut test = new_resize_test("10.10.0.0/24", "6");
+        test.subg
+            .assert()
+            .success()
+            .stdout("Resized subnet 10.10.0.0/24 to 10.10.0.0/26\n")
+            .stderr("");
+        test.load();
+        assert_eq!(
+            test.pool.find_by_name("web").unwrap().to_string(),
+            "10.10.0.0/26"
+        );
+    }
+
+    #[test]
+    fn not_allocated() {
+        let mut test = new_resize_test("10.10.1.0/24", "9");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not resize subnet 10.10.1.0/24\nNo such object\n");
+    }
+
+    #[test]
+    fn too_large() {
+        let mut test = new_resize_test("web", "/8");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not resize subnet web\nNo space available\n");
+    }
+}
+
+mod split {
+    use super::*;
+
+    fn new_split_test(args: &[&str]) -> Test {
+        let mut test = fixture::new_test();
+        test.pool.allocate(10, Some("block")).unwrap();
+        test.store();
+        test.subg.arg("split").args(args);
+        test
+    }
+
+    #[test]
+    fn named() {
+        let mut test = new_split_test(&["block", "/24", "web-{}", "%0..4"]);
+        test.subg
+            .assert()
+            .success()
+            .stdout(
+                "10.10.0.0/24  web-0\n\
+                 10.10.1.0/24  web-1\n\
+                 10.10.2.0/24  web-2\n\
+                 10.10.3.0/24  web-3\n",
+            )
+            .stderr("");
+        test.load();
+        assert_eq!(test.pool.allocated_count(), 4);
+        assert_eq!(test.pool.find_by_name("block"), None);
+        assert_eq!(
+            test.pool.find_by_name("web-2").unwrap().to_string(),
+            "10.10.2.0/24"
+        );
+    }
+
+    #[test]
+    fn unnamed() {
+        let mut test = new_split_test(&["10.10.0.0/22", "9"]);
+        test.subg
+            .assert()
+            .success()
+            .stdout("10.10.0.0/23\n10.10.2.0/23\n")
+            .stderr("");
+        test.load();
+        assert_eq!(test.pool.named_count(), 0);
+    }
+
+    #[test]
+    fn name_count() {
+        let mut test = new_split_test(&["block", "8", "web-{}", "%0..2"]);
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not split subnet block\nExpected 4 names\n");
+    }
+}
+
+mod merge {
+    use super::*;
+
+    fn new_merge_test(args: &[&str]) -> Test {
+        let mut test = fixture::new_test();
+        test.pool.allocate(8, Some("a")).unwrap();
+        test.pool.allocate(8, Some("b")).unwrap();
+        test.store();
+        test.subg.arg("merge").args(args);
+        test
+    }
+
+    #[test]
+    fn success() {
+        let mut test = new_merge_test(&["a", "b", "--name", "ab"]);
+        test.subg
+            .assert()
+            .success()
+            .stdout("10.10.0.0/23\n")
+            .stderr("");
+        test.load();
+        assert_eq!(test.pool.allocated_count(), 1);
+        assert_eq!(
+            test.pool.find_by_name("ab").unwrap().to_string(),
+            "10.10.0.0/23"
+        );
+    }
+
+    #[test]
+    fn not_allocated() {
+        let mut test = new_merge_test(&["b"]);
+        test.subg.arg("10.10.2.0/24");
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not merge subnets\nNo such object\n");
+    }
+
+    #[test]
+    fn not_aligned() {
+        let mut test = new_merge_test(&["b", "10.10.2.0/24"]);
+        test.pool.allocate(8, Some("c")).unwrap();
+        test.store();
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not merge subnets\nSubnets do not fill one aligned block\n");
+    }
+}
+
+mod parent {
+    use super::*;
+    use cidr::IpCidr;
+
+    fn new_parent_test() -> (Test, IpCidr) {
+        let mut test = fixture::new_test();
+        let cidr = test.pool.allocate(8, Some("region")).unwrap();
+        test.pool.make_child(&cidr).unwrap();
+        test.store();
+        (test, cidr)
+    }
+
+    #[test]
+    fn allocate() {
+        let (mut test, cidr) = new_parent_test();
+        test.subg
+            .args(["allocate", "--parent", "region", "4", "web"])
+            .assert()
+            .success()
+            .stdout("10.10.0.0/28  16 usable hosts\n")
+            .stderr("");
+        test.load();
+        let child = test.pool.child(&cidr).unwrap();
+        assert_eq!(
+            child.find_by_name("web"),
+            Some("10.10.0.0/28".parse().unwrap())
+        );
+        assert_eq!(test.pool.find_by_name("web"), None);
+        assert_eq!(test.pool.allocated_count(), 1);
+    }
+
+    #[test]
+    fn nested() {
+        let (mut test, cidr) = new_parent_test();
+        let child = test.pool.child_mut(&cidr).unwrap();
+        let zone = child.allocate(6, Some("zone")).unwrap();
+        child.make_child(&zone).unwrap();
+        test.store();
+        test.subg
+            .args([
+                "--parent", "region", "--parent", "zone", "allocate", "4", "web",
+            ])
+            .assert()
+            .success();
+        test.load();
+        let zone_pool = test.pool.child(&cidr).unwrap().child(&zone).unwrap();
+        assert!(zone_pool.find_by_name("web").is_some());
+    }
+
+    #[test]
+    fn names() {
+        let (mut test, cidr) = new_parent_test();
+        test.pool
+            .child_mut(&cidr)
+            .unwrap()
+            .allocate(4, Some("web"))
+            .unwrap();
+        test.store();
+        test.subg
+            .args(["names", "--parent", "10.10.0.0/24"])
+            .assert()
+            .success()
+            .stdout("web\n")
+            .stderr("");
+    }
+
+    #[test]
+    fn not_a_pool() {
+        let mut test = fixture::new_test();
+        test.pool.allocate(8, Some("region")).unwrap();
+        test.store();
+        test.subg
+            .args(["cidrs", "--parent", "region"])
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Subnet region is not a pool\n");
+    }
+}
+
+mod capacity {
+    use super::*;
+
+    fn new_capacity_test(args: &[&str]) -> Test {
+        let mut test = fixture::new_test();
+        test.pool.allocate(15, None).unwrap();
+        test.pool.allocate(8, None).unwrap();
+        test.store();
+        test.subg.arg("capacity").args(args);
+        test
+    }
+
+    #[test]
+    fn size() {
+        let mut test = new_capacity_test(&["8"]);
+        test.subg.assert().success().stdout("127\n").stderr("");
+    }
+
+    #[test]
+    fn prefix_length() {
+        let mut test = new_capacity_test(&["/18"]);
+        test.subg.assert().success().stdout("1\n").stderr("");
+    }
+
+    #[test]
+    fn request_fits() {
+        let mut test = new_capacity_test(&["--request", "10x8", "--request", "1x/18"]);
+        test.subg
+            .assert()
+            .success()
+            .stdout("Request fits\n")
+            .stderr("");
+    }
+
+    #[test]
+    fn request_does_not_fit() {
+        let mut test = new_capacity_test(&["--request", "64x8", "--request", "1x/18"]);
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Request does not fit\n");
+    }
+
+    fn new_dual_capacity_test(args: &[&str]) -> Test {
+        let mut test = new_capacity_test(args);
+        test.pool.add_root("fc00::/112".parse().unwrap()).unwrap();
+        test.store();
+        test
+    }
+
+    #[test]
+    fn prefix_length_per_family() {
+        let mut test = new_dual_capacity_test(&["/24"]);
+        test.subg.assert().success().stdout("127\n").stderr("");
+        let mut test = new_dual_capacity_test(&["/120"]);
+        test.subg.assert().success().stdout("256\n").stderr("");
+    }
+
+    #[test]
+    fn size_in_root() {
+        let mut test = new_dual_capacity_test(&["8"]);
+        test.subg.assert().success().stdout("383\n").stderr("");
+        let mut test = new_dual_capacity_test(&["--root", "ipv6", "8"]);
+        test.subg.assert().success().stdout("256\n").stderr("");
+    }
+
+    #[test]
+    fn request_per_family() {
+        let mut test = new_dual_capacity_test(&["--request", "127x/24", "--request", "256x/120"]);
+        test.subg
+            .assert()
+            .success()
+            .stdout("Request fits\n")
+            .stderr("");
+        let mut test = new_dual_capacity_test(&["--request", "128x/24"]);
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Request does not fit\n");
+    }
+
+    #[test]
+    fn missing_root() {
+        let mut test = new_capacity_test(&["--root", "ipv6", "8"]);
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::SOFTWARE)
+            .stdout("")
+            .stderr("Could not compute capacity\nNo such root\n");
+    }
+
+    #[test]
+    fn prefix_length_too_long() {
+        let mut test = new_capacity_test(&["/33"]);
+        test.subg
+            .assert()
+            .failure()
+            .code(exitcode::USAGE)
+            .stdout("")
+            .stderr("Could not parse subnet size\nNo space available\n");
+    }
+
+    #[test]
+    fn invalid_request() {
+        let mut test = new_capacity_test(&["--request", "10"]);
+        test.subg
+            .assert()
+            .failure()
+            .code(2)
+            .stdout("")
+            .stderr(predicates::str::contains("Invalid size request: 10"));
+    }
+}
+
 mod max_available {
     use super::*;
     fn new_max_available_test() -> Test {
@@ -0,0 +1,113 @@
+// Copyright 2024 The Milton Hirsch Institute, B.V.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Allocate, free, claim and load in an IPv6 pool holding many /64 subnets.
+
+use cidr::{IpCidr, Ipv6Cidr};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::net::Ipv6Addr;
+use std::time::{Duration, Instant};
+use subnet_garden_core::pool::SubnetPool;
+
+const SIZES: [usize; 2] = [100_000, 1_000_000];
+
+const SUBNET_BITS: u8 = 64;
+
+fn pool_cidr() -> IpCidr {
+    "2001:db8::/32".parse().unwrap()
+}
+
+fn filled_pool(count: usize) -> SubnetPool {
+    let mut pool = SubnetPool::new(pool_cidr());
+    for _ in 0..count {
+        pool.allocate(SUBNET_BITS, None).unwrap();
+    }
+    pool
+}
+
+/// The nth /64 of the high half of the pool, which allocations in a filled
+/// pool never reach.
+fn high_subnet(index: u64) -> IpCidr {
+    let address = u128::from(Ipv6Addr::new(0x2001, 0xdb8, 0x8000, 0, 0, 0, 0, 0));
+    let address = Ipv6Addr::from(address + (u128::from(index) << SUBNET_BITS));
+    IpCidr::V6(Ipv6Cidr::new(address, 64).unwrap())
+}
+
+fn allocate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("allocate");
+    for count in SIZES {
+        let mut pool = filled_pool(count);
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.iter_custom(|iters| {
+                let start = Instant::now();
+                let cidrs: Vec<IpCidr> = (0..iters)
+                    .map(|_| pool.allocate(SUBNET_BITS, None).unwrap())
+                    .collect();
+                let elapsed = start.elapsed();
+                for cidr in cidrs {
+                    assert!(pool.free(&cidr));
+                }
+                elapsed
+            })
+        });
+    }
+    group.finish();
+}
+
+fn free(c: &mut Criterion) {
+    let mut group = c.benchmark_group("free");
+    for count in SIZES {
+        let mut pool = filled_pool(count);
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.iter_custom(|iters| {
+                let cidrs: Vec<IpCidr> = (0..iters)
+                    .map(|_| pool.allocate(SUBNET_BITS, None).unwrap())
+                    .collect();
+                let start = Instant::now();
+                for cidr in cidrs {
+                    assert!(pool.free(&cidr));
+                }
+                start.elapsed()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn claim(c: &mut Criterion) {
+    let mut group = c.benchmark_group("claim");
+    for count in SIZES {
+        let mut pool = filled_pool(count);
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.iter_custom(|iters| {
+                let cidrs: Vec<IpCidr> = (0..iters).map(high_subnet).collect();
+                let start = Instant::now();
+                for cidr in cidrs.iter() {
+                    pool.claim(cidr, None).unwrap();
+                }
+                let elapsed = start.elapsed();
+                for cidr in cidrs.iter() {
+                    assert!(pool.free(cidr));
+                }
+                elapsed
+            })
+        });
+    }
+    group.finish();
+}
+
+fn load(c: &mut Criterion) {
+    let mut group = c.benchmark_group("load");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(30));
+    for count in SIZES {
+        let stored = serde_json::to_string(&filled_pool(count)).unwrap();
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.iter(|| serde_json::from_str::<SubnetPool>(&stored).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, allocate, free, claim, load);
+criterion_main!(benches);
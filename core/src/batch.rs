@@ -0,0 +1,300 @@
+// Copyright 2024 The Milton Hirsch Institute, B.V.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::errors::BatchError;
+use crate::pool::SubnetPool;
+use crate::{AllocationStrategy, BatchResult, Bits, RootSelector};
+use cidr::IpCidr;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operation {
+    Allocate {
+        bits: Bits,
+        name: Option<String>,
+        strategy: Option<AllocationStrategy>,
+        root: RootSelector,
+    },
+    AllocatePrefix {
+        network_length: Bits,
+        name: Option<String>,
+        strategy: Option<AllocationStrategy>,
+        root: RootSelector,
+    },
+    AllocateForHosts {
+        count: u128,
+        reserved: u128,
+        name: Option<String>,
+        strategy: Option<AllocationStrategy>,
+        root: RootSelector,
+    },
+    AllocateDual {
+        v4_bits: Bits,
+        v6_bits: Bits,
+        name: Option<String>,
+        strategy: Option<AllocationStrategy>,
+    },
+    Claim {
+        cidr: IpCidr,
+        name: Option<String>,
+    },
+    Free {
+        cidr: IpCidr,
+        force: bool,
+    },
+    Rename {
+        cidr: IpCidr,
+        name: Option<String>,
+    },
+}
+
+/// Set of pool changes that are applied all together or not at all.
+///
+/// Operations are applied in the order they are staged, so later operations
+/// see the effects of earlier ones.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PoolBatch {
+    operations: Vec<Operation>,
+}
+
+impl PoolBatch {
+    pub fn new() -> Self {
+        PoolBatch::default()
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    pub fn allocate(&mut self, bits: Bits, name: Option<&str>) -> &mut Self {
+        self.push_allocate(bits, name, None, RootSelector::Any)
+    }
+
+    pub fn allocate_with_strategy(
+        &mut self,
+        bits: Bits,
+        name: Option<&str>,
+        strategy: AllocationStrategy,
+    ) -> &mut Self {
+        self.push_allocate(bits, name, Some(strategy), RootSelector::Any)
+    }
+
+    pub fn allocate_in(
+        &mut self,
+        bits: Bits,
+        name: Option<&str>,
+        strategy: AllocationStrategy,
+        root: RootSelector,
+    ) -> &mut Self {
+        self.push_allocate(bits, name, Some(strategy), root)
+    }
+
+    fn push_allocate(
+        &mut self,
+        bits: Bits,
+        name: Option<&str>,
+        strategy: Option<AllocationStrategy>,
+        root: RootSelector,
+    ) -> &mut Self {
+        self.operations.push(Operation::Allocate {
+            bits,
+            name: name.map(|name| name.to_string()),
+            strategy,
+            root,
+        });
+        self
+    }
+
+    /// Allocate a subnet by prefix length, in a root of an address family
+    /// the prefix length fits.
+    pub fn allocate_prefix_in(
+        &mut self,
+        network_length: Bits,
+        name: Option<&str>,
+        strategy: Option<AllocationStrategy>,
+        root: RootSelector,
+    ) -> &mut Self {
+        self.operations.push(Operation::AllocatePrefix {
+            network_length,
+            name: name.map(|name| name.to_string()),
+            strategy,
+            root,
+        });
+        self
+    }
+
+    /// Allocate the smallest subnet with at least `count` usable hosts once
+    /// `reserved` addresses per subnet are set aside.
+    pub fn allocate_for_hosts_in(
+        &mut self,
+        count: u128,
+        reserved: u128,
+        name: Option<&str>,
+        strategy: Option<AllocationStrategy>,
+        root: RootSelector,
+    ) -> &mut Self {
+        self.operations.push(Operation::AllocateForHosts {
+            count,
+            reserved,
+            name: name.map(|name| name.to_string()),
+            strategy,
+            root,
+        });
+        self
+    }
+
+    /// Allocate an IPv4 and an IPv6 subnet under one name.
+    ///
+    /// The IPv4 subnet is reported as the CIDR the operation acted on.
+    pub fn allocate_dual(
+        &mut self,
+        v4_bits: Bits,
+        v6_bits: Bits,
+        name: Option<&str>,
+        strategy: Option<AllocationStrategy>,
+    ) -> &mut Self {
+        self.operations.push(Operation::AllocateDual {
+            v4_bits,
+            v6_bits,
+            name: name.map(|name| name.to_string()),
+            strategy,
+        });
+        self
+    }
+
+    pub fn claim(&mut self, cidr: &IpCidr, name: Option<&str>) -> &mut Self {
+        self.operations.push(Operation::Claim {
+            cidr: *cidr,
+            name: name.map(|name| name.to_string()),
+        });
+        self
+    }
+
+    pub fn free(&mut self, cidr: &IpCidr) -> &mut Self {
+        self.push_free(cidr, false)
+    }
+
+    /// Free a subnet even if it holds a child pool with live allocations.
+    pub fn free_forced(&mut self, cidr: &IpCidr) -> &mut Self {
+        self.push_free(cidr, true)
+    }
+
+    fn push_free(&mut self, cidr: &IpCidr, force: bool) -> &mut Self {
+        self.operations.push(Operation::Free { cidr: *cidr, force });
+        self
+    }
+
+    pub fn rename(&mut self, cidr: &IpCidr, name: Option<&str>) -> &mut Self {
+        self.operations.push(Operation::Rename {
+            cidr: *cidr,
+            name: name.map(|name| name.to_string()),
+        });
+        self
+    }
+
+    /// Apply every operation to a pool, returning the CIDR each one acted on.
+    pub(crate) fn apply_to(&self, pool: &mut SubnetPool) -> BatchResult<Vec<IpCidr>> {
+        let mut cidrs = Vec::with_capacity(self.operations.len());
+        for (index, operation) in self.operations.iter().enumerate() {
+            let cidr = match operation {
+                Operation::Allocate {
+                    bits,
+                    name,
+                    strategy,
+                    root,
+                } => {
+                    let strategy = strategy.unwrap_or(pool.strategy());
+                    pool.allocate_in(*bits, name.as_deref(), strategy, *root)
+                        .map_err(|error| BatchError::Allocate { index, error })?
+                }
+                Operation::AllocatePrefix {
+                    network_length,
+                    name,
+                    strategy,
+                    root,
+                } => {
+                    let strategy = strategy.unwrap_or(pool.strategy());
+                    pool.allocate_prefix_in(*network_length, name.as_deref(), strategy, *root)
+                        .map_err(|error| BatchError::Allocate { index, error })?
+                }
+                Operation::AllocateForHosts {
+                    count,
+                    reserved,
+                    name,
+                    strategy,
+                    root,
+                } => {
+                    let strategy = strategy.unwrap_or(pool.strategy());
+                    pool.allocate_for_hosts_in(*count, *reserved, name.as_deref(), strategy, *root)
+                        .map_err(|error| BatchError::Allocate { index, error })?
+                }
+                Operation::AllocateDual {
+                    v4_bits,
+                    v6_bits,
+                    name,
+                    strategy,
+                } => {
+                    let strategy = strategy.unwrap_or(pool.strategy());
+                    let (v4, _) = pool
+                        .allocate_dual_with_strategy(*v4_bits, *v6_bits, name.as_deref(), strategy)
+                        .map_err(|error| BatchError::Allocate { index, error })?;
+                    v4
+                }
+                Operation::Claim { cidr, name } => {
+                    pool.claim(cidr, name.as_deref())
+                        .map_err(|error| BatchError::Claim { index, error })?;
+                    *cidr
+                }
+                Operation::Free { cidr, force } => {
+                    pool.free_with(cidr, *force)
+                        .map_err(|error| BatchError::Free { index, error })?;
+                    *cidr
+                }
+                Operation::Rename { cidr, name } => {
+                    pool.rename(cidr, name.as_deref())
+                        .map_err(|error| BatchError::Rename { index, error })?;
+                    *cidr
+                }
+            };
+            cidrs.push(cidr);
+        }
+        Ok(cidrs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new() {
+        let batch = PoolBatch::new();
+        assert!(batch.is_empty());
+        assert_eq!(batch.len(), 0);
+    }
+
+    #[test]
+    fn staged_in_order() {
+        let cidr: IpCidr = "10.20.0.0/28".parse().unwrap();
+        let mut batch = PoolBatch::new();
+        batch.allocate(4, Some("a-name")).free(&cidr);
+        assert_eq!(batch.len(), 2);
+        assert_eq!(
+            batch.operations,
+            vec![
+                Operation::Allocate {
+                    bits: 4,
+                    name: Some("a-name".to_string()),
+                    strategy: None,
+                    root: RootSelector::Any,
+                },
+                Operation::Free { cidr, force: false },
+            ]
+        );
+    }
+}
@@ -1,17 +1,23 @@
 // Copyright 2023 The Milton Hirsch Institute, B.V.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::{Bits, CidrRecord};
+use cidr::IpCidr;
 use std::error::Error;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum CreateError {
     DuplicateObject,
+    NoSuchObject,
+    Overlap,
 }
 
 impl std::fmt::Display for CreateError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             CreateError::DuplicateObject => write!(f, "Duplicate object"),
+            CreateError::NoSuchObject => write!(f, "No such object"),
+            CreateError::Overlap => write!(f, "Overlapping object"),
         }
     }
 }
@@ -25,12 +31,18 @@ impl Error for CreateError {
 #[derive(Debug, Clone, PartialEq)]
 pub enum DeleteError {
     NoSuchObject,
+    HasChildren,
+    NotEmpty,
+    LastRoot,
 }
 
 impl std::fmt::Display for DeleteError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             DeleteError::NoSuchObject => write!(f, "No such object"),
+            DeleteError::HasChildren => write!(f, "Subnet has child allocations"),
+            DeleteError::NotEmpty => write!(f, "Not empty"),
+            DeleteError::LastRoot => write!(f, "Cannot remove the last root"),
         }
     }
 }
@@ -45,6 +57,7 @@ impl Error for DeleteError {
 pub enum AllocateError {
     DuplicateName,
     NoSpaceAvailable,
+    NoSuchRoot,
 }
 
 impl std::fmt::Display for AllocateError {
@@ -52,6 +65,7 @@ impl std::fmt::Display for AllocateError {
         match self {
             AllocateError::DuplicateName => write!(f, "Duplicate name"),
             AllocateError::NoSpaceAvailable => write!(f, "No space available"),
+            AllocateError::NoSuchRoot => write!(f, "No such root"),
         }
     }
 }
@@ -62,6 +76,35 @@ impl Error for AllocateError {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClaimError {
+    DuplicateName,
+    NoSpaceAvailable,
+    Conflict(Vec<CidrRecord>),
+}
+
+impl std::fmt::Display for ClaimError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ClaimError::DuplicateName => write!(f, "Duplicate name"),
+            ClaimError::NoSpaceAvailable => write!(f, "No space available"),
+            ClaimError::Conflict(records) => {
+                let cidrs: Vec<String> = records
+                    .iter()
+                    .map(|record| record.cidr.to_string())
+                    .collect();
+                write!(f, "Overlaps {}", cidrs.join(", "))
+            }
+        }
+    }
+}
+
+impl Error for ClaimError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum RenameError {
     DuplicateName,
@@ -82,6 +125,476 @@ impl Error for RenameError {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum UpdateError {
+    NoSuchObject,
+}
+
+impl std::fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            UpdateError::NoSuchObject => write!(f, "No such object"),
+        }
+    }
+}
+
+impl Error for UpdateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResizeError {
+    NoSuchObject,
+    NoSuchRoot,
+    NoSpaceAvailable,
+    InvalidSize,
+    Overlap,
+    Blocked(Vec<IpCidr>),
+    Occupied(Vec<IpCidr>),
+}
+
+impl std::fmt::Display for ResizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ResizeError::NoSuchObject => write!(f, "No such object"),
+            ResizeError::NoSuchRoot => write!(f, "No such root"),
+            ResizeError::NoSpaceAvailable => write!(f, "No space available"),
+            ResizeError::InvalidSize => write!(f, "Invalid subnet size"),
+            ResizeError::Overlap => write!(f, "Overlapping object"),
+            ResizeError::Blocked(cidrs) => {
+                write!(f, "{} subnets outside the new root", cidrs.len())
+            }
+            ResizeError::Occupied(cidrs) => {
+                write!(f, "{} subnets in the way", cidrs.len())
+            }
+        }
+    }
+}
+
+impl Error for ResizeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SplitError {
+    NoSuchObject,
+    InvalidSize,
+    NameCount(usize),
+    DuplicateName,
+    HasChildren,
+    Paired,
+    /// The split would create more than this many subnets
+    TooMany(usize),
+}
+
+impl std::fmt::Display for SplitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SplitError::NoSuchObject => write!(f, "No such object"),
+            SplitError::InvalidSize => write!(f, "Invalid subnet size"),
+            SplitError::NameCount(count) => write!(f, "Expected {} names", count),
+            SplitError::DuplicateName => write!(f, "Duplicate name"),
+            SplitError::HasChildren => write!(f, "Subnet has a child pool"),
+            SplitError::Paired => write!(f, "Subnet is paired"),
+            SplitError::TooMany(max) => write!(f, "Cannot split into more than {} subnets", max),
+        }
+    }
+}
+
+impl Error for SplitError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeError {
+    NoSuchObject,
+    NotOneBlock,
+    DuplicateName,
+    HasChildren,
+    Paired,
+}
+
+impl std::fmt::Display for MergeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MergeError::NoSuchObject => write!(f, "No such object"),
+            MergeError::NotOneBlock => write!(f, "Subnets do not fill one aligned block"),
+            MergeError::DuplicateName => write!(f, "Duplicate name"),
+            MergeError::HasChildren => write!(f, "Subnet has a child pool"),
+            MergeError::Paired => write!(f, "Subnet is paired"),
+        }
+    }
+}
+
+impl Error for MergeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostError {
+    NoSuchObject,
+    NoSuchHost,
+    OutOfRange,
+    DuplicateHost,
+    DuplicateName,
+    NoSpaceAvailable,
+}
+
+impl std::fmt::Display for HostError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            HostError::NoSuchObject => write!(f, "No such object"),
+            HostError::NoSuchHost => write!(f, "No such host"),
+            HostError::OutOfRange => write!(f, "Address is outside the subnet"),
+            HostError::DuplicateHost => write!(f, "Duplicate host"),
+            HostError::DuplicateName => write!(f, "Duplicate name"),
+            HostError::NoSpaceAvailable => write!(f, "No space available"),
+        }
+    }
+}
+
+impl Error for HostError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+/// Record of a stored pool, by its position in the pool file
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredRecord {
+    /// Position within the subnets, or within the reserved subnets
+    pub index: usize,
+    pub reserved: bool,
+    pub cidr: IpCidr,
+    pub name: Option<String>,
+}
+
+impl std::fmt::Display for StoredRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{} ({})", self.cidr, name),
+            None => write!(f, "{}", self.cidr),
+        }
+    }
+}
+
+/// Why one record of a stored pool was rejected
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordError {
+    OutsideRoots,
+    Overlap(StoredRecord),
+    /// Name is already used by the given record
+    DuplicateName(StoredRecord),
+    /// Pair does not point back at this record
+    UnmatchedPair(IpCidr),
+}
+
+impl RecordError {
+    /// Other record that this one conflicts with
+    pub fn conflict(&self) -> Option<&StoredRecord> {
+        match self {
+            RecordError::Overlap(record) | RecordError::DuplicateName(record) => Some(record),
+            RecordError::OutsideRoots | RecordError::UnmatchedPair(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RecordError::OutsideRoots => write!(f, "Outside every root"),
+            RecordError::Overlap(record) => write!(f, "Overlaps {}", record),
+            RecordError::DuplicateName(record) => write!(f, "Name already used by {}", record),
+            RecordError::UnmatchedPair(pair) => write!(f, "Unmatched pair {}", pair),
+        }
+    }
+}
+
+impl Error for RecordError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+/// Record of a stored pool that could not be loaded
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidRecord {
+    pub record: StoredRecord,
+    pub error: RecordError,
+}
+
+impl std::fmt::Display for InvalidRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", self.record, self.error)
+    }
+}
+
+impl Error for InvalidRecord {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadError {
+    NoRoots,
+    Root(CreateError),
+    InvalidRecords(Vec<InvalidRecord>),
+    /// Stored at a version newer than the supported one
+    NewerVersion {
+        version: u32,
+        supported: u32,
+    },
+    /// Stored at a version older than the first one
+    InvalidVersion(u32),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LoadError::NoRoots => write!(f, "No root CIDR"),
+            LoadError::Root(error) => write!(f, "{}", error),
+            LoadError::NewerVersion { version, supported } => write!(
+                f,
+                "Pool file version {} is newer than the supported version {}",
+                version, supported
+            ),
+            LoadError::InvalidVersion(version) => write!(
+                f,
+                "Pool file version {} is not valid, versions start at {}",
+                version,
+                crate::schema::UNVERSIONED
+            ),
+            LoadError::InvalidRecords(records) => {
+                let records: Vec<String> =
+                    records.iter().map(|record| record.to_string()).collect();
+                write!(f, "{}", records.join("\n"))
+            }
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Root(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Derived state of a pool that does not match its subnets
+#[derive(Debug, Clone, PartialEq)]
+pub enum InvariantError {
+    /// Cached number of allocated subnets under a node
+    AllocatedCount {
+        cidr: IpCidr,
+        cached: usize,
+        actual: usize,
+    },
+    /// Cached size of the largest free block under a node
+    MaxAvailableBits {
+        cidr: IpCidr,
+        cached: Bits,
+        actual: Bits,
+    },
+    /// Node that is not where the tree shape allows it
+    MalformedNode(IpCidr),
+    /// Name index entry for a subnet that does not carry the name
+    StaleName { name: String, cidr: IpCidr },
+    /// Named subnet that is missing from the name index
+    UnindexedName { name: String, cidr: IpCidr },
+    /// Name carried by more than one subnet
+    DuplicateName { name: String, cidr: IpCidr },
+    /// Pair does not point back at the subnet
+    UnmatchedPair { cidr: IpCidr, pair: IpCidr },
+    /// Stored pair that is free to point back at the subnet but does not,
+    /// or that has another name
+    OneSidedPair { cidr: IpCidr, pair: IpCidr },
+    /// Child pool whose root is not its subnet
+    MismatchedChild(IpCidr),
+    /// Problem within the child pool of a subnet
+    Child {
+        cidr: IpCidr,
+        error: Box<InvariantError>,
+    },
+}
+
+impl InvariantError {
+    /// Whether the problem is in derived state that can be rebuilt from the
+    /// subnets
+    pub fn is_repairable(&self) -> bool {
+        match self {
+            InvariantError::AllocatedCount { .. }
+            | InvariantError::MaxAvailableBits { .. }
+            | InvariantError::MalformedNode(_)
+            | InvariantError::StaleName { .. }
+            | InvariantError::UnindexedName { .. }
+            | InvariantError::OneSidedPair { .. } => true,
+            InvariantError::DuplicateName { .. }
+            | InvariantError::UnmatchedPair { .. }
+            | InvariantError::MismatchedChild(_) => false,
+            InvariantError::Child { error, .. } => error.is_repairable(),
+        }
+    }
+}
+
+impl std::fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            InvariantError::AllocatedCount {
+                cidr,
+                cached,
+                actual,
+            } => write!(
+                f,
+                "Allocated count of {} is {} but should be {}",
+                cidr, cached, actual
+            ),
+            InvariantError::MaxAvailableBits {
+                cidr,
+                cached,
+                actual,
+            } => write!(
+                f,
+                "Max available bits of {} is {} but should be {}",
+                cidr, cached, actual
+            ),
+            InvariantError::MalformedNode(cidr) => write!(f, "Malformed node {}", cidr),
+            InvariantError::StaleName { name, cidr } => {
+                write!(
+                    f,
+                    "Name {} points at {}, which does not carry it",
+                    name, cidr
+                )
+            }
+            InvariantError::UnindexedName { name, cidr } => {
+                write!(
+                    f,
+                    "Name {} of {} is missing from the name index",
+                    name, cidr
+                )
+            }
+            InvariantError::DuplicateName { name, cidr } => {
+                write!(f, "Name {} of {} is already used", name, cidr)
+            }
+            InvariantError::UnmatchedPair { cidr, pair } => {
+                write!(f, "Pair {} of {} does not point back", pair, cidr)
+            }
+            InvariantError::OneSidedPair { cidr, pair } => {
+                write!(f, "Pair {} of {} does not match it", pair, cidr)
+            }
+            InvariantError::MismatchedChild(cidr) => {
+                write!(f, "Child pool of {} has other roots", cidr)
+            }
+            InvariantError::Child { cidr, error } => {
+                write!(f, "In child pool {}: {}", cidr, error)
+            }
+        }
+    }
+}
+
+impl Error for InvariantError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            InvariantError::Child { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchError {
+    Allocate { index: usize, error: AllocateError },
+    Claim { index: usize, error: ClaimError },
+    Free { index: usize, error: DeleteError },
+    Rename { index: usize, error: RenameError },
+}
+
+impl BatchError {
+    /// Position of the failed operation within the batch
+    pub fn index(&self) -> usize {
+        match self {
+            BatchError::Allocate { index, .. }
+            | BatchError::Claim { index, .. }
+            | BatchError::Free { index, .. }
+            | BatchError::Rename { index, .. } => *index,
+        }
+    }
+}
+
+impl std::fmt::Display for BatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BatchError::Allocate { error, .. } => write!(f, "{}", error),
+            BatchError::Claim { error, .. } => write!(f, "{}", error),
+            BatchError::Free { error, .. } => write!(f, "{}", error),
+            BatchError::Rename { error, .. } => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for BatchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BatchError::Allocate { error, .. } => Some(error),
+            BatchError::Claim { error, .. } => Some(error),
+            BatchError::Free { error, .. } => Some(error),
+            BatchError::Rename { error, .. } => Some(error),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseStrategyError {
+    UnknownStrategy(String),
+}
+
+impl std::fmt::Display for ParseStrategyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ParseStrategyError::UnknownStrategy(name) => {
+                write!(f, "Unknown allocation strategy: {}", name)
+            }
+        }
+    }
+}
+
+impl Error for ParseStrategyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseRootSelectorError {
+    InvalidSelector(String),
+}
+
+impl std::fmt::Display for ParseRootSelectorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ParseRootSelectorError::InvalidSelector(selector) => {
+                write!(f, "Invalid root selector: {}", selector)
+            }
+        }
+    }
+}
+
+impl Error for ParseRootSelectorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -98,4 +611,196 @@ mod tests {
     fn display_delete_error_no_such_object() {
         assert_eq!(format!("{}", DeleteError::NoSuchObject), "No such object");
     }
+
+    #[test]
+    fn display_delete_error_has_children() {
+        assert_eq!(
+            format!("{}", DeleteError::HasChildren),
+            "Subnet has child allocations"
+        );
+    }
+
+    #[test]
+    fn display_delete_error_last_root() {
+        assert_eq!(
+            format!("{}", DeleteError::LastRoot),
+            "Cannot remove the last root"
+        );
+    }
+
+    #[test]
+    fn display_load_error_invalid_records() {
+        let record = |index, cidr: &str, name: Option<&str>| StoredRecord {
+            index,
+            reserved: false,
+            cidr: cidr.parse().unwrap(),
+            name: name.map(|name| name.to_string()),
+        };
+        let error = LoadError::InvalidRecords(vec![
+            InvalidRecord {
+                record: record(1, "10.20.0.0/28", Some("b")),
+                error: RecordError::Overlap(record(0, "10.20.0.0/24", Some("a"))),
+            },
+            InvalidRecord {
+                record: record(2, "10.30.0.0/24", None),
+                error: RecordError::OutsideRoots,
+            },
+        ]);
+        assert_eq!(
+            format!("{}", error),
+            "10.20.0.0/28 (b): Overlaps 10.20.0.0/24 (a)\n10.30.0.0/24: Outside every root"
+        );
+    }
+
+    #[test]
+    fn display_load_error_newer_version() {
+        assert_eq!(
+            format!(
+                "{}",
+                LoadError::NewerVersion {
+                    version: 3,
+                    supported: 2
+                }
+            ),
+            "Pool file version 3 is newer than the supported version 2"
+        );
+    }
+
+    #[test]
+    fn display_load_error_invalid_version() {
+        assert_eq!(
+            format!("{}", LoadError::InvalidVersion(0)),
+            "Pool file version 0 is not valid, versions start at 1"
+        );
+    }
+
+    #[test]
+    fn display_update_error_no_such_object() {
+        assert_eq!(format!("{}", UpdateError::NoSuchObject), "No such object");
+    }
+
+    #[test]
+    fn display_resize_error_blocked() {
+        let cidrs = vec!["10.20.1.0/24".parse().unwrap()];
+        assert_eq!(
+            format!("{}", ResizeError::Blocked(cidrs)),
+            "1 subnets outside the new root"
+        );
+    }
+
+    #[test]
+    fn display_resize_error_occupied() {
+        let cidrs = vec![
+            "10.20.1.0/24".parse().unwrap(),
+            "10.20.2.0/24".parse().unwrap(),
+        ];
+        assert_eq!(
+            format!("{}", ResizeError::Occupied(cidrs)),
+            "2 subnets in the way"
+        );
+    }
+
+    #[test]
+    fn display_claim_error_conflict() {
+        let records = vec![
+            CidrRecord::new("10.20.0.0/24".parse().unwrap(), None),
+            CidrRecord::new("10.20.1.0/28".parse().unwrap(), Some("a-name")),
+        ];
+        assert_eq!(
+            format!("{}", ClaimError::Conflict(records)),
+            "Overlaps 10.20.0.0/24, 10.20.1.0/28"
+        );
+    }
+
+    #[test]
+    fn display_split_error_name_count() {
+        assert_eq!(format!("{}", SplitError::NameCount(4)), "Expected 4 names");
+    }
+
+    #[test]
+    fn display_split_error_too_many() {
+        assert_eq!(
+            format!("{}", SplitError::TooMany(65536)),
+            "Cannot split into more than 65536 subnets"
+        );
+    }
+
+    #[test]
+    fn display_merge_error_not_one_block() {
+        assert_eq!(
+            format!("{}", MergeError::NotOneBlock),
+            "Subnets do not fill one aligned block"
+        );
+    }
+
+    #[test]
+    fn display_host_error_out_of_range() {
+        assert_eq!(
+            format!("{}", HostError::OutOfRange),
+            "Address is outside the subnet"
+        );
+    }
+
+    #[test]
+    fn display_batch_error() {
+        let error = BatchError::Allocate {
+            index: 3,
+            error: AllocateError::NoSpaceAvailable,
+        };
+        assert_eq!(error.index(), 3);
+        assert_eq!(format!("{}", error), "No space available");
+    }
+
+    #[test]
+    fn display_invariant_error() {
+        let cidr: IpCidr = "10.20.0.0/24".parse().unwrap();
+        let error = InvariantError::AllocatedCount {
+            cidr,
+            cached: 2,
+            actual: 1,
+        };
+        assert_eq!(
+            format!("{}", error),
+            "Allocated count of 10.20.0.0/24 is 2 but should be 1"
+        );
+        let nested = InvariantError::Child {
+            cidr,
+            error: Box::new(InvariantError::StaleName {
+                name: "a-name".to_string(),
+                cidr,
+            }),
+        };
+        assert_eq!(
+            format!("{}", nested),
+            "In child pool 10.20.0.0/24: Name a-name points at 10.20.0.0/24, which does not carry it"
+        );
+        assert!(nested.is_repairable());
+        assert!(!InvariantError::MismatchedChild(cidr).is_repairable());
+        let pair: IpCidr = "fc00::/64".parse().unwrap();
+        let one_sided = InvariantError::OneSidedPair { cidr, pair };
+        assert_eq!(
+            format!("{}", one_sided),
+            "Pair fc00::/64 of 10.20.0.0/24 does not match it"
+        );
+        assert!(one_sided.is_repairable());
+    }
+
+    #[test]
+    fn display_parse_strategy_error_unknown_strategy() {
+        assert_eq!(
+            format!("{}", ParseStrategyError::UnknownStrategy("bad".to_string())),
+            "Unknown allocation strategy: bad"
+        );
+    }
+
+    #[test]
+    fn display_parse_root_selector_error_invalid_selector() {
+        assert_eq!(
+            format!(
+                "{}",
+                ParseRootSelectorError::InvalidSelector("bad".to_string())
+            ),
+            "Invalid root selector: bad"
+        );
+    }
 }
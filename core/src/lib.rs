@@ -1,31 +1,249 @@
 // Copyright 2023-2024 The Milton Hirsch Institute, B.V.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::errors::{AllocateError, CreateError, DeleteError, RenameError};
+use crate::errors::{
+    AllocateError, BatchError, ClaimError, CreateError, DeleteError, HostError, LoadError,
+    MergeError, ParseRootSelectorError, ParseStrategyError, RenameError, ResizeError, SplitError,
+    UpdateError,
+};
+use crate::pool::SubnetPool;
 use cidr::IpCidr;
 use serde::de;
 use serde::ser::SerializeStruct;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
 use std::str::FromStr;
 
+pub mod batch;
 pub mod errors;
 pub mod pool;
+pub mod schema;
 mod subspace;
 mod util;
 
+pub use util::{address_value, bits_for_hosts, host_length, usable_hosts};
+
 pub type CreateResult<T> = Result<T, CreateError>;
 
 pub type DeleteResult<T> = Result<T, DeleteError>;
 
 pub type AllocateResult<T> = Result<T, AllocateError>;
 
+pub type ClaimResult<T> = Result<T, ClaimError>;
+
 pub type RenameResult<T> = Result<T, RenameError>;
 
+pub type BatchResult<T> = Result<T, BatchError>;
+
+pub type UpdateResult<T> = Result<T, UpdateError>;
+
+pub type ResizeResult<T> = Result<T, ResizeError>;
+
+pub type SplitResult<T> = Result<T, SplitError>;
+
+pub type MergeResult<T> = Result<T, MergeError>;
+
+pub type HostResult<T> = Result<T, HostError>;
+
+pub type LoadResult<T> = Result<T, LoadError>;
+
 pub type Bits = u8;
 
+/// Where in the pool a new subnet is placed.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum AllocationStrategy {
+    /// Lowest-addressed free block that fits
+    #[default]
+    LowestFirst,
+    /// Highest-addressed free block that fits
+    HighestFirst,
+    /// Smallest free block that fits, lowest-addressed on ties
+    BestFit,
+    /// Largest free block, lowest-addressed on ties
+    WorstFit,
+}
+
+impl AllocationStrategy {
+    const NAMES: [(AllocationStrategy, &'static str); 4] = [
+        (AllocationStrategy::LowestFirst, "lowest-first"),
+        (AllocationStrategy::HighestFirst, "highest-first"),
+        (AllocationStrategy::BestFit, "best-fit"),
+        (AllocationStrategy::WorstFit, "worst-fit"),
+    ];
+}
+
+impl std::fmt::Display for AllocationStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (_, name) = AllocationStrategy::NAMES
+            .iter()
+            .find(|(strategy, _)| strategy == self)
+            .unwrap();
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for AllocationStrategy {
+    type Err = ParseStrategyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        AllocationStrategy::NAMES
+            .iter()
+            .find(|(_, name)| *name == s)
+            .map(|(strategy, _)| *strategy)
+            .ok_or_else(|| ParseStrategyError::UnknownStrategy(s.to_string()))
+    }
+}
+
+/// Size of a requested subnet.
+///
+/// A prefix length needs a different number of host bits in IPv4 and IPv6
+/// roots, so it is resolved against each root it may be placed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestSize {
+    /// Number of host bits
+    HostBits(Bits),
+    /// Network prefix length
+    PrefixLength(Bits),
+}
+
+impl RequestSize {
+    /// Host bits of this size in a root, if the size fits its address
+    /// family.
+    pub fn host_bits(&self, root: &IpCidr) -> Option<Bits> {
+        match self {
+            RequestSize::HostBits(bits) => Some(*bits),
+            RequestSize::PrefixLength(length) => util::max_bits(root).checked_sub(*length),
+        }
+    }
+}
+
+/// Which roots of a pool a new subnet may be placed in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RootSelector {
+    /// Any root, in the order the roots were added
+    #[default]
+    Any,
+    /// Any IPv4 root
+    V4,
+    /// Any IPv6 root
+    V6,
+    /// The root with exactly this CIDR
+    Cidr(IpCidr),
+}
+
+impl RootSelector {
+    pub fn matches(&self, root: &IpCidr) -> bool {
+        match self {
+            RootSelector::Any => true,
+            RootSelector::V4 => root.is_ipv4(),
+            RootSelector::V6 => root.is_ipv6(),
+            RootSelector::Cidr(cidr) => cidr == root,
+        }
+    }
+}
+
+impl std::fmt::Display for RootSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RootSelector::Any => write!(f, "any"),
+            RootSelector::V4 => write!(f, "ipv4"),
+            RootSelector::V6 => write!(f, "ipv6"),
+            RootSelector::Cidr(cidr) => write!(f, "{}", cidr),
+        }
+    }
+}
+
+impl FromStr for RootSelector {
+    type Err = ParseRootSelectorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "any" => Ok(RootSelector::Any),
+            "ipv4" => Ok(RootSelector::V4),
+            "ipv6" => Ok(RootSelector::V6),
+            _ => IpCidr::from_str(s)
+                .map(RootSelector::Cidr)
+                .map_err(|_| ParseRootSelectorError::InvalidSelector(s.to_string())),
+        }
+    }
+}
+
+/// What the block holding an address is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Containing<'a> {
+    Allocated(&'a CidrRecord),
+    Reserved(&'a CidrRecord),
+    /// Largest free block holding the address
+    Free(IpCidr),
+}
+
+impl Containing<'_> {
+    /// CIDR of the block holding the address.
+    pub fn cidr(&self) -> IpCidr {
+        match self {
+            Containing::Allocated(record) | Containing::Reserved(record) => record.cidr,
+            Containing::Free(cidr) => *cidr,
+        }
+    }
+}
+
+/// Number of subnets and addresses allocated at one prefix length.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct PrefixStats {
+    pub subnets: usize,
+    pub addresses: u128,
+}
+
+/// Summary of how full and how fragmented a pool is.
+///
+/// Address counts saturate at `u128::MAX`.
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize)]
+pub struct PoolStats {
+    /// Addresses in all roots
+    pub total: u128,
+    pub allocated: u128,
+    pub reserved: u128,
+    pub free: u128,
+    /// Number of allocated subnets
+    pub subnets: usize,
+    /// Share of all addresses that is allocated, between 0 and 1
+    pub utilization: f64,
+    /// Allocated subnets by prefix length
+    pub prefixes: BTreeMap<Bits, PrefixStats>,
+    /// Number of largest free blocks by prefix length
+    pub free_blocks: BTreeMap<Bits, usize>,
+    /// Share of free addresses outside the largest free block, between 0
+    /// (all free space in one block) and 1
+    pub fragmentation: f64,
+}
+
+pub type Labels = BTreeMap<String, String>;
+
+/// Individual addresses handed out within a subnet, with optional names
+pub type Hosts = BTreeMap<IpAddr, Option<String>>;
+
 #[derive(Debug, Eq, Ord, PartialEq, PartialOrd, Clone)]
 pub struct CidrRecord {
     pub cidr: IpCidr,
     pub name: Option<String>,
+    /// Other half of a paired IPv4 and IPv6 allocation
+    pub pair: Option<IpCidr>,
+    pub description: Option<String>,
+    pub labels: Labels,
+    pub hosts: Hosts,
+    pub(crate) child: Option<Box<SubnetPool>>,
 }
 
 impl CidrRecord {
@@ -33,8 +251,45 @@ impl CidrRecord {
         CidrRecord {
             cidr,
             name: name.map(|name| name.to_string()),
+            pair: None,
+            description: None,
+            labels: Labels::new(),
+            hosts: Hosts::new(),
+            child: None,
         }
     }
+
+    /// Pool that sub-allocates within this subnet, if it was made into one.
+    #[inline(always)]
+    pub fn child(&self) -> Option<&SubnetPool> {
+        self.child.as_deref()
+    }
+}
+
+// Host addresses are written as strings, in address order.
+struct SerializedHosts<'a>(&'a Hosts);
+
+impl serde::Serialize for SerializedHosts<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_map(
+            self.0
+                .iter()
+                .map(|(address, name)| (address.to_string(), name)),
+        )
+    }
+}
+
+fn parse_hosts<E: de::Error>(hosts: BTreeMap<String, Option<String>>) -> Result<Hosts, E> {
+    hosts
+        .into_iter()
+        .map(|(address, name)| match IpAddr::from_str(&address) {
+            Ok(address) => Ok((address, name)),
+            Err(err) => Err(E::custom(err)),
+        })
+        .collect()
 }
 
 impl serde::Serialize for CidrRecord {
@@ -42,11 +297,32 @@ impl serde::Serialize for CidrRecord {
     where
         S: serde::Serializer,
     {
-        let mut structure = serializer.serialize_struct("CidrRecord", 2)?;
+        let len = 2
+            + self.pair.is_some() as usize
+            + self.description.is_some() as usize
+            + !self.labels.is_empty() as usize
+            + !self.hosts.is_empty() as usize
+            + self.child.is_some() as usize;
+        let mut structure = serializer.serialize_struct("CidrRecord", len)?;
         structure.serialize_field("cidr", &self.cidr.to_string())?;
         if let Some(name) = &self.name {
             structure.serialize_field("name", name)?;
         }
+        if let Some(pair) = &self.pair {
+            structure.serialize_field("pair", &pair.to_string())?;
+        }
+        if let Some(description) = &self.description {
+            structure.serialize_field("description", description)?;
+        }
+        if !self.labels.is_empty() {
+            structure.serialize_field("labels", &self.labels)?;
+        }
+        if !self.hosts.is_empty() {
+            structure.serialize_field("hosts", &SerializedHosts(&self.hosts))?;
+        }
+        if let Some(child) = &self.child {
+            structure.serialize_field("pool", child)?;
+        }
         structure.end()
     }
 }
@@ -61,6 +337,21 @@ impl<'s> serde::Deserialize<'s> for CidrRecord {
         enum Field {
             Cidr,
             Name,
+            Pair,
+            Description,
+            Labels,
+            Hosts,
+            Pool,
+        }
+        fn check_child<E: de::Error>(cidr: &IpCidr, child: Option<&SubnetPool>) -> Result<(), E> {
+            match child {
+                Some(child) if child.roots().ne([cidr]) => Err(E::custom(format!(
+                    "Child pool {} does not match subnet {}",
+                    child.cidr(),
+                    cidr
+                ))),
+                _ => Ok(()),
+            }
         }
         struct CidrRecordVisitor;
         impl<'d> de::Visitor<'d> for CidrRecordVisitor {
@@ -85,8 +376,26 @@ impl<'s> serde::Deserialize<'s> for CidrRecord {
                 let name = seq
                     .next_element::<Option<&str>>()?
                     .ok_or_else(|| serde::de::Error::missing_field("name"))?;
+                let pair = match seq.next_element::<Option<&str>>()?.flatten() {
+                    Some(pair) => Some(IpCidr::from_str(pair).map_err(de::Error::custom)?),
+                    None => None,
+                };
+                let description = seq.next_element::<Option<String>>()?.flatten();
+                let labels = seq.next_element::<Labels>()?.unwrap_or_default();
+                let hosts = match seq.next_element()? {
+                    Some(hosts) => parse_hosts(hosts)?,
+                    None => Hosts::new(),
+                };
+                let child = seq.next_element::<SubnetPool>()?.map(Box::new);
+                check_child(&cidr, child.as_deref())?;
 
-                Ok(CidrRecord::new(cidr, name))
+                let mut record = CidrRecord::new(cidr, name);
+                record.pair = pair;
+                record.description = description;
+                record.labels = labels;
+                record.hosts = hosts;
+                record.child = child;
+                Ok(record)
             }
             fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
             where
@@ -94,6 +403,11 @@ impl<'s> serde::Deserialize<'s> for CidrRecord {
             {
                 let mut cidr: Option<IpCidr> = None;
                 let mut name: Option<String> = None;
+                let mut pair: Option<IpCidr> = None;
+                let mut description: Option<String> = None;
+                let mut labels: Option<Labels> = None;
+                let mut hosts: Option<Hosts> = None;
+                let mut child: Option<Box<SubnetPool>> = None;
                 while let Some(key) = map.next_key()? {
                     match key {
                         Field::Cidr => {
@@ -114,17 +428,70 @@ impl<'s> serde::Deserialize<'s> for CidrRecord {
                             }
                             name = Some(map.next_value()?);
                         }
+                        Field::Pair => {
+                            if pair.is_some() {
+                                return Err(de::Error::duplicate_field("pair"));
+                            }
+                            let pair_string = map.next_value::<String>()?;
+                            pair = Some(
+                                IpCidr::from_str(pair_string.as_str())
+                                    .map_err(de::Error::custom)?,
+                            );
+                        }
+                        Field::Description => {
+                            if description.is_some() {
+                                return Err(de::Error::duplicate_field("description"));
+                            }
+                            description = Some(map.next_value()?);
+                        }
+                        Field::Labels => {
+                            if labels.is_some() {
+                                return Err(de::Error::duplicate_field("labels"));
+                            }
+                            labels = Some(map.next_value()?);
+                        }
+                        Field::Hosts => {
+                            if hosts.is_some() {
+                                return Err(de::Error::duplicate_field("hosts"));
+                            }
+                            hosts = Some(parse_hosts(map.next_value()?)?);
+                        }
+                        Field::Pool => {
+                            if child.is_some() {
+                                return Err(de::Error::duplicate_field("pool"));
+                            }
+                            child = Some(Box::new(map.next_value()?));
+                        }
                     }
                 }
                 let cidr = match cidr {
                     Some(cidr) => cidr,
                     None => return Err(de::Error::missing_field("cidr")),
                 };
-                Ok(CidrRecord::new(cidr, name.as_deref()))
+                check_child(&cidr, child.as_deref())?;
+                let mut record = CidrRecord::new(cidr, name.as_deref());
+                record.pair = pair;
+                record.description = description;
+                record.labels = labels.unwrap_or_default();
+                record.hosts = hosts.unwrap_or_default();
+                record.child = child;
+                Ok(record)
             }
         }
 
-        deserializer.deserialize_struct("CidrRecord", &["cidr", "name"], CidrRecordVisitor)
+        deserializer.deserialize_struct(
+            "CidrRecord",
+            &[
+                "cidr",
+                "name",
+                "pair",
+                "description",
+                "labels",
+                "hosts",
+                "pool",
+            ],
+            CidrRecordVisitor,
+        )
     }
 }
 
@@ -146,6 +513,93 @@ mod tests {
             _ => panic!("Failed to create test v6 cidr"),
         },
     );
+    mod root_selector {
+        use super::*;
+
+        #[test]
+        fn round_trip_str() {
+            for selector in [
+                RootSelector::Any,
+                RootSelector::V4,
+                RootSelector::V6,
+                RootSelector::Cidr(TEST_CIDR4),
+            ] {
+                assert_eq!(RootSelector::from_str(&selector.to_string()), Ok(selector));
+            }
+        }
+
+        #[test]
+        fn from_str_invalid() {
+            assert_eq!(
+                RootSelector::from_str("ipv5"),
+                Err(ParseRootSelectorError::InvalidSelector("ipv5".to_string()))
+            );
+        }
+
+        #[test]
+        fn matches() {
+            assert!(RootSelector::Any.matches(&TEST_CIDR4));
+            assert!(RootSelector::Any.matches(&TEST_CIDR6));
+            assert!(RootSelector::V4.matches(&TEST_CIDR4));
+            assert!(!RootSelector::V4.matches(&TEST_CIDR6));
+            assert!(RootSelector::V6.matches(&TEST_CIDR6));
+            assert!(!RootSelector::V6.matches(&TEST_CIDR4));
+            assert!(RootSelector::Cidr(TEST_CIDR4).matches(&TEST_CIDR4));
+            assert!(!RootSelector::Cidr(TEST_CIDR4).matches(&TEST_CIDR6));
+        }
+    }
+
+    mod allocation_strategy {
+        use super::*;
+        use serde_test::{assert_de_tokens_error, assert_tokens};
+
+        #[test]
+        fn default() {
+            assert_eq!(
+                AllocationStrategy::default(),
+                AllocationStrategy::LowestFirst
+            );
+        }
+
+        #[test]
+        fn round_trip_str() {
+            for (strategy, name) in AllocationStrategy::NAMES {
+                assert_eq!(strategy.to_string(), name);
+                assert_eq!(AllocationStrategy::from_str(name), Ok(strategy));
+            }
+        }
+
+        #[test]
+        fn from_str_unknown() {
+            assert_eq!(
+                AllocationStrategy::from_str("first-fit"),
+                Err(ParseStrategyError::UnknownStrategy("first-fit".to_string()))
+            );
+        }
+
+        #[test]
+        fn serialize() {
+            assert_tokens(
+                &AllocationStrategy::BestFit,
+                &[serde_test::Token::UnitVariant {
+                    name: "AllocationStrategy",
+                    variant: "best-fit",
+                }],
+            );
+        }
+
+        #[test]
+        fn deserialize_unknown() {
+            assert_de_tokens_error::<AllocationStrategy>(
+                &[serde_test::Token::UnitVariant {
+                    name: "AllocationStrategy",
+                    variant: "first-fit",
+                }],
+                "unknown variant `first-fit`, expected one of `lowest-first`, `highest-first`, `best-fit`, `worst-fit`",
+            );
+        }
+    }
+
     mod cidr_record {
         use super::*;
         use serde_test::{assert_de_tokens_error, assert_tokens};
@@ -198,6 +652,74 @@ mod tests {
             );
         }
 
+        #[test]
+        fn serialize_labeled() {
+            let cidr = IpCidr::from_str("10.20.30.0/24").unwrap();
+            let mut record = CidrRecord::new(cidr, Some("a-record"));
+            record.description = Some("A description".to_string());
+            record
+                .labels
+                .insert("team".to_string(), "infra".to_string());
+            record.labels.insert("env".to_string(), "prod".to_string());
+            assert_tokens(
+                &record,
+                &[
+                    serde_test::Token::Struct {
+                        name: "CidrRecord",
+                        len: 4,
+                    },
+                    serde_test::Token::Str("cidr"),
+                    serde_test::Token::Str("10.20.30.0/24"),
+                    serde_test::Token::Str("name"),
+                    serde_test::Token::Str("a-record"),
+                    serde_test::Token::Str("description"),
+                    serde_test::Token::Str("A description"),
+                    serde_test::Token::Str("labels"),
+                    serde_test::Token::Map { len: Some(2) },
+                    serde_test::Token::Str("env"),
+                    serde_test::Token::Str("prod"),
+                    serde_test::Token::Str("team"),
+                    serde_test::Token::Str("infra"),
+                    serde_test::Token::MapEnd,
+                    serde_test::Token::StructEnd,
+                ],
+            );
+        }
+
+        #[test]
+        fn deserialize_unknown_key() {
+            assert_de_tokens_error::<CidrRecord>(
+                &[
+                    serde_test::Token::Struct {
+                        name: "CidrRecord",
+                        len: 3,
+                    },
+                    serde_test::Token::Str("cidr"),
+                    serde_test::Token::Str("10.20.30.0/24"),
+                    serde_test::Token::Str("nmae"),
+                ],
+                "unknown field `nmae`, expected one of \
+                `cidr`, `name`, `pair`, `description`, `labels`, `hosts`, `pool`",
+            );
+        }
+
+        #[test]
+        fn deserialize_duplicate_labels() {
+            assert_de_tokens_error::<CidrRecord>(
+                &[
+                    serde_test::Token::Struct {
+                        name: "CidrRecord",
+                        len: 3,
+                    },
+                    serde_test::Token::Str("labels"),
+                    serde_test::Token::Map { len: Some(0) },
+                    serde_test::Token::MapEnd,
+                    serde_test::Token::Str("labels"),
+                ],
+                "duplicate field `labels`",
+            );
+        }
+
         #[test]
         fn deserialize_as_sequence() {
             assert_de_tokens_error::<CidrRecord>(
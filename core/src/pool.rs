@@ -4,60 +4,305 @@
 #[cfg(test)]
 mod tests;
 
-use crate::errors::{AllocateError, RenameError};
-use crate::subspace::{State, Subspace};
-use crate::{AllocateResult, Bits, CidrRecord, RenameResult};
+use crate::batch::PoolBatch;
+use crate::errors::{
+    AllocateError, ClaimError, CreateError, DeleteError, HostError, InvalidRecord, InvariantError,
+    LoadError, MergeError, RecordError, RenameError, ResizeError, SplitError, StoredRecord,
+    UpdateError,
+};
+use crate::schema;
+use crate::subspace::{Node, State, Subspace};
+use crate::{
+    AllocateResult, AllocationStrategy, BatchResult, Bits, CidrRecord, ClaimResult, Containing,
+    CreateResult, DeleteResult, HostResult, LoadResult, MergeResult, PoolStats, RenameResult,
+    RequestSize, ResizeResult, RootSelector, SplitResult, UpdateResult,
+};
 use cidr::IpCidr;
 use serde::ser::SerializeStruct;
 use std::collections::HashMap;
+use std::net::IpAddr;
 
-#[derive(PartialEq, Debug)]
+/// Largest number of subnets a single split may create
+pub const MAX_SPLIT_COUNT: usize = 1 << 16;
+
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct SubnetPool {
-    root: Subspace,
+    roots: Vec<Subspace>,
     names: HashMap<String, IpCidr>,
+    strategy: AllocationStrategy,
 }
 
 impl SubnetPool {
     pub fn new(cidr: IpCidr) -> Self {
         SubnetPool {
-            root: Subspace::new(cidr),
+            roots: vec![Subspace::new(cidr)],
             names: HashMap::new(),
+            strategy: AllocationStrategy::default(),
         }
     }
 
-    fn iter_allocated_subspaces_from<'a>(
-        &'a self,
-        subspace: &'a Subspace,
-    ) -> impl Iterator<Item = &Subspace> {
-        let mut stack = Vec::new();
-        stack.push(subspace);
-        std::iter::from_fn(move || {
-            while let Some(subspace) = stack.pop() {
-                match subspace.state {
-                    State::Allocated => return Some(subspace),
-                    State::Free => {}
-                    State::Unavailable => {
-                        stack.push(subspace.high.as_deref().unwrap());
-                        stack.push(subspace.low.as_deref().unwrap());
+    fn records_in_state(&self, state: State) -> Box<dyn Iterator<Item = &CidrRecord> + '_> {
+        Box::new(self.roots.iter().flat_map(move |root| root.records(state)))
+    }
+
+    fn records_in_state_within(
+        &self,
+        cidr: &IpCidr,
+        state: State,
+    ) -> Box<dyn Iterator<Item = &CidrRecord> + '_> {
+        let cidr = *cidr;
+        Box::new(
+            self.roots
+                .iter()
+                .flat_map(move |root| root.records_within(&cidr, state)),
+        )
+    }
+
+    fn root_for(&self, cidr: &IpCidr) -> Option<&Subspace> {
+        self.roots
+            .iter()
+            .find(|root| crate::util::cidr_contains(root.cidr(), cidr))
+    }
+
+    fn root_for_mut(&mut self, cidr: &IpCidr) -> Option<&mut Subspace> {
+        self.roots
+            .iter_mut()
+            .find(|root| crate::util::cidr_contains(root.cidr(), cidr))
+    }
+
+    fn find_subspace(&self, cidr: &IpCidr) -> Option<&Node> {
+        self.root_for(cidr)?.find_record(cidr)
+    }
+
+    fn find_subspace_mut(&mut self, cidr: &IpCidr) -> Option<&mut Node> {
+        self.root_for_mut(cidr)?.find_record_mut(cidr)
+    }
+
+    /// CIDR of the first root of the pool.
+    #[inline(always)]
+    pub fn cidr(&self) -> &IpCidr {
+        self.roots[0].cidr()
+    }
+
+    /// CIDRs of all roots, in the order they were added.
+    pub fn roots(&self) -> impl Iterator<Item = &IpCidr> {
+        self.roots.iter().map(|root| root.cidr())
+    }
+
+    /// Build a pool from stored subnets and reserved subnets in one pass.
+    ///
+    /// The records are sorted by address once and each root is built
+    /// bottom-up, rather than claiming them one at a time. Every record that
+    /// lies outside the roots, overlaps another, repeats a name or has an
+    /// unmatched pair is reported, not only the first.
+    pub fn from_sorted_records(
+        roots: &[IpCidr],
+        subnets: Vec<CidrRecord>,
+        reserved: Vec<CidrRecord>,
+    ) -> LoadResult<SubnetPool> {
+        let (first, rest) = roots.split_first().ok_or(LoadError::NoRoots)?;
+        let mut pool = SubnetPool::new(*first);
+        for cidr in rest {
+            pool.add_root(*cidr).map_err(LoadError::Root)?;
+        }
+
+        // Each record with its position in the stored subnets or reserved
+        // subnets
+        let stored = |(index, reserved), record: &CidrRecord| StoredRecord {
+            index,
+            reserved,
+            cidr: record.cidr,
+            name: record.name.clone(),
+        };
+        let invalid_record = |position, record: &CidrRecord, error| InvalidRecord {
+            record: stored(position, record),
+            error,
+        };
+        let reserved = reserved.into_iter().map(|record| CidrRecord {
+            description: record.description,
+            ..CidrRecord::new(record.cidr, None)
+        });
+        let entries = subnets
+            .into_iter()
+            .enumerate()
+            .map(|(index, record)| ((index, false), record, State::Allocated))
+            .chain(
+                reserved
+                    .enumerate()
+                    .map(|(index, record)| ((index, true), record, State::Reserved)),
+            );
+
+        let mut invalid = Vec::new();
+        let mut by_root: Vec<Vec<_>> = pool.roots.iter().map(|_| Vec::new()).collect();
+        for entry in entries {
+            let cidr = &entry.1.cidr;
+            match pool
+                .roots
+                .iter()
+                .position(|root| crate::util::cidr_contains(root.cidr(), cidr))
+            {
+                Some(root) => by_root[root].push(entry),
+                None => invalid.push(invalid_record(entry.0, &entry.1, RecordError::OutsideRoots)),
+            }
+        }
+
+        // Aligned blocks either nest or are apart, so in address order with
+        // larger blocks first a record can only overlap the last one kept
+        for entries in by_root.iter_mut() {
+            let mut sorted = std::mem::take(entries);
+            sorted.sort_by_key(|(_, record, _)| {
+                (record.cidr.first_address(), record.cidr.network_length())
+            });
+            for entry in sorted {
+                match entries.last() {
+                    Some((position, last, _))
+                        if last.cidr.contains(&entry.1.cidr.first_address()) =>
+                    {
+                        let error = RecordError::Overlap(stored(*position, last));
+                        invalid.push(invalid_record(entry.0, &entry.1, error));
                     }
+                    _ => entries.push(entry),
                 }
             }
-            None
-        })
+        }
+
+        let mut subnets: Vec<_> = by_root
+            .iter()
+            .flatten()
+            .filter(|(_, _, state)| *state == State::Allocated)
+            .collect();
+        subnets.sort_by_key(|(position, _, _)| *position);
+        let by_cidr: HashMap<IpCidr, &CidrRecord> = subnets
+            .iter()
+            .map(|(_, record, _)| (record.cidr, record))
+            .collect();
+        let mut names: HashMap<&str, ((usize, bool), &CidrRecord)> = HashMap::new();
+        for (position, record, _) in subnets {
+            // The name of a pair belongs to its IPv4 half
+            let owns_name = record.pair.is_none() || record.cidr.is_ipv4();
+            if let (Some(name), true) = (&record.name, owns_name) {
+                match names.get(name.as_str()) {
+                    Some((other_position, other)) => {
+                        let error = RecordError::DuplicateName(stored(*other_position, other));
+                        invalid.push(invalid_record(*position, record, error));
+                    }
+                    None => {
+                        names.insert(name, (*position, record));
+                    }
+                }
+            }
+            if let Some(pair) = record.pair {
+                let matched = by_cidr.get(&pair).is_some_and(|other| {
+                    other.pair == Some(record.cidr)
+                        && other.name == record.name
+                        && record.cidr.is_ipv4() != pair.is_ipv4()
+                });
+                if !matched {
+                    let error = RecordError::UnmatchedPair(pair);
+                    invalid.push(invalid_record(*position, record, error));
+                }
+            }
+        }
+        if !invalid.is_empty() {
+            invalid.sort_by_key(|invalid| (invalid.record.reserved, invalid.record.index));
+            return Err(LoadError::InvalidRecords(invalid));
+        }
+
+        pool.names = names
+            .into_iter()
+            .map(|(name, (_, record))| (name.to_string(), record.cidr))
+            .collect();
+        for (root, entries) in pool.roots.iter_mut().zip(by_root) {
+            let records = entries
+                .into_iter()
+                .map(|(_, record, state)| (record, state));
+            *root = Subspace::from_sorted(*root.cidr(), records);
+        }
+        Ok(pool)
     }
 
-    fn iter_allocated_subspaces(&self) -> impl Iterator<Item = &Subspace> {
-        self.iter_allocated_subspaces_from(&self.root)
+    /// Add another root CIDR that does not overlap any existing root.
+    pub fn add_root(&mut self, cidr: IpCidr) -> CreateResult<()> {
+        for root in self.roots() {
+            if *root == cidr {
+                return Err(CreateError::DuplicateObject);
+            }
+            if crate::util::cidr_contains(root, &cidr) || crate::util::cidr_contains(&cidr, root) {
+                return Err(CreateError::Overlap);
+            }
+        }
+        self.roots.push(Subspace::new(cidr));
+        Ok(())
     }
 
-    #[inline(always)]
-    pub fn cidr(&self) -> &IpCidr {
-        &self.root.record.cidr
+    /// Remove a root that has no allocated or reserved subnets.
+    pub fn remove_root(&mut self, cidr: &IpCidr) -> DeleteResult<()> {
+        let index = self
+            .roots()
+            .position(|root| root == cidr)
+            .ok_or(DeleteError::NoSuchObject)?;
+        if self.roots.len() == 1 {
+            return Err(DeleteError::LastRoot);
+        }
+        if !self.roots[index].is_empty() {
+            return Err(DeleteError::NotEmpty);
+        }
+        self.roots.remove(index);
+        Ok(())
+    }
+
+    /// Widen the root inside the given supernet to cover all of it.
+    ///
+    /// Returns the CIDR of the root that was replaced.
+    pub fn expand_to(&mut self, supernet: &IpCidr) -> ResizeResult<IpCidr> {
+        let index = self
+            .roots()
+            .position(|root| root != supernet && crate::util::cidr_contains(supernet, root))
+            .ok_or(ResizeError::NoSuchRoot)?;
+        let overlaps = self.roots().enumerate().any(|(other, root)| {
+            other != index
+                && (crate::util::cidr_contains(supernet, root)
+                    || crate::util::cidr_contains(root, supernet))
+        });
+        if overlaps {
+            return Err(ResizeError::Overlap);
+        }
+        let root = self.roots.remove(index);
+        let replaced = *root.cidr();
+        self.roots.insert(index, root.expand_to(supernet));
+        Ok(replaced)
+    }
+
+    /// Narrow the root containing the given subnet down to it.
+    ///
+    /// Fails with every allocated or reserved subnet that would be left
+    /// outside. Returns the CIDR of the root that was replaced.
+    pub fn shrink_to(&mut self, subnet: &IpCidr) -> ResizeResult<IpCidr> {
+        let index = self
+            .roots()
+            .position(|root| root != subnet && crate::util::cidr_contains(root, subnet))
+            .ok_or(ResizeError::NoSuchRoot)?;
+        let root = &self.roots[index];
+        let blocked: Vec<IpCidr> = root
+            .records(State::Allocated)
+            .chain(root.records(State::Reserved))
+            .map(|record| record.cidr)
+            .filter(|cidr| !crate::util::cidr_contains(subnet, cidr))
+            .collect();
+        if !blocked.is_empty() {
+            let mut blocked = blocked;
+            blocked.sort_by_key(|cidr| cidr.first_address());
+            return Err(ResizeError::Blocked(blocked));
+        }
+        let root = self.roots.remove(index);
+        let replaced = *root.cidr();
+        self.roots.insert(index, root.shrink_to(subnet));
+        Ok(replaced)
     }
 
     #[inline(always)]
     pub fn allocated_count(&self) -> usize {
-        self.root.allocated_count
+        self.roots.iter().map(|root| root.allocated_count()).sum()
     }
 
     #[inline(always)]
@@ -67,7 +312,67 @@ impl SubnetPool {
 
     #[inline(always)]
     pub fn max_available_bits(&self) -> Bits {
-        self.root.max_available_bits
+        self.roots
+            .iter()
+            .map(|root| root.max_available_bits())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Address counts, allocations by prefix length, free blocks and
+    /// fragmentation of the whole pool.
+    pub fn stats(&self) -> PoolStats {
+        let addresses = |cidr: &IpCidr| crate::util::usable_hosts(cidr, 0);
+        let mut stats = PoolStats {
+            subnets: self.allocated_count(),
+            ..PoolStats::default()
+        };
+        for root in self.roots.iter() {
+            stats.total = stats.total.saturating_add(addresses(root.cidr()));
+        }
+        for record in self.records_in_state(State::Allocated) {
+            let cidr = &record.cidr;
+            stats.allocated = stats.allocated.saturating_add(addresses(cidr));
+            let prefix = stats.prefixes.entry(cidr.network_length()).or_default();
+            prefix.subnets += 1;
+            prefix.addresses = prefix.addresses.saturating_add(addresses(cidr));
+        }
+        for record in self.reserved() {
+            stats.reserved = stats.reserved.saturating_add(addresses(&record.cidr));
+        }
+        for block in self.free_blocks() {
+            stats.free = stats.free.saturating_add(addresses(&block));
+            *stats.free_blocks.entry(block.network_length()).or_default() += 1;
+        }
+        if stats.total > 0 {
+            stats.utilization = stats.allocated as f64 / stats.total as f64;
+        }
+        if stats.free > 0 {
+            // A full root reports no available bits, which counts as one
+            // address here and never exceeds a real free block
+            let largest = self
+                .roots
+                .iter()
+                .map(|root| {
+                    1u128
+                        .checked_shl(root.max_available_bits() as u32)
+                        .unwrap_or(u128::MAX)
+                })
+                .max()
+                .unwrap_or(0);
+            stats.fragmentation = 1.0 - largest as f64 / stats.free as f64;
+        }
+        stats
+    }
+
+    #[inline(always)]
+    pub fn strategy(&self) -> AllocationStrategy {
+        self.strategy
+    }
+
+    #[inline(always)]
+    pub fn set_strategy(&mut self, strategy: AllocationStrategy) {
+        self.strategy = strategy;
     }
 
     #[inline(always)]
@@ -75,54 +380,616 @@ impl SubnetPool {
         self.names.get(name).copied()
     }
 
+    fn find_allocated_mut(&mut self, cidr: &IpCidr) -> Option<&mut Node> {
+        match self.find_subspace_mut(cidr) {
+            Some(subspace) if subspace.state == State::Allocated => Some(subspace),
+            _ => None,
+        }
+    }
+
+    pub fn find_record(&self, cidr: &IpCidr) -> Option<&CidrRecord> {
+        match self.find_subspace(cidr) {
+            Some(subspace) if subspace.state == State::Allocated => Some(&subspace.record),
+            _ => None,
+        }
+    }
+
+    /// Find the allocated, reserved or free block that holds an address.
+    ///
+    /// Returns `None` if the address is outside every root.
+    pub fn find_containing(&self, address: &IpAddr) -> Option<Containing<'_>> {
+        self.roots
+            .iter()
+            .find_map(|root| root.find_containing(address))
+    }
+
     pub fn contains(&self, cidr: &IpCidr) -> bool {
-        if let Some(subspace) = self.root.find_record(cidr) {
+        if let Some(subspace) = self.find_subspace(cidr) {
             return subspace.state == State::Allocated;
         }
         false
     }
 
     pub fn allocate(&mut self, bits: Bits, name: Option<&str>) -> AllocateResult<IpCidr> {
-        match self.root.allocate_free_space(bits, name) {
-            Some(cidr) => {
-                if let Some(name) = name {
-                    if self.names.contains_key(name) {
-                        return Err(AllocateError::DuplicateName);
-                    }
-                    self.names.insert(name.to_string(), cidr);
-                }
-                Ok(cidr)
+        self.allocate_with_strategy(bits, name, self.strategy)
+    }
+
+    pub fn allocate_with_strategy(
+        &mut self,
+        bits: Bits,
+        name: Option<&str>,
+        strategy: AllocationStrategy,
+    ) -> AllocateResult<IpCidr> {
+        self.allocate_in(bits, name, strategy, RootSelector::Any)
+    }
+
+    /// Allocate a subnet in the first root matched by the selector that has
+    /// space for it.
+    pub fn allocate_in(
+        &mut self,
+        bits: Bits,
+        name: Option<&str>,
+        strategy: AllocationStrategy,
+        root: RootSelector,
+    ) -> AllocateResult<IpCidr> {
+        self.allocate_sized(|_| Some(bits), name, strategy, root)
+    }
+
+    /// Allocate a subnet with the given prefix length in the first root
+    /// matched by the selector that has space for it.
+    ///
+    /// Only roots of an address family the prefix length fits are tried.
+    pub fn allocate_prefix_in(
+        &mut self,
+        network_length: Bits,
+        name: Option<&str>,
+        strategy: AllocationStrategy,
+        root: RootSelector,
+    ) -> AllocateResult<IpCidr> {
+        let size = RequestSize::PrefixLength(network_length);
+        self.allocate_sized(|root_cidr| size.host_bits(root_cidr), name, strategy, root)
+    }
+
+    // Allocate with the host bits each root needs for the requested size.
+    fn allocate_sized(
+        &mut self,
+        bits: impl Fn(&IpCidr) -> Option<Bits>,
+        name: Option<&str>,
+        strategy: AllocationStrategy,
+        root: RootSelector,
+    ) -> AllocateResult<IpCidr> {
+        if !self.roots().any(|cidr| root.matches(cidr)) {
+            return Err(AllocateError::NoSuchRoot);
+        }
+        if let Some(name) = name {
+            if self.names.contains_key(name) {
+                return Err(AllocateError::DuplicateName);
             }
-            None => Err(AllocateError::NoSpaceAvailable),
         }
+        let cidr = self.allocate_subspace(bits, name, strategy, root)?;
+        if let Some(name) = name {
+            self.names.insert(name.to_string(), cidr);
+        }
+        Ok(cidr)
     }
 
-    pub fn free(&mut self, cidr: &IpCidr) -> bool {
-        self.root.free(cidr)
+    // Allocate without registering the name.
+    fn allocate_subspace(
+        &mut self,
+        bits: impl Fn(&IpCidr) -> Option<Bits>,
+        name: Option<&str>,
+        strategy: AllocationStrategy,
+        root: RootSelector,
+    ) -> AllocateResult<IpCidr> {
+        self.roots
+            .iter_mut()
+            .filter(|subspace| root.matches(subspace.cidr()))
+            .find_map(|subspace| {
+                let bits = bits(subspace.cidr())?;
+                subspace.allocate_free_space(bits, name, strategy)
+            })
+            .ok_or(AllocateError::NoSpaceAvailable)
+    }
+
+    pub fn allocate_dual(
+        &mut self,
+        v4_bits: Bits,
+        v6_bits: Bits,
+        name: Option<&str>,
+    ) -> AllocateResult<(IpCidr, IpCidr)> {
+        self.allocate_dual_with_strategy(v4_bits, v6_bits, name, self.strategy)
     }
 
-    pub fn claim(&mut self, cidr: &IpCidr, name: Option<&str>) -> AllocateResult<()> {
+    /// Allocate an IPv4 and an IPv6 subnet under one name, or neither.
+    ///
+    /// Each record refers to the other as its pair. The name resolves to the
+    /// IPv4 subnet.
+    pub fn allocate_dual_with_strategy(
+        &mut self,
+        v4_bits: Bits,
+        v6_bits: Bits,
+        name: Option<&str>,
+        strategy: AllocationStrategy,
+    ) -> AllocateResult<(IpCidr, IpCidr)> {
+        for family in [RootSelector::V4, RootSelector::V6] {
+            if !self.roots().any(|cidr| family.matches(cidr)) {
+                return Err(AllocateError::NoSuchRoot);
+            }
+        }
         if let Some(name) = name {
             if self.names.contains_key(name) {
                 return Err(AllocateError::DuplicateName);
             }
+        }
+        let v4 = self.allocate_subspace(|_| Some(v4_bits), name, strategy, RootSelector::V4)?;
+        let v6 = match self.allocate_subspace(|_| Some(v6_bits), name, strategy, RootSelector::V6) {
+            Ok(v6) => v6,
+            Err(err) => {
+                self.root_for_mut(&v4).unwrap().free(&v4);
+                return Err(err);
+            }
+        };
+        self.find_subspace_mut(&v4).unwrap().record.pair = Some(v6);
+        self.find_subspace_mut(&v6).unwrap().record.pair = Some(v4);
+        if let Some(name) = name {
+            self.names.insert(name.to_string(), v4);
+        }
+        Ok((v4, v6))
+    }
+
+    pub fn allocate_for_hosts(
+        &mut self,
+        count: u128,
+        reserved_per_subnet: u128,
+        name: Option<&str>,
+    ) -> AllocateResult<IpCidr> {
+        self.allocate_for_hosts_in(
+            count,
+            reserved_per_subnet,
+            name,
+            self.strategy,
+            RootSelector::Any,
+        )
+    }
+
+    /// Allocate the smallest subnet with at least `count` usable hosts once
+    /// `reserved_per_subnet` addresses are set aside, in the first root
+    /// matched by the selector that has space for it.
+    pub fn allocate_for_hosts_in(
+        &mut self,
+        count: u128,
+        reserved_per_subnet: u128,
+        name: Option<&str>,
+        strategy: AllocationStrategy,
+        root: RootSelector,
+    ) -> AllocateResult<IpCidr> {
+        match crate::util::bits_for_hosts(count, reserved_per_subnet) {
+            Some(bits) => self.allocate_in(bits, name, strategy, root),
+            None => Err(AllocateError::NoSpaceAvailable),
+        }
+    }
+
+    pub fn free(&mut self, cidr: &IpCidr) -> bool {
+        self.free_with(cidr, false).is_ok()
+    }
+
+    /// Free a subnet, refusing to drop a child pool with live allocations
+    /// unless forced.
+    ///
+    /// Both halves of a paired allocation are freed together.
+    pub fn free_with(&mut self, cidr: &IpCidr, force: bool) -> DeleteResult<()> {
+        let record = self.find_record(cidr).ok_or(DeleteError::NoSuchObject)?;
+        let name = record.name.clone();
+        let cidrs: Vec<IpCidr> = std::iter::once(*cidr).chain(record.pair).collect();
+        for cidr in cidrs.iter() {
+            let child = self.find_record(cidr).and_then(|record| record.child());
+            if let Some(child) = child {
+                if !force && child.allocated_count() > 0 {
+                    return Err(DeleteError::HasChildren);
+                }
+            }
+        }
+        for cidr in cidrs.iter() {
+            if !self.root_for_mut(cidr).is_some_and(|root| root.free(cidr)) {
+                return Err(DeleteError::NoSuchObject);
+            }
+        }
+        if let Some(name) = name {
+            self.names.remove(&name);
+        }
+        Ok(())
+    }
+
+    /// Grow an allocated subnet in place to the given number of host bits.
+    ///
+    /// The subnet expands into the aligned supernet that contains it, which
+    /// must hold nothing else. Fails with every subnet in the way. Returns the
+    /// new CIDR, which keeps the name, labels and child pool of the original.
+    pub fn grow(&mut self, cidr: &IpCidr, bits: Bits) -> ResizeResult<IpCidr> {
+        let mut record = self
+            .find_record(cidr)
+            .ok_or(ResizeError::NoSuchObject)?
+            .clone();
+        let host_length = crate::util::host_length(cidr);
+        if bits < host_length {
+            return Err(ResizeError::InvalidSize);
+        }
+        if bits == host_length {
+            return Ok(*cidr);
+        }
+        if bits > self.root_for(cidr).unwrap().host_length() {
+            return Err(ResizeError::NoSpaceAvailable);
+        }
+        let supernet = crate::util::enclosing_cidr(cidr, bits);
+        let mut occupied: Vec<IpCidr> = self
+            .records_in_state_within(&supernet, State::Allocated)
+            .chain(self.records_in_state_within(&supernet, State::Reserved))
+            .map(|record| record.cidr)
+            .filter(|occupied| occupied != cidr)
+            .collect();
+        if !occupied.is_empty() {
+            occupied.sort_by_key(|cidr| cidr.first_address());
+            return Err(ResizeError::Occupied(occupied));
+        }
+        if let Some(child) = record.child.as_deref_mut() {
+            child.expand_to(&supernet)?;
+        }
+        self.move_record(record, supernet);
+        Ok(supernet)
+    }
+
+    /// Shrink an allocated subnet in place to the given number of host bits.
+    ///
+    /// The lowest part of the subnet is kept and the rest is freed. Host
+    /// addresses and a child pool must fit in what is kept. Returns the new
+    /// CIDR.
+    pub fn shrink(&mut self, cidr: &IpCidr, bits: Bits) -> ResizeResult<IpCidr> {
+        let mut record = self
+            .find_record(cidr)
+            .ok_or(ResizeError::NoSuchObject)?
+            .clone();
+        let host_length = crate::util::host_length(cidr);
+        if bits > host_length {
+            return Err(ResizeError::InvalidSize);
+        }
+        if bits == host_length {
+            return Ok(*cidr);
+        }
+        let subnet = crate::util::lowest_subnet(cidr, bits);
+        let outside: Vec<IpCidr> = record
+            .hosts
+            .keys()
+            .filter(|address| !subnet.contains(address))
+            .map(|address| IpCidr::new(*address, crate::util::max_bits(cidr)).unwrap())
+            .collect();
+        if !outside.is_empty() {
+            return Err(ResizeError::Blocked(outside));
+        }
+        if let Some(child) = record.child.as_deref_mut() {
+            child.shrink_to(&subnet)?;
+        }
+        self.move_record(record, subnet);
+        Ok(subnet)
+    }
+
+    // Move an allocated record to a new CIDR whose space holds nothing else.
+    fn move_record(&mut self, mut record: CidrRecord, cidr: IpCidr) {
+        let old = record.cidr;
+        let root = self.root_for_mut(&old).unwrap();
+        root.free(&old);
+        root.claim(&cidr, None);
+        record.cidr = cidr;
+        if let Some(pair) = record.pair {
+            self.find_subspace_mut(&pair).unwrap().record.pair = Some(cidr);
+        }
+        if let Some(name) = &record.name {
+            if self.names.get(name) == Some(&old) {
+                self.names.insert(name.clone(), cidr);
+            }
+        }
+        self.find_subspace_mut(&cidr).unwrap().record = record;
+    }
+
+    /// Split an allocated subnet into equal subnets with the given number of
+    /// host bits.
+    ///
+    /// The new subnets copy the labels and description of the original. They
+    /// are named in order from the given names, or left unnamed if there are
+    /// none. The space is never free in between. Returns the new CIDRs in
+    /// address order.
+    ///
+    /// At most [`MAX_SPLIT_COUNT`] subnets are created at once.
+    pub fn split(&mut self, cidr: &IpCidr, bits: Bits, names: &[&str]) -> SplitResult<Vec<IpCidr>> {
+        let record = self.find_record(cidr).ok_or(SplitError::NoSuchObject)?;
+        let host_length = crate::util::host_length(cidr);
+        if bits >= host_length {
+            return Err(SplitError::InvalidSize);
+        }
+        if record.child.is_some() {
+            return Err(SplitError::HasChildren);
+        }
+        if record.pair.is_some() {
+            return Err(SplitError::Paired);
+        }
+        if host_length - bits > MAX_SPLIT_COUNT.ilog2() as Bits {
+            return Err(SplitError::TooMany(MAX_SPLIT_COUNT));
+        }
+        let count = 1usize << (host_length - bits);
+        if !names.is_empty() && names.len() != count {
+            return Err(SplitError::NameCount(count));
+        }
+        for (index, name) in names.iter().enumerate() {
+            let taken = match self.names.get(*name) {
+                Some(named) => named != cidr,
+                None => false,
+            };
+            if taken || names[..index].contains(name) {
+                return Err(SplitError::DuplicateName);
+            }
+        }
+
+        if let Some(name) = self.find_subspace_mut(cidr).unwrap().record.name.take() {
+            self.names.remove(&name);
+        }
+        self.root_for_mut(cidr).unwrap().divide(cidr, bits);
+        let cidrs: Vec<IpCidr> = self
+            .records_within(cidr)
+            .map(|record| record.cidr)
+            .collect();
+        for cidr in cidrs.iter() {
+            let record = &mut self.find_subspace_mut(cidr).unwrap().record;
+            record.hosts.retain(|address, _| cidr.contains(address));
+        }
+        for (cidr, name) in cidrs.iter().zip(names) {
+            self.find_subspace_mut(cidr).unwrap().record.name = Some(name.to_string());
             self.names.insert(name.to_string(), *cidr);
         }
-        if self.root.claim(cidr, name) {
-            return Ok(());
+        Ok(cidrs)
+    }
+
+    /// Merge allocated subnets that together fill one aligned block into a
+    /// single allocation.
+    ///
+    /// The merged subnet keeps the labels, and the description, that all of
+    /// the originals agree on. The space is never free in between. Returns
+    /// the CIDR of the merged subnet.
+    pub fn merge(&mut self, cidrs: &[IpCidr], name: Option<&str>) -> MergeResult<IpCidr> {
+        let first = cidrs.first().ok_or(MergeError::NotOneBlock)?;
+        let mut records = Vec::with_capacity(cidrs.len());
+        for cidr in cidrs {
+            let record = self.find_record(cidr).ok_or(MergeError::NoSuchObject)?;
+            if record.child.is_some() {
+                return Err(MergeError::HasChildren);
+            }
+            if record.pair.is_some() {
+                return Err(MergeError::Paired);
+            }
+            records.push(record);
+        }
+
+        // Smallest aligned block holding every subnet
+        let mut block = *first;
+        while !cidrs
+            .iter()
+            .all(|cidr| crate::util::cidr_contains(&block, cidr))
+        {
+            block = crate::util::parent_cidr(&block).ok_or(MergeError::NotOneBlock)?;
+        }
+        // The block must be filled by exactly these subnets
+        let root = self.root_for(&block).ok_or(MergeError::NotOneBlock)?;
+        let mut filled = 0;
+        for record in root.records_within(&block, State::Allocated) {
+            if !cidrs.contains(&record.cidr) {
+                return Err(MergeError::NotOneBlock);
+            }
+            filled += 1;
+        }
+        if filled != records.len()
+            || root
+                .records_within(&block, State::Reserved)
+                .next()
+                .is_some()
+            || root.free_blocks_within(&block).next().is_some()
+        {
+            return Err(MergeError::NotOneBlock);
+        }
+
+        let names: Vec<String> = records
+            .iter()
+            .filter_map(|record| record.name.clone())
+            .collect();
+        if let Some(name) = name {
+            if self.names.contains_key(name) && !names.iter().any(|merged| merged == name) {
+                return Err(MergeError::DuplicateName);
+            }
+        }
+        let mut hosts = crate::Hosts::new();
+        for record in records.iter() {
+            for (address, host) in record.hosts.iter() {
+                if host.is_some() && hosts.values().any(|merged| merged == host) {
+                    return Err(MergeError::DuplicateName);
+                }
+                hosts.insert(*address, host.clone());
+            }
+        }
+        let mut merged = CidrRecord::new(block, name);
+        merged.hosts = hosts;
+        merged.labels = records[0].labels.clone();
+        merged.labels.retain(|key, value| {
+            records
+                .iter()
+                .all(|record| record.labels.get(key) == Some(value))
+        });
+        if records
+            .iter()
+            .all(|record| record.description == records[0].description)
+        {
+            merged.description = records[0].description.clone();
+        }
+
+        for merged_name in names {
+            self.names.remove(&merged_name);
+        }
+        if let Some(name) = name {
+            self.names.insert(name.to_string(), block);
+        }
+        self.root_for_mut(&block).unwrap().combine(merged);
+        Ok(block)
+    }
+
+    /// Turn an allocated subnet into a pool of its own.
+    ///
+    /// The child pool starts empty and uses the allocation strategy of
+    /// this pool.
+    pub fn make_child(&mut self, cidr: &IpCidr) -> CreateResult<&mut SubnetPool> {
+        let strategy = self.strategy;
+        let subspace = self
+            .find_allocated_mut(cidr)
+            .ok_or(CreateError::NoSuchObject)?;
+        if subspace.record.child.is_some() {
+            return Err(CreateError::DuplicateObject);
+        }
+        let mut child = SubnetPool::new(*cidr);
+        child.set_strategy(strategy);
+        Ok(subspace.record.child.insert(Box::new(child)))
+    }
+
+    pub fn child(&self, cidr: &IpCidr) -> Option<&SubnetPool> {
+        self.find_record(cidr)?.child()
+    }
+
+    pub fn child_mut(&mut self, cidr: &IpCidr) -> Option<&mut SubnetPool> {
+        self.find_allocated_mut(cidr)?.record.child.as_deref_mut()
+    }
+
+    pub fn reserve(&mut self, cidr: &IpCidr, description: Option<&str>) -> AllocateResult<()> {
+        if !self
+            .root_for_mut(cidr)
+            .is_some_and(|root| root.reserve(cidr))
+        {
+            return Err(AllocateError::NoSpaceAvailable);
+        }
+        let subspace = self.find_subspace_mut(cidr).unwrap();
+        subspace.record.description = description.map(|description| description.to_string());
+        Ok(())
+    }
+
+    pub fn unreserve(&mut self, cidr: &IpCidr) -> bool {
+        self.root_for_mut(cidr)
+            .is_some_and(|root| root.unreserve(cidr))
+    }
+
+    /// Apply all operations of a batch, or none of them if any fails.
+    ///
+    /// Returns the CIDR that each operation acted on, in batch order.
+    pub fn commit(&mut self, batch: &PoolBatch) -> BatchResult<Vec<IpCidr>> {
+        let mut staged = self.clone();
+        let cidrs = batch.apply_to(&mut staged)?;
+        *self = staged;
+        Ok(cidrs)
+    }
+
+    /// Allocated and reserved records overlapping a CIDR, in address order.
+    ///
+    /// This is either the subnet covering the CIDR or every subnet inside it.
+    pub fn conflicts(&self, cidr: &IpCidr) -> Vec<&CidrRecord> {
+        let mut conflicts: Vec<&CidrRecord> = Vec::new();
+        match self.find_containing(&cidr.first_address()) {
+            Some(Containing::Allocated(record)) | Some(Containing::Reserved(record))
+                if record.cidr.network_length() < cidr.network_length() =>
+            {
+                conflicts.push(record);
+            }
+            _ => {}
+        }
+        conflicts.extend(self.records_within(cidr));
+        conflicts.extend(self.reserved_within(cidr));
+        conflicts.sort_by_key(|record| record.cidr.first_address());
+        conflicts
+    }
+
+    pub fn claim(&mut self, cidr: &IpCidr, name: Option<&str>) -> ClaimResult<()> {
+        if name.is_some_and(|name| self.names.contains_key(name)) {
+            return Err(ClaimError::DuplicateName);
+        }
+        let conflicts = self.conflicts(cidr);
+        if !conflicts.is_empty() {
+            return Err(ClaimError::Conflict(
+                conflicts.into_iter().cloned().collect(),
+            ));
+        }
+        if !self
+            .root_for_mut(cidr)
+            .is_some_and(|root| root.claim(cidr, name))
+        {
+            return Err(ClaimError::NoSpaceAvailable);
+        }
+        if let Some(name) = name {
+            self.names.insert(name.to_string(), *cidr);
+        }
+        Ok(())
+    }
+
+    /// Claim a subnet, moving the subnets inside it into its child pool.
+    ///
+    /// Reserved ranges inside the subnet become reservations of the child
+    /// pool. Fails with the records that cannot be moved: a subnet covering
+    /// the CIDR, the CIDR itself and paired subnets.
+    pub fn adopt(&mut self, cidr: &IpCidr, name: Option<&str>) -> ClaimResult<()> {
+        if self.root_for(cidr).is_none() {
+            return Err(ClaimError::NoSpaceAvailable);
+        }
+        let blocking: Vec<CidrRecord> = self
+            .conflicts(cidr)
+            .into_iter()
+            .filter(|record| {
+                record.cidr.network_length() <= cidr.network_length() || record.pair.is_some()
+            })
+            .cloned()
+            .collect();
+        if !blocking.is_empty() {
+            return Err(ClaimError::Conflict(blocking));
+        }
+        let allocated: Vec<CidrRecord> = self.records_within(cidr).cloned().collect();
+        let reserved: Vec<CidrRecord> = self.reserved_within(cidr).cloned().collect();
+        if allocated.is_empty() && reserved.is_empty() {
+            return self.claim(cidr, name);
+        }
+        // The name may belong to a subnet that moves into the child pool
+        if let Some(named) = name.and_then(|name| self.names.get(name)) {
+            if !allocated.iter().any(|record| record.cidr == *named) {
+                return Err(ClaimError::DuplicateName);
+            }
+        }
+
+        let mut child = SubnetPool::new(*cidr);
+        child.set_strategy(self.strategy);
+        for record in reserved {
+            self.unreserve(&record.cidr);
+            child
+                .reserve(&record.cidr, record.description.as_deref())
+                .unwrap();
         }
-        Err(AllocateError::NoSpaceAvailable)
+        for record in allocated {
+            self.free_with(&record.cidr, true).unwrap();
+            child.claim(&record.cidr, record.name.as_deref()).unwrap();
+            let cidr = record.cidr;
+            child.find_allocated_mut(&cidr).unwrap().record = record;
+        }
+        self.claim(cidr, name)?;
+        self.find_allocated_mut(cidr).unwrap().record.child = Some(Box::new(child));
+        Ok(())
     }
 
     pub fn rename(&mut self, cidr: &IpCidr, name: Option<&str>) -> RenameResult<()> {
         // Find record that is being renamed
-        let subspace: &mut Subspace = match self.root.find_record_mut(cidr) {
-            Some(record) => record,
+        let (record_name, pair) = match self.find_allocated_mut(cidr) {
+            Some(subspace) => (subspace.record.name.clone(), subspace.record.pair),
             None => return Err(RenameError::NoSuchObject),
         };
 
         // Ignore if name is not changing
-        if subspace.record.name.as_deref() == name {
+        if record_name.as_deref() == name {
             return Ok(());
         }
 
@@ -131,59 +998,617 @@ impl SubnetPool {
             if self.names.contains_key(name) {
                 return Err(RenameError::DuplicateName);
             }
-            self.names.insert(name.to_string(), *cidr);
+            // Paired subnets are found by their IPv4 half
+            let named = match pair {
+                Some(pair) if pair.is_ipv4() => pair,
+                _ => *cidr,
+            };
+            self.names.insert(name.to_string(), named);
         }
 
         // Remove old name
-        if let Some(record_name) = &subspace.record.name {
+        if let Some(record_name) = &record_name {
             self.names.remove(record_name);
         }
 
-        // Update record name
-        subspace.record.name = name.map(|name| name.to_string());
+        // Update record name of both halves of a pair
+        for cidr in std::iter::once(*cidr).chain(pair) {
+            let subspace = self.find_subspace_mut(&cidr).unwrap();
+            subspace.record.name = name.map(|name| name.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn label(&mut self, cidr: &IpCidr, labels: &[(&str, &str)]) -> UpdateResult<()> {
+        let subspace = self
+            .find_allocated_mut(cidr)
+            .ok_or(UpdateError::NoSuchObject)?;
+        for (key, value) in labels {
+            subspace
+                .record
+                .labels
+                .insert(key.to_string(), value.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn unlabel(&mut self, cidr: &IpCidr, keys: &[&str]) -> UpdateResult<()> {
+        let subspace = self
+            .find_allocated_mut(cidr)
+            .ok_or(UpdateError::NoSuchObject)?;
+        for key in keys {
+            subspace.record.labels.remove(*key);
+        }
+        Ok(())
+    }
+
+    pub fn describe(&mut self, cidr: &IpCidr, description: Option<&str>) -> UpdateResult<()> {
+        let subspace = self
+            .find_allocated_mut(cidr)
+            .ok_or(UpdateError::NoSuchObject)?;
+        subspace.record.description = description.map(|description| description.to_string());
+        Ok(())
+    }
+
+    pub fn allocate_host(&mut self, cidr: &IpCidr, name: Option<&str>) -> HostResult<IpAddr> {
+        self.allocate_host_with(cidr, name, false)
+    }
+
+    /// Assign the lowest unassigned address of an allocated subnet.
+    ///
+    /// The network and broadcast addresses of IPv4 subnets with more than
+    /// two addresses are skipped unless all addresses are allowed.
+    pub fn allocate_host_with(
+        &mut self,
+        cidr: &IpCidr,
+        name: Option<&str>,
+        all_addresses: bool,
+    ) -> HostResult<IpAddr> {
+        let subspace = self
+            .find_allocated_mut(cidr)
+            .ok_or(HostError::NoSuchObject)?;
+        let hosts = &mut subspace.record.hosts;
+        if name.is_some_and(|name| hosts.values().flatten().any(|host| host == name)) {
+            return Err(HostError::DuplicateName);
+        }
+        let mut first = crate::util::address_value(&cidr.first_address());
+        let mut last = crate::util::address_value(&cidr.last_address());
+        if cidr.is_ipv4() && !all_addresses && last - first > 1 {
+            first += 1;
+            last -= 1;
+        }
+        let mut candidate = first;
+        for address in hosts.keys() {
+            let address = crate::util::address_value(address);
+            if address > candidate {
+                break;
+            }
+            if address == candidate {
+                candidate += 1;
+            }
+        }
+        if candidate > last {
+            return Err(HostError::NoSpaceAvailable);
+        }
+        let address = crate::util::address_from_value(cidr, candidate);
+        hosts.insert(address, name.map(|name| name.to_string()));
+        Ok(address)
+    }
+
+    /// Assign a specific address of an allocated subnet.
+    pub fn claim_host(
+        &mut self,
+        cidr: &IpCidr,
+        address: &IpAddr,
+        name: Option<&str>,
+    ) -> HostResult<()> {
+        let subspace = self
+            .find_allocated_mut(cidr)
+            .ok_or(HostError::NoSuchObject)?;
+        if !cidr.contains(address) {
+            return Err(HostError::OutOfRange);
+        }
+        let hosts = &mut subspace.record.hosts;
+        if hosts.contains_key(address) {
+            return Err(HostError::DuplicateHost);
+        }
+        if name.is_some_and(|name| hosts.values().flatten().any(|host| host == name)) {
+            return Err(HostError::DuplicateName);
+        }
+        hosts.insert(*address, name.map(|name| name.to_string()));
+        Ok(())
+    }
+
+    pub fn rename_host(
+        &mut self,
+        cidr: &IpCidr,
+        address: &IpAddr,
+        name: Option<&str>,
+    ) -> HostResult<()> {
+        let subspace = self
+            .find_allocated_mut(cidr)
+            .ok_or(HostError::NoSuchObject)?;
+        let hosts = &mut subspace.record.hosts;
+        let current = hosts.get(address).ok_or(HostError::NoSuchHost)?;
+        if current.as_deref() == name {
+            return Ok(());
+        }
+        if name.is_some_and(|name| hosts.values().flatten().any(|host| host == name)) {
+            return Err(HostError::DuplicateName);
+        }
+        hosts.insert(*address, name.map(|name| name.to_string()));
         Ok(())
     }
 
+    pub fn free_host(&mut self, cidr: &IpCidr, address: &IpAddr) -> HostResult<()> {
+        let subspace = self
+            .find_allocated_mut(cidr)
+            .ok_or(HostError::NoSuchObject)?;
+        match subspace.record.hosts.remove(address) {
+            Some(_) => Ok(()),
+            None => Err(HostError::NoSuchHost),
+        }
+    }
+
+    /// Find a host address of an allocated subnet by name.
+    pub fn find_host(&self, cidr: &IpCidr, name: &str) -> Option<IpAddr> {
+        self.find_record(cidr)?
+            .hosts
+            .iter()
+            .find(|(_, host)| host.as_deref() == Some(name))
+            .map(|(address, _)| *address)
+    }
+
     pub fn names(&self) -> impl Iterator<Item = String> + '_ {
         self.names.keys().map(|name| name.to_string())
     }
 
     pub fn cidrs(&self) -> impl Iterator<Item = &IpCidr> {
-        self.iter_allocated_subspaces()
-            .map(|subspace| &subspace.record.cidr)
+        self.records_in_state(State::Allocated)
+            .map(|record| &record.cidr)
     }
 
     pub fn records(&self) -> Box<dyn Iterator<Item = &CidrRecord> + '_> {
-        self.records_within(&self.root.record.cidr)
+        self.records_in_state(State::Allocated)
     }
 
     pub fn records_within(&self, cidr: &IpCidr) -> Box<dyn Iterator<Item = &CidrRecord> + '_> {
-        let start_cidr = match crate::util::cidr_contains(cidr, &self.root.record.cidr) {
-            true => &self.root.record.cidr,
-            false => cidr,
+        self.records_in_state_within(cidr, State::Allocated)
+    }
+
+    pub fn reserved(&self) -> Box<dyn Iterator<Item = &CidrRecord> + '_> {
+        self.records_in_state(State::Reserved)
+    }
+
+    pub fn reserved_within(&self, cidr: &IpCidr) -> Box<dyn Iterator<Item = &CidrRecord> + '_> {
+        self.records_in_state_within(cidr, State::Reserved)
+    }
+
+    /// Largest free blocks, in address order within each root.
+    ///
+    /// Each block is an aligned CIDR that could be allocated or claimed as a
+    /// whole.
+    pub fn free_blocks(&self) -> Box<dyn Iterator<Item = IpCidr> + '_> {
+        Box::new(self.roots.iter().flat_map(|root| root.free_blocks()))
+    }
+
+    /// Largest free blocks within the given CIDR.
+    pub fn free_blocks_within(&self, cidr: &IpCidr) -> Box<dyn Iterator<Item = IpCidr> + '_> {
+        let cidr = *cidr;
+        Box::new(
+            self.roots
+                .iter()
+                .flat_map(
+                    move |root| match crate::util::cidr_contains(&cidr, root.cidr()) {
+                        true => Some(root.free_blocks()),
+                        false => crate::util::cidr_contains(root.cidr(), &cidr)
+                            .then(|| root.free_blocks_within(&cidr)),
+                    },
+                )
+                .flatten(),
+        )
+    }
+
+    /// Every subnet with the given number of host bits that could be
+    /// allocated or claimed, in the same order as the free blocks.
+    pub fn candidates(&self, bits: Bits) -> Box<dyn Iterator<Item = IpCidr> + '_> {
+        Box::new(Self::candidates_in(self.free_blocks(), bits))
+    }
+
+    /// Every subnet with the given number of host bits that could be
+    /// allocated or claimed within the given CIDR.
+    pub fn candidates_within(
+        &self,
+        bits: Bits,
+        cidr: &IpCidr,
+    ) -> Box<dyn Iterator<Item = IpCidr> + '_> {
+        Box::new(Self::candidates_in(self.free_blocks_within(cidr), bits))
+    }
+
+    /// Number of subnets with the given number of host bits that still fit,
+    /// saturating at `u128::MAX`.
+    pub fn capacity(&self, bits: Bits) -> u128 {
+        self.capacity_in(RequestSize::HostBits(bits), RootSelector::Any)
+    }
+
+    /// Number of subnets of the given size that still fit in the roots
+    /// matched by the selector, saturating at `u128::MAX`.
+    pub fn capacity_in(&self, size: RequestSize, root: RootSelector) -> u128 {
+        self.roots
+            .iter()
+            .filter(|subspace| root.matches(subspace.cidr()))
+            .filter_map(|subspace| Some((subspace, size.host_bits(subspace.cidr())?)))
+            .flat_map(|(subspace, bits)| {
+                subspace
+                    .free_blocks()
+                    .filter_map(move |block| crate::util::host_length(&block).checked_sub(bits))
+            })
+            .map(|extra| 1u128.checked_shl(extra as u32).unwrap_or(u128::MAX))
+            .fold(0, u128::saturating_add)
+    }
+
+    /// Whether all of the requested subnets, given as host bits and count,
+    /// could be allocated together.
+    ///
+    /// The allocations are tried on a scratch copy of the pool, largest
+    /// first, so the pool itself is left untouched.
+    pub fn can_fit(&self, requests: &[(Bits, usize)]) -> bool {
+        let requests: Vec<(RequestSize, usize)> = requests
+            .iter()
+            .map(|(bits, count)| (RequestSize::HostBits(*bits), *count))
+            .collect();
+        self.can_fit_in(&requests, RootSelector::Any)
+    }
+
+    /// Whether all of the requested subnets, given as size and count, could
+    /// be allocated together in the roots matched by the selector.
+    pub fn can_fit_in(&self, requests: &[(RequestSize, usize)], root: RootSelector) -> bool {
+        // Largest first, by the most host bits a size takes in any root
+        let largest = |size: &RequestSize| {
+            self.roots()
+                .filter(|cidr| root.matches(cidr))
+                .filter_map(|cidr| size.host_bits(cidr))
+                .max()
         };
-        match self.root.find_record(start_cidr) {
-            Some(subspace) => Box::new(
-                self.iter_allocated_subspaces_from(subspace)
-                    .map(|subspace| &subspace.record),
-            ),
-            None => Box::new(std::iter::empty::<&CidrRecord>()),
+        let mut requests = requests.to_vec();
+        requests.sort_by_key(|(size, _)| std::cmp::Reverse(largest(size)));
+        let mut scratch = self.clone();
+        requests.iter().all(|(size, count)| {
+            (0..*count).all(|_| {
+                scratch
+                    .allocate_subspace(
+                        |cidr| size.host_bits(cidr),
+                        None,
+                        AllocationStrategy::BestFit,
+                        root,
+                    )
+                    .is_ok()
+            })
+        })
+    }
+
+    // Name index entries implied by the subnets, keeping the first subnet
+    // in address order for a repeated name.
+    fn owned_names(&self) -> Vec<(&str, IpCidr)> {
+        self.records()
+            .filter(|record| record.pair.is_none() || record.cidr.is_ipv4())
+            .filter_map(|record| Some((record.name.as_deref()?, record.cidr)))
+            .collect()
+    }
+
+    /// Check that the derived state of the pool matches its subnets.
+    ///
+    /// The cached counts and shape of each root tree, the name index, pairs
+    /// and child pools are all checked, and every problem found is
+    /// returned. An empty list means the pool is consistent.
+    pub fn check_invariants(&self) -> Vec<InvariantError> {
+        let mut problems: Vec<InvariantError> =
+            self.roots.iter().flat_map(|root| root.check()).collect();
+
+        let mut named: HashMap<&str, IpCidr> = HashMap::new();
+        for (name, cidr) in self.owned_names() {
+            if named.contains_key(name) {
+                let name = name.to_string();
+                problems.push(InvariantError::DuplicateName { name, cidr });
+                continue;
+            }
+            named.insert(name, cidr);
+            if self.names.get(name) != Some(&cidr) {
+                let name = name.to_string();
+                problems.push(InvariantError::UnindexedName { name, cidr });
+            }
+        }
+        let mut stale: Vec<(&String, &IpCidr)> = self
+            .names
+            .iter()
+            .filter(|(name, cidr)| named.get(name.as_str()) != Some(*cidr))
+            .collect();
+        stale.sort();
+        for (name, cidr) in stale {
+            problems.push(InvariantError::StaleName {
+                name: name.clone(),
+                cidr: *cidr,
+            });
+        }
+
+        for record in self.records() {
+            let cidr = record.cidr;
+            if let Some(pair) = record.pair {
+                let matched = self.find_record(&pair).is_some_and(|other| {
+                    other.pair == Some(cidr)
+                        && other.name == record.name
+                        && cidr.is_ipv4() != pair.is_ipv4()
+                });
+                if !matched {
+                    problems.push(InvariantError::UnmatchedPair { cidr, pair });
+                }
+            }
+            if let Some(child) = record.child() {
+                if child.roots.len() != 1 || *child.cidr() != cidr {
+                    problems.push(InvariantError::MismatchedChild(cidr));
+                }
+                problems.extend(child.check_invariants().into_iter().map(|error| {
+                    InvariantError::Child {
+                        cidr,
+                        error: Box::new(error),
+                    }
+                }));
+            }
+        }
+        problems
+    }
+
+    /// Rebuild the root trees and the name index from the subnets.
+    ///
+    /// Returns the problems found beforehand. Those that are not
+    /// repairable, such as a name used twice, are left in place for the
+    /// caller to resolve.
+    pub fn repair(&mut self) -> Vec<InvariantError> {
+        let problems = self.check_invariants();
+        self.roots = std::mem::take(&mut self.roots)
+            .into_iter()
+            .map(Subspace::repaired)
+            .collect();
+        let mut names: HashMap<String, IpCidr> = HashMap::new();
+        for (name, cidr) in self.owned_names() {
+            names.entry(name.to_string()).or_insert(cidr);
+        }
+        self.names = names;
+        let parents: Vec<IpCidr> = self
+            .records()
+            .filter(|record| record.child.is_some())
+            .map(|record| record.cidr)
+            .collect();
+        for cidr in parents {
+            if let Some(child) = self.child_mut(&cidr) {
+                child.repair();
+            }
         }
+        problems
+    }
+
+    fn candidates_in(
+        blocks: impl Iterator<Item = IpCidr>,
+        bits: Bits,
+    ) -> impl Iterator<Item = IpCidr> {
+        blocks
+            .filter(move |block| crate::util::host_length(block) >= bits)
+            .flat_map(move |block| {
+                let first = crate::util::lowest_subnet(&block, bits);
+                std::iter::successors(Some(first), crate::util::next_cidr)
+                    .take_while(move |cidr| crate::util::cidr_contains(&block, cidr))
+            })
+    }
+}
+
+impl PartialOrd for SubnetPool {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SubnetPool {
+    // Consistent with equality, which compares the name index as a map.
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        fn sorted_names(pool: &SubnetPool) -> Vec<(&String, &IpCidr)> {
+            let mut names: Vec<(&String, &IpCidr)> = pool.names.iter().collect();
+            names.sort();
+            names
+        }
+        self.roots
+            .cmp(&other.roots)
+            .then_with(|| self.strategy.cmp(&other.strategy))
+            .then_with(|| sorted_names(self).cmp(&sorted_names(other)))
+    }
+}
+
+impl SubnetPool {
+    fn serialize_with_version<S: serde::Serializer>(
+        &self,
+        serializer: S,
+        version: Option<u32>,
+    ) -> Result<S::Ok, S::Error> {
+        // Records of all roots in address order, so the output does not
+        // depend on the order roots were added
+        fn sorted<'a>(records: impl Iterator<Item = &'a CidrRecord>) -> Vec<&'a CidrRecord> {
+            let mut records: Vec<&CidrRecord> = records.collect();
+            records
+                .sort_by_key(|record| (record.cidr.first_address(), record.cidr.network_length()));
+            records
+        }
+        let reserved = sorted(self.reserved());
+        let has_reserved = !reserved.is_empty();
+        let has_strategy = self.strategy != AllocationStrategy::default();
+        let len = 2 + version.is_some() as usize + has_reserved as usize + has_strategy as usize;
+        let mut pool = serializer.serialize_struct("SubnetPool", len)?;
+        if let Some(version) = version {
+            pool.serialize_field("version", &version)?;
+        }
+        match self.roots.as_slice() {
+            [root] => pool.serialize_field("cidr", &root.cidr().to_string())?,
+            roots => {
+                let cidrs: Vec<String> = roots.iter().map(|root| root.cidr().to_string()).collect();
+                pool.serialize_field("cidrs", &cidrs)?;
+            }
+        }
+        pool.serialize_field("subnets", &sorted(self.records()))?;
+        if has_reserved {
+            pool.serialize_field("reserved", &reserved)?;
+        }
+        if has_strategy {
+            pool.serialize_field("strategy", &self.strategy)?;
+        }
+
+        pool.end()
     }
 }
 
 impl serde::Serialize for SubnetPool {
     fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut pool = serializer.serialize_struct("SubnetPool", 2)?;
-        pool.serialize_field("cidr", &self.root.record.cidr.to_string())?;
-        let records: Vec<&CidrRecord> = self.records().collect();
-        pool.serialize_field("subnets", &records)?;
+        self.serialize_with_version(serializer, None)
+    }
+}
 
-        pool.end()
+/// Pool at the top of a pool file, which also records the format version.
+///
+/// Child pools are written without a version, as they are part of the file
+/// of their parent.
+pub struct VersionedPool<'a>(pub &'a SubnetPool);
+
+impl serde::Serialize for VersionedPool<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0
+            .serialize_with_version(serializer, Some(schema::SCHEMA_VERSION))
+    }
+}
+
+/// Contents of a pool file, before its records are checked and placed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredPool {
+    pub version: u32,
+    pub roots: Vec<IpCidr>,
+    pub subnets: Vec<CidrRecord>,
+    pub reserved: Vec<CidrRecord>,
+    pub strategy: AllocationStrategy,
+}
+
+impl StoredPool {
+    /// Upgrade to the current format version, one version at a time.
+    pub fn migrate(&mut self) -> LoadResult<()> {
+        if self.version > schema::SCHEMA_VERSION {
+            return Err(LoadError::NewerVersion {
+                version: self.version,
+                supported: schema::SCHEMA_VERSION,
+            });
+        }
+        if self.version < schema::UNVERSIONED {
+            return Err(LoadError::InvalidVersion(self.version));
+        }
+        let first = (self.version - schema::UNVERSIONED) as usize;
+        for migration in schema::MIGRATIONS[first..].iter() {
+            migration(self);
+            self.version += 1;
+        }
+        Ok(())
     }
+
+    /// Fix the stored pairs that only one half records, returning what was
+    /// fixed.
+    ///
+    /// When a subnet names a pair in the other address family that has no
+    /// pair of its own, or already names the subnet back, both halves are
+    /// linked and take the name of the IPv4 half. Pairs that point
+    /// elsewhere are left for loading to report.
+    pub fn repair(&mut self) -> Vec<InvariantError> {
+        let positions: HashMap<IpCidr, usize> = self
+            .subnets
+            .iter()
+            .enumerate()
+            .map(|(index, record)| (record.cidr, index))
+            .collect();
+        let mut repaired = Vec::new();
+        for index in 0..self.subnets.len() {
+            let record = &self.subnets[index];
+            let cidr = record.cidr;
+            let Some(pair) = record.pair else {
+                continue;
+            };
+            let Some(&other) = positions.get(&pair) else {
+                continue;
+            };
+            let other_record = &self.subnets[other];
+            let free = other_record.pair.is_none() || other_record.pair == Some(cidr);
+            if !free || cidr.is_ipv4() == pair.is_ipv4() {
+                continue;
+            }
+            if other_record.pair == Some(cidr) && other_record.name == record.name {
+                continue;
+            }
+            let name = match cidr.is_ipv4() {
+                true => record.name.clone(),
+                false => other_record.name.clone(),
+            };
+            self.subnets[other].pair = Some(cidr);
+            self.subnets[other].name = name.clone();
+            self.subnets[index].name = name;
+            repaired.push(InvariantError::OneSidedPair { cidr, pair });
+        }
+        repaired
+    }
+
+    /// Check every record and build the pool from them, after upgrading to
+    /// the current format version.
+    pub fn into_pool(mut self) -> LoadResult<SubnetPool> {
+        self.migrate()?;
+        let mut pool = SubnetPool::from_sorted_records(&self.roots, self.subnets, self.reserved)?;
+        pool.set_strategy(self.strategy);
+        Ok(pool)
+    }
+}
+
+/// Entry of the `reserved` list of a pool file, which only has a CIDR and a
+/// description.
+#[derive(serde::Deserialize)]
+#[serde(rename = "CidrRecord", deny_unknown_fields)]
+struct StoredReserved {
+    cidr: String,
+    #[serde(default, deserialize_with = "some_string")]
+    description: Option<String>,
+}
+
+fn some_string<'s, D: serde::Deserializer<'s>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    <String as serde::Deserialize>::deserialize(deserializer).map(Some)
+}
+
+fn reserved_records<E: serde::de::Error>(
+    reserved: Vec<StoredReserved>,
+) -> Result<Vec<CidrRecord>, E> {
+    reserved
+        .into_iter()
+        .map(|reserved| {
+            let cidr = reserved.cidr.parse::<IpCidr>().map_err(E::custom)?;
+            Ok(CidrRecord {
+                description: reserved.description,
+                ..CidrRecord::new(cidr, None)
+            })
+        })
+        .collect()
 }
 
 impl<'s> serde::Deserialize<'s> for SubnetPool {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'s>,
+    {
+        StoredPool::deserialize(deserializer)?
+            .into_pool()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl<'s> serde::Deserialize<'s> for StoredPool {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'s>,
@@ -191,59 +1616,111 @@ impl<'s> serde::Deserialize<'s> for SubnetPool {
         #[derive(serde::Deserialize)]
         #[serde(field_identifier, rename_all = "lowercase")]
         enum Field {
+            Version,
             Cidr,
+            Cidrs,
             Subnets,
+            Reserved,
+            Strategy,
+        }
+
+        fn parse_cidr<E: serde::de::Error>(cidr: &str) -> Result<IpCidr, E> {
+            cidr.parse::<IpCidr>().map_err(E::custom)
         }
 
-        fn load_cidrs(
-            entries: &Vec<CidrRecord>,
-            cidr: &IpCidr,
-        ) -> Result<SubnetPool, AllocateError> {
-            let mut pool = SubnetPool::new(*cidr);
-            for entry in entries {
-                let entry_name = entry.name.as_deref();
-                pool.claim(&entry.cidr, entry_name)?;
+        fn stored_pool<E: serde::de::Error>(
+            version: u32,
+            roots: Vec<IpCidr>,
+            subnets: Vec<CidrRecord>,
+            reserved: Vec<CidrRecord>,
+            strategy: AllocationStrategy,
+        ) -> Result<StoredPool, E> {
+            if roots.is_empty() {
+                return Err(E::invalid_length(0, &"at least one root CIDR"));
             }
-            Ok(pool)
+            Ok(StoredPool {
+                version,
+                roots,
+                subnets,
+                reserved,
+                strategy,
+            })
         }
         struct SubnetPoolVisitor;
         impl<'s> serde::de::Visitor<'s> for SubnetPoolVisitor {
-            type Value = SubnetPool;
+            type Value = StoredPool;
 
             fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
                 formatter.write_str("struct SubspacePool")
             }
-            fn visit_seq<V>(self, mut seq: V) -> Result<SubnetPool, V::Error>
+            fn visit_seq<V>(self, mut seq: V) -> Result<StoredPool, V::Error>
             where
                 V: serde::de::SeqAccess<'s>,
             {
                 let cidr = seq
                     .next_element::<&str>()?
                     .ok_or_else(|| serde::de::Error::missing_field("cidr"))?;
-                let cidr = cidr.parse::<IpCidr>().map_err(serde::de::Error::custom)?;
+                let cidr = parse_cidr(cidr)?;
                 let entries = seq
                     .next_element::<Vec<CidrRecord>>()?
                     .ok_or_else(|| serde::de::Error::missing_field("subnets"))?;
+                let reserved = seq
+                    .next_element::<Vec<StoredReserved>>()?
+                    .unwrap_or_default();
+                let strategy = seq
+                    .next_element::<AllocationStrategy>()?
+                    .unwrap_or_default();
 
-                load_cidrs(&entries, &cidr).map_err(serde::de::Error::custom)
+                let reserved = reserved_records(reserved)?;
+                stored_pool(schema::UNVERSIONED, vec![cidr], entries, reserved, strategy)
             }
             fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
             where
                 V: serde::de::MapAccess<'s>,
             {
-                let mut cidr: Option<IpCidr> = None;
+                let mut version: Option<u32> = None;
+                let mut cidrs: Option<Vec<IpCidr>> = None;
                 let mut entries: Option<Vec<CidrRecord>> = None;
+                let mut reserved: Option<Vec<CidrRecord>> = None;
+                let mut strategy: Option<AllocationStrategy> = None;
                 while let Some(key) = map.next_key()? {
                     match key {
+                        Field::Version => {
+                            if version.is_some() {
+                                return Err(serde::de::Error::duplicate_field("version"));
+                            }
+                            let value = map.next_value::<u32>()?;
+                            if value < schema::UNVERSIONED {
+                                return Err(serde::de::Error::invalid_value(
+                                    serde::de::Unexpected::Unsigned(value.into()),
+                                    &"a pool file version of at least 1",
+                                ));
+                            }
+                            if value > schema::SCHEMA_VERSION {
+                                return Err(serde::de::Error::custom(LoadError::NewerVersion {
+                                    version: value,
+                                    supported: schema::SCHEMA_VERSION,
+                                }));
+                            }
+                            version = Some(value);
+                        }
                         Field::Cidr => {
-                            if cidr.is_some() {
+                            if cidrs.is_some() {
                                 return Err(serde::de::Error::duplicate_field("cidr"));
                             }
                             let cidr_string = map.next_value::<String>()?;
-                            cidr = Some(
-                                cidr_string
-                                    .parse::<IpCidr>()
-                                    .map_err(serde::de::Error::custom)?,
+                            cidrs = Some(vec![parse_cidr(&cidr_string)?]);
+                        }
+                        Field::Cidrs => {
+                            if cidrs.is_some() {
+                                return Err(serde::de::Error::duplicate_field("cidrs"));
+                            }
+                            let cidr_strings = map.next_value::<Vec<String>>()?;
+                            cidrs = Some(
+                                cidr_strings
+                                    .iter()
+                                    .map(|cidr| parse_cidr(cidr))
+                                    .collect::<Result<_, _>>()?,
                             );
                         }
                         Field::Subnets => {
@@ -252,14 +1729,34 @@ impl<'s> serde::Deserialize<'s> for SubnetPool {
                             }
                             entries = Some(map.next_value()?);
                         }
+                        Field::Reserved => {
+                            if reserved.is_some() {
+                                return Err(serde::de::Error::duplicate_field("reserved"));
+                            }
+                            reserved = Some(reserved_records(map.next_value()?)?);
+                        }
+                        Field::Strategy => {
+                            if strategy.is_some() {
+                                return Err(serde::de::Error::duplicate_field("strategy"));
+                            }
+                            strategy = Some(map.next_value()?);
+                        }
                     }
                 }
-                let cidr = cidr.ok_or_else(|| serde::de::Error::missing_field("cidr"))?;
+                let cidrs = cidrs.ok_or_else(|| serde::de::Error::missing_field("cidr"))?;
                 let subnets = entries.ok_or_else(|| serde::de::Error::missing_field("subnets"))?;
-                load_cidrs(&subnets, &cidr).map_err(serde::de::Error::custom)
+                stored_pool(
+                    version.unwrap_or(schema::UNVERSIONED),
+                    cidrs,
+                    subnets,
+                    reserved.unwrap_or_default(),
+                    strategy.unwrap_or_default(),
+                )
             }
         }
-        const FIELDS: &[&str] = &["cidr", "subnets"];
+        const FIELDS: &[&str] = &[
+            "version", "cidr", "cidrs", "subnets", "reserved", "strategy",
+        ];
         deserializer.deserialize_struct("SubnetPool", FIELDS, SubnetPoolVisitor)
     }
 }
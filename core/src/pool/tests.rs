@@ -4,7 +4,8 @@
 use super::*;
 use crate::tests::*;
 
-use crate::errors::AllocateError;
+use crate::errors::{AllocateError, ClaimError};
+use crate::CidrRecord;
 use cidr::{IpCidr, Ipv4Cidr, Ipv6Cidr};
 use itertools::Itertools;
 use std::net::{Ipv4Addr, Ipv6Addr};
@@ -93,6 +94,7 @@ mod allocate {
         let result = pool.allocate(4, Some("a-name"));
         assert_eq!(result.err(), Some(AllocateError::DuplicateName));
         assert_eq!(pool.allocated_count(), pool.cidrs().count());
+        assert_eq!(pool.allocated_count(), 1);
     }
 
     #[test]
@@ -187,6 +189,145 @@ mod allocate {
     }
 }
 
+mod allocate_with_strategy {
+    use super::*;
+    use crate::AllocationStrategy;
+
+    #[test]
+    fn lowest_first() {
+        let mut pool = new_test_pool();
+        let result = pool
+            .allocate_with_strategy(4, None, AllocationStrategy::LowestFirst)
+            .unwrap();
+        assert_eq!(result, IpCidr::from_str("10.20.0.0/28").unwrap());
+    }
+
+    #[test]
+    fn highest_first() {
+        let mut pool = new_test_pool();
+        let result1 = pool
+            .allocate_with_strategy(4, Some("a-name"), AllocationStrategy::HighestFirst)
+            .unwrap();
+        assert_eq!(result1, IpCidr::from_str("10.20.255.240/28").unwrap());
+        let result2 = pool
+            .allocate_with_strategy(8, None, AllocationStrategy::HighestFirst)
+            .unwrap();
+        assert_eq!(result2, IpCidr::from_str("10.20.254.0/24").unwrap());
+        assert_eq!(pool.find_by_name("a-name"), Some(result1));
+        assert_eq!(pool.allocated_count(), pool.cidrs().count());
+    }
+
+    #[test]
+    fn best_fit() {
+        let mut pool = new_test_pool();
+        pool.claim(&IpCidr::from_str("10.20.128.0/28").unwrap(), None)
+            .unwrap();
+        let result = pool
+            .allocate_with_strategy(4, Some("a-name"), AllocationStrategy::BestFit)
+            .unwrap();
+        assert_eq!(result, IpCidr::from_str("10.20.128.16/28").unwrap());
+        assert_eq!(pool.find_by_name("a-name"), Some(result));
+        assert_eq!(pool.allocated_count(), pool.cidrs().count());
+    }
+
+    #[test]
+    fn worst_fit() {
+        let mut pool = new_test_pool();
+        pool.claim(&IpCidr::from_str("10.20.0.16/28").unwrap(), None)
+            .unwrap();
+        let result = pool
+            .allocate_with_strategy(4, None, AllocationStrategy::WorstFit)
+            .unwrap();
+        assert_eq!(result, IpCidr::from_str("10.20.128.0/28").unwrap());
+        assert_eq!(pool.allocated_count(), pool.cidrs().count());
+    }
+
+    #[test]
+    fn no_space_available() {
+        for strategy in [
+            AllocationStrategy::LowestFirst,
+            AllocationStrategy::HighestFirst,
+            AllocationStrategy::BestFit,
+            AllocationStrategy::WorstFit,
+        ] {
+            let mut pool = new_test_pool();
+            pool.allocate(15, None).unwrap();
+            let result = pool.allocate_with_strategy(16, None, strategy);
+            assert_eq!(result.err(), Some(AllocateError::NoSpaceAvailable));
+            assert_eq!(pool.allocated_count(), 1);
+        }
+    }
+
+    #[test]
+    fn pool_default() {
+        let mut pool = new_test_pool();
+        pool.set_strategy(AllocationStrategy::HighestFirst);
+        assert_eq!(pool.strategy(), AllocationStrategy::HighestFirst);
+        let result = pool.allocate(4, None).unwrap();
+        assert_eq!(result, IpCidr::from_str("10.20.255.240/28").unwrap());
+    }
+}
+
+mod allocate_for_hosts {
+    use super::*;
+
+    #[test]
+    fn exact() {
+        let mut pool = new_test_pool();
+        let result = pool.allocate_for_hosts(256, 0, Some("a-name")).unwrap();
+        assert_eq!(result, IpCidr::from_str("10.20.0.0/24").unwrap());
+        assert_eq!(pool.find_by_name("a-name"), Some(result));
+    }
+
+    #[test]
+    fn rounds_up() {
+        let mut pool = new_test_pool();
+        let result = pool.allocate_for_hosts(300, 0, None).unwrap();
+        assert_eq!(result, IpCidr::from_str("10.20.0.0/23").unwrap());
+    }
+
+    #[test]
+    fn reserved() {
+        let mut pool = new_test_pool();
+        let result = pool.allocate_for_hosts(252, 5, None).unwrap();
+        assert_eq!(result, IpCidr::from_str("10.20.0.0/23").unwrap());
+        assert_eq!(crate::usable_hosts(&result, 5), 507);
+    }
+
+    #[test]
+    fn too_many_hosts() {
+        let mut pool = new_test_pool();
+        let result = pool.allocate_for_hosts(65536, 1, None);
+        assert_eq!(result.err(), Some(AllocateError::NoSpaceAvailable));
+        let result = pool.allocate_for_hosts(u128::MAX, 1, None);
+        assert_eq!(result.err(), Some(AllocateError::NoSpaceAvailable));
+        assert_eq!(pool.allocated_count(), 0);
+    }
+
+    #[test]
+    fn pool_strategy() {
+        let mut pool = new_test_pool();
+        pool.set_strategy(AllocationStrategy::HighestFirst);
+        let result = pool.allocate_for_hosts(300, 0, None).unwrap();
+        assert_eq!(result, IpCidr::from_str("10.20.254.0/23").unwrap());
+    }
+
+    #[test]
+    fn with_strategy() {
+        let mut pool = new_test_pool();
+        let result = pool
+            .allocate_for_hosts_in(
+                300,
+                0,
+                None,
+                AllocationStrategy::HighestFirst,
+                crate::RootSelector::Any,
+            )
+            .unwrap();
+        assert_eq!(result, IpCidr::from_str("10.20.254.0/23").unwrap());
+    }
+}
+
 mod free {
     use super::*;
 
@@ -220,16 +361,240 @@ mod free {
     }
 }
 
+mod free_name {
+    use super::*;
+
+    #[test]
+    fn removes_name() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(4, Some("a-name")).unwrap();
+        assert!(pool.free(&cidr));
+        assert_eq!(pool.find_by_name("a-name"), None);
+        assert_eq!(pool.named_count(), 0);
+    }
+
+    #[test]
+    fn name_reusable() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(4, Some("a-name")).unwrap();
+        assert!(pool.free(&cidr));
+        let reused = pool.allocate(8, Some("a-name")).unwrap();
+        assert_eq!(pool.find_by_name("a-name"), Some(reused));
+    }
+
+    #[test]
+    fn not_allocated() {
+        let mut pool = new_test_pool();
+        pool.allocate(4, Some("a-name")).unwrap();
+        assert!(!pool.free(&IpCidr::from_str("10.20.0.0/27").unwrap()));
+        assert_eq!(pool.named_count(), 1);
+    }
+}
+
+mod commit {
+    use super::*;
+    use crate::batch::PoolBatch;
+    use crate::errors::{BatchError, DeleteError, RenameError};
+
+    #[test]
+    fn empty() {
+        let mut pool = new_test_pool();
+        let result = pool.commit(&PoolBatch::new());
+        assert_eq!(result, Ok(vec![]));
+        assert_eq!(pool, new_test_pool());
+    }
+
+    #[test]
+    fn success() {
+        let mut pool = new_test_pool();
+        let existing = pool.allocate(4, Some("old-name")).unwrap();
+        let mut batch = PoolBatch::new();
+        batch
+            .allocate(4, Some("a-name"))
+            .claim(&IpCidr::from_str("10.20.1.0/24").unwrap(), Some("b-name"))
+            .free(&existing)
+            .allocate(4, Some("old-name"))
+            .rename(&IpCidr::from_str("10.20.1.0/24").unwrap(), Some("c-name"));
+        let cidrs = pool.commit(&batch).unwrap();
+        assert_eq!(
+            cidrs,
+            vec![
+                IpCidr::from_str("10.20.0.16/28").unwrap(),
+                IpCidr::from_str("10.20.1.0/24").unwrap(),
+                IpCidr::from_str("10.20.0.0/28").unwrap(),
+                IpCidr::from_str("10.20.0.0/28").unwrap(),
+                IpCidr::from_str("10.20.1.0/24").unwrap(),
+            ]
+        );
+        assert_eq!(pool.find_by_name("a-name"), Some(cidrs[0]));
+        assert_eq!(pool.find_by_name("b-name"), None);
+        assert_eq!(pool.find_by_name("c-name"), Some(cidrs[1]));
+        assert_eq!(pool.find_by_name("old-name"), Some(existing));
+        assert_eq!(pool.allocated_count(), 3);
+    }
+
+    #[test]
+    fn strategy() {
+        let mut pool = new_test_pool();
+        let mut batch = PoolBatch::new();
+        batch
+            .allocate_with_strategy(4, None, crate::AllocationStrategy::HighestFirst)
+            .allocate(4, None);
+        let cidrs = pool.commit(&batch).unwrap();
+        assert_eq!(
+            cidrs,
+            vec![
+                IpCidr::from_str("10.20.255.240/28").unwrap(),
+                IpCidr::from_str("10.20.0.0/28").unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn allocate_for_hosts() {
+        let mut pool = new_test_pool();
+        pool.set_strategy(crate::AllocationStrategy::HighestFirst);
+        let mut batch = PoolBatch::new();
+        batch
+            .allocate_for_hosts_in(300, 0, Some("a-name"), None, crate::RootSelector::Any)
+            .allocate_for_hosts_in(
+                252,
+                5,
+                None,
+                Some(crate::AllocationStrategy::LowestFirst),
+                crate::RootSelector::Any,
+            );
+        let cidrs = pool.commit(&batch).unwrap();
+        assert_eq!(
+            cidrs,
+            vec![
+                IpCidr::from_str("10.20.254.0/23").unwrap(),
+                IpCidr::from_str("10.20.0.0/23").unwrap(),
+            ]
+        );
+        assert_eq!(pool.find_by_name("a-name"), Some(cidrs[0]));
+    }
+
+    fn assert_untouched(batch: &PoolBatch, expected: BatchError) {
+        let mut pool = new_test_pool();
+        pool.allocate(4, Some("a-name")).unwrap();
+        let before = pool.clone();
+        assert_eq!(pool.commit(batch), Err(expected));
+        assert_eq!(pool, before);
+    }
+
+    #[test]
+    fn allocate_failure() {
+        let mut batch = PoolBatch::new();
+        for index in 0..5 {
+            batch.allocate(14, Some(format!("name-{}", index).as_str()));
+        }
+        assert_untouched(
+            &batch,
+            BatchError::Allocate {
+                index: 3,
+                error: AllocateError::NoSpaceAvailable,
+            },
+        );
+    }
+
+    #[test]
+    fn duplicate_name() {
+        let mut batch = PoolBatch::new();
+        batch
+            .allocate(4, Some("b-name"))
+            .allocate(4, Some("b-name"));
+        assert_untouched(
+            &batch,
+            BatchError::Allocate {
+                index: 1,
+                error: AllocateError::DuplicateName,
+            },
+        );
+    }
+
+    #[test]
+    fn claim_failure() {
+        let mut batch = PoolBatch::new();
+        batch
+            .allocate(4, Some("b-name"))
+            .claim(&IpCidr::from_str("10.20.0.0/24").unwrap(), None);
+        assert_untouched(
+            &batch,
+            BatchError::Claim {
+                index: 1,
+                error: ClaimError::Conflict(vec![
+                    CidrRecord::new(IpCidr::from_str("10.20.0.0/28").unwrap(), Some("a-name")),
+                    CidrRecord::new(IpCidr::from_str("10.20.0.16/28").unwrap(), Some("b-name")),
+                ]),
+            },
+        );
+    }
+
+    #[test]
+    fn free_failure() {
+        let mut batch = PoolBatch::new();
+        batch
+            .free(&IpCidr::from_str("10.20.0.0/28").unwrap())
+            .free(&IpCidr::from_str("10.20.0.0/28").unwrap());
+        assert_untouched(
+            &batch,
+            BatchError::Free {
+                index: 1,
+                error: DeleteError::NoSuchObject,
+            },
+        );
+    }
+
+    #[test]
+    fn rename_failure() {
+        let mut batch = PoolBatch::new();
+        batch
+            .allocate(4, None)
+            .rename(&IpCidr::from_str("10.20.0.16/28").unwrap(), Some("a-name"));
+        assert_untouched(
+            &batch,
+            BatchError::Rename {
+                index: 1,
+                error: RenameError::DuplicateName,
+            },
+        );
+    }
+}
+
 mod claim {
     use super::*;
-    use crate::errors::AllocateError;
+    use crate::errors::ClaimError;
+
+    fn cidr(cidr: &str) -> IpCidr {
+        IpCidr::from_str(cidr).unwrap()
+    }
+
+    fn conflict(pool: &SubnetPool, cidrs: &[&str]) -> ClaimError {
+        ClaimError::Conflict(
+            cidrs
+                .iter()
+                .map(|cidr| {
+                    let cidr = IpCidr::from_str(cidr).unwrap();
+                    match pool.find_record(&cidr) {
+                        Some(record) => record.clone(),
+                        None => pool
+                            .reserved()
+                            .find(|record| record.cidr == cidr)
+                            .unwrap()
+                            .clone(),
+                    }
+                })
+                .collect(),
+        )
+    }
 
     #[test]
     fn out_of_range() {
         let mut pool = new_test_pool();
         let cidr = IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 21, 0, 0), 28).unwrap());
         let result = pool.claim(&cidr, None);
-        assert_eq!(result, Err(AllocateError::NoSpaceAvailable));
+        assert_eq!(result, Err(ClaimError::NoSpaceAvailable));
         assert_eq!(pool.allocated_count(), pool.cidrs().count());
     }
 
@@ -237,10 +602,11 @@ mod claim {
     fn already_claimed() {
         let mut pool = new_test_pool();
         let cidr = IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 0), 28).unwrap());
-        pool.claim(&cidr, None).unwrap();
-        let result = pool.claim(&cidr, None);
-        assert_eq!(result, Err(AllocateError::NoSpaceAvailable));
+        pool.claim(&cidr, Some("a-name")).unwrap();
+        let result = pool.claim(&cidr, Some("b-name"));
+        assert_eq!(result, Err(conflict(&pool, &["10.20.0.0/28"])));
         assert_eq!(pool.allocated_count(), pool.cidrs().count());
+        assert_eq!(pool.find_by_name("b-name"), None);
     }
 
     #[test]
@@ -249,7 +615,7 @@ mod claim {
         let cidr = IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 0), 28).unwrap());
         pool.allocate(16, None).unwrap();
         let result = pool.claim(&cidr, None);
-        assert_eq!(result, Err(AllocateError::NoSpaceAvailable));
+        assert_eq!(result, Err(conflict(&pool, &["10.20.0.0/16"])));
         assert_eq!(pool.allocated_count(), pool.cidrs().count());
     }
 
@@ -259,10 +625,111 @@ mod claim {
         let cidr = IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 16), 28).unwrap());
         pool.allocate(4, Some("a-name")).unwrap();
         let result = pool.claim(&cidr, Some("a-name"));
-        assert_eq!(result, Err(AllocateError::DuplicateName));
+        assert_eq!(result, Err(ClaimError::DuplicateName));
         assert_eq!(pool.allocated_count(), pool.cidrs().count());
     }
 
+    #[test]
+    fn overlaps_children() {
+        let mut pool = new_test_pool();
+        pool.claim(&cidr("10.20.0.16/28"), Some("a-name")).unwrap();
+        pool.claim(&cidr("10.20.0.64/26"), None).unwrap();
+        pool.reserve(&cidr("10.20.0.128/28"), None).unwrap();
+        pool.claim(&cidr("10.20.1.0/24"), None).unwrap();
+        let result = pool.claim(&cidr("10.20.0.0/24"), Some("b-name"));
+        assert_eq!(
+            result,
+            Err(conflict(
+                &pool,
+                &["10.20.0.16/28", "10.20.0.64/26", "10.20.0.128/28"]
+            ))
+        );
+        assert_eq!(pool.allocated_count(), 3);
+        assert_eq!(pool.find_by_name("b-name"), None);
+        assert_eq!(pool.find_by_name("a-name"), Some(cidr("10.20.0.16/28")));
+    }
+
+    #[test]
+    fn name_untouched_on_failure() {
+        let mut pool = new_test_pool();
+        pool.allocate(4, None).unwrap();
+        pool.claim(&cidr("10.20.0.0/28"), Some("a-name"))
+            .unwrap_err();
+        assert_eq!(pool.find_by_name("a-name"), None);
+        assert_eq!(pool.named_count(), 0);
+        pool.claim(&cidr("10.20.0.16/28"), Some("a-name")).unwrap();
+        assert_eq!(pool.find_by_name("a-name"), Some(cidr("10.20.0.16/28")));
+    }
+
+    #[test]
+    fn adopt() {
+        let mut pool = new_test_pool();
+        pool.claim(&cidr("10.20.0.16/28"), Some("a-name")).unwrap();
+        pool.label(&cidr("10.20.0.16/28"), &[("env", "prod")])
+            .unwrap();
+        pool.claim(&cidr("10.20.0.64/26"), None).unwrap();
+        pool.reserve(&cidr("10.20.0.128/28"), Some("management"))
+            .unwrap();
+        pool.claim(&cidr("10.20.1.0/24"), Some("c-name")).unwrap();
+
+        assert_eq!(pool.adopt(&cidr("10.20.0.0/24"), Some("region")), Ok(()));
+        let cidrs: Vec<IpCidr> = pool.cidrs().copied().collect();
+        assert_eq!(cidrs, vec![cidr("10.20.0.0/24"), cidr("10.20.1.0/24")]);
+        assert_eq!(pool.reserved().count(), 0);
+        assert_eq!(pool.find_by_name("a-name"), None);
+        assert_eq!(pool.find_by_name("region"), Some(cidr("10.20.0.0/24")));
+
+        let child = pool.child(&cidr("10.20.0.0/24")).unwrap();
+        let cidrs: Vec<IpCidr> = child.cidrs().copied().collect();
+        assert_eq!(cidrs, vec![cidr("10.20.0.16/28"), cidr("10.20.0.64/26")]);
+        assert_eq!(child.find_by_name("a-name"), Some(cidr("10.20.0.16/28")));
+        let record = child.find_record(&cidr("10.20.0.16/28")).unwrap();
+        assert_eq!(record.labels.get("env").unwrap(), "prod");
+        let reserved: Vec<&CidrRecord> = child.reserved().collect();
+        assert_eq!(reserved.len(), 1);
+        assert_eq!(reserved[0].cidr, cidr("10.20.0.128/28"));
+        assert_eq!(reserved[0].description.as_deref(), Some("management"));
+    }
+
+    #[test]
+    fn adopt_name_of_child() {
+        let mut pool = new_test_pool();
+        pool.claim(&cidr("10.20.0.16/28"), Some("a-name")).unwrap();
+        assert_eq!(pool.adopt(&cidr("10.20.0.0/24"), Some("a-name")), Ok(()));
+        assert_eq!(pool.find_by_name("a-name"), Some(cidr("10.20.0.0/24")));
+        let child = pool.child(&cidr("10.20.0.0/24")).unwrap();
+        assert_eq!(child.find_by_name("a-name"), Some(cidr("10.20.0.16/28")));
+    }
+
+    #[test]
+    fn adopt_empty() {
+        let mut pool = new_test_pool();
+        assert_eq!(pool.adopt(&cidr("10.20.0.0/24"), None), Ok(()));
+        assert!(pool.contains(&cidr("10.20.0.0/24")));
+        assert!(pool.child(&cidr("10.20.0.0/24")).is_none());
+    }
+
+    #[test]
+    fn adopt_covered() {
+        let mut pool = new_test_pool();
+        pool.claim(&cidr("10.20.0.0/23"), None).unwrap();
+        let result = pool.adopt(&cidr("10.20.0.0/24"), None);
+        assert_eq!(result, Err(conflict(&pool, &["10.20.0.0/23"])));
+        let result = pool.adopt(&cidr("10.20.0.0/23"), None);
+        assert_eq!(result, Err(conflict(&pool, &["10.20.0.0/23"])));
+    }
+
+    #[test]
+    fn adopt_duplicate_name() {
+        let mut pool = new_test_pool();
+        pool.claim(&cidr("10.20.0.16/28"), None).unwrap();
+        pool.claim(&cidr("10.20.1.0/24"), Some("a-name")).unwrap();
+        let result = pool.adopt(&cidr("10.20.0.0/24"), Some("a-name"));
+        assert_eq!(result, Err(ClaimError::DuplicateName));
+        assert!(pool.contains(&cidr("10.20.0.16/28")));
+        assert!(pool.child(&cidr("10.20.0.0/24")).is_none());
+    }
+
     #[test]
     fn unnamed() {
         let mut pool = new_test_pool();
@@ -284,6 +751,111 @@ mod claim {
     }
 }
 
+mod reserve {
+    use super::*;
+    use crate::AllocationStrategy;
+
+    #[test]
+    fn success() {
+        let mut pool = new_test_pool();
+        let cidr = IpCidr::from_str("10.20.0.0/24").unwrap();
+        pool.reserve(&cidr, Some("management")).unwrap();
+        let reserved: Vec<&CidrRecord> = pool.reserved().collect();
+        assert_eq!(reserved.len(), 1);
+        assert_eq!(reserved[0].cidr, cidr);
+        assert_eq!(reserved[0].description.as_deref(), Some("management"));
+        assert_eq!(pool.allocated_count(), 0);
+        assert_eq!(pool.cidrs().count(), 0);
+        assert!(!pool.contains(&cidr));
+        assert_eq!(pool.find_record(&cidr), None);
+    }
+
+    #[test]
+    fn skipped_by_allocate() {
+        for strategy in [
+            AllocationStrategy::LowestFirst,
+            AllocationStrategy::HighestFirst,
+            AllocationStrategy::BestFit,
+            AllocationStrategy::WorstFit,
+        ] {
+            let mut pool = new_test_pool();
+            pool.reserve(&IpCidr::from_str("10.20.0.0/17").unwrap(), None)
+                .unwrap();
+            pool.reserve(&IpCidr::from_str("10.20.192.0/18").unwrap(), None)
+                .unwrap();
+            assert_eq!(pool.max_available_bits(), 14);
+            let result = pool.allocate_with_strategy(14, None, strategy).unwrap();
+            assert_eq!(result, IpCidr::from_str("10.20.128.0/18").unwrap());
+            let result = pool.allocate_with_strategy(8, None, strategy);
+            assert_eq!(result.err(), Some(AllocateError::NoSpaceAvailable));
+        }
+    }
+
+    #[test]
+    fn rejected_by_claim() {
+        let mut pool = new_test_pool();
+        let cidr = IpCidr::from_str("10.20.0.0/24").unwrap();
+        pool.reserve(&cidr, None).unwrap();
+        for claimed in ["10.20.0.0/24", "10.20.0.0/28", "10.20.0.0/23"] {
+            let result = pool.claim(&IpCidr::from_str(claimed).unwrap(), None);
+            assert_eq!(
+                result,
+                Err(ClaimError::Conflict(pool.reserved().cloned().collect()))
+            );
+        }
+        assert_eq!(pool.allocated_count(), 0);
+    }
+
+    #[test]
+    fn already_allocated() {
+        let mut pool = new_test_pool();
+        pool.allocate(4, None).unwrap();
+        let result = pool.reserve(&IpCidr::from_str("10.20.0.0/24").unwrap(), None);
+        assert_eq!(result, Err(AllocateError::NoSpaceAvailable));
+        assert_eq!(pool.reserved().count(), 0);
+    }
+
+    #[test]
+    fn not_freed() {
+        let mut pool = new_test_pool();
+        let cidr = IpCidr::from_str("10.20.0.0/24").unwrap();
+        pool.reserve(&cidr, None).unwrap();
+        assert!(!pool.free(&cidr));
+        assert_eq!(pool.reserved().count(), 1);
+    }
+
+    #[test]
+    fn unreserve() {
+        let mut pool = new_test_pool();
+        let cidr = IpCidr::from_str("10.20.1.0/24").unwrap();
+        let allocated = pool.allocate(8, None).unwrap();
+        pool.reserve(&cidr, None).unwrap();
+        assert!(!pool.unreserve(&allocated));
+        assert!(pool.unreserve(&cidr));
+        assert!(!pool.unreserve(&cidr));
+        assert_eq!(pool.reserved().count(), 0);
+        pool.free(&allocated);
+        assert_eq!(pool.max_available_bits(), 16);
+    }
+
+    #[test]
+    fn reserved_within() {
+        let mut pool = new_test_pool();
+        pool.reserve(&IpCidr::from_str("10.20.0.0/24").unwrap(), None)
+            .unwrap();
+        pool.reserve(&IpCidr::from_str("10.20.128.0/24").unwrap(), None)
+            .unwrap();
+        let reserved: Vec<&CidrRecord> = pool
+            .reserved_within(&IpCidr::from_str("10.20.128.0/17").unwrap())
+            .collect();
+        assert_eq!(reserved.len(), 1);
+        assert_eq!(
+            reserved[0].cidr,
+            IpCidr::from_str("10.20.128.0/24").unwrap()
+        );
+    }
+}
+
 mod rename {
     use super::*;
     use crate::errors::RenameError;
@@ -297,6 +869,17 @@ mod rename {
         assert_eq!(pool.find_by_name("a-name"), None);
     }
     #[test]
+    fn branch() {
+        let mut pool = new_test_pool();
+        pool.allocate(4, None).unwrap();
+        pool.allocate(4, None).unwrap();
+        let branch = IpCidr::from_str("10.20.0.0/27").unwrap();
+        let result = pool.rename(&branch, Some("a-name"));
+        assert_eq!(result, Err(RenameError::NoSuchObject));
+        assert_eq!(pool.find_by_name("a-name"), None);
+        assert_eq!(pool.check_invariants(), vec![]);
+    }
+    #[test]
     fn already_not_set() {
         let mut pool = new_test_pool();
         let cidr = IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 0), 28).unwrap());
@@ -336,203 +919,2493 @@ mod rename {
     }
 }
 
-mod names {
+mod find_record {
     use super::*;
+
     #[test]
-    fn success() {
+    fn found() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(4, Some("a-name")).unwrap();
+        let record = pool.find_record(&cidr).unwrap();
+        assert_eq!(record.cidr, cidr);
+        assert_eq!(record.name.as_deref(), Some("a-name"));
+    }
+
+    #[test]
+    fn not_allocated() {
         let mut pool = new_test_pool();
-        pool.allocate(4, Some("a-name")).unwrap();
-        pool.allocate(4, Some("b-name")).unwrap();
         pool.allocate(4, None).unwrap();
-        let mut names: Vec<String> = pool.names().collect();
-        names.sort();
-        assert_eq!(names.len(), 2);
-        assert_eq!(names[0], "a-name");
-        assert_eq!(names[1], "b-name");
+        assert_eq!(
+            pool.find_record(&IpCidr::from_str("10.20.0.0/27").unwrap()),
+            None
+        );
+        assert_eq!(
+            pool.find_record(&IpCidr::from_str("10.20.0.16/28").unwrap()),
+            None
+        );
     }
 }
 
-mod cidrs {
+mod labels {
     use super::*;
+    use crate::errors::UpdateError;
+
     #[test]
-    fn no_cidrs() {
-        let pool = new_test_pool();
-        let cidrs = pool.cidrs();
-        assert_eq!(cidrs.count(), 0);
+    fn label() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(4, None).unwrap();
+        pool.label(&cidr, &[("env", "prod"), ("team", "infra")])
+            .unwrap();
+        pool.label(&cidr, &[("env", "dev")]).unwrap();
+        let labels = &pool.find_record(&cidr).unwrap().labels;
+        assert_eq!(
+            labels.iter().collect::<Vec<_>>(),
+            vec![
+                (&"env".to_string(), &"dev".to_string()),
+                (&"team".to_string(), &"infra".to_string())
+            ]
+        );
     }
 
     #[test]
-    fn some() {
+    fn unlabel() {
         let mut pool = new_test_pool();
-        for bits in [4, 5, 5, 4, 4, 4].iter() {
-            pool.allocate(*bits, None).unwrap();
+        let cidr = pool.allocate(4, None).unwrap();
+        pool.label(&cidr, &[("env", "prod"), ("team", "infra")])
+            .unwrap();
+        pool.unlabel(&cidr, &["env", "missing"]).unwrap();
+        let labels = &pool.find_record(&cidr).unwrap().labels;
+        assert_eq!(labels.keys().collect::<Vec<_>>(), vec!["team"]);
+    }
+
+    #[test]
+    fn describe() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(4, None).unwrap();
+        pool.describe(&cidr, Some("A description")).unwrap();
+        assert_eq!(
+            pool.find_record(&cidr).unwrap().description.as_deref(),
+            Some("A description")
+        );
+        pool.describe(&cidr, None).unwrap();
+        assert_eq!(pool.find_record(&cidr).unwrap().description, None);
+    }
+
+    #[test]
+    fn no_such_object() {
+        let mut pool = new_test_pool();
+        let cidr = IpCidr::from_str("10.20.0.0/28").unwrap();
+        assert_eq!(
+            pool.label(&cidr, &[("env", "prod")]),
+            Err(UpdateError::NoSuchObject)
+        );
+        assert_eq!(
+            pool.unlabel(&cidr, &["env"]),
+            Err(UpdateError::NoSuchObject)
+        );
+        assert_eq!(
+            pool.describe(&cidr, Some("A description")),
+            Err(UpdateError::NoSuchObject)
+        );
+    }
+
+    #[test]
+    fn cleared_on_free() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(4, None).unwrap();
+        pool.label(&cidr, &[("env", "prod")]).unwrap();
+        pool.describe(&cidr, Some("A description")).unwrap();
+        pool.free(&cidr);
+        pool.claim(&cidr, None).unwrap();
+        let record = pool.find_record(&cidr).unwrap();
+        assert!(record.labels.is_empty());
+        assert_eq!(record.description, None);
+    }
+}
+
+mod child {
+    use super::*;
+    use crate::errors::{CreateError, DeleteError};
+
+    #[test]
+    fn make_child() {
+        let mut pool = new_test_pool();
+        pool.set_strategy(crate::AllocationStrategy::HighestFirst);
+        let cidr = pool.allocate(8, Some("region")).unwrap();
+        let child = pool.make_child(&cidr).unwrap();
+        assert_eq!(child.cidr(), &cidr);
+        assert_eq!(child.strategy(), crate::AllocationStrategy::HighestFirst);
+        assert_eq!(child.allocated_count(), 0);
+        assert!(pool.find_record(&cidr).unwrap().child().is_some());
+    }
+
+    #[test]
+    fn make_child_not_allocated() {
+        let mut pool = new_test_pool();
+        assert_eq!(
+            pool.make_child(&IpCidr::from_str("10.20.0.0/24").unwrap()),
+            Err(CreateError::NoSuchObject)
+        );
+    }
+
+    #[test]
+    fn make_child_twice() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(8, None).unwrap();
+        pool.make_child(&cidr).unwrap();
+        assert_eq!(pool.make_child(&cidr), Err(CreateError::DuplicateObject));
+    }
+
+    #[test]
+    fn ordered_by_child_contents() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(8, None).unwrap();
+        pool.make_child(&cidr).unwrap();
+        let empty = pool.find_record(&cidr).unwrap().clone();
+        pool.child_mut(&cidr).unwrap().allocate(4, None).unwrap();
+        let allocated = pool.find_record(&cidr).unwrap().clone();
+        assert_ne!(empty, allocated);
+        assert_ne!(empty.cmp(&allocated), std::cmp::Ordering::Equal);
+        assert_eq!(empty.cmp(&allocated), allocated.cmp(&empty).reverse());
+        assert_eq!(allocated.cmp(&allocated.clone()), std::cmp::Ordering::Equal);
+    }
+
+    #[test]
+    fn separate_names() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(8, Some("a-name")).unwrap();
+        let child = pool.make_child(&cidr).unwrap();
+        let inner = child.allocate(4, Some("a-name")).unwrap();
+        assert_eq!(inner, IpCidr::from_str("10.20.0.0/28").unwrap());
+        assert_eq!(pool.find_by_name("a-name"), Some(cidr));
+        assert_eq!(
+            pool.child(&cidr).unwrap().find_by_name("a-name"),
+            Some(inner)
+        );
+        assert_eq!(pool.allocated_count(), 1);
+    }
+
+    #[test]
+    fn child_not_found() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(8, None).unwrap();
+        assert!(pool.child(&cidr).is_none());
+        assert!(pool.child_mut(&cidr).is_none());
+    }
+
+    #[test]
+    fn free_with_children() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(8, Some("region")).unwrap();
+        pool.make_child(&cidr).unwrap().allocate(4, None).unwrap();
+        assert_eq!(pool.free_with(&cidr, false), Err(DeleteError::HasChildren));
+        assert!(!pool.free(&cidr));
+        assert!(pool.contains(&cidr));
+        assert_eq!(pool.free_with(&cidr, true), Ok(()));
+        assert!(!pool.contains(&cidr));
+        assert_eq!(pool.find_by_name("region"), None);
+
+        let cidr = pool.allocate(8, None).unwrap();
+        assert!(pool.child(&cidr).is_none());
+    }
+
+    #[test]
+    fn free_empty_child() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(8, None).unwrap();
+        pool.make_child(&cidr).unwrap();
+        assert!(pool.free(&cidr));
+    }
+
+    #[test]
+    fn rename_keeps_child() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(8, Some("region")).unwrap();
+        pool.make_child(&cidr).unwrap().allocate(4, None).unwrap();
+        pool.rename(&cidr, Some("renamed")).unwrap();
+        assert_eq!(pool.child(&cidr).unwrap().allocated_count(), 1);
+    }
+
+    #[test]
+    fn commit_free_with_children() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(8, None).unwrap();
+        pool.make_child(&cidr).unwrap().allocate(4, None).unwrap();
+        let mut batch = PoolBatch::new();
+        batch.free(&cidr);
+        assert_eq!(
+            pool.commit(&batch),
+            Err(crate::errors::BatchError::Free {
+                index: 0,
+                error: DeleteError::HasChildren
+            })
+        );
+        let mut batch = PoolBatch::new();
+        batch.free_forced(&cidr);
+        assert_eq!(pool.commit(&batch), Ok(vec![cidr]));
+    }
+}
+
+mod roots {
+    use super::*;
+    use crate::errors::{CreateError, DeleteError};
+    use crate::RootSelector;
+
+    fn new_dual_pool() -> SubnetPool {
+        let mut pool = new_test_pool();
+        pool.add_root(TEST_CIDR6).unwrap();
+        pool
+    }
+
+    #[test]
+    fn add_root() {
+        let pool = new_dual_pool();
+        assert_eq!(pool.cidr(), &TEST_CIDR4);
+        assert_eq!(
+            pool.roots().collect::<Vec<_>>(),
+            vec![&TEST_CIDR4, &TEST_CIDR6]
+        );
+    }
+
+    #[test]
+    fn add_root_duplicate() {
+        let mut pool = new_dual_pool();
+        assert_eq!(pool.add_root(TEST_CIDR6), Err(CreateError::DuplicateObject));
+    }
+
+    #[test]
+    fn add_root_overlap() {
+        let mut pool = new_test_pool();
+        for cidr in ["10.20.1.0/24", "10.0.0.0/8"] {
+            assert_eq!(
+                pool.add_root(IpCidr::from_str(cidr).unwrap()),
+                Err(CreateError::Overlap)
+            );
         }
-        let mut cidrs = pool.cidrs();
+    }
+
+    #[test]
+    fn remove_root() {
+        let mut pool = new_dual_pool();
+        assert_eq!(pool.remove_root(&TEST_CIDR4), Ok(()));
+        assert_eq!(pool.cidr(), &TEST_CIDR6);
         assert_eq!(
-            &IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 0), 28).unwrap()),
-            cidrs.next().unwrap(),
+            pool.remove_root(&TEST_CIDR4),
+            Err(DeleteError::NoSuchObject)
         );
+    }
+
+    #[test]
+    fn remove_last_root() {
+        let mut pool = new_test_pool();
+        assert_eq!(pool.remove_root(&TEST_CIDR4), Err(DeleteError::LastRoot));
+    }
+
+    #[test]
+    fn remove_root_not_empty() {
+        let mut pool = new_dual_pool();
+        pool.reserve(&IpCidr::from_str("10.20.0.0/24").unwrap(), None)
+            .unwrap();
+        assert_eq!(pool.remove_root(&TEST_CIDR4), Err(DeleteError::NotEmpty));
+        let cidr = pool
+            .allocate_in(8, None, AllocationStrategy::default(), RootSelector::V6)
+            .unwrap();
+        assert_eq!(pool.remove_root(&TEST_CIDR6), Err(DeleteError::NotEmpty));
+        assert!(pool.free(&cidr));
+        assert_eq!(pool.remove_root(&TEST_CIDR6), Ok(()));
+    }
+
+    #[test]
+    fn allocate_first_root() {
+        let mut pool = new_dual_pool();
+        let cidr = pool.allocate(8, None).unwrap();
+        assert_eq!(cidr, IpCidr::from_str("10.20.0.0/24").unwrap());
+    }
+
+    #[test]
+    fn allocate_next_root_when_full() {
+        let mut pool = new_test_pool();
+        pool.add_root(IpCidr::from_str("172.16.0.0/20").unwrap())
+            .unwrap();
+        pool.allocate(16, None).unwrap();
+        let cidr = pool.allocate(8, None).unwrap();
+        assert_eq!(cidr, IpCidr::from_str("172.16.0.0/24").unwrap());
+        assert_eq!(pool.allocated_count(), 2);
+        assert_eq!(pool.max_available_bits(), 11);
+    }
+
+    #[test]
+    fn allocate_in_family() {
+        let mut pool = new_dual_pool();
+        let cidr = pool
+            .allocate_in(
+                8,
+                Some("v6"),
+                AllocationStrategy::default(),
+                RootSelector::V6,
+            )
+            .unwrap();
+        assert!(cidr.is_ipv6());
+        assert_eq!(pool.find_by_name("v6"), Some(cidr));
+    }
+
+    #[test]
+    fn allocate_prefix_in() {
+        let mut pool = new_dual_pool();
+        let mut allocate = |length| {
+            pool.allocate_prefix_in(
+                length,
+                None,
+                AllocationStrategy::default(),
+                RootSelector::Any,
+            )
+        };
         assert_eq!(
-            &IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 16), 28).unwrap()),
-            cidrs.next().unwrap(),
+            allocate(120),
+            Ok(IpCidr::from_str("1:2:3:4:a:14::/120").unwrap())
         );
+        assert_eq!(allocate(24), Ok(IpCidr::from_str("10.20.0.0/24").unwrap()));
+    }
+
+    #[test]
+    fn allocate_prefix_in_keeps_length() {
+        let mut pool = new_dual_pool();
+        pool.allocate(16, None).unwrap();
+        // A /24 does not fit the IPv6 root, so no /120 is allocated there
         assert_eq!(
-            &IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 32), 27).unwrap()),
-            cidrs.next().unwrap(),
+            pool.allocate_prefix_in(24, None, AllocationStrategy::default(), RootSelector::Any),
+            Err(AllocateError::NoSpaceAvailable)
+        );
+        assert_eq!(pool.allocated_count(), 1);
+    }
+
+    #[test]
+    fn allocate_in_missing_root() {
+        let mut pool = new_test_pool();
+        assert_eq!(
+            pool.allocate_in(8, None, AllocationStrategy::default(), RootSelector::V6),
+            Err(AllocateError::NoSuchRoot)
         );
+    }
+
+    #[test]
+    fn shared_names() {
+        let mut pool = new_dual_pool();
+        pool.allocate(8, Some("a-name")).unwrap();
         assert_eq!(
-            &IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 64), 27).unwrap()),
-            cidrs.next().unwrap(),
+            pool.allocate_in(
+                8,
+                Some("a-name"),
+                AllocationStrategy::default(),
+                RootSelector::V6
+            ),
+            Err(AllocateError::DuplicateName)
         );
+    }
+
+    #[test]
+    fn records_across_roots() {
+        let mut pool = new_dual_pool();
+        let v4 = pool.allocate(8, None).unwrap();
+        let v6 = pool
+            .allocate_in(8, None, AllocationStrategy::default(), RootSelector::V6)
+            .unwrap();
+        let cidrs: Vec<IpCidr> = pool.records().map(|record| record.cidr).collect();
+        assert_eq!(cidrs, vec![v4, v6]);
+        assert_eq!(pool.cidrs().copied().collect::<Vec<_>>(), vec![v4, v6]);
+        let within: Vec<IpCidr> = pool
+            .records_within(&TEST_CIDR6)
+            .map(|record| record.cidr)
+            .collect();
+        assert_eq!(within, vec![v6]);
+        assert!(pool.contains(&v6));
+        pool.rename(&v6, Some("v6")).unwrap();
+        assert_eq!(pool.find_by_name("v6"), Some(v6));
+    }
+
+    #[test]
+    fn claim_outside_roots() {
+        let mut pool = new_dual_pool();
         assert_eq!(
-            &IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 96), 28).unwrap()),
-            cidrs.next().unwrap(),
+            pool.claim(&IpCidr::from_str("192.168.0.0/24").unwrap(), None),
+            Err(ClaimError::NoSpaceAvailable)
+        );
+    }
+}
+
+mod resize_pool {
+    use super::*;
+    use crate::errors::ResizeError;
+
+    fn cidr(s: &str) -> IpCidr {
+        IpCidr::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn expand_to() {
+        let mut pool = new_test_pool();
+        let a = pool.allocate(8, Some("a")).unwrap();
+        assert_eq!(pool.expand_to(&cidr("10.16.0.0/12")), Ok(TEST_CIDR4));
+        assert_eq!(pool.cidr(), &cidr("10.16.0.0/12"));
+        assert_eq!(pool.find_by_name("a"), Some(a));
+        assert!(pool.contains(&a));
+        assert_eq!(pool.max_available_bits(), 19);
+        assert_eq!(pool.allocate(16, None), Ok(cidr("10.16.0.0/16")));
+        assert_eq!(pool.allocate(16, None), Ok(cidr("10.17.0.0/16")));
+    }
+
+    #[test]
+    fn expand_empty() {
+        let mut pool = new_test_pool();
+        pool.expand_to(&cidr("10.0.0.0/8")).unwrap();
+        assert_eq!(pool.max_available_bits(), 24);
+        assert_eq!(pool, SubnetPool::new(cidr("10.0.0.0/8")));
+    }
+
+    #[test]
+    fn expand_not_supernet() {
+        let mut pool = new_test_pool();
+        for supernet in ["10.20.0.0/16", "10.20.0.0/24", "192.168.0.0/16"] {
+            assert_eq!(
+                pool.expand_to(&cidr(supernet)),
+                Err(ResizeError::NoSuchRoot)
+            );
+        }
+    }
+
+    #[test]
+    fn expand_overlap() {
+        let mut pool = new_test_pool();
+        pool.add_root(cidr("10.21.0.0/16")).unwrap();
+        assert_eq!(
+            pool.expand_to(&cidr("10.20.0.0/15")),
+            Err(ResizeError::Overlap)
+        );
+    }
+
+    #[test]
+    fn shrink_to() {
+        let mut pool = new_test_pool();
+        let a = pool.allocate(8, Some("a")).unwrap();
+        pool.reserve(&cidr("10.20.1.0/24"), None).unwrap();
+        assert_eq!(pool.shrink_to(&cidr("10.20.0.0/20")), Ok(TEST_CIDR4));
+        assert_eq!(pool.cidr(), &cidr("10.20.0.0/20"));
+        assert_eq!(pool.find_by_name("a"), Some(a));
+        assert_eq!(pool.reserved().count(), 1);
+        assert_eq!(pool.max_available_bits(), 11);
+    }
+
+    #[test]
+    fn shrink_empty_side() {
+        let mut pool = new_test_pool();
+        pool.claim(&cidr("10.20.200.0/24"), None).unwrap();
+        assert!(pool.free(&cidr("10.20.200.0/24")));
+        pool.shrink_to(&cidr("10.20.128.0/17")).unwrap();
+        assert_eq!(pool, SubnetPool::new(cidr("10.20.128.0/17")));
+    }
+
+    #[test]
+    fn shrink_blocked() {
+        let mut pool = new_test_pool();
+        pool.allocate(8, None).unwrap();
+        pool.claim(&cidr("10.20.200.0/24"), Some("b")).unwrap();
+        pool.reserve(&cidr("10.20.128.0/24"), None).unwrap();
+        let before = pool.clone();
+        assert_eq!(
+            pool.shrink_to(&cidr("10.20.0.0/17")),
+            Err(ResizeError::Blocked(vec![
+                cidr("10.20.128.0/24"),
+                cidr("10.20.200.0/24")
+            ]))
         );
+        assert_eq!(pool, before);
+    }
+
+    #[test]
+    fn shrink_not_subnet() {
+        let mut pool = new_test_pool();
         assert_eq!(
-            &IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 112), 28).unwrap()),
-            cidrs.next().unwrap(),
+            pool.shrink_to(&cidr("10.0.0.0/8")),
+            Err(ResizeError::NoSuchRoot)
+        );
+    }
+}
+
+mod resize_subnet {
+    use super::*;
+    use crate::errors::ResizeError;
+
+    fn cidr(s: &str) -> IpCidr {
+        IpCidr::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn grow() {
+        let mut pool = new_test_pool();
+        let a = pool.allocate(8, Some("a")).unwrap();
+        pool.label(&a, &[("env", "prod")]).unwrap();
+        pool.describe(&a, Some("web")).unwrap();
+        assert_eq!(pool.grow(&a, 10), Ok(cidr("10.20.0.0/22")));
+        assert!(!pool.contains(&a));
+        assert_eq!(pool.find_by_name("a"), Some(cidr("10.20.0.0/22")));
+        let record = pool.find_record(&cidr("10.20.0.0/22")).unwrap();
+        assert_eq!(record.name.as_deref(), Some("a"));
+        assert_eq!(record.description.as_deref(), Some("web"));
+        assert_eq!(record.labels.get("env").map(String::as_str), Some("prod"));
+        assert_eq!(pool.allocated_count(), 1);
+        assert_eq!(pool.allocate(10, None), Ok(cidr("10.20.4.0/22")));
+    }
+
+    #[test]
+    fn grow_same_size() {
+        let mut pool = new_test_pool();
+        let a = pool.allocate(8, Some("a")).unwrap();
+        assert_eq!(pool.grow(&a, 8), Ok(a));
+        assert_eq!(pool.find_by_name("a"), Some(a));
+    }
+
+    #[test]
+    fn grow_occupied() {
+        let mut pool = new_test_pool();
+        let a = pool.allocate(8, Some("a")).unwrap();
+        let b = pool.allocate(8, Some("b")).unwrap();
+        pool.reserve(&cidr("10.20.3.0/24"), None).unwrap();
+        let before = pool.clone();
+        assert_eq!(
+            pool.grow(&a, 10),
+            Err(ResizeError::Occupied(vec![b, cidr("10.20.3.0/24")]))
+        );
+        assert_eq!(pool, before);
+    }
+
+    #[test]
+    fn grow_high_half() {
+        let mut pool = new_test_pool();
+        pool.allocate(8, None).unwrap();
+        let b = pool.allocate(8, Some("b")).unwrap();
+        assert_eq!(
+            pool.grow(&b, 9),
+            Err(ResizeError::Occupied(vec![cidr("10.20.0.0/24")]))
+        );
+    }
+
+    #[test]
+    fn grow_beyond_root() {
+        let mut pool = new_test_pool();
+        let a = pool.allocate(8, None).unwrap();
+        assert_eq!(pool.grow(&a, 16), Ok(TEST_CIDR4));
+        assert_eq!(
+            pool.grow(&TEST_CIDR4, 17),
+            Err(ResizeError::NoSpaceAvailable)
+        );
+    }
+
+    #[test]
+    fn grow_invalid() {
+        let mut pool = new_test_pool();
+        let a = pool.allocate(8, None).unwrap();
+        assert_eq!(pool.grow(&a, 7), Err(ResizeError::InvalidSize));
+        assert_eq!(
+            pool.grow(&cidr("10.20.1.0/24"), 9),
+            Err(ResizeError::NoSuchObject)
+        );
+    }
+
+    #[test]
+    fn grow_pair() {
+        let mut pool = new_test_pool();
+        pool.add_root(TEST_CIDR6).unwrap();
+        let (v4, v6) = pool.allocate_dual(8, 8, Some("a")).unwrap();
+        let grown_v6 = pool.grow(&v6, 9).unwrap();
+        assert_eq!(pool.find_record(&v4).unwrap().pair, Some(grown_v6));
+        assert_eq!(pool.find_by_name("a"), Some(v4));
+        let grown_v4 = pool.grow(&v4, 9).unwrap();
+        assert_eq!(pool.find_by_name("a"), Some(grown_v4));
+        assert_eq!(pool.find_record(&grown_v6).unwrap().pair, Some(grown_v4));
+    }
+
+    #[test]
+    fn grow_child() {
+        let mut pool = new_test_pool();
+        let a = pool.allocate(8, None).unwrap();
+        pool.make_child(&a).unwrap().allocate(4, Some("x")).unwrap();
+        let grown = pool.grow(&a, 9).unwrap();
+        let child = pool.child(&grown).unwrap();
+        assert_eq!(child.cidr(), &grown);
+        assert_eq!(child.find_by_name("x"), Some(cidr("10.20.0.0/28")));
+    }
+
+    #[test]
+    fn shrink() {
+        let mut pool = new_test_pool();
+        let a = pool.allocate(10, Some("a")).unwrap();
+        assert_eq!(pool.shrink(&a, 8), Ok(cidr("10.20.0.0/24")));
+        assert_eq!(pool.find_by_name("a"), Some(cidr("10.20.0.0/24")));
+        assert_eq!(pool.allocate(8, None), Ok(cidr("10.20.1.0/24")));
+        assert_eq!(pool.allocate(9, None), Ok(cidr("10.20.2.0/23")));
+    }
+
+    #[test]
+    fn shrink_invalid() {
+        let mut pool = new_test_pool();
+        let a = pool.allocate(8, None).unwrap();
+        assert_eq!(pool.shrink(&a, 9), Err(ResizeError::InvalidSize));
+        assert_eq!(pool.shrink(&a, 8), Ok(a));
+    }
+
+    #[test]
+    fn shrink_child_blocked() {
+        let mut pool = new_test_pool();
+        let a = pool.allocate(8, None).unwrap();
+        let child = pool.make_child(&a).unwrap();
+        child.claim(&cidr("10.20.0.128/28"), None).unwrap();
+        let before = pool.clone();
+        assert_eq!(
+            pool.shrink(&a, 7),
+            Err(ResizeError::Blocked(vec![cidr("10.20.0.128/28")]))
+        );
+        assert_eq!(pool, before);
+    }
+}
+
+mod split {
+    use super::*;
+    use crate::errors::SplitError;
+
+    fn cidr(s: &str) -> IpCidr {
+        IpCidr::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn too_many() {
+        let mut pool = new_test_pool();
+        pool.add_root(IpCidr::from_str("fc00::/56").unwrap())
+            .unwrap();
+        let block = cidr("fc00::/64");
+        pool.claim(&block, Some("block")).unwrap();
+        let before = pool.clone();
+        assert_eq!(
+            pool.split(&block, 0, &[]),
+            Err(SplitError::TooMany(crate::pool::MAX_SPLIT_COUNT))
+        );
+        assert_eq!(pool, before);
+    }
+
+    #[test]
+    fn largest() {
+        let mut pool = new_test_pool();
+        pool.claim(&TEST_CIDR4, None).unwrap();
+        let cidrs = pool.split(&TEST_CIDR4, 0, &[]).unwrap();
+        assert_eq!(cidrs.len(), crate::pool::MAX_SPLIT_COUNT);
+    }
+
+    #[test]
+    fn named() {
+        let mut pool = new_test_pool();
+        let block = pool.allocate(10, Some("block")).unwrap();
+        pool.label(&block, &[("env", "prod")]).unwrap();
+        let cidrs = pool.split(&block, 8, &["a", "b", "c", "d"]).unwrap();
+        assert_eq!(
+            cidrs,
+            vec![
+                cidr("10.20.0.0/24"),
+                cidr("10.20.1.0/24"),
+                cidr("10.20.2.0/24"),
+                cidr("10.20.3.0/24"),
+            ]
+        );
+        assert_eq!(pool.find_by_name("block"), None);
+        assert_eq!(pool.find_by_name("c"), Some(cidr("10.20.2.0/24")));
+        assert_eq!(pool.allocated_count(), 4);
+        let record = pool.find_record(&cidrs[3]).unwrap();
+        assert_eq!(record.name.as_deref(), Some("d"));
+        assert_eq!(record.labels.get("env").map(String::as_str), Some("prod"));
+        assert!(!pool.contains(&block));
+        assert_eq!(pool.allocate(10, None), Ok(cidr("10.20.4.0/22")));
+    }
+
+    #[test]
+    fn unnamed() {
+        let mut pool = new_test_pool();
+        let block = pool.allocate(9, Some("block")).unwrap();
+        let cidrs = pool.split(&block, 8, &[]).unwrap();
+        assert_eq!(cidrs.len(), 2);
+        assert_eq!(pool.named_count(), 0);
+        assert!(pool.records().all(|record| record.name.is_none()));
+    }
+
+    #[test]
+    fn reuse_name() {
+        let mut pool = new_test_pool();
+        let block = pool.allocate(9, Some("block")).unwrap();
+        pool.split(&block, 8, &["block", "other"]).unwrap();
+        assert_eq!(pool.find_by_name("block"), Some(cidr("10.20.0.0/24")));
+    }
+
+    #[test]
+    fn invalid() {
+        let mut pool = new_test_pool();
+        let block = pool.allocate(9, None).unwrap();
+        pool.allocate(4, Some("taken")).unwrap();
+        let before = pool.clone();
+        assert_eq!(pool.split(&block, 9, &[]), Err(SplitError::InvalidSize));
+        assert_eq!(pool.split(&block, 7, &["a"]), Err(SplitError::NameCount(4)));
+        assert_eq!(
+            pool.split(&block, 8, &["a", "a"]),
+            Err(SplitError::DuplicateName)
+        );
+        assert_eq!(
+            pool.split(&block, 8, &["a", "taken"]),
+            Err(SplitError::DuplicateName)
+        );
+        assert_eq!(
+            pool.split(&cidr("10.20.128.0/24"), 4, &[]),
+            Err(SplitError::NoSuchObject)
+        );
+        assert_eq!(pool, before);
+    }
+
+    #[test]
+    fn child_or_pair() {
+        let mut pool = new_test_pool();
+        pool.add_root(TEST_CIDR6).unwrap();
+        let block = pool.allocate(9, None).unwrap();
+        pool.make_child(&block).unwrap();
+        assert_eq!(pool.split(&block, 8, &[]), Err(SplitError::HasChildren));
+        let (v4, _) = pool.allocate_dual(8, 8, None).unwrap();
+        assert_eq!(pool.split(&v4, 7, &[]), Err(SplitError::Paired));
+    }
+}
+
+mod merge {
+    use super::*;
+    use crate::errors::MergeError;
+
+    fn cidr(s: &str) -> IpCidr {
+        IpCidr::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn merged() {
+        let mut pool = new_test_pool();
+        let a = pool.allocate(8, Some("a")).unwrap();
+        let b = pool.allocate(9, Some("b")).unwrap();
+        let c = pool.allocate(8, Some("c")).unwrap();
+        pool.label(&a, &[("env", "prod"), ("team", "web")]).unwrap();
+        pool.label(&b, &[("env", "prod"), ("team", "db")]).unwrap();
+        pool.label(&c, &[("env", "prod")]).unwrap();
+        assert_eq!(
+            pool.merge(&[c, a, b], Some("block")),
+            Ok(cidr("10.20.0.0/22"))
+        );
+        assert_eq!(pool.allocated_count(), 1);
+        assert_eq!(pool.named_count(), 1);
+        assert_eq!(pool.find_by_name("block"), Some(cidr("10.20.0.0/22")));
+        let record = pool.find_record(&cidr("10.20.0.0/22")).unwrap();
+        assert_eq!(record.labels.len(), 1);
+        assert_eq!(record.labels.get("env").map(String::as_str), Some("prod"));
+    }
+
+    #[test]
+    fn reverses_split() {
+        let mut pool = new_test_pool();
+        let block = pool.allocate(10, Some("block")).unwrap();
+        let before = pool.clone();
+        let cidrs = pool.split(&block, 8, &["a", "b", "c", "d"]).unwrap();
+        assert_eq!(pool.merge(&cidrs, Some("block")), Ok(block));
+        assert_eq!(pool, before);
+    }
+
+    #[test]
+    fn not_one_block() {
+        let mut pool = new_test_pool();
+        let a = pool.allocate(8, None).unwrap();
+        let b = pool.allocate(8, None).unwrap();
+        let c = pool.allocate(8, None).unwrap();
+        pool.reserve(&cidr("10.20.3.0/24"), None).unwrap();
+        let before = pool.clone();
+        assert_eq!(pool.merge(&[b, c], None), Err(MergeError::NotOneBlock));
+        assert_eq!(pool.merge(&[a, b, c], None), Err(MergeError::NotOneBlock));
+        assert_eq!(pool.merge(&[a, a], None), Err(MergeError::NotOneBlock));
+        assert_eq!(pool.merge(&[], None), Err(MergeError::NotOneBlock));
+        assert_eq!(pool, before);
+    }
+
+    #[test]
+    fn free_space_in_block() {
+        let mut pool = new_test_pool();
+        let a = pool.allocate(8, None).unwrap();
+        pool.allocate(8, None).unwrap();
+        let c = pool.allocate(8, None).unwrap();
+        assert_eq!(pool.merge(&[a, c], None), Err(MergeError::NotOneBlock));
+    }
+
+    #[test]
+    fn invalid() {
+        let mut pool = new_test_pool();
+        let a = pool.allocate(8, None).unwrap();
+        let b = pool.allocate(8, None).unwrap();
+        pool.allocate(4, Some("taken")).unwrap();
+        assert_eq!(
+            pool.merge(&[a, b], Some("taken")),
+            Err(MergeError::DuplicateName)
+        );
+        assert_eq!(
+            pool.merge(&[a, cidr("10.20.128.0/24")], None),
+            Err(MergeError::NoSuchObject)
+        );
+        pool.make_child(&b).unwrap();
+        assert_eq!(pool.merge(&[a, b], None), Err(MergeError::HasChildren));
+    }
+}
+
+mod available {
+    use super::*;
+
+    fn cidr(s: &str) -> IpCidr {
+        IpCidr::from_str(s).unwrap()
+    }
+
+    fn cidrs(cidrs: &[&str]) -> Vec<IpCidr> {
+        cidrs.iter().map(|s| cidr(s)).collect()
+    }
+
+    #[test]
+    fn free_blocks_empty() {
+        let pool = new_test_pool();
+        assert_eq!(pool.free_blocks().collect::<Vec<_>>(), vec![TEST_CIDR4]);
+    }
+
+    #[test]
+    fn free_blocks() {
+        let mut pool = new_test_pool();
+        pool.allocate(8, None).unwrap();
+        pool.reserve(&cidr("10.20.2.0/24"), None).unwrap();
+        pool.claim(&cidr("10.20.128.0/17"), None).unwrap();
+        assert_eq!(
+            pool.free_blocks().collect::<Vec<_>>(),
+            cidrs(&[
+                "10.20.1.0/24",
+                "10.20.3.0/24",
+                "10.20.4.0/22",
+                "10.20.8.0/21",
+                "10.20.16.0/20",
+                "10.20.32.0/19",
+                "10.20.64.0/18",
+            ])
+        );
+    }
+
+    #[test]
+    fn free_blocks_roots() {
+        let mut pool = new_test_pool();
+        pool.add_root(TEST_CIDR6).unwrap();
+        pool.claim(&TEST_CIDR4, None).unwrap();
+        assert_eq!(pool.free_blocks().collect::<Vec<_>>(), vec![TEST_CIDR6]);
+    }
+
+    #[test]
+    fn free_blocks_within() {
+        let mut pool = new_test_pool();
+        pool.allocate(8, None).unwrap();
+        assert_eq!(
+            pool.free_blocks_within(&cidr("10.20.0.0/22"))
+                .collect::<Vec<_>>(),
+            cidrs(&["10.20.1.0/24", "10.20.2.0/23"])
+        );
+        assert_eq!(
+            pool.free_blocks_within(&cidr("10.20.200.0/24"))
+                .collect::<Vec<_>>(),
+            cidrs(&["10.20.200.0/24"])
+        );
+        assert_eq!(pool.free_blocks_within(&cidr("10.20.0.0/25")).count(), 0);
+    }
+
+    #[test]
+    fn candidates() {
+        let mut pool = new_test_pool();
+        pool.claim(&cidr("10.20.0.0/17"), None).unwrap();
+        pool.claim(&cidr("10.20.128.0/18"), None).unwrap();
+        pool.claim(&cidr("10.20.192.0/19"), None).unwrap();
+        pool.claim(&cidr("10.20.224.0/21"), None).unwrap();
+        assert_eq!(
+            pool.candidates(12).collect::<Vec<_>>(),
+            cidrs(&["10.20.240.0/20"])
+        );
+        assert_eq!(
+            pool.candidates(11).collect::<Vec<_>>(),
+            cidrs(&["10.20.232.0/21", "10.20.240.0/21", "10.20.248.0/21"])
+        );
+        assert_eq!(pool.candidates(8).count(), 24);
+        assert_eq!(pool.candidates(13).count(), 0);
+    }
+
+    #[test]
+    fn candidates_within() {
+        let pool = new_test_pool();
+        assert_eq!(
+            pool.candidates_within(8, &cidr("10.20.4.0/23"))
+                .collect::<Vec<_>>(),
+            cidrs(&["10.20.4.0/24", "10.20.5.0/24"])
+        );
+    }
+
+    #[test]
+    fn candidates_can_be_claimed() {
+        let mut pool = new_test_pool();
+        pool.allocate(6, None).unwrap();
+        let candidates: Vec<IpCidr> = pool.candidates(6).take(3).collect();
+        for candidate in candidates {
+            assert_eq!(pool.claim(&candidate, None), Ok(()));
+        }
+    }
+}
+
+mod stats {
+    use super::*;
+    use crate::PrefixStats;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn empty() {
+        let stats = new_test_pool().stats();
+        assert_eq!(stats.total, 65536);
+        assert_eq!(stats.allocated, 0);
+        assert_eq!(stats.free, 65536);
+        assert_eq!(stats.subnets, 0);
+        assert_eq!(stats.utilization, 0.0);
+        assert_eq!(stats.free_blocks, BTreeMap::from([(16, 1)]));
+        assert_eq!(stats.fragmentation, 0.0);
+    }
+
+    #[test]
+    fn allocated() {
+        let mut pool = new_test_pool();
+        pool.allocate(8, None).unwrap();
+        pool.allocate(8, None).unwrap();
+        pool.allocate(4, None).unwrap();
+        pool.reserve(&IpCidr::from_str("10.20.255.0/24").unwrap(), None)
+            .unwrap();
+        let stats = pool.stats();
+        assert_eq!(stats.total, 65536);
+        assert_eq!(stats.allocated, 528);
+        assert_eq!(stats.reserved, 256);
+        assert_eq!(stats.free, 65536 - 528 - 256);
+        assert_eq!(stats.subnets, 3);
+        assert_eq!(stats.utilization, 528.0 / 65536.0);
+        assert_eq!(
+            stats.prefixes,
+            BTreeMap::from([
+                (
+                    24,
+                    PrefixStats {
+                        subnets: 2,
+                        addresses: 512
+                    }
+                ),
+                (
+                    28,
+                    PrefixStats {
+                        subnets: 1,
+                        addresses: 16
+                    }
+                ),
+            ])
+        );
+        assert_eq!(stats.free_blocks.get(&17), None);
+        assert_eq!(stats.free_blocks.get(&18), Some(&2));
+        assert_eq!(stats.free_blocks.get(&24), Some(&2));
+        assert_eq!(stats.free_blocks.values().sum::<usize>(), 17);
+        assert_eq!(stats.fragmentation, 1.0 - 16384.0 / 64752.0);
+    }
+
+    #[test]
+    fn full() {
+        let mut pool = new_test_pool();
+        pool.claim(&TEST_CIDR4, None).unwrap();
+        let stats = pool.stats();
+        assert_eq!(stats.free, 0);
+        assert_eq!(stats.utilization, 1.0);
+        assert_eq!(stats.fragmentation, 0.0);
+    }
+
+    #[test]
+    fn roots() {
+        let mut pool = new_test_pool();
+        pool.add_root(TEST_CIDR6).unwrap();
+        pool.claim(&TEST_CIDR4, None).unwrap();
+        let stats = pool.stats();
+        assert_eq!(stats.total, 131072);
+        assert_eq!(stats.free, 65536);
+        assert_eq!(stats.fragmentation, 0.0);
+    }
+}
+
+mod capacity {
+    use super::*;
+    use crate::{RequestSize, RootSelector};
+
+    #[test]
+    fn capacity() {
+        let mut pool = new_test_pool();
+        assert_eq!(pool.capacity(8), 256);
+        assert_eq!(pool.capacity(16), 1);
+        assert_eq!(pool.capacity(17), 0);
+        pool.allocate(9, None).unwrap();
+        pool.allocate(4, None).unwrap();
+        assert_eq!(pool.capacity(8), 253);
+        assert_eq!(pool.capacity(4), 253 * 16 + 15);
+        assert_eq!(pool.capacity(15), 1);
+    }
+
+    #[test]
+    fn capacity_saturates() {
+        let pool = SubnetPool::new(IpCidr::from_str("::/0").unwrap());
+        assert_eq!(pool.capacity(0), u128::MAX);
+        assert_eq!(pool.capacity(1), 1 << 127);
+    }
+
+    #[test]
+    fn can_fit() {
+        let mut pool = new_test_pool();
+        pool.allocate(4, None).unwrap();
+        assert!(pool.can_fit(&[]));
+        assert!(pool.can_fit(&[(8, 10), (14, 3)]));
+        assert!(!pool.can_fit(&[(14, 4)]));
+        // Small subnets are placed after large ones
+        assert!(pool.can_fit(&[(4, 511), (15, 1), (14, 1), (13, 1)]));
+        assert!(!pool.can_fit(&[(4, 512), (15, 1), (14, 1), (13, 1)]));
+        assert_eq!(pool.allocated_count(), 1);
+    }
+
+    fn new_dual_pool() -> SubnetPool {
+        let mut pool = new_test_pool();
+        pool.add_root(TEST_CIDR6).unwrap();
+        pool
+    }
+
+    #[test]
+    fn capacity_in() {
+        let pool = new_dual_pool();
+        let capacity = |size, root| pool.capacity_in(size, root);
+        assert_eq!(capacity(RequestSize::HostBits(8), RootSelector::Any), 512);
+        assert_eq!(capacity(RequestSize::HostBits(8), RootSelector::V6), 256);
+        assert_eq!(
+            capacity(RequestSize::PrefixLength(24), RootSelector::Any),
+            256
+        );
+        assert_eq!(
+            capacity(RequestSize::PrefixLength(120), RootSelector::Any),
+            256
+        );
+        assert_eq!(capacity(RequestSize::PrefixLength(24), RootSelector::V6), 0);
+    }
+
+    #[test]
+    fn can_fit_in() {
+        let pool = new_dual_pool();
+        let v4 = RequestSize::PrefixLength(24);
+        let v6 = RequestSize::PrefixLength(120);
+        assert!(pool.can_fit_in(&[(v4, 256), (v6, 256)], RootSelector::Any));
+        assert!(!pool.can_fit_in(&[(v4, 257)], RootSelector::Any));
+        assert!(!pool.can_fit_in(&[(v4, 1)], RootSelector::V6));
+        assert!(pool.can_fit_in(&[(RequestSize::HostBits(8), 512)], RootSelector::Any));
+        assert!(!pool.can_fit_in(&[(RequestSize::HostBits(8), 257)], RootSelector::V4));
+    }
+}
+
+mod find_containing {
+    use super::*;
+    use crate::Containing;
+    use std::net::IpAddr;
+
+    fn address(s: &str) -> IpAddr {
+        IpAddr::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn allocated() {
+        let mut pool = new_test_pool();
+        pool.allocate(8, None).unwrap();
+        let web = pool.allocate(8, Some("web")).unwrap();
+        let found = pool.find_containing(&address("10.20.1.77")).unwrap();
+        assert_eq!(
+            found,
+            Containing::Allocated(pool.find_record(&web).unwrap())
+        );
+        assert_eq!(found.cidr(), web);
+    }
+
+    #[test]
+    fn reserved() {
+        let mut pool = new_test_pool();
+        let reserved = IpCidr::from_str("10.20.128.0/24").unwrap();
+        pool.reserve(&reserved, Some("management")).unwrap();
+        match pool.find_containing(&address("10.20.128.1")) {
+            Some(Containing::Reserved(record)) => {
+                assert_eq!(record.cidr, reserved);
+                assert_eq!(record.description.as_deref(), Some("management"));
+            }
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn free() {
+        let mut pool = new_test_pool();
+        pool.allocate(8, None).unwrap();
+        assert_eq!(
+            pool.find_containing(&address("10.20.200.1")),
+            Some(Containing::Free(
+                IpCidr::from_str("10.20.128.0/17").unwrap()
+            ))
+        );
+        assert_eq!(
+            new_test_pool().find_containing(&address("10.20.0.0")),
+            Some(Containing::Free(TEST_CIDR4))
+        );
+    }
+
+    #[test]
+    fn outside() {
+        let mut pool = new_test_pool();
+        pool.add_root(TEST_CIDR6).unwrap();
+        assert_eq!(pool.find_containing(&address("10.21.0.0")), None);
+        assert_eq!(
+            pool.find_containing(&address("1:2:3:4:a:14::1")),
+            Some(Containing::Free(TEST_CIDR6))
+        );
+    }
+}
+
+mod hosts {
+    use super::*;
+    use crate::errors::{HostError, MergeError, ResizeError};
+    use std::net::IpAddr;
+
+    fn address(s: &str) -> IpAddr {
+        IpAddr::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn allocate_host() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(2, None).unwrap();
+        assert_eq!(
+            pool.allocate_host(&cidr, Some("router")),
+            Ok(address("10.20.0.1"))
+        );
+        assert_eq!(pool.allocate_host(&cidr, None), Ok(address("10.20.0.2")));
+        assert_eq!(
+            pool.allocate_host(&cidr, None),
+            Err(HostError::NoSpaceAvailable)
+        );
+        assert_eq!(
+            pool.allocate_host_with(&cidr, None, true),
+            Ok(address("10.20.0.0"))
+        );
+        assert_eq!(
+            pool.allocate_host_with(&cidr, None, true),
+            Ok(address("10.20.0.3"))
+        );
+        assert_eq!(pool.find_host(&cidr, "router"), Some(address("10.20.0.1")));
+    }
+
+    #[test]
+    fn allocate_host_fills_gaps() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(4, None).unwrap();
+        pool.claim_host(&cidr, &address("10.20.0.2"), None).unwrap();
+        pool.claim_host(&cidr, &address("10.20.0.4"), None).unwrap();
+        assert_eq!(pool.allocate_host(&cidr, None), Ok(address("10.20.0.1")));
+        assert_eq!(pool.allocate_host(&cidr, None), Ok(address("10.20.0.3")));
+        assert_eq!(pool.allocate_host(&cidr, None), Ok(address("10.20.0.5")));
+    }
+
+    #[test]
+    fn allocate_host_small_v4() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(1, None).unwrap();
+        assert_eq!(pool.allocate_host(&cidr, None), Ok(address("10.20.0.0")));
+        assert_eq!(pool.allocate_host(&cidr, None), Ok(address("10.20.0.1")));
+    }
+
+    #[test]
+    fn allocate_host_v6() {
+        let mut pool = SubnetPool::new(TEST_CIDR6);
+        let cidr = pool.allocate(4, None).unwrap();
+        assert_eq!(
+            pool.allocate_host(&cidr, None),
+            Ok(address("1:2:3:4:a:14::"))
+        );
+    }
+
+    #[test]
+    fn claim_host() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(4, None).unwrap();
+        let dns = address("10.20.0.53");
+        assert_eq!(
+            pool.claim_host(&cidr, &dns, Some("dns")),
+            Err(HostError::OutOfRange)
+        );
+        let dns = address("10.20.0.13");
+        assert_eq!(pool.claim_host(&cidr, &dns, Some("dns")), Ok(()));
+        assert_eq!(
+            pool.claim_host(&cidr, &dns, None),
+            Err(HostError::DuplicateHost)
+        );
+        assert_eq!(
+            pool.claim_host(&cidr, &address("10.20.0.14"), Some("dns")),
+            Err(HostError::DuplicateName)
+        );
+        assert_eq!(
+            pool.claim_host(&IpCidr::from_str("10.20.1.0/28").unwrap(), &dns, None),
+            Err(HostError::NoSuchObject)
+        );
+        let record = pool.find_record(&cidr).unwrap();
+        assert_eq!(record.hosts.get(&dns), Some(&Some("dns".to_string())));
+    }
+
+    #[test]
+    fn rename_and_free_host() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(4, None).unwrap();
+        let router = pool.allocate_host(&cidr, Some("router")).unwrap();
+        let vip = pool.allocate_host(&cidr, None).unwrap();
+        assert_eq!(
+            pool.rename_host(&cidr, &vip, Some("router")),
+            Err(HostError::DuplicateName)
+        );
+        assert_eq!(pool.rename_host(&cidr, &vip, Some("vip")), Ok(()));
+        assert_eq!(pool.find_host(&cidr, "vip"), Some(vip));
+        assert_eq!(pool.free_host(&cidr, &router), Ok(()));
+        assert_eq!(pool.free_host(&cidr, &router), Err(HostError::NoSuchHost));
+        assert_eq!(
+            pool.rename_host(&cidr, &router, None),
+            Err(HostError::NoSuchHost)
+        );
+        assert_eq!(pool.find_host(&cidr, "router"), None);
+    }
+
+    #[test]
+    fn split_and_merge() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(9, None).unwrap();
+        pool.claim_host(&cidr, &address("10.20.0.1"), Some("a"))
+            .unwrap();
+        pool.claim_host(&cidr, &address("10.20.1.1"), Some("b"))
+            .unwrap();
+        let cidrs = pool.split(&cidr, 8, &[]).unwrap();
+        assert_eq!(pool.find_host(&cidrs[0], "a"), Some(address("10.20.0.1")));
+        assert_eq!(pool.find_host(&cidrs[0], "b"), None);
+        assert_eq!(pool.find_host(&cidrs[1], "b"), Some(address("10.20.1.1")));
+        pool.claim_host(&cidrs[1], &address("10.20.1.2"), Some("a"))
+            .unwrap();
+        assert_eq!(pool.merge(&cidrs, None), Err(MergeError::DuplicateName));
+        pool.free_host(&cidrs[1], &address("10.20.1.2")).unwrap();
+        assert_eq!(pool.merge(&cidrs, None), Ok(cidr));
+        assert_eq!(pool.find_record(&cidr).unwrap().hosts.len(), 2);
+    }
+
+    #[test]
+    fn shrink_blocked() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(8, None).unwrap();
+        pool.claim_host(&cidr, &address("10.20.0.200"), None)
+            .unwrap();
+        assert_eq!(
+            pool.shrink(&cidr, 7),
+            Err(ResizeError::Blocked(vec![IpCidr::from_str(
+                "10.20.0.200/32"
+            )
+            .unwrap()]))
+        );
+        assert!(pool.shrink(&cidr, 5).is_err());
+        pool.free_host(&cidr, &address("10.20.0.200")).unwrap();
+        assert!(pool.shrink(&cidr, 7).is_ok());
+    }
+
+    #[test]
+    fn free_subnet_drops_hosts() {
+        let mut pool = new_test_pool();
+        let cidr = pool.allocate(8, None).unwrap();
+        pool.allocate_host(&cidr, None).unwrap();
+        assert!(pool.free(&cidr));
+        pool.claim(&cidr, None).unwrap();
+        assert!(pool.find_record(&cidr).unwrap().hosts.is_empty());
+    }
+}
+
+mod dual {
+    use super::*;
+    use crate::errors::DeleteError;
+
+    fn new_dual_pool() -> SubnetPool {
+        let mut pool = new_test_pool();
+        pool.add_root(TEST_CIDR6).unwrap();
+        pool
+    }
+
+    #[test]
+    fn allocate_dual() {
+        let mut pool = new_dual_pool();
+        let (v4, v6) = pool.allocate_dual(8, 4, Some("web-a")).unwrap();
+        assert_eq!(v4, IpCidr::from_str("10.20.0.0/24").unwrap());
+        assert!(v6.is_ipv6());
+        assert_eq!(pool.find_by_name("web-a"), Some(v4));
+        assert_eq!(pool.find_record(&v4).unwrap().pair, Some(v6));
+        assert_eq!(pool.find_record(&v6).unwrap().pair, Some(v4));
+        assert_eq!(
+            pool.find_record(&v6).unwrap().name.as_deref(),
+            Some("web-a")
+        );
+        assert_eq!(pool.allocated_count(), 2);
+        assert_eq!(pool.named_count(), 1);
+    }
+
+    #[test]
+    fn missing_family() {
+        let mut pool = new_test_pool();
+        assert_eq!(
+            pool.allocate_dual(8, 4, Some("web-a")),
+            Err(AllocateError::NoSuchRoot)
+        );
+    }
+
+    #[test]
+    fn duplicate_name() {
+        let mut pool = new_dual_pool();
+        pool.allocate(8, Some("web-a")).unwrap();
+        assert_eq!(
+            pool.allocate_dual(8, 4, Some("web-a")),
+            Err(AllocateError::DuplicateName)
+        );
+        assert_eq!(pool.allocated_count(), 1);
+    }
+
+    #[test]
+    fn both_or_neither() {
+        let mut pool = new_dual_pool();
+        assert_eq!(
+            pool.allocate_dual(8, 17, Some("web-a")),
+            Err(AllocateError::NoSpaceAvailable)
+        );
+        assert_eq!(pool.allocated_count(), 0);
+        assert_eq!(pool.find_by_name("web-a"), None);
+        assert_eq!(pool.max_available_bits(), 16);
+    }
+
+    #[test]
+    fn free_both() {
+        let mut pool = new_dual_pool();
+        let (v4, v6) = pool.allocate_dual(8, 4, Some("web-a")).unwrap();
+        assert!(pool.free(&v6));
+        assert!(!pool.contains(&v4));
+        assert!(!pool.contains(&v6));
+        assert_eq!(pool.find_by_name("web-a"), None);
+    }
+
+    #[test]
+    fn free_child_in_pair() {
+        let mut pool = new_dual_pool();
+        let (v4, v6) = pool.allocate_dual(8, 4, None).unwrap();
+        pool.make_child(&v6).unwrap().allocate(2, None).unwrap();
+        assert_eq!(pool.free_with(&v4, false), Err(DeleteError::HasChildren));
+        assert_eq!(pool.free_with(&v4, true), Ok(()));
+    }
+
+    #[test]
+    fn rename_both() {
+        let mut pool = new_dual_pool();
+        let (v4, v6) = pool.allocate_dual(8, 4, Some("web-a")).unwrap();
+        pool.rename(&v6, Some("web-b")).unwrap();
+        assert_eq!(pool.find_by_name("web-a"), None);
+        assert_eq!(pool.find_by_name("web-b"), Some(v4));
+        assert_eq!(
+            pool.find_record(&v4).unwrap().name.as_deref(),
+            Some("web-b")
+        );
+        assert_eq!(
+            pool.find_record(&v6).unwrap().name.as_deref(),
+            Some("web-b")
+        );
+    }
+
+    #[test]
+    fn serialize() {
+        let mut pool = new_dual_pool();
+        pool.allocate_dual(8, 4, Some("web-a")).unwrap();
+
+        serde_test::assert_tokens(
+            &pool,
+            &[
+                serde_test::Token::Struct {
+                    name: "SubnetPool",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidrs"),
+                serde_test::Token::Seq { len: Some(2) },
+                serde_test::Token::Str("10.20.0.0/16"),
+                serde_test::Token::Str("1:2:3:4:a:14::/112"),
+                serde_test::Token::SeqEnd,
+                serde_test::Token::Str("subnets"),
+                serde_test::Token::Seq { len: Some(2) },
+                serde_test::Token::Struct {
+                    name: "CidrRecord",
+                    len: 3,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.0/24"),
+                serde_test::Token::Str("name"),
+                serde_test::Token::Str("web-a"),
+                serde_test::Token::Str("pair"),
+                serde_test::Token::Str("1:2:3:4:a:14::/124"),
+                serde_test::Token::StructEnd,
+                serde_test::Token::Struct {
+                    name: "CidrRecord",
+                    len: 3,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("1:2:3:4:a:14::/124"),
+                serde_test::Token::Str("name"),
+                serde_test::Token::Str("web-a"),
+                serde_test::Token::Str("pair"),
+                serde_test::Token::Str("10.20.0.0/24"),
+                serde_test::Token::StructEnd,
+                serde_test::Token::SeqEnd,
+                serde_test::Token::StructEnd,
+            ],
+        );
+    }
+}
+
+mod names {
+    use super::*;
+    #[test]
+    fn success() {
+        let mut pool = new_test_pool();
+        pool.allocate(4, Some("a-name")).unwrap();
+        pool.allocate(4, Some("b-name")).unwrap();
+        pool.allocate(4, None).unwrap();
+        let mut names: Vec<String> = pool.names().collect();
+        names.sort();
+        assert_eq!(names.len(), 2);
+        assert_eq!(names[0], "a-name");
+        assert_eq!(names[1], "b-name");
+    }
+}
+
+mod cidrs {
+    use super::*;
+    #[test]
+    fn no_cidrs() {
+        let pool = new_test_pool();
+        let cidrs = pool.cidrs();
+        assert_eq!(cidrs.count(), 0);
+    }
+
+    #[test]
+    fn some() {
+        let mut pool = new_test_pool();
+        for bits in [4, 5, 5, 4, 4, 4].iter() {
+            pool.allocate(*bits, None).unwrap();
+        }
+        let mut cidrs = pool.cidrs();
+        assert_eq!(
+            &IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 0), 28).unwrap()),
+            cidrs.next().unwrap(),
+        );
+        assert_eq!(
+            &IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 16), 28).unwrap()),
+            cidrs.next().unwrap(),
+        );
+        assert_eq!(
+            &IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 32), 27).unwrap()),
+            cidrs.next().unwrap(),
+        );
+        assert_eq!(
+            &IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 64), 27).unwrap()),
+            cidrs.next().unwrap(),
+        );
+        assert_eq!(
+            &IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 96), 28).unwrap()),
+            cidrs.next().unwrap(),
+        );
+        assert_eq!(
+            &IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 112), 28).unwrap()),
+            cidrs.next().unwrap(),
+        );
+        assert_eq!(cidrs.count(), 0);
+    }
+}
+
+mod records {
+    use super::*;
+    use crate::CidrRecord;
+
+    #[test]
+    fn success() {
+        let mut pool = new_test_pool();
+        pool.allocate(4, Some("a-name")).unwrap();
+        pool.allocate(4, Some("b-name")).unwrap();
+        pool.allocate(4, None).unwrap();
+        let mut entries: Vec<&CidrRecord> = pool.records().collect();
+        entries.sort();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[0],
+            &CidrRecord::new(
+                IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 0), 28).unwrap()),
+                Some("a-name")
+            )
+        );
+        assert_eq!(
+            entries[1],
+            &CidrRecord::new(
+                IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 16), 28).unwrap()),
+                Some("b-name")
+            )
+        );
+        assert_eq!(
+            entries[2],
+            &CidrRecord::new(
+                IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 32), 28).unwrap()),
+                None
+            )
+        );
+    }
+}
+
+mod records_within {
+    use super::*;
+    use crate::CidrRecord;
+
+    #[test]
+    fn no_overlap() {
+        let mut pool = new_test_pool();
+        pool.allocate(4, Some("a-name")).unwrap();
+        pool.allocate(4, Some("b-name")).unwrap();
+        pool.allocate(4, None).unwrap();
+        let entries: Vec<&CidrRecord> = pool
+            .records_within(&IpCidr::from_str("20.20.0.0/16").unwrap())
+            .collect();
+        assert_eq!(entries.len(), 0);
+    }
+
+    #[test]
+    fn superset() {
+        let mut pool = new_test_pool();
+        pool.allocate(4, Some("a-name")).unwrap();
+        pool.allocate(4, Some("b-name")).unwrap();
+        pool.allocate(4, None).unwrap();
+        let mut entries: Vec<&CidrRecord> = pool
+            .records_within(&IpCidr::from_str("10.0.0.0/8").unwrap())
+            .collect();
+        entries.sort();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[0],
+            &CidrRecord::new(
+                IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 0), 28).unwrap()),
+                Some("a-name")
+            )
+        );
+        assert_eq!(
+            entries[1],
+            &CidrRecord::new(
+                IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 16), 28).unwrap()),
+                Some("b-name")
+            )
+        );
+        assert_eq!(
+            entries[2],
+            &CidrRecord::new(
+                IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 32), 28).unwrap()),
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn subset() {
+        let mut pool = new_test_pool();
+        pool.allocate(4, Some("a-name")).unwrap();
+        pool.allocate(4, Some("b-name")).unwrap();
+        pool.allocate(4, None).unwrap();
+        let mut entries: Vec<&CidrRecord> = pool
+            .records_within(&IpCidr::from_str("10.20.0.0/27").unwrap())
+            .collect();
+        entries.sort();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0],
+            &CidrRecord::new(
+                IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 0), 28).unwrap()),
+                Some("a-name")
+            )
+        );
+        assert_eq!(
+            entries[1],
+            &CidrRecord::new(
+                IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 16), 28).unwrap()),
+                Some("b-name")
+            )
+        );
+    }
+}
+
+mod from_sorted_records {
+    use super::*;
+    use crate::errors::{InvalidRecord, LoadError, RecordError, StoredRecord};
+
+    fn records(pool: &SubnetPool) -> (Vec<CidrRecord>, Vec<CidrRecord>) {
+        (
+            pool.records().cloned().collect(),
+            pool.reserved().cloned().collect(),
+        )
+    }
+
+    fn stored(index: usize, cidr: &str, name: Option<&str>) -> StoredRecord {
+        StoredRecord {
+            index,
+            reserved: false,
+            cidr: cidr.parse().unwrap(),
+            name: name.map(|name| name.to_string()),
+        }
+    }
+
+    fn invalid(record: StoredRecord, error: RecordError) -> InvalidRecord {
+        InvalidRecord { record, error }
+    }
+
+    #[test]
+    fn no_roots() {
+        assert_eq!(
+            SubnetPool::from_sorted_records(&[], vec![], vec![]),
+            Err(LoadError::NoRoots)
+        );
+    }
+
+    #[test]
+    fn overlapping_roots() {
+        let roots = [TEST_CIDR4, "10.20.0.0/24".parse().unwrap()];
+        assert_eq!(
+            SubnetPool::from_sorted_records(&roots, vec![], vec![]),
+            Err(LoadError::Root(CreateError::Overlap))
+        );
+    }
+
+    #[test]
+    fn success() {
+        let mut pool = new_test_pool();
+        pool.add_root(TEST_CIDR6).unwrap();
+        for name in ["a", "b", "c"] {
+            pool.allocate(4, Some(name)).unwrap();
+        }
+        pool.allocate(8, None).unwrap();
+        pool.allocate_dual(4, 4, Some("dual")).unwrap();
+        let cidr = pool.allocate(6, Some("labeled")).unwrap();
+        pool.label(&cidr, &[("env", "prod")]).unwrap();
+        pool.reserve(&"10.20.128.0/24".parse().unwrap(), Some("gateway"))
+            .unwrap();
+
+        let (mut subnets, reserved) = records(&pool);
+        subnets.reverse();
+        let roots: Vec<IpCidr> = pool.roots().copied().collect();
+        let built = SubnetPool::from_sorted_records(&roots, subnets, reserved).unwrap();
+        assert_eq!(built, pool);
+        assert_eq!(built.find_by_name("dual"), pool.find_by_name("dual"));
+        assert_eq!(built.named_count(), 5);
+    }
+
+    #[test]
+    fn reports_every_record() {
+        let mut pool = new_test_pool();
+        pool.allocate(8, Some("a")).unwrap();
+        pool.allocate(4, Some("b")).unwrap();
+        let (mut subnets, _) = records(&pool);
+        let mut overlapping = subnets[0].clone();
+        overlapping.cidr = "10.20.0.16/28".parse().unwrap();
+        overlapping.name = Some("c".to_string());
+        let mut outside = subnets[1].clone();
+        outside.cidr = "10.30.0.0/24".parse().unwrap();
+        outside.name = None;
+        let mut duplicate = subnets[1].clone();
+        duplicate.cidr = "10.20.2.0/24".parse().unwrap();
+        let mut unpaired = subnets[1].clone();
+        unpaired.cidr = "10.20.3.0/24".parse().unwrap();
+        unpaired.name = None;
+        unpaired.pair = Some("fc00::/64".parse().unwrap());
+        subnets.extend([overlapping, outside, duplicate, unpaired]);
+        let mut reserved = CidrRecord::new("10.20.0.32/28".parse().unwrap(), None);
+        reserved.description = Some("inside a".to_string());
+
+        let error = SubnetPool::from_sorted_records(&[TEST_CIDR4], subnets, vec![reserved]);
+        let a = stored(0, "10.20.0.0/24", Some("a"));
+        assert_eq!(
+            error,
+            Err(LoadError::InvalidRecords(vec![
+                invalid(
+                    stored(2, "10.20.0.16/28", Some("c")),
+                    RecordError::Overlap(a.clone())
+                ),
+                invalid(stored(3, "10.30.0.0/24", None), RecordError::OutsideRoots),
+                invalid(
+                    stored(4, "10.20.2.0/24", Some("b")),
+                    RecordError::DuplicateName(stored(1, "10.20.1.0/28", Some("b")))
+                ),
+                invalid(
+                    stored(5, "10.20.3.0/24", None),
+                    RecordError::UnmatchedPair("fc00::/64".parse().unwrap())
+                ),
+                invalid(
+                    StoredRecord {
+                        reserved: true,
+                        ..stored(0, "10.20.0.32/28", None)
+                    },
+                    RecordError::Overlap(a)
+                ),
+            ]))
+        );
+    }
+}
+
+mod stored_pool {
+    use super::*;
+    use crate::pool::StoredPool;
+    use serde_test::{assert_de_tokens, assert_de_tokens_error};
+
+    #[test]
+    fn into_pool() {
+        let mut pool = new_test_pool();
+        pool.set_strategy(crate::AllocationStrategy::BestFit);
+        pool.allocate(4, Some("a-name")).unwrap();
+        let stored = StoredPool {
+            version: crate::schema::UNVERSIONED,
+            roots: vec![TEST_CIDR4],
+            subnets: pool.records().cloned().collect(),
+            reserved: vec![],
+            strategy: crate::AllocationStrategy::BestFit,
+        };
+        assert_eq!(stored.into_pool(), Ok(pool));
+    }
+
+    #[test]
+    fn deserialize() {
+        assert_de_tokens(
+            &StoredPool {
+                version: crate::schema::UNVERSIONED,
+                roots: vec![TEST_CIDR4, TEST_CIDR4],
+                subnets: vec![],
+                reserved: vec![],
+                strategy: crate::AllocationStrategy::default(),
+            },
+            &[
+                serde_test::Token::Struct {
+                    name: "SubnetPool",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidrs"),
+                serde_test::Token::Seq { len: Some(2) },
+                serde_test::Token::Str("10.20.0.0/16"),
+                serde_test::Token::Str("10.20.0.0/16"),
+                serde_test::Token::SeqEnd,
+                serde_test::Token::Str("subnets"),
+                serde_test::Token::Seq { len: Some(0) },
+                serde_test::Token::SeqEnd,
+                serde_test::Token::StructEnd,
+            ],
+        );
+    }
+
+    fn stored(version: u32) -> StoredPool {
+        StoredPool {
+            version,
+            roots: vec![TEST_CIDR4],
+            subnets: vec![],
+            reserved: vec![],
+            strategy: crate::AllocationStrategy::default(),
+        }
+    }
+
+    #[test]
+    fn migrate_unversioned() {
+        let mut pool = stored(crate::schema::UNVERSIONED);
+        assert_eq!(pool.migrate(), Ok(()));
+        assert_eq!(pool, stored(crate::schema::SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn migrate_current() {
+        let mut pool = stored(crate::schema::SCHEMA_VERSION);
+        assert_eq!(pool.migrate(), Ok(()));
+        assert_eq!(pool, stored(crate::schema::SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn migrate_newer() {
+        let version = crate::schema::SCHEMA_VERSION + 1;
+        assert_eq!(
+            stored(version).into_pool(),
+            Err(LoadError::NewerVersion {
+                version,
+                supported: crate::schema::SCHEMA_VERSION,
+            })
+        );
+    }
+
+    fn paired(pair_v6: bool, name_v6: Option<&str>) -> StoredPool {
+        let v4 = IpCidr::from_str("10.20.0.0/24").unwrap();
+        let v6 = IpCidr::from_str("1:2:3:4:a:14::/120").unwrap();
+        let mut record_v4 = CidrRecord::new(v4, Some("web"));
+        record_v4.pair = Some(v6);
+        let mut record_v6 = CidrRecord::new(v6, name_v6);
+        record_v6.pair = pair_v6.then_some(v4);
+        StoredPool {
+            roots: vec![TEST_CIDR4, TEST_CIDR6],
+            subnets: vec![record_v6, record_v4],
+            ..stored(crate::schema::SCHEMA_VERSION)
+        }
+    }
+
+    #[test]
+    fn repair_one_sided_pair() {
+        let mut pool = paired(false, None);
+        assert_eq!(
+            pool.repair(),
+            vec![InvariantError::OneSidedPair {
+                cidr: IpCidr::from_str("10.20.0.0/24").unwrap(),
+                pair: IpCidr::from_str("1:2:3:4:a:14::/120").unwrap(),
+            }]
+        );
+        assert_eq!(pool, paired(true, Some("web")));
+        assert!(pool.into_pool().is_ok());
+    }
+
+    #[test]
+    fn repair_pair_name() {
+        let mut pool = paired(true, Some("other"));
+        assert_eq!(
+            pool.repair(),
+            vec![InvariantError::OneSidedPair {
+                cidr: IpCidr::from_str("1:2:3:4:a:14::/120").unwrap(),
+                pair: IpCidr::from_str("10.20.0.0/24").unwrap(),
+            }]
+        );
+        assert_eq!(pool, paired(true, Some("web")));
+    }
+
+    #[test]
+    fn repair_consistent_pair() {
+        let mut pool = paired(true, Some("web"));
+        assert_eq!(pool.repair(), vec![]);
+        assert_eq!(pool, paired(true, Some("web")));
+    }
+
+    #[test]
+    fn repair_pair_taken() {
+        let mut pool = paired(false, None);
+        pool.subnets[0].pair = Some(IpCidr::from_str("10.20.1.0/24").unwrap());
+        let expected = pool.clone();
+        assert_eq!(pool.repair(), vec![]);
+        assert_eq!(pool, expected);
+    }
+
+    #[test]
+    fn migrate_invalid() {
+        let mut pool = stored(0);
+        assert_eq!(pool.migrate(), Err(LoadError::InvalidVersion(0)));
+        assert_eq!(pool, stored(0));
+    }
+
+    #[test]
+    fn deserialize_version() {
+        assert_de_tokens(
+            &stored(crate::schema::SCHEMA_VERSION),
+            &[
+                serde_test::Token::Struct {
+                    name: "SubnetPool",
+                    len: 3,
+                },
+                serde_test::Token::Str("version"),
+                serde_test::Token::U32(crate::schema::SCHEMA_VERSION),
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.0/16"),
+                serde_test::Token::Str("subnets"),
+                serde_test::Token::Seq { len: Some(0) },
+                serde_test::Token::SeqEnd,
+                serde_test::Token::StructEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn deserialize_newer_version() {
+        assert_de_tokens_error::<StoredPool>(
+            &[
+                serde_test::Token::Struct {
+                    name: "SubnetPool",
+                    len: 3,
+                },
+                serde_test::Token::Str("version"),
+                serde_test::Token::U32(crate::schema::SCHEMA_VERSION + 1),
+            ],
+            &format!(
+                "Pool file version {} is newer than the supported version {}",
+                crate::schema::SCHEMA_VERSION + 1,
+                crate::schema::SCHEMA_VERSION
+            ),
+        );
+    }
+
+    #[test]
+    fn deserialize_version_zero() {
+        assert_de_tokens_error::<StoredPool>(
+            &[
+                serde_test::Token::Struct {
+                    name: "SubnetPool",
+                    len: 3,
+                },
+                serde_test::Token::Str("version"),
+                serde_test::Token::U32(0),
+            ],
+            "invalid value: integer `0`, expected a pool file version of at least 1",
+        );
+    }
+}
+
+mod versioned_pool {
+    use super::*;
+    use crate::pool::VersionedPool;
+    use serde_test::{assert_ser_tokens, Token};
+
+    #[test]
+    fn serialize() {
+        assert_ser_tokens(
+            &VersionedPool(&new_test_pool()),
+            &[
+                Token::Struct {
+                    name: "SubnetPool",
+                    len: 3,
+                },
+                Token::Str("version"),
+                Token::U32(crate::schema::SCHEMA_VERSION),
+                Token::Str("cidr"),
+                Token::Str("10.20.0.0/16"),
+                Token::Str("subnets"),
+                Token::Seq { len: Some(0) },
+                Token::SeqEnd,
+                Token::StructEnd,
+            ],
+        );
+    }
+}
+
+mod check_invariants {
+    use super::*;
+    use crate::errors::InvariantError;
+
+    fn cidr(cidr: &str) -> IpCidr {
+        IpCidr::from_str(cidr).unwrap()
+    }
+
+    #[test]
+    fn consistent() {
+        let mut pool = new_test_pool();
+        pool.allocate(4, Some("a-name")).unwrap();
+        pool.claim(&cidr("10.20.1.0/24"), Some("b-name")).unwrap();
+        pool.reserve(&cidr("10.20.2.0/24"), None).unwrap();
+        let parent = pool.allocate(8, Some("parent")).unwrap();
+        pool.make_child(&parent)
+            .unwrap()
+            .allocate(4, Some("c-name"))
+            .unwrap();
+        assert_eq!(pool.check_invariants(), vec![]);
+        let before = pool.clone();
+        assert_eq!(pool.repair(), vec![]);
+        assert_eq!(pool, before);
+    }
+
+    #[test]
+    fn freed_name_is_removed() {
+        let mut pool = new_test_pool();
+        let freed = pool.allocate(4, Some("a-name")).unwrap();
+        assert!(pool.free(&freed));
+        assert_eq!(pool.check_invariants(), vec![]);
+    }
+
+    #[test]
+    fn stale_name() {
+        let mut pool = new_test_pool();
+        let allocated = pool.allocate(4, None).unwrap();
+        pool.names.insert("ghost".to_string(), allocated);
+        let problem = InvariantError::StaleName {
+            name: "ghost".to_string(),
+            cidr: allocated,
+        };
+        assert_eq!(pool.check_invariants(), vec![problem.clone()]);
+        assert_eq!(pool.repair(), vec![problem]);
+        assert_eq!(pool.check_invariants(), vec![]);
+        assert_eq!(pool.find_by_name("ghost"), None);
+    }
+
+    #[test]
+    fn unindexed_name() {
+        let mut pool = new_test_pool();
+        let allocated = pool.allocate(4, Some("a-name")).unwrap();
+        pool.names.clear();
+        assert_eq!(
+            pool.check_invariants(),
+            vec![InvariantError::UnindexedName {
+                name: "a-name".to_string(),
+                cidr: allocated,
+            }]
+        );
+        pool.repair();
+        assert_eq!(pool.check_invariants(), vec![]);
+        assert_eq!(pool.find_by_name("a-name"), Some(allocated));
+    }
+
+    #[test]
+    fn duplicate_name() {
+        let mut pool = new_test_pool();
+        let first = pool.allocate(4, Some("a-name")).unwrap();
+        let second = pool.allocate(4, None).unwrap();
+        pool.find_subspace_mut(&second).unwrap().record.name = Some("a-name".to_string());
+        let problem = InvariantError::DuplicateName {
+            name: "a-name".to_string(),
+            cidr: second,
+        };
+        assert_eq!(pool.check_invariants(), vec![problem.clone()]);
+        assert!(!problem.is_repairable());
+        assert_eq!(pool.repair(), vec![problem.clone()]);
+        assert_eq!(pool.check_invariants(), vec![problem]);
+        assert_eq!(pool.find_by_name("a-name"), Some(first));
+    }
+
+    #[test]
+    fn unmatched_pair() {
+        let mut pool = new_test_pool();
+        let allocated = pool.allocate(4, None).unwrap();
+        pool.find_subspace_mut(&allocated).unwrap().record.pair = Some(TEST_CIDR6);
+        assert_eq!(
+            pool.check_invariants(),
+            vec![InvariantError::UnmatchedPair {
+                cidr: allocated,
+                pair: TEST_CIDR6,
+            }]
+        );
+    }
+
+    #[test]
+    fn child_pool() {
+        let mut pool = new_test_pool();
+        let parent = pool.allocate(8, None).unwrap();
+        let child = pool.make_child(&parent).unwrap();
+        let allocated = child.allocate(4, Some("a-name")).unwrap();
+        child.names.clear();
+        let problem = InvariantError::Child {
+            cidr: parent,
+            error: Box::new(InvariantError::UnindexedName {
+                name: "a-name".to_string(),
+                cidr: allocated,
+            }),
+        };
+        assert_eq!(pool.check_invariants(), vec![problem.clone()]);
+        assert_eq!(pool.repair(), vec![problem]);
+        assert_eq!(pool.check_invariants(), vec![]);
+        assert_eq!(
+            pool.child(&parent).unwrap().find_by_name("a-name"),
+            Some(allocated)
+        );
+    }
+
+    #[test]
+    fn mismatched_child() {
+        let mut pool = new_test_pool();
+        let parent = pool.allocate(8, None).unwrap();
+        pool.find_subspace_mut(&parent).unwrap().record.child =
+            Some(Box::new(SubnetPool::new(cidr("10.20.128.0/24"))));
+        assert_eq!(
+            pool.check_invariants(),
+            vec![InvariantError::MismatchedChild(parent)]
+        );
+    }
+}
+
+mod serialize {
+    use super::*;
+    use serde_test::{assert_de_tokens_error, assert_tokens};
+
+    #[test]
+    fn parse_bad_network() {
+        assert_de_tokens_error::<SubnetPool>(
+            &[
+                serde_test::Token::Struct {
+                    name: "SubnetPool",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("invalid"),
+                serde_test::Token::StructEnd,
+            ],
+            "couldn't parse address in network: invalid IP address syntax",
+        );
+    }
+
+    #[test]
+    fn allocation_error() {
+        assert_de_tokens_error::<SubnetPool>(
+            &[
+                serde_test::Token::Struct {
+                    name: "SubnetPool",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.10.0.0/24"),
+                serde_test::Token::Str("subnets"),
+                serde_test::Token::Seq { len: Some(3) },
+                serde_test::Token::Struct {
+                    name: "CidrRecord",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.0/24"),
+                serde_test::Token::Str("name"),
+                serde_test::Token::Str("a-name"),
+                serde_test::Token::StructEnd,
+                serde_test::Token::Struct {
+                    name: "CidrRecord",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.16/28"),
+                serde_test::Token::Str("name"),
+                serde_test::Token::Str("b-name"),
+                serde_test::Token::StructEnd,
+                serde_test::Token::Struct {
+                    name: "CidrRecord",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.32/28"),
+                serde_test::Token::StructEnd,
+                serde_test::Token::SeqEnd,
+                serde_test::Token::StructEnd,
+            ],
+            "10.20.0.0/24 (a-name): Outside every root\n\
+            10.20.0.16/28 (b-name): Outside every root\n\
+            10.20.0.32/28: Outside every root",
+        );
+    }
+
+    #[test]
+    fn child() {
+        let mut pool = SubnetPool::new(TEST_CIDR4);
+        let cidr = pool.allocate(8, Some("region")).unwrap();
+        pool.make_child(&cidr)
+            .unwrap()
+            .allocate(4, Some("a-name"))
+            .unwrap();
+
+        assert_tokens(
+            &pool,
+            &[
+                serde_test::Token::Struct {
+                    name: "SubnetPool",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.0/16"),
+                serde_test::Token::Str("subnets"),
+                serde_test::Token::Seq { len: Some(1) },
+                serde_test::Token::Struct {
+                    name: "CidrRecord",
+                    len: 3,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.0/24"),
+                serde_test::Token::Str("name"),
+                serde_test::Token::Str("region"),
+                serde_test::Token::Str("pool"),
+                serde_test::Token::Struct {
+                    name: "SubnetPool",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.0/24"),
+                serde_test::Token::Str("subnets"),
+                serde_test::Token::Seq { len: Some(1) },
+                serde_test::Token::Struct {
+                    name: "CidrRecord",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.0/28"),
+                serde_test::Token::Str("name"),
+                serde_test::Token::Str("a-name"),
+                serde_test::Token::StructEnd,
+                serde_test::Token::SeqEnd,
+                serde_test::Token::StructEnd,
+                serde_test::Token::StructEnd,
+                serde_test::Token::SeqEnd,
+                serde_test::Token::StructEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn records_in_address_order() {
+        let mut pool = SubnetPool::new(TEST_CIDR6);
+        pool.add_root(TEST_CIDR4).unwrap();
+        pool.claim(&TEST_CIDR6, None).unwrap();
+        pool.claim(&IpCidr::from_str("10.20.0.0/24").unwrap(), None)
+            .unwrap();
+
+        serde_test::assert_ser_tokens(
+            &pool,
+            &[
+                serde_test::Token::Struct {
+                    name: "SubnetPool",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidrs"),
+                serde_test::Token::Seq { len: Some(2) },
+                serde_test::Token::Str("1:2:3:4:a:14::/112"),
+                serde_test::Token::Str("10.20.0.0/16"),
+                serde_test::Token::SeqEnd,
+                serde_test::Token::Str("subnets"),
+                serde_test::Token::Seq { len: Some(2) },
+                serde_test::Token::Struct {
+                    name: "CidrRecord",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.0/24"),
+                serde_test::Token::StructEnd,
+                serde_test::Token::Struct {
+                    name: "CidrRecord",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("1:2:3:4:a:14::/112"),
+                serde_test::Token::StructEnd,
+                serde_test::Token::SeqEnd,
+                serde_test::Token::StructEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn child_mismatch() {
+        assert_de_tokens_error::<SubnetPool>(
+            &[
+                serde_test::Token::Struct {
+                    name: "SubnetPool",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.0/16"),
+                serde_test::Token::Str("subnets"),
+                serde_test::Token::Seq { len: Some(1) },
+                serde_test::Token::Struct {
+                    name: "CidrRecord",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.0/24"),
+                serde_test::Token::Str("pool"),
+                serde_test::Token::Struct {
+                    name: "SubnetPool",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.1.0/24"),
+                serde_test::Token::Str("subnets"),
+                serde_test::Token::Seq { len: Some(0) },
+                serde_test::Token::SeqEnd,
+                serde_test::Token::StructEnd,
+                serde_test::Token::StructEnd,
+            ],
+            "Child pool 10.20.1.0/24 does not match subnet 10.20.0.0/24",
+        );
+    }
+
+    #[test]
+    fn roots() {
+        let mut pool = SubnetPool::new(TEST_CIDR4);
+        pool.add_root(TEST_CIDR6).unwrap();
+
+        assert_tokens(
+            &pool,
+            &[
+                serde_test::Token::Struct {
+                    name: "SubnetPool",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidrs"),
+                serde_test::Token::Seq { len: Some(2) },
+                serde_test::Token::Str("10.20.0.0/16"),
+                serde_test::Token::Str("1:2:3:4:a:14::/112"),
+                serde_test::Token::SeqEnd,
+                serde_test::Token::Str("subnets"),
+                serde_test::Token::Seq { len: Some(0) },
+                serde_test::Token::SeqEnd,
+                serde_test::Token::StructEnd,
+            ],
         );
-        assert_eq!(cidrs.count(), 0);
     }
-}
-
-mod records {
-    use super::*;
-    use crate::CidrRecord;
 
     #[test]
-    fn success() {
-        let mut pool = new_test_pool();
-        pool.allocate(4, Some("a-name")).unwrap();
-        pool.allocate(4, Some("b-name")).unwrap();
-        pool.allocate(4, None).unwrap();
-        let mut entries: Vec<&CidrRecord> = pool.records().collect();
-        entries.sort();
-        assert_eq!(entries.len(), 3);
-        assert_eq!(
-            entries[0],
-            &CidrRecord::new(
-                IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 0), 28).unwrap()),
-                Some("a-name")
-            )
-        );
-        assert_eq!(
-            entries[1],
-            &CidrRecord::new(
-                IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 16), 28).unwrap()),
-                Some("b-name")
-            )
-        );
-        assert_eq!(
-            entries[2],
-            &CidrRecord::new(
-                IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 32), 28).unwrap()),
-                None
-            )
+    fn unmatched_pair() {
+        assert_de_tokens_error::<SubnetPool>(
+            &[
+                serde_test::Token::Struct {
+                    name: "SubnetPool",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.0/16"),
+                serde_test::Token::Str("subnets"),
+                serde_test::Token::Seq { len: Some(1) },
+                serde_test::Token::Struct {
+                    name: "CidrRecord",
+                    len: 3,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.0/24"),
+                serde_test::Token::Str("name"),
+                serde_test::Token::Str("web-a"),
+                serde_test::Token::Str("pair"),
+                serde_test::Token::Str("fc00::/64"),
+                serde_test::Token::StructEnd,
+                serde_test::Token::SeqEnd,
+                serde_test::Token::StructEnd,
+            ],
+            "10.20.0.0/24 (web-a): Unmatched pair fc00::/64",
         );
     }
-}
-
-mod records_within {
-    use super::*;
-    use crate::CidrRecord;
 
     #[test]
-    fn no_overlap() {
-        let mut pool = new_test_pool();
-        pool.allocate(4, Some("a-name")).unwrap();
-        pool.allocate(4, Some("b-name")).unwrap();
-        pool.allocate(4, None).unwrap();
-        let entries: Vec<&CidrRecord> = pool
-            .records_within(&IpCidr::from_str("20.20.0.0/16").unwrap())
-            .collect();
-        assert_eq!(entries.len(), 0);
+    fn cidr_and_cidrs() {
+        assert_de_tokens_error::<SubnetPool>(
+            &[
+                serde_test::Token::Struct {
+                    name: "SubnetPool",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.0/16"),
+                serde_test::Token::Str("cidrs"),
+            ],
+            "duplicate field `cidrs`",
+        );
     }
 
     #[test]
-    fn superset() {
-        let mut pool = new_test_pool();
-        pool.allocate(4, Some("a-name")).unwrap();
-        pool.allocate(4, Some("b-name")).unwrap();
-        pool.allocate(4, None).unwrap();
-        let mut entries: Vec<&CidrRecord> = pool
-            .records_within(&IpCidr::from_str("10.0.0.0/8").unwrap())
-            .collect();
-        entries.sort();
-        assert_eq!(entries.len(), 3);
-        assert_eq!(
-            entries[0],
-            &CidrRecord::new(
-                IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 0), 28).unwrap()),
-                Some("a-name")
-            )
-        );
-        assert_eq!(
-            entries[1],
-            &CidrRecord::new(
-                IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 16), 28).unwrap()),
-                Some("b-name")
-            )
-        );
-        assert_eq!(
-            entries[2],
-            &CidrRecord::new(
-                IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 32), 28).unwrap()),
-                None
-            )
+    fn overlapping_roots() {
+        assert_de_tokens_error::<SubnetPool>(
+            &[
+                serde_test::Token::Struct {
+                    name: "SubnetPool",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidrs"),
+                serde_test::Token::Seq { len: Some(2) },
+                serde_test::Token::Str("10.20.0.0/16"),
+                serde_test::Token::Str("10.20.0.0/24"),
+                serde_test::Token::SeqEnd,
+                serde_test::Token::Str("subnets"),
+                serde_test::Token::Seq { len: Some(0) },
+                serde_test::Token::SeqEnd,
+                serde_test::Token::StructEnd,
+            ],
+            "Overlapping object",
         );
     }
 
     #[test]
-    fn subset() {
-        let mut pool = new_test_pool();
-        pool.allocate(4, Some("a-name")).unwrap();
-        pool.allocate(4, Some("b-name")).unwrap();
-        pool.allocate(4, None).unwrap();
-        let mut entries: Vec<&CidrRecord> = pool
-            .records_within(&IpCidr::from_str("10.20.0.0/27").unwrap())
-            .collect();
-        entries.sort();
-        assert_eq!(entries.len(), 2);
-        assert_eq!(
-            entries[0],
-            &CidrRecord::new(
-                IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 0), 28).unwrap()),
-                Some("a-name")
-            )
-        );
-        assert_eq!(
-            entries[1],
-            &CidrRecord::new(
-                IpCidr::V4(Ipv4Cidr::new(Ipv4Addr::new(10, 20, 0, 16), 28).unwrap()),
-                Some("b-name")
-            )
+    fn labels() {
+        let mut pool = SubnetPool::new(TEST_CIDR4);
+        let cidr = pool.allocate(4, Some("a-name")).unwrap();
+        pool.label(&cidr, &[("env", "prod")]).unwrap();
+        pool.describe(&cidr, Some("A description")).unwrap();
+
+        assert_tokens(
+            &pool,
+            &[
+                serde_test::Token::Struct {
+                    name: "SubnetPool",
+                    len: 2,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.0/16"),
+                serde_test::Token::Str("subnets"),
+                serde_test::Token::Seq { len: Some(1) },
+                serde_test::Token::Struct {
+                    name: "CidrRecord",
+                    len: 4,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.0/28"),
+                serde_test::Token::Str("name"),
+                serde_test::Token::Str("a-name"),
+                serde_test::Token::Str("description"),
+                serde_test::Token::Str("A description"),
+                serde_test::Token::Str("labels"),
+                serde_test::Token::Map { len: Some(1) },
+                serde_test::Token::Str("env"),
+                serde_test::Token::Str("prod"),
+                serde_test::Token::MapEnd,
+                serde_test::Token::StructEnd,
+                serde_test::Token::SeqEnd,
+                serde_test::Token::StructEnd,
+            ],
         );
     }
-}
-
-mod serialize {
-    use super::*;
-    use serde_test::{assert_de_tokens_error, assert_tokens};
 
     #[test]
-    fn parse_bad_network() {
-        assert_de_tokens_error::<SubnetPool>(
+    fn hosts() {
+        let mut pool = SubnetPool::new(TEST_CIDR4);
+        let cidr = pool.allocate(4, None).unwrap();
+        pool.allocate_host(&cidr, Some("router")).unwrap();
+        pool.allocate_host(&cidr, None).unwrap();
+
+        assert_tokens(
+            &pool,
             &[
                 serde_test::Token::Struct {
                     name: "SubnetPool",
                     len: 2,
                 },
                 serde_test::Token::Str("cidr"),
-                serde_test::Token::Str("invalid"),
+                serde_test::Token::Str("10.20.0.0/16"),
+                serde_test::Token::Str("subnets"),
+                serde_test::Token::Seq { len: Some(1) },
+                serde_test::Token::Struct {
+                    name: "CidrRecord",
+                    len: 3,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.0/28"),
+                serde_test::Token::Str("hosts"),
+                serde_test::Token::Map { len: Some(2) },
+                serde_test::Token::Str("10.20.0.1"),
+                serde_test::Token::Some,
+                serde_test::Token::Str("router"),
+                serde_test::Token::Str("10.20.0.2"),
+                serde_test::Token::None,
+                serde_test::Token::MapEnd,
+                serde_test::Token::StructEnd,
+                serde_test::Token::SeqEnd,
                 serde_test::Token::StructEnd,
             ],
-            "couldn't parse address in network: invalid IP address syntax",
         );
     }
 
     #[test]
-    fn allocation_error() {
+    fn hosts_invalid_address() {
         assert_de_tokens_error::<SubnetPool>(
             &[
                 serde_test::Token::Struct {
@@ -540,38 +3413,114 @@ mod serialize {
                     len: 2,
                 },
                 serde_test::Token::Str("cidr"),
-                serde_test::Token::Str("10.10.0.0/24"),
+                serde_test::Token::Str("10.20.0.0/16"),
                 serde_test::Token::Str("subnets"),
-                serde_test::Token::Seq { len: Some(3) },
+                serde_test::Token::Seq { len: Some(1) },
                 serde_test::Token::Struct {
                     name: "CidrRecord",
                     len: 2,
                 },
                 serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.0/28"),
+                serde_test::Token::Str("hosts"),
+                serde_test::Token::Map { len: Some(1) },
+                serde_test::Token::Str("router"),
+                serde_test::Token::None,
+                serde_test::Token::MapEnd,
+            ],
+            "invalid IP address syntax",
+        );
+    }
+
+    #[test]
+    fn reserved() {
+        let mut pool = SubnetPool::new(TEST_CIDR4);
+        pool.reserve(
+            &IpCidr::from_str("10.20.0.0/24").unwrap(),
+            Some("management"),
+        )
+        .unwrap();
+
+        assert_tokens(
+            &pool,
+            &[
+                serde_test::Token::Struct {
+                    name: "SubnetPool",
+                    len: 3,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.0/16"),
+                serde_test::Token::Str("subnets"),
+                serde_test::Token::Seq { len: Some(0) },
+                serde_test::Token::SeqEnd,
+                serde_test::Token::Str("reserved"),
+                serde_test::Token::Seq { len: Some(1) },
+                serde_test::Token::Struct {
+                    name: "CidrRecord",
+                    len: 3,
+                },
+                serde_test::Token::Str("cidr"),
                 serde_test::Token::Str("10.20.0.0/24"),
-                serde_test::Token::Str("name"),
-                serde_test::Token::Str("a-name"),
+                serde_test::Token::Str("description"),
+                serde_test::Token::Str("management"),
+                serde_test::Token::StructEnd,
+                serde_test::Token::SeqEnd,
                 serde_test::Token::StructEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn reserved_with_name() {
+        assert_de_tokens_error::<SubnetPool>(
+            &[
+                serde_test::Token::Struct {
+                    name: "SubnetPool",
+                    len: 3,
+                },
+                serde_test::Token::Str("cidr"),
+                serde_test::Token::Str("10.20.0.0/16"),
+                serde_test::Token::Str("subnets"),
+                serde_test::Token::Seq { len: Some(0) },
+                serde_test::Token::SeqEnd,
+                serde_test::Token::Str("reserved"),
+                serde_test::Token::Seq { len: Some(1) },
                 serde_test::Token::Struct {
                     name: "CidrRecord",
                     len: 2,
                 },
                 serde_test::Token::Str("cidr"),
-                serde_test::Token::Str("10.20.0.16/28"),
+                serde_test::Token::Str("10.20.0.0/24"),
                 serde_test::Token::Str("name"),
-                serde_test::Token::Str("b-name"),
-                serde_test::Token::StructEnd,
+            ],
+            "unknown field `name`, expected `cidr` or `description`",
+        );
+    }
+
+    #[test]
+    fn strategy() {
+        let mut pool = SubnetPool::new(TEST_CIDR4);
+        pool.set_strategy(crate::AllocationStrategy::BestFit);
+
+        assert_tokens(
+            &pool,
+            &[
                 serde_test::Token::Struct {
-                    name: "CidrRecord",
-                    len: 2,
+                    name: "SubnetPool",
+                    len: 3,
                 },
                 serde_test::Token::Str("cidr"),
-                serde_test::Token::Str("10.20.0.32/28"),
-                serde_test::Token::StructEnd,
+                serde_test::Token::Str("10.20.0.0/16"),
+                serde_test::Token::Str("subnets"),
+                serde_test::Token::Seq { len: Some(0) },
                 serde_test::Token::SeqEnd,
+                serde_test::Token::Str("strategy"),
+                serde_test::Token::UnitVariant {
+                    name: "AllocationStrategy",
+                    variant: "best-fit",
+                },
                 serde_test::Token::StructEnd,
             ],
-            "No space available",
         );
     }
 
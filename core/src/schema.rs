@@ -0,0 +1,77 @@
+// Copyright 2024 The Milton Hirsch Institute, B.V.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Versions of the stored pool format and the migrations between them.
+//!
+//! Each migration upgrades a stored pool by one version. Pools are read at
+//! any version up to [`SCHEMA_VERSION`] and always written at it, so a
+//! binary refuses files written by a newer one rather than dropping what it
+//! does not understand.
+//!
+//! Only the pool at the top of a file records its version. Child pools are
+//! migrated as unversioned pools, so a migration must leave a pool that is
+//! already in the newer form as it is.
+
+use crate::pool::StoredPool;
+
+/// Version of the pool format read and written by this crate
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Version of pool files written before the format carried one
+pub const UNVERSIONED: u32 = 1;
+
+type Migration = fn(&mut StoredPool);
+
+/// Migration from each version to the next, starting with the unversioned
+/// format
+pub(crate) const MIGRATIONS: [Migration; (SCHEMA_VERSION - UNVERSIONED) as usize] =
+    [from_unversioned];
+
+// Labels, reserved ranges and extra roots were all added as optional keys,
+// so unversioned files only lack the version itself.
+fn from_unversioned(_pool: &mut StoredPool) {}
+
+/// Version of a stored pool, read without the rest of it.
+///
+/// Lets a newer file be refused before any of its other keys are parsed.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+pub struct StoredVersion {
+    #[serde(default = "unversioned")]
+    pub version: u32,
+}
+
+fn unversioned() -> u32 {
+    UNVERSIONED
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_test::{assert_de_tokens, Token};
+
+    #[test]
+    fn one_migration_per_version() {
+        assert_eq!(MIGRATIONS.len() as u32, SCHEMA_VERSION - UNVERSIONED);
+    }
+
+    #[test]
+    fn stored_version() {
+        assert_de_tokens(
+            &StoredVersion { version: 3 },
+            &[
+                Token::Map { len: Some(2) },
+                Token::Str("cidr"),
+                Token::Str("10.20.0.0/16"),
+                Token::Str("version"),
+                Token::U32(3),
+                Token::MapEnd,
+            ],
+        );
+        assert_de_tokens(
+            &StoredVersion {
+                version: UNVERSIONED,
+            },
+            &[Token::Map { len: Some(0) }, Token::MapEnd],
+        );
+    }
+}
@@ -1,215 +1,1089 @@
 // Copyright 2023-2024 The Milton Hirsch Institute, B.V.
 // SPDX-License-Identifier: Apache-2.0
 
+//! Subnets of one root, kept in a radix tree on an index arena.
+//!
+//! Only allocated and reserved subnets are stored as leaves. An unavailable
+//! node branches where both of its halves hold something, and levels with
+//! nothing to branch are skipped, so a single /64 in a /32 needs two nodes
+//! rather than 33. Any space that is not under a leaf is free.
+
+use crate::errors::InvariantError;
 use crate::util::host_length;
-use crate::Bits;
-use crate::{util, CidrRecord};
+use crate::{util, CidrRecord, Containing};
+use crate::{AllocationStrategy, Bits};
 use cidr::IpCidr;
-use cidr_utils::separator;
 use std::cmp;
+use std::net::IpAddr;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub(crate) enum State {
     Allocated,
     Free,
+    Reserved,
     Unavailable,
 }
 
-#[derive(PartialEq, Debug)]
-pub(crate) struct Subspace {
+type NodeId = usize;
+
+const ROOT: NodeId = 0;
+
+#[derive(Debug, Clone)]
+pub(crate) struct Node {
     pub(crate) record: CidrRecord,
-    pub(crate) high: Option<Box<Self>>,
-    pub(crate) low: Option<Box<Self>>,
     pub(crate) state: State,
-    pub(crate) allocated_count: usize,
-    pub(crate) max_available_bits: Bits,
+    // Nodes within the low and high half, which need not fill the half
+    children: [Option<NodeId>; 2],
+    allocated_count: usize,
+    max_available_bits: Bits,
+}
+
+impl Node {
+    fn new(record: CidrRecord, state: State) -> Self {
+        let (allocated_count, max_available_bits) = match state {
+            State::Allocated => (1, 0),
+            State::Free => (0, host_length(&record.cidr)),
+            State::Reserved | State::Unavailable => (0, 0),
+        };
+        Node {
+            record,
+            state,
+            children: [None, None],
+            allocated_count,
+            max_available_bits,
+        }
+    }
+}
+
+/// Low and high half of a CIDR.
+fn halves(cidr: &IpCidr) -> [IpCidr; 2] {
+    let low = util::lowest_subnet(cidr, host_length(cidr) - 1);
+    [
+        low,
+        util::next_cidr(&low).expect("High half follows low half"),
+    ]
+}
+
+/// Index of the half of `outer` that holds the address.
+fn half_of(outer: &IpCidr, address: &IpAddr) -> usize {
+    let bit = host_length(outer) - 1;
+    ((util::address_value(address) >> bit) & 1) as usize
+}
+
+/// Smallest aligned block holding two disjoint CIDRs.
+fn common_supernet(a: &IpCidr, b: &IpCidr) -> IpCidr {
+    let diff = util::address_value(&a.first_address()) ^ util::address_value(&b.first_address());
+    util::enclosing_cidr(a, (128 - diff.leading_zeros()) as Bits)
+}
+
+/// Free blocks between an aligned block and a CIDR inside it, split into
+/// those below and those above the CIDR, each in address order.
+fn path_blocks(outer: &IpCidr, inner: &IpCidr) -> (Vec<IpCidr>, Vec<IpCidr>) {
+    let mut below = Vec::new();
+    let mut above = Vec::new();
+    let mut block = *outer;
+    while block != *inner {
+        let [low, high] = halves(&block);
+        match util::cidr_contains(&low, inner) {
+            true => {
+                above.push(high);
+                block = low;
+            }
+            false => {
+                below.push(low);
+                block = high;
+            }
+        }
+    }
+    above.reverse();
+    (below, above)
+}
+
+fn highest_subnet(cidr: &IpCidr, host_length: Bits) -> IpCidr {
+    let last = IpCidr::new(cidr.last_address(), util::max_bits(cidr)).expect("Single address");
+    util::enclosing_cidr(&last, host_length)
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Subspace {
+    nodes: Vec<Node>,
+    vacant: Vec<NodeId>,
 }
 
 impl Subspace {
     pub(crate) fn new(cidr: IpCidr) -> Self {
         Subspace {
-            record: CidrRecord::new(cidr, None),
-            high: None,
-            low: None,
-            state: State::Free,
-            allocated_count: 0,
-            max_available_bits: util::host_length(&cidr),
+            nodes: vec![Node::new(CidrRecord::new(cidr, None), State::Free)],
+            vacant: Vec::new(),
         }
     }
 
-    fn update_info(&mut self) {
-        match self.state {
-            State::Allocated => {
-                self.allocated_count = 1;
-                self.max_available_bits = 0;
+    /// Build a tree from allocated and reserved records in one pass.
+    ///
+    /// Records must lie within the CIDR, be in address order and not
+    /// overlap. The tree is built bottom-up along its right edge, so each
+    /// record is only visited once.
+    pub(crate) fn from_sorted(
+        cidr: IpCidr,
+        records: impl IntoIterator<Item = (CidrRecord, State)>,
+    ) -> Self {
+        let mut subspace = Subspace::new(cidr);
+        // Nodes along the right edge of the tree so far, each waiting for
+        // its high child, which is the node above it
+        let mut edge: Vec<NodeId> = Vec::new();
+        for (record, state) in records {
+            let cidr = record.cidr;
+            let leaf = subspace.add(record, state);
+            let mut low = None;
+            while let Some(&top) = edge.last() {
+                if util::cidr_contains(&subspace.nodes[top].record.cidr, &cidr) {
+                    break;
+                }
+                edge.pop();
+                if let Some(high) = low {
+                    subspace.set_high(top, high);
+                }
+                low = Some(top);
             }
-            State::Free => {
-                self.allocated_count = 0;
-                self.max_available_bits = host_length(&self.record.cidr);
+            if let Some(low) = low {
+                let supernet = common_supernet(&subspace.nodes[low].record.cidr, &cidr);
+                let branch = subspace.add(CidrRecord::new(supernet, None), State::Unavailable);
+                subspace.nodes[branch].children[0] = Some(low);
+                edge.push(branch);
             }
-            State::Unavailable => {
-                let low = self.low.as_deref_mut().unwrap();
-                let high = self.high.as_deref_mut().unwrap();
-                self.allocated_count = low.allocated_count + high.allocated_count;
-                self.max_available_bits = cmp::max(low.max_available_bits, high.max_available_bits);
+            edge.push(leaf);
+        }
+        let mut top = None;
+        while let Some(id) = edge.pop() {
+            if let Some(high) = top {
+                subspace.set_high(id, high);
+            }
+            top = Some(id);
+        }
+
+        if let Some(top) = top {
+            match subspace.nodes[top].record.cidr == cidr {
+                true => {
+                    subspace.nodes.swap(ROOT, top);
+                    subspace.vacant.push(top);
+                }
+                false => {
+                    let side = half_of(&cidr, &subspace.nodes[top].record.cidr.first_address());
+                    let root = &mut subspace.nodes[ROOT];
+                    root.state = State::Unavailable;
+                    root.children[side] = Some(top);
+                    subspace.update_info(ROOT);
+                }
             }
         }
+        subspace
+    }
+
+    fn set_high(&mut self, id: NodeId, high: NodeId) {
+        self.nodes[id].children[1] = Some(high);
+        self.update_info(id);
+    }
+
+    #[inline(always)]
+    pub(crate) fn cidr(&self) -> &IpCidr {
+        &self.nodes[ROOT].record.cidr
     }
 
     pub(crate) fn host_length(&self) -> Bits {
-        host_length(&self.record.cidr)
+        host_length(self.cidr())
+    }
+
+    #[inline(always)]
+    pub(crate) fn allocated_count(&self) -> usize {
+        self.nodes[ROOT].allocated_count
+    }
+
+    #[inline(always)]
+    pub(crate) fn max_available_bits(&self) -> Bits {
+        self.nodes[ROOT].max_available_bits
+    }
+
+    /// Whether nothing is allocated or reserved.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.nodes[ROOT].state == State::Free
     }
 
-    pub(crate) fn split(&mut self) {
-        self.state = State::Unavailable;
-        let new_network_length = self.record.cidr.network_length() + 1;
-        let cidrs: Vec<_> = (match self.record.cidr {
-            IpCidr::V4(cidr) => {
-                separator::Ipv4CidrSeparator::sub_networks(&cidr, new_network_length)
-                    .map(|vs| vs.into_iter().map(IpCidr::V4).collect())
+    fn add(&mut self, record: CidrRecord, state: State) -> NodeId {
+        let node = Node::new(record, state);
+        match self.vacant.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
             }
-            IpCidr::V6(cidr) => {
-                separator::Ipv6CidrSeparator::sub_networks(&cidr, new_network_length)
-                    .map(|vs| vs.into_iter().map(IpCidr::V6).collect())
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
             }
-        })
-        .expect("Vec of length 2");
-        self.low = Some(Box::new(Subspace::new(cidrs[0])));
-        self.high = Some(Box::new(Subspace::new(cidrs[1])));
+        }
+    }
+
+    // Return a node and everything under it to the arena.
+    fn remove_node(&mut self, id: NodeId) {
+        let node = &mut self.nodes[id];
+        let children = std::mem::take(&mut node.children);
+        node.record = CidrRecord::new(node.record.cidr, None);
+        node.state = State::Free;
+        self.vacant.push(id);
+        for child in children.into_iter().flatten() {
+            self.remove_node(child);
+        }
+    }
+
+    fn update_info(&mut self, id: NodeId) {
+        let node = &self.nodes[id];
+        if node.state != State::Unavailable {
+            let Node {
+                allocated_count,
+                max_available_bits,
+                ..
+            } = Node::new(CidrRecord::new(node.record.cidr, None), node.state);
+            let node = &mut self.nodes[id];
+            node.allocated_count = allocated_count;
+            node.max_available_bits = max_available_bits;
+            return;
+        }
+        let mut allocated_count = 0;
+        let mut max_available_bits = 0;
+        for (half, child) in halves(&node.record.cidr).iter().zip(node.children) {
+            let available = match child {
+                None => host_length(half),
+                Some(child) => {
+                    let child = &self.nodes[child];
+                    allocated_count += child.allocated_count;
+                    match child.record.cidr == *half {
+                        true => child.max_available_bits,
+                        // The half is free next to the child
+                        false => cmp::max(host_length(half) - 1, child.max_available_bits),
+                    }
+                }
+            };
+            max_available_bits = cmp::max(max_available_bits, available);
+        }
+        let node = &mut self.nodes[id];
+        node.allocated_count = allocated_count;
+        node.max_available_bits = max_available_bits;
     }
 
     pub(crate) fn allocate_free_space(
         &mut self,
         host_length: Bits,
         name: Option<&str>,
+        strategy: AllocationStrategy,
     ) -> Option<IpCidr> {
-        if host_length > self.max_available_bits {
+        if host_length > self.max_available_bits() {
             return None;
         }
-        if self.state == State::Free {
-            if host_length == self.host_length() {
-                self.state = State::Allocated;
-                self.update_info();
-                self.record.name = name.map(|s| s.to_string());
-                return Some(self.record.cidr);
-            } else {
-                self.split();
-            }
-        }
-        if self.state == State::Unavailable {
-            let found_low = self
-                .low
-                .as_deref_mut()?
-                .allocate_free_space(host_length, name);
-            return match found_low {
-                Some(_) => {
-                    self.update_info();
-                    found_low
-                }
-                None => {
-                    let found_high = self
-                        .high
-                        .as_deref_mut()?
-                        .allocate_free_space(host_length, name);
-                    match found_high {
-                        Some(_) => {
-                            self.update_info();
-                            found_high
-                        }
-                        None => None,
+        let cidr = match strategy {
+            AllocationStrategy::LowestFirst => {
+                let block = self.fitting_blocks(host_length, false).next()?;
+                util::lowest_subnet(&block, host_length)
+            }
+            AllocationStrategy::HighestFirst => {
+                let block = self.fitting_blocks(host_length, true).next()?;
+                highest_subnet(&block, host_length)
+            }
+            AllocationStrategy::BestFit | AllocationStrategy::WorstFit => {
+                let mut found: Option<IpCidr> = None;
+                for block in self.fitting_blocks(host_length, false) {
+                    let better = match found {
+                        None => true,
+                        Some(current) => match strategy {
+                            AllocationStrategy::WorstFit => {
+                                util::host_length(&block) > util::host_length(&current)
+                            }
+                            _ => util::host_length(&block) < util::host_length(&current),
+                        },
+                    };
+                    if better {
+                        found = Some(block);
                     }
                 }
-            };
+                util::lowest_subnet(&found?, host_length)
+            }
+        };
+        match self.claim(&cidr, name) {
+            true => Some(cidr),
+            false => None,
         }
-        None
     }
+
     pub(crate) fn free(&mut self, cidr: &IpCidr) -> bool {
-        if !util::cidr_contains(&self.record.cidr, cidr) {
+        self.release(cidr, State::Allocated)
+    }
+
+    pub(crate) fn unreserve(&mut self, cidr: &IpCidr) -> bool {
+        self.release(cidr, State::Reserved)
+    }
+
+    fn release(&mut self, cidr: &IpCidr, state: State) -> bool {
+        if !util::cidr_contains(self.cidr(), cidr) {
             return false;
         }
+        let root = &mut self.nodes[ROOT];
+        if root.record.cidr == *cidr {
+            if root.state != state {
+                return false;
+            }
+            root.state = State::Free;
+            root.record = CidrRecord::new(*cidr, None);
+            self.update_info(ROOT);
+            return true;
+        }
+        if root.state != State::Unavailable {
+            return false;
+        }
+        self.remove_leaf(ROOT, cidr, state)
+    }
 
-        match self.state {
-            State::Allocated => match self.record.cidr == *cidr {
-                true => {
-                    self.state = State::Free;
-                    self.record.name = None;
-                    self.update_info();
-                    true
-                }
-                false => false,
-            },
-            State::Free => false,
-            State::Unavailable => {
-                let low = self.low.as_deref_mut().unwrap();
-                let high = self.high.as_deref_mut().unwrap();
-                let freed = low.free(cidr) || high.free(cidr);
-                if freed {
-                    if low.state == State::Free && high.state == State::Free {
-                        self.low = None;
-                        self.high = None;
-                        self.state = State::Free;
-                    }
-                    self.update_info();
-                }
-
-                freed
+    // Remove the leaf at the CIDR from under an unavailable node, folding
+    // away branches left with a single child.
+    fn remove_leaf(&mut self, id: NodeId, cidr: &IpCidr, state: State) -> bool {
+        let side = half_of(&self.nodes[id].record.cidr, &cidr.first_address());
+        let Some(child) = self.nodes[id].children[side] else {
+            return false;
+        };
+        let child_node = &self.nodes[child];
+        if child_node.record.cidr == *cidr {
+            if child_node.state != state {
+                return false;
+            }
+            self.remove_node(child);
+            self.nodes[id].children[side] = None;
+        } else if child_node.state == State::Unavailable
+            && util::cidr_contains(&child_node.record.cidr, cidr)
+        {
+            if !self.remove_leaf(child, cidr, state) {
+                return false;
+            }
+            if let [Some(only), None] | [None, Some(only)] = self.nodes[child].children {
+                self.nodes[child].children = [None, None];
+                self.remove_node(child);
+                self.nodes[id].children[side] = Some(only);
             }
+        } else {
+            return false;
+        }
+        if id == ROOT && self.nodes[id].children == [None, None] {
+            self.nodes[id].state = State::Free;
         }
+        self.update_info(id);
+        true
     }
 
     pub(crate) fn claim(&mut self, cidr: &IpCidr, name: Option<&str>) -> bool {
-        if !util::cidr_contains(&self.record.cidr, cidr) {
+        self.occupy(CidrRecord::new(*cidr, name), State::Allocated)
+    }
+
+    pub(crate) fn reserve(&mut self, cidr: &IpCidr) -> bool {
+        self.occupy(CidrRecord::new(*cidr, None), State::Reserved)
+    }
+
+    fn occupy(&mut self, record: CidrRecord, state: State) -> bool {
+        if !util::cidr_contains(self.cidr(), &record.cidr) {
             return false;
         }
+        self.insert(ROOT, record, state)
+    }
 
-        match self.state {
-            State::Allocated => return false,
+    // Add a leaf under a node, if all of its space is free.
+    fn insert(&mut self, id: NodeId, record: CidrRecord, state: State) -> bool {
+        let node = &mut self.nodes[id];
+        let node_cidr = node.record.cidr;
+        match node.state {
+            State::Allocated | State::Reserved => return false,
             State::Free => {
-                if self.record.cidr == *cidr {
-                    self.state = State::Allocated;
-                    self.update_info();
-                    self.record.name = name.map(|name| name.to_string());
+                if node_cidr == record.cidr {
+                    node.state = state;
+                    node.record = record;
+                    self.update_info(id);
                     return true;
                 }
-                self.split();
+                node.state = State::Unavailable;
+            }
+            State::Unavailable => {
+                if node_cidr == record.cidr {
+                    return false;
+                }
             }
-            State::Unavailable => {}
         }
 
-        if self.low.as_deref_mut().unwrap().claim(cidr, name)
-            || self.high.as_deref_mut().unwrap().claim(cidr, name)
-        {
-            self.update_info();
+        let side = half_of(&node_cidr, &record.cidr.first_address());
+        let inserted = match self.nodes[id].children[side] {
+            None => {
+                let leaf = self.add(record, state);
+                self.nodes[id].children[side] = Some(leaf);
+                true
+            }
+            Some(child) => {
+                let child_cidr = self.nodes[child].record.cidr;
+                if util::cidr_contains(&child_cidr, &record.cidr) {
+                    self.insert(child, record, state)
+                } else if util::cidr_contains(&record.cidr, &child_cidr) {
+                    false
+                } else {
+                    // Branch where the child and the new leaf part ways
+                    let supernet = common_supernet(&child_cidr, &record.cidr);
+                    let leaf_side = half_of(&supernet, &record.cidr.first_address());
+                    let leaf = self.add(record, state);
+                    let branch = self.add(CidrRecord::new(supernet, None), State::Unavailable);
+                    self.nodes[branch].children[leaf_side] = Some(leaf);
+                    self.nodes[branch].children[1 - leaf_side] = Some(child);
+                    self.update_info(branch);
+                    self.nodes[id].children[side] = Some(branch);
+                    true
+                }
+            }
+        };
+        if inserted {
+            self.update_info(id);
+        }
+        inserted
+    }
+
+    /// Replace the allocation at the given CIDR with equal allocations of a
+    /// smaller host length, each copying the original record.
+    pub(crate) fn divide(&mut self, cidr: &IpCidr, host_length: Bits) -> bool {
+        self.update_at(ROOT, cidr, &mut |subspace, id| {
+            subspace.fill(id, host_length)
+        })
+    }
+
+    fn fill(&mut self, id: NodeId, host_length: Bits) {
+        let node = &mut self.nodes[id];
+        let cidr = node.record.cidr;
+        if util::host_length(&cidr) > host_length {
+            let record = std::mem::replace(&mut node.record, CidrRecord::new(cidr, None));
+            node.state = State::Unavailable;
+            for (side, half) in halves(&cidr).into_iter().enumerate() {
+                let half_record = CidrRecord {
+                    cidr: half,
+                    ..record.clone()
+                };
+                let child = self.add(half_record, State::Allocated);
+                self.nodes[id].children[side] = Some(child);
+                self.fill(child, host_length);
+            }
+        }
+        self.update_info(id);
+    }
+
+    /// Replace everything at the given CIDR with one allocation.
+    pub(crate) fn combine(&mut self, record: CidrRecord) -> bool {
+        let cidr = record.cidr;
+        let mut record = Some(record);
+        self.update_at(ROOT, &cidr, &mut |subspace, id| {
+            let children = std::mem::take(&mut subspace.nodes[id].children);
+            for child in children.into_iter().flatten() {
+                subspace.remove_node(child);
+            }
+            let node = &mut subspace.nodes[id];
+            node.state = State::Allocated;
+            node.record = record.take().unwrap();
+        })
+    }
+
+    // Change the node at the given CIDR and refresh the info of every node
+    // above it.
+    fn update_at(
+        &mut self,
+        id: NodeId,
+        cidr: &IpCidr,
+        change: &mut dyn FnMut(&mut Self, NodeId),
+    ) -> bool {
+        let node = &self.nodes[id];
+        if !util::cidr_contains(&node.record.cidr, cidr) {
+            return false;
+        }
+        if node.record.cidr == *cidr {
+            change(self, id);
+            self.update_info(id);
             return true;
         }
-        false
+        if node.state != State::Unavailable {
+            return false;
+        }
+        let side = half_of(&node.record.cidr, &cidr.first_address());
+        let Some(child) = node.children[side] else {
+            return false;
+        };
+        let updated = self.update_at(child, cidr, change);
+        if updated {
+            self.update_info(id);
+        }
+        updated
     }
 
-    pub(crate) fn find_record(&self, cidr: &IpCidr) -> Option<&Self> {
-        if !util::cidr_contains(&self.record.cidr, cidr) {
-            return None;
+    /// Wrap this tree in free space up to the given supernet.
+    pub(crate) fn expand_to(self, supernet: &IpCidr) -> Self {
+        self.rebuild(supernet)
+    }
+
+    /// Cut this tree down to the given subnet.
+    ///
+    /// Anything outside the subnet is dropped, so callers must first check
+    /// that nothing is allocated or reserved there.
+    pub(crate) fn shrink_to(self, subnet: &IpCidr) -> Self {
+        self.rebuild(subnet)
+    }
+
+    fn rebuild(self, cidr: &IpCidr) -> Self {
+        let leaves: Vec<NodeId> = self.preorder(ROOT).collect();
+        self.rebuild_from(cidr, leaves)
+    }
+
+    fn rebuild_from(mut self, cidr: &IpCidr, leaves: Vec<NodeId>) -> Self {
+        let mut subspace = Subspace::new(*cidr);
+        for id in leaves {
+            let node = &mut self.nodes[id];
+            if matches!(node.state, State::Allocated | State::Reserved) {
+                let placeholder = CidrRecord::new(node.record.cidr, None);
+                let record = std::mem::replace(&mut node.record, placeholder);
+                subspace.occupy(record, node.state);
+            }
+        }
+        subspace
+    }
+
+    /// Rebuild the tree and its cached counts from its leaves.
+    ///
+    /// Leaves are taken from the whole arena rather than by walking the
+    /// tree, so that none are lost when the tree itself is malformed.
+    pub(crate) fn repaired(self) -> Self {
+        let cidr = *self.cidr();
+        let mut live = vec![true; self.nodes.len()];
+        for id in self.vacant.iter() {
+            live[*id] = false;
+        }
+        let mut leaves: Vec<NodeId> = (0..self.nodes.len()).filter(|id| live[*id]).collect();
+        leaves.sort_by_key(|id| {
+            let cidr = &self.nodes[*id].record.cidr;
+            (cidr.first_address(), cidr.network_length())
+        });
+        self.rebuild_from(&cidr, leaves)
+    }
+
+    /// Problems with the shape of the tree or its cached counts.
+    pub(crate) fn check(&self) -> Vec<InvariantError> {
+        let mut problems = Vec::new();
+        self.check_node(ROOT, &mut problems);
+        problems
+    }
+
+    // Recompute the counts of a node from the nodes under it, recording
+    // every place where the tree differs from them.
+    fn check_node(&self, id: NodeId, problems: &mut Vec<InvariantError>) -> (usize, Bits) {
+        let node = &self.nodes[id];
+        let cidr = node.record.cidr;
+        let filled = node.children.iter().flatten().count();
+        let well_formed = match node.state {
+            State::Allocated | State::Reserved => filled == 0,
+            // Only the root may stand for free space or hold a single child
+            State::Free => id == ROOT && filled == 0,
+            State::Unavailable => {
+                host_length(&cidr) > 0 && (filled == 2 || id == ROOT && filled == 1)
+            }
+        };
+        if !well_formed {
+            problems.push(InvariantError::MalformedNode(cidr));
+        }
+        let actual = match node.state {
+            State::Allocated => (1, 0),
+            State::Reserved => (0, 0),
+            State::Free => (0, host_length(&cidr)),
+            State::Unavailable if host_length(&cidr) == 0 => (0, 0),
+            State::Unavailable => {
+                let mut allocated_count = 0;
+                let mut max_available_bits = 0;
+                for (half, child) in halves(&cidr).iter().zip(node.children) {
+                    let available = match child {
+                        None => host_length(half),
+                        Some(child) => {
+                            let child_cidr = self.nodes[child].record.cidr;
+                            if !util::cidr_contains(half, &child_cidr) {
+                                problems.push(InvariantError::MalformedNode(child_cidr));
+                            }
+                            let (count, bits) = self.check_node(child, problems);
+                            allocated_count += count;
+                            match child_cidr == *half {
+                                true => bits,
+                                false => cmp::max(host_length(half).saturating_sub(1), bits),
+                            }
+                        }
+                    };
+                    max_available_bits = cmp::max(max_available_bits, available);
+                }
+                (allocated_count, max_available_bits)
+            }
+        };
+        if node.allocated_count != actual.0 {
+            problems.push(InvariantError::AllocatedCount {
+                cidr,
+                cached: node.allocated_count,
+                actual: actual.0,
+            });
+        }
+        if node.max_available_bits != actual.1 {
+            problems.push(InvariantError::MaxAvailableBits {
+                cidr,
+                cached: node.max_available_bits,
+                actual: actual.1,
+            });
         }
-        if self.record.cidr == *cidr {
-            return Some(self);
+        actual
+    }
+
+    // Every node under the given one, parents first and in address order.
+    fn preorder(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        let mut stack = vec![id];
+        std::iter::from_fn(move || {
+            let id = stack.pop()?;
+            let [low, high] = self.nodes[id].children;
+            stack.extend(high);
+            stack.extend(low);
+            Some(id)
+        })
+    }
+
+    fn entries(&self) -> impl Iterator<Item = (&CidrRecord, State)> + '_ {
+        self.preorder(ROOT)
+            .map(|id| (&self.nodes[id].record, self.nodes[id].state))
+    }
+
+    /// Records of the subnets in the given state, in address order.
+    pub(crate) fn records(&self, state: State) -> impl Iterator<Item = &CidrRecord> + '_ {
+        self.records_from(Some(ROOT), state)
+    }
+
+    /// Records of the subnets in the given state within a CIDR, in address
+    /// order.
+    pub(crate) fn records_within(
+        &self,
+        cidr: &IpCidr,
+        state: State,
+    ) -> impl Iterator<Item = &CidrRecord> + '_ {
+        // Find the largest node inside the CIDR
+        let mut start = Some(ROOT);
+        while let Some(id) = start {
+            let node = &self.nodes[id];
+            if util::cidr_contains(cidr, &node.record.cidr) {
+                break;
+            }
+            start = match node.state == State::Unavailable
+                && util::cidr_contains(&node.record.cidr, cidr)
+            {
+                true => node.children[half_of(&node.record.cidr, &cidr.first_address())],
+                false => None,
+            };
         }
-        let found_low = self.low.as_deref()?.find_record(cidr);
-        return match found_low {
-            Some(_) => found_low,
-            None => self.high.as_deref()?.find_record(cidr),
+        self.records_from(start, state)
+    }
+
+    fn records_from(
+        &self,
+        start: Option<NodeId>,
+        state: State,
+    ) -> impl Iterator<Item = &CidrRecord> + '_ {
+        start
+            .into_iter()
+            .flat_map(move |id| self.preorder(id))
+            .map(|id| &self.nodes[id])
+            .filter(move |node| node.state == state)
+            .map(|node| &node.record)
+    }
+
+    /// Largest free blocks, in address order.
+    pub(crate) fn free_blocks(&self) -> FreeBlocks<'_> {
+        FreeBlocks::new(self, vec![Item::Node(ROOT)], 0, false)
+    }
+
+    /// Largest free blocks within a CIDR, or the CIDR itself if it is inside
+    /// a free block.
+    pub(crate) fn free_blocks_within(&self, cidr: &IpCidr) -> FreeBlocks<'_> {
+        let mut id = ROOT;
+        let items = loop {
+            let node = &self.nodes[id];
+            if util::cidr_contains(cidr, &node.record.cidr) {
+                break vec![Item::Node(id)];
+            }
+            if !util::cidr_contains(&node.record.cidr, cidr) {
+                break Vec::new();
+            }
+            match node.state {
+                State::Free => break vec![Item::Block(*cidr)],
+                State::Allocated | State::Reserved => break Vec::new(),
+                State::Unavailable => {}
+            }
+            let child = match node.children[half_of(&node.record.cidr, &cidr.first_address())] {
+                Some(child) => child,
+                None => break vec![Item::Block(*cidr)],
+            };
+            let child_cidr = &self.nodes[child].record.cidr;
+            if util::cidr_contains(cidr, child_cidr) {
+                break Item::around(cidr, child_cidr, child);
+            }
+            if !util::cidr_contains(child_cidr, cidr) {
+                break vec![Item::Block(*cidr)];
+            }
+            id = child;
         };
+        FreeBlocks::new(self, items, 0, false)
+    }
+
+    // Free blocks with at least the given host length.
+    fn fitting_blocks(&self, host_length: Bits, reverse: bool) -> FreeBlocks<'_> {
+        FreeBlocks::new(self, vec![Item::Node(ROOT)], host_length, reverse)
     }
 
-    pub(crate) fn find_record_mut(&mut self, cidr: &IpCidr) -> Option<&mut Self> {
-        if !util::cidr_contains(&self.record.cidr, cidr) {
+    /// Allocated or reserved subnet or largest free block holding the
+    /// address.
+    pub(crate) fn find_containing(&self, address: &IpAddr) -> Option<Containing<'_>> {
+        if !self.cidr().contains(address) {
             return None;
         }
-        if self.record.cidr == *cidr {
-            return Some(self);
+        let mut id = ROOT;
+        loop {
+            let node = &self.nodes[id];
+            match node.state {
+                State::Allocated => return Some(Containing::Allocated(&node.record)),
+                State::Reserved => return Some(Containing::Reserved(&node.record)),
+                State::Free => return Some(Containing::Free(node.record.cidr)),
+                State::Unavailable => {}
+            }
+            let side = half_of(&node.record.cidr, address);
+            let half = halves(&node.record.cidr)[side];
+            let child = match node.children[side] {
+                Some(child) => child,
+                None => return Some(Containing::Free(half)),
+            };
+            let child_cidr = &self.nodes[child].record.cidr;
+            if !child_cidr.contains(address) {
+                let (below, above) = path_blocks(&half, child_cidr);
+                return below
+                    .into_iter()
+                    .chain(above)
+                    .find(|block| block.contains(address))
+                    .map(Containing::Free);
+            }
+            id = child;
+        }
+    }
+
+    fn find_id(&self, cidr: &IpCidr) -> Option<NodeId> {
+        let mut id = ROOT;
+        loop {
+            let node = &self.nodes[id];
+            if node.record.cidr == *cidr {
+                return Some(id);
+            }
+            if node.state != State::Unavailable || !util::cidr_contains(&node.record.cidr, cidr) {
+                return None;
+            }
+            id = node.children[half_of(&node.record.cidr, &cidr.first_address())]?;
         }
-        let found_low = self.low.as_deref_mut()?.find_record_mut(cidr);
-        return match found_low {
-            Some(_) => found_low,
-            None => self.high.as_deref_mut()?.find_record_mut(cidr),
+    }
+
+    pub(crate) fn find_record(&self, cidr: &IpCidr) -> Option<&Node> {
+        Some(&self.nodes[self.find_id(cidr)?])
+    }
+
+    pub(crate) fn find_record_mut(&mut self, cidr: &IpCidr) -> Option<&mut Node> {
+        let id = self.find_id(cidr)?;
+        Some(&mut self.nodes[id])
+    }
+}
+
+impl PartialEq for Subspace {
+    fn eq(&self, other: &Self) -> bool {
+        // Arena layouts differ with history, so compare the trees instead
+        self.entries().eq(other.entries())
+    }
+}
+
+impl Eq for Subspace {}
+
+impl PartialOrd for Subspace {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Subspace {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.entries().cmp(other.entries())
+    }
+}
+
+enum Item {
+    Node(NodeId),
+    Block(IpCidr),
+}
+
+impl Item {
+    // The free blocks around a node inside an aligned block, and the node,
+    // in address order.
+    fn around(outer: &IpCidr, inner: &IpCidr, id: NodeId) -> Vec<Item> {
+        let (below, above) = path_blocks(outer, inner);
+        let mut items: Vec<Item> = below.into_iter().map(Item::Block).collect();
+        items.push(Item::Node(id));
+        items.extend(above.into_iter().map(Item::Block));
+        items
+    }
+}
+
+/// Iterator over free blocks, skipping parts of the tree with no block of
+/// the minimum host length.
+pub(crate) struct FreeBlocks<'a> {
+    subspace: &'a Subspace,
+    stack: Vec<Item>,
+    host_length: Bits,
+    reverse: bool,
+}
+
+impl<'a> FreeBlocks<'a> {
+    fn new(subspace: &'a Subspace, items: Vec<Item>, host_length: Bits, reverse: bool) -> Self {
+        let mut blocks = FreeBlocks {
+            subspace,
+            stack: Vec::new(),
+            host_length,
+            reverse,
         };
+        blocks.push(items);
+        blocks
+    }
+
+    // Push items given in address order so they come out in iteration order.
+    fn push(&mut self, items: Vec<Item>) {
+        let host_length = self.host_length;
+        let items = items.into_iter().filter(|item| match item {
+            Item::Block(block) => util::host_length(block) >= host_length,
+            Item::Node(_) => true,
+        });
+        match self.reverse {
+            true => self.stack.extend(items),
+            false => {
+                let items: Vec<Item> = items.collect();
+                self.stack.extend(items.into_iter().rev());
+            }
+        }
+    }
+}
+
+impl Iterator for FreeBlocks<'_> {
+    type Item = IpCidr;
+
+    fn next(&mut self) -> Option<IpCidr> {
+        while let Some(item) = self.stack.pop() {
+            let id = match item {
+                Item::Block(block) => return Some(block),
+                Item::Node(id) => id,
+            };
+            let node = &self.subspace.nodes[id];
+            if node.max_available_bits < self.host_length {
+                continue;
+            }
+            match node.state {
+                State::Free => return Some(node.record.cidr),
+                State::Allocated | State::Reserved => continue,
+                State::Unavailable => {}
+            }
+            let mut items = Vec::new();
+            for (half, child) in halves(&node.record.cidr).iter().zip(node.children) {
+                match child {
+                    None => items.push(Item::Block(*half)),
+                    Some(child) => {
+                        let child_cidr = &self.subspace.nodes[child].record.cidr;
+                        items.extend(Item::around(half, child_cidr, child));
+                    }
+                }
+            }
+            self.push(items);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TEST_CIDR4;
+    use std::str::FromStr;
+
+    fn cidr(cidr: &str) -> IpCidr {
+        IpCidr::from_str(cidr).unwrap()
+    }
+
+    fn live_nodes(subspace: &Subspace) -> usize {
+        subspace.nodes.len() - subspace.vacant.len()
+    }
+
+    #[test]
+    fn skips_empty_levels() {
+        let mut subspace = Subspace::new(cidr("2001:db8::/32"));
+        assert!(subspace.claim(&cidr("2001:db8:0:1::/64"), None));
+        assert_eq!(live_nodes(&subspace), 2);
+        assert!(subspace.claim(&cidr("2001:db8:0:2::/64"), None));
+        assert_eq!(live_nodes(&subspace), 4);
+        assert_eq!(subspace.max_available_bits(), 95);
+    }
+
+    #[test]
+    fn folds_branches_on_release() {
+        let mut subspace = Subspace::new(TEST_CIDR4);
+        assert!(subspace.claim(&cidr("10.20.0.0/28"), None));
+        assert!(subspace.claim(&cidr("10.20.0.16/28"), None));
+        assert!(subspace.reserve(&cidr("10.20.128.0/24")));
+        assert!(subspace.free(&cidr("10.20.0.0/28")));
+        assert_eq!(live_nodes(&subspace), 3);
+        assert!(subspace.unreserve(&cidr("10.20.128.0/24")));
+        assert!(subspace.free(&cidr("10.20.0.16/28")));
+        assert_eq!(live_nodes(&subspace), 1);
+        assert_eq!(subspace, Subspace::new(TEST_CIDR4));
+    }
+
+    #[test]
+    fn reuses_vacant_nodes() {
+        let mut subspace = Subspace::new(TEST_CIDR4);
+        for _ in 0..3 {
+            let a = subspace.allocate_free_space(4, None, AllocationStrategy::LowestFirst);
+            let b = subspace.allocate_free_space(4, None, AllocationStrategy::LowestFirst);
+            assert!(subspace.free(&a.unwrap()));
+            assert!(subspace.free(&b.unwrap()));
+        }
+        assert_eq!(subspace.nodes.len(), 4);
+    }
+
+    #[test]
+    fn free_blocks_match_buddy_blocks() {
+        let mut subspace = Subspace::new(cidr("10.20.0.0/24"));
+        assert!(subspace.claim(&cidr("10.20.0.64/28"), None));
+        let blocks: Vec<IpCidr> = subspace.free_blocks().collect();
+        assert_eq!(
+            blocks,
+            vec![
+                cidr("10.20.0.0/26"),
+                cidr("10.20.0.80/28"),
+                cidr("10.20.0.96/27"),
+                cidr("10.20.0.128/25"),
+            ]
+        );
+    }
+
+    #[test]
+    fn from_sorted() {
+        let cidrs = [
+            "10.20.0.0/28",
+            "10.20.0.16/28",
+            "10.20.0.64/26",
+            "10.20.1.0/24",
+            "10.20.128.0/17",
+        ];
+        let mut subspace = Subspace::new(TEST_CIDR4);
+        for cidr in cidrs.map(cidr) {
+            assert!(subspace.claim(&cidr, None));
+        }
+        assert!(subspace.reserve(&cidr("10.20.2.0/24")));
+        let mut records: Vec<(CidrRecord, State)> = cidrs
+            .map(|cidr| (CidrRecord::new(self::cidr(cidr), None), State::Allocated))
+            .to_vec();
+        records.insert(
+            4,
+            (CidrRecord::new(cidr("10.20.2.0/24"), None), State::Reserved),
+        );
+        let built = Subspace::from_sorted(TEST_CIDR4, records);
+        assert_eq!(built, subspace);
+        assert_eq!(live_nodes(&built), live_nodes(&subspace));
+        assert_eq!(built.allocated_count(), 5);
+        assert_eq!(built.max_available_bits(), 14);
+    }
+
+    #[test]
+    fn from_sorted_whole_root() {
+        let records = [(CidrRecord::new(TEST_CIDR4, None), State::Reserved)];
+        let built = Subspace::from_sorted(TEST_CIDR4, records);
+        let mut subspace = Subspace::new(TEST_CIDR4);
+        assert!(subspace.reserve(&TEST_CIDR4));
+        assert_eq!(built, subspace);
+        assert_eq!(
+            Subspace::from_sorted(TEST_CIDR4, []),
+            Subspace::new(TEST_CIDR4)
+        );
+    }
+
+    #[test]
+    fn equal_regardless_of_history() {
+        let mut subspace = Subspace::new(TEST_CIDR4);
+        assert!(subspace.claim(&cidr("10.20.0.0/28"), None));
+        assert!(subspace.claim(&cidr("10.20.64.0/24"), None));
+        assert!(subspace.free(&cidr("10.20.0.0/28")));
+        let mut other = Subspace::new(TEST_CIDR4);
+        assert!(other.claim(&cidr("10.20.64.0/24"), None));
+        assert_eq!(subspace, other);
+        assert_ne!(subspace.nodes.len(), other.nodes.len());
+    }
+
+    fn node_at(subspace: &Subspace, block: &str) -> NodeId {
+        let block = cidr(block);
+        (0..subspace.nodes.len())
+            .find(|id| subspace.nodes[*id].record.cidr == block)
+            .unwrap()
+    }
+
+    #[test]
+    fn check_cached_counts() {
+        let mut subspace = Subspace::new(TEST_CIDR4);
+        assert!(subspace.claim(&cidr("10.20.0.0/28"), None));
+        assert!(subspace.claim(&cidr("10.20.0.16/28"), None));
+        assert_eq!(subspace.check(), vec![]);
+        let branch = node_at(&subspace, "10.20.0.0/27");
+        subspace.nodes[branch].allocated_count = 5;
+        subspace.nodes[ROOT].max_available_bits = 3;
+        assert_eq!(
+            subspace.check(),
+            vec![
+                InvariantError::AllocatedCount {
+                    cidr: cidr("10.20.0.0/27"),
+                    cached: 5,
+                    actual: 2,
+                },
+                InvariantError::MaxAvailableBits {
+                    cidr: TEST_CIDR4,
+                    cached: 3,
+                    actual: 15,
+                },
+            ]
+        );
+        let repaired = subspace.clone().repaired();
+        assert_eq!(repaired.check(), vec![]);
+        assert_eq!(repaired, subspace);
+    }
+
+    #[test]
+    fn check_shape() {
+        let mut subspace = Subspace::new(TEST_CIDR4);
+        assert!(subspace.claim(&cidr("10.20.0.0/28"), None));
+        assert!(subspace.claim(&cidr("10.20.0.16/28"), None));
+        let branch = node_at(&subspace, "10.20.0.0/27");
+        subspace.nodes[branch].children[1] = None;
+        let problems = subspace.check();
+        assert_eq!(
+            problems[0],
+            InvariantError::MalformedNode(cidr("10.20.0.0/27"))
+        );
+        assert!(problems.contains(&InvariantError::AllocatedCount {
+            cidr: TEST_CIDR4,
+            cached: 2,
+            actual: 1,
+        }));
+
+        // The detached leaf is still found when repairing
+        let repaired = subspace.repaired();
+        assert_eq!(repaired.check(), vec![]);
+        assert_eq!(repaired.allocated_count(), 2);
+        assert!(repaired.find_record(&cidr("10.20.0.16/28")).is_some());
     }
 }
@@ -2,7 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::Bits;
-use cidr::IpCidr;
+use cidr::{IpCidr, IpInet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
 #[inline(always)]
 pub fn max_bits(cidr: &IpCidr) -> Bits {
@@ -23,6 +24,65 @@ pub fn cidr_contains(outer: &IpCidr, inner: &IpCidr) -> bool {
     outer.contains(&first) && outer.contains(&last)
 }
 
+pub fn bits_for_hosts(count: u128, reserved: u128) -> Option<Bits> {
+    let required = count.checked_add(reserved)?;
+    match required.checked_next_power_of_two() {
+        Some(total) => Some(total.trailing_zeros() as Bits),
+        None => Some(128),
+    }
+}
+
+pub fn usable_hosts(cidr: &IpCidr, reserved: u128) -> u128 {
+    let total = match host_length(cidr) {
+        128 => u128::MAX,
+        host_length => 1 << host_length,
+    };
+    total.saturating_sub(reserved)
+}
+
+/// Enclosing network one prefix bit shorter, if there is one.
+pub fn parent_cidr(cidr: &IpCidr) -> Option<IpCidr> {
+    let length = cidr.network_length().checked_sub(1)?;
+    Some(IpInet::new(cidr.first_address(), length).ok()?.network())
+}
+
+/// Aligned network with the given host length that contains the CIDR.
+pub fn enclosing_cidr(cidr: &IpCidr, host_length: Bits) -> IpCidr {
+    IpInet::new(cidr.first_address(), max_bits(cidr) - host_length)
+        .expect("Host length within address family")
+        .network()
+}
+
+/// Network of the same size that directly follows the CIDR, if there is one.
+pub fn next_cidr(cidr: &IpCidr) -> Option<IpCidr> {
+    let first = match cidr.last_address() {
+        IpAddr::V4(last) => IpAddr::V4(Ipv4Addr::from(u32::from(last).checked_add(1)?)),
+        IpAddr::V6(last) => IpAddr::V6(Ipv6Addr::from(u128::from(last).checked_add(1)?)),
+    };
+    IpCidr::new(first, cidr.network_length()).ok()
+}
+
+/// Address as a number, for arithmetic across address families.
+pub fn address_value(address: &IpAddr) -> u128 {
+    match address {
+        IpAddr::V4(address) => u32::from(*address) as u128,
+        IpAddr::V6(address) => u128::from(*address),
+    }
+}
+
+/// Address with the given value in the address family of the CIDR.
+pub fn address_from_value(cidr: &IpCidr, value: u128) -> IpAddr {
+    match cidr {
+        IpCidr::V4(_) => IpAddr::V4(Ipv4Addr::from(value as u32)),
+        IpCidr::V6(_) => IpAddr::V6(Ipv6Addr::from(value)),
+    }
+}
+
+pub fn lowest_subnet(cidr: &IpCidr, host_length: Bits) -> IpCidr {
+    IpCidr::new(cidr.first_address(), max_bits(cidr) - host_length)
+        .expect("Host length within CIDR")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -56,6 +116,79 @@ mod tests {
         }
     }
 
+    mod parent_cidr {
+        use super::*;
+        use std::str::FromStr;
+
+        #[test]
+        fn parent() {
+            let cidr = IpCidr::from_str("10.20.1.0/24").unwrap();
+            assert_eq!(
+                parent_cidr(&cidr),
+                Some(IpCidr::from_str("10.20.0.0/23").unwrap())
+            );
+        }
+
+        #[test]
+        fn no_parent() {
+            assert_eq!(parent_cidr(&IpCidr::from_str("0.0.0.0/0").unwrap()), None);
+        }
+    }
+
+    mod enclosing_cidr {
+        use super::*;
+        use std::str::FromStr;
+
+        #[test]
+        fn enclosing() {
+            let cidr = IpCidr::from_str("10.20.3.0/24").unwrap();
+            assert_eq!(
+                enclosing_cidr(&cidr, 10),
+                IpCidr::from_str("10.20.0.0/22").unwrap()
+            );
+            assert_eq!(enclosing_cidr(&cidr, 8), cidr);
+        }
+    }
+
+    mod next_cidr {
+        use super::*;
+        use std::str::FromStr;
+
+        #[test]
+        fn next() {
+            let cidr = IpCidr::from_str("10.20.1.0/24").unwrap();
+            assert_eq!(
+                next_cidr(&cidr),
+                Some(IpCidr::from_str("10.20.2.0/24").unwrap())
+            );
+            let cidr = IpCidr::from_str("fc00::/112").unwrap();
+            assert_eq!(
+                next_cidr(&cidr),
+                Some(IpCidr::from_str("fc00::1:0/112").unwrap())
+            );
+        }
+
+        #[test]
+        fn last() {
+            let cidr = IpCidr::from_str("255.255.255.0/24").unwrap();
+            assert_eq!(next_cidr(&cidr), None);
+        }
+    }
+
+    mod address_value {
+        use super::*;
+        use std::str::FromStr;
+
+        #[test]
+        fn round_trip() {
+            let v4 = IpAddr::from_str("10.20.1.2").unwrap();
+            assert_eq!(address_value(&v4), 0x0a140102);
+            assert_eq!(address_from_value(&TEST_CIDR4, 0x0a140102), v4);
+            let v6 = IpAddr::from_str("1:2:3:4:a:14::9").unwrap();
+            assert_eq!(address_from_value(&TEST_CIDR6, address_value(&v6)), v6);
+        }
+    }
+
     mod cidr_contains {
         use super::*;
         use cidr_utils::separator;
@@ -125,4 +258,69 @@ mod tests {
             assert!(cidr_contains(&TEST_CIDR4, &get_nth_of(&TEST_CIDR4, 12, 15)));
         }
     }
+
+    mod bits_for_hosts {
+        use super::*;
+
+        #[test]
+        fn exact() {
+            assert_eq!(bits_for_hosts(256, 0), Some(8));
+        }
+
+        #[test]
+        fn rounds_up() {
+            assert_eq!(bits_for_hosts(300, 0), Some(9));
+        }
+
+        #[test]
+        fn reserved() {
+            assert_eq!(bits_for_hosts(254, 2), Some(8));
+            assert_eq!(bits_for_hosts(255, 2), Some(9));
+        }
+
+        #[test]
+        fn none() {
+            assert_eq!(bits_for_hosts(0, 0), Some(0));
+            assert_eq!(bits_for_hosts(1, 0), Some(0));
+        }
+
+        #[test]
+        fn largest() {
+            assert_eq!(bits_for_hosts(u128::MAX, 0), Some(128));
+            assert_eq!(bits_for_hosts(u128::MAX, 1), None);
+        }
+    }
+
+    mod usable_hosts {
+        use super::*;
+
+        #[test]
+        fn v4() {
+            assert_eq!(usable_hosts(&TEST_CIDR4, 0), 65536);
+            assert_eq!(usable_hosts(&TEST_CIDR4, 2), 65534);
+        }
+
+        #[test]
+        fn all_reserved() {
+            assert_eq!(usable_hosts(&TEST_CIDR6, 70000), 0);
+        }
+    }
+
+    mod lowest_subnet {
+        use super::*;
+        use std::str::FromStr;
+
+        #[test]
+        fn v4() {
+            assert_eq!(
+                lowest_subnet(&TEST_CIDR4, 8),
+                IpCidr::from_str("10.20.0.0/24").unwrap()
+            );
+        }
+
+        #[test]
+        fn whole() {
+            assert_eq!(lowest_subnet(&TEST_CIDR6, 16), TEST_CIDR6);
+        }
+    }
 }